@@ -0,0 +1,150 @@
+//! 主题快照命令行工具，在 broker 停止时使用
+//!
+//! ```text
+//! topic-snapshot create --base-dir <dir> --topic <topic> --output <dir|file.tar>
+//!                       [--partition <id>] [--up-to <offset>] [--master-key <file>]
+//! topic-snapshot restore --input <dir|file.tar> --base-dir <dir>
+//! ```
+//!
+//! 消费者组 offset 只保存在运行中的 broker 内存里，离线创建的快照不包含；需要时使用 `Broker::snapshot_topic`。
+//! 离线创建的快照不知道主题级配置，恢复后按原配置创建主题即可加载恢复的分区。
+
+use broker::metadata::{PartitionMetadata, TopicConfig};
+use broker::snapshot::{self, OpenSnapshot, SnapshotManifest};
+use broker::topic::Topic;
+use queue::MasterKey;
+use std::collections::HashMap;
+use std::fs;
+use std::process;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+用法:
+  topic-snapshot create --base-dir <dir> --topic <topic> --output <dir|file.tar>
+                        [--partition <id>] [--up-to <offset>] [--master-key <file>]
+  topic-snapshot restore --input <dir|file.tar> --base-dir <dir>";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("create") => parse_options(&args[1..]).and_then(|options| create(&options)),
+        Some("restore") => parse_options(&args[1..]).and_then(|options| restore(&options)),
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// 解析 `--name value` 形式的参数
+fn parse_options(args: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(name) = args.next() {
+        let name = name.strip_prefix("--").ok_or_else(|| format!("无效的参数 {}\n{}", name, USAGE))?;
+        let value = args.next().ok_or_else(|| format!("参数 --{} 缺少值", name))?;
+        options.insert(name.to_string(), value.clone());
+    }
+    Ok(options)
+}
+
+fn required<'a>(options: &'a HashMap<String, String>, name: &str) -> Result<&'a str, String> {
+    options.get(name)
+        .map(String::as_str)
+        .ok_or_else(|| format!("缺少参数 --{}\n{}", name, USAGE))
+}
+
+fn optional<T: std::str::FromStr>(options: &HashMap<String, String>, name: &str) -> Result<Option<T>, String> {
+    options.get(name)
+        .map(|value| value.parse().map_err(|_| format!("参数 --{} 的值 {} 无效", name, value)))
+        .transpose()
+}
+
+fn create(options: &HashMap<String, String>) -> Result<(), String> {
+    let base_dir = required(options, "base-dir")?;
+    let topic_name = required(options, "topic")?;
+    let output = required(options, "output")?;
+    let partition = optional::<usize>(options, "partition")?;
+    let up_to = optional::<u64>(options, "up-to")?;
+
+    // 分区数量由 base_dir 下已有的分区目录确定
+    let prefix = format!("{}-", topic_name);
+    let mut partition_ids = Vec::new();
+    for entry in fs::read_dir(base_dir).map_err(|e| format!("读取 {} 失败: {}", base_dir, e))? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(id) = name.strip_prefix(&prefix).and_then(|id| id.parse::<usize>().ok()) {
+            if entry.path().is_dir() {
+                partition_ids.push(id);
+            }
+        }
+    }
+    partition_ids.sort_unstable();
+    let Some(&last) = partition_ids.last() else {
+        return Err(format!("{} 下没有主题 {} 的分区目录", base_dir, topic_name));
+    };
+    if let Some(partition) = partition {
+        if !partition_ids.contains(&partition) {
+            return Err(format!("分区 {} 不存在", partition));
+        }
+    }
+
+    let mut config = TopicConfig {
+        name: topic_name.to_string(),
+        partitions: last + 1,
+        base_dir: base_dir.to_string(),
+        ..TopicConfig::default()
+    };
+    // 指定主密钥时按加密主题打开日志段
+    if options.contains_key("master-key") {
+        config.configs.insert("encryption.enable".to_string(), "true".to_string());
+    }
+    let mut topic = Topic::new(topic_name.to_string(), config.clone());
+    if let Some(path) = options.get("master-key") {
+        let master_key = MasterKey::load(path).map_err(|e| format!("加载主密钥 {} 失败: {}", path, e))?;
+        topic.set_master_key(Arc::new(master_key))?;
+    }
+    for id in partition_ids.iter().filter(|id| partition.is_none_or(|partition| partition == **id)) {
+        let metadata = PartitionMetadata { id: *id, leader: 1, replicas: vec![1], isr: vec![1] };
+        topic.create_partition(*id, metadata)?;
+    }
+
+    let manifest = snapshot::create(output, |dir| {
+        let mut manifest = SnapshotManifest::new(&config, topic_name);
+        manifest.snapshots = topic.snapshot(dir, partition, up_to)?;
+        Ok(manifest)
+    })?;
+    print_manifest(&manifest);
+    println!("快照已写入 {}", output);
+    Ok(())
+}
+
+fn restore(options: &HashMap<String, String>) -> Result<(), String> {
+    let input = required(options, "input")?;
+    let base_dir = required(options, "base-dir")?;
+    let snapshot = OpenSnapshot::open(input)?;
+    fs::create_dir_all(base_dir).map_err(|e| format!("创建 {} 失败: {}", base_dir, e))?;
+    snapshot.restore_partitions(|name| Ok(format!("{}/{}", base_dir, name)))?;
+    print_manifest(snapshot.manifest());
+    println!("分区已恢复到 {}，启动 broker 后按以上配置创建主题即可加载", base_dir);
+    Ok(())
+}
+
+fn print_manifest(manifest: &SnapshotManifest) {
+    println!("主题: {} ({} 个分区)", manifest.topic, manifest.partitions);
+    let mut configs: Vec<_> = manifest.configs.iter().collect();
+    configs.sort();
+    for (key, value) in configs {
+        println!("  {}={}", key, value);
+    }
+    for partition in &manifest.snapshots {
+        println!(
+            "  分区 {}: offset [{}, {})",
+            partition.partition, partition.log_start_offset, partition.end_offset
+        );
+    }
+    for offset in &manifest.consumer_offsets {
+        println!("  消费者组 {} 分区 {}: offset {}", offset.group, offset.partition, offset.offset);
+    }
+}
@@ -1,27 +1,278 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
 use crate::metadata::{TopicMetadata, MetadataManager, TopicConfig, PartitionMetadata};
-use crate::topic::Topic;
-use protocol::{ClientRequest, ProduceRequest, FetchRequest, MetadataRequest, OffsetFetchRequest, JoinGroupRequest, SyncGroupRequest};
+use crate::log_dirs::{LogDirInfo, LogDirs};
+use crate::snapshot::{self, ConsumerOffset, OpenSnapshot, SnapshotManifest};
+use crate::topic::{PartitionAppender, Topic};
+use queue::{FileSlice, FlushHandle, FlushPolicy, LogFlusher, ReadRange, RemoteStorage, RetentionHandle, RetentionManager, RetentionPolicy};
+use queue::{recovery, LogQueue, MasterKey, SegmentConfig};
+use std::time::Duration;
+use protocol::{ClientRequest, ProduceRequest, FetchRequest, MetadataRequest, OffsetFetchRequest, JoinGroupRequest, SyncGroupRequest, RecordBatch};
+use protocol::response::{error_code, ProduceResponse};
 
 /// Broker 是 Kafka 的核心组件，负责管理主题、处理消息和客户端请求
 pub struct Broker {
-    /// 存储所有主题的映射表，使用 Arc<Mutex> 实现线程安全
-    topics: Arc<Mutex<HashMap<String, Topic>>>,
+    /// 存储所有主题的映射表，使用 Arc<RwLock> 实现线程安全，读写消息只需要读锁
+    topics: Arc<RwLock<HashMap<String, Topic>>>,
     /// 管理主题元数据的组件
     metadata_manager: Arc<MetadataManager>,
     /// 存储消费者组的偏移量信息，格式为: group_id -> (topic-partition -> offset)
     offsets: Arc<Mutex<HashMap<String, HashMap<String, u32>>>>,
+    /// 日志保留管理器，所有分区创建后注册到这里
+    retention: RetentionManager,
+    /// 日志刷盘管理器，所有分区创建后注册到这里
+    flusher: LogFlusher,
+    /// 远程存储，启用分层存储的主题把已关闭的日志段转移到这里
+    remote_storage: Option<Arc<dyn RemoteStorage>>,
+    /// 默认压缩类型，未设置 compression.type 的主题使用
+    compression_type: String,
+    /// 多个日志目录，设置后新分区按放置策略分布在这些目录上
+    log_dirs: Option<Arc<LogDirs>>,
+    /// 静态数据加密的主密钥，设置后新建的主题加密日志段
+    master_key: Option<Arc<MasterKey>>,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Broker {
     /// 创建一个新的 Broker 实例
     pub fn new() -> Self {
         Broker {
-            topics: Arc::new(Mutex::new(HashMap::new())),
+            topics: Arc::new(RwLock::new(HashMap::new())),
             metadata_manager: Arc::new(MetadataManager::new()),
             offsets: Arc::new(Mutex::new(HashMap::new())),
+            retention: RetentionManager::default(),
+            flusher: LogFlusher::default(),
+            remote_storage: None,
+            compression_type: "producer".to_string(),
+            log_dirs: None,
+            master_key: None,
+        }
+    }
+
+    /// 设置远程存储，之后创建的 remote.storage.enable=true 的主题使用分层存储
+    /// 
+    /// # Arguments
+    /// * `storage` - 远程存储实现
+    pub fn with_remote_storage(mut self, storage: Arc<dyn RemoteStorage>) -> Self {
+        self.remote_storage = Some(storage);
+        self
+    }
+
+    /// 设置默认压缩类型，之后创建的未设置 compression.type 的主题使用
+    /// 
+    /// # Arguments
+    /// * `compression_type` - producer（保留生产者的压缩编码）、none、gzip、snappy、lz4 或 zstd
+    pub fn with_compression_type(mut self, compression_type: &str) -> Self {
+        self.compression_type = compression_type.to_string();
+        self
+    }
+
+    /// 设置日志目录，之后创建的主题的分区分布在这些目录上，已有的分区由 load_logs 在原目录打开
+    /// 
+    /// # Arguments
+    /// * `log_dirs` - 启动时已经发现已有分区的日志目录
+    pub fn with_log_dirs(mut self, log_dirs: LogDirs) -> Self {
+        self.log_dirs = Some(Arc::new(log_dirs));
+        self
+    }
+
+    /// 设置主密钥，之后创建的设置了 encryption.enable=true 的主题使用各自的数据密钥加密日志段
+    /// 
+    /// # Arguments
+    /// * `master_key` - 主密钥
+    pub fn with_master_key(mut self, master_key: MasterKey) -> Self {
+        self.master_key = Some(Arc::new(master_key));
+        self
+    }
+
+    /// 从密钥文件加载主密钥，路径为空时不启用加密
+    /// 
+    /// # Arguments
+    /// * `path` - 主密钥文件（storage.encryption_master_key_file）
+    /// 
+    /// # Returns
+    /// * `Result<Self, String>` - 成功返回 Broker，密钥文件无法读取或格式错误时返回错误信息
+    pub fn with_master_key_file(self, path: &str) -> Result<Self, String> {
+        if path.is_empty() {
+            return Ok(self);
         }
+        let master_key = MasterKey::load(path)
+            .map_err(|e| format!("加载主密钥 {} 失败: {}", path, e))?;
+        Ok(self.with_master_key(master_key))
+    }
+
+    /// 轮换主题的数据密钥，之后新建的日志段使用新密钥
+    /// 
+    /// # Arguments
+    /// * `topic` - 主题名称
+    /// 
+    /// # Returns
+    /// * `Result<u32, String>` - 成功返回新密钥的 ID，主题不存在或未加密时返回错误信息
+    pub fn rotate_encryption_key(&self, topic: &str) -> Result<u32, String> {
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
+            .ok_or_else(|| "Topic not found".to_string())?;
+        topic.rotate_encryption_key()
+    }
+
+    /// 所有日志目录的状态
+    /// 
+    /// # Returns
+    /// * `Vec<LogDirInfo>` - 每个目录的路径、是否在线以及目录上的分区，未配置日志目录时为空
+    pub fn describe_log_dirs(&self) -> Vec<LogDirInfo> {
+        self.log_dirs.as_ref().map(|log_dirs| log_dirs.describe()).unwrap_or_default()
+    }
+
+    /// 把分区迁移到另一个日志目录
+    /// 
+    /// # Arguments
+    /// * `topic` - 主题名称
+    /// * `partition` - 分区 ID
+    /// * `dest_dir` - 目标日志目录
+    /// 
+    /// # Returns
+    /// * `Result<(), String>` - 迁移成功返回 Ok(()), 失败返回错误信息
+    pub fn move_partition(&self, topic: &str, partition: usize, dest_dir: &str) -> Result<(), String> {
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
+            .ok_or_else(|| "Topic not found".to_string())?;
+        topic.move_partition(partition, dest_dir)
+    }
+
+    /// 创建主题快照，包含分区的日志段、索引、日志起始 offset 以及消费者组提交的 offset
+    /// 
+    /// # Arguments
+    /// * `topic` - 主题名称
+    /// * `partition` - 只快照指定分区，为 None 时快照所有分区
+    /// * `up_to` - 每个分区快照的结束 offset，为 None 时到日志结束 offset
+    /// * `dest` - 快照目录，以 .tar 结尾时打包为 tar 文件
+    /// 
+    /// # Returns
+    /// * `Result<SnapshotManifest, String>` - 成功返回快照清单，失败返回错误信息
+    pub fn snapshot_topic(&self, topic: &str, partition: Option<usize>, up_to: Option<u64>, dest: &str) -> Result<SnapshotManifest, String> {
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
+            .ok_or_else(|| "Topic not found".to_string())?;
+        snapshot::create(dest, |dir| {
+            let mut manifest = SnapshotManifest::new(topic.config(), topic.get_name());
+            manifest.snapshots = topic.snapshot(dir, partition, up_to)?;
+
+            // 提交的 offset 超过快照结束 offset 时按结束 offset 保存，恢复后从快照末尾继续消费
+            let offsets = self.offsets.lock().map_err(|e| e.to_string())?;
+            for (group, group_offsets) in offsets.iter() {
+                for partition in &manifest.snapshots {
+                    let key = format!("{}-{}", manifest.topic, partition.partition);
+                    if let Some(offset) = group_offsets.get(&key) {
+                        manifest.consumer_offsets.push(ConsumerOffset {
+                            group: group.clone(),
+                            partition: partition.partition,
+                            offset: (*offset as u64).min(partition.end_offset) as u32,
+                        });
+                    }
+                }
+            }
+            manifest.consumer_offsets.sort_by(|a, b| (&a.group, a.partition).cmp(&(&b.group, b.partition)));
+            Ok(manifest)
+        })
+    }
+
+    /// 从快照恢复主题：分区复制到日志目录后创建主题，并恢复消费者组提交的 offset
+    /// 
+    /// 快照不包含的分区创建为空分区。加密主题的快照需要 broker 使用创建快照时的主密钥
+    /// 
+    /// # Arguments
+    /// * `src` - 快照目录或 tar 文件
+    /// * `base_dir` - 恢复的主题的基础目录，配置了多个日志目录时分区按放置策略分布
+    /// 
+    /// # Returns
+    /// * `Result<SnapshotManifest, String>` - 成功返回快照清单，主题已存在或恢复失败时返回错误信息
+    pub fn restore_topic(&self, src: &str, base_dir: &str) -> Result<SnapshotManifest, String> {
+        let snapshot = OpenSnapshot::open(src)?;
+        let manifest = snapshot.manifest().clone();
+        if self.topics.read().map_err(|e| e.to_string())?.contains_key(&manifest.topic) {
+            return Err(format!("主题 {} 已存在", manifest.topic));
+        }
+        let config = manifest.topic_config(base_dir);
+        snapshot.restore_partitions(|name| match &self.log_dirs {
+            Some(log_dirs) => log_dirs.assign(name),
+            None => Ok(format!("{}/{}", base_dir, name)),
+        })?;
+        self.create_topic(&manifest.topic, config)?;
+        for offset in &manifest.consumer_offsets {
+            self.commit_offset(&offset.group, &manifest.topic, offset.partition, offset.offset)?;
+        }
+        Ok(manifest)
+    }
+
+    /// 按 broker 配置启动日志保留任务
+    /// 
+    /// 默认策略来自 log_retention_hours / log_retention_bytes，主题可以通过 retention.ms / retention.bytes 覆盖，
+    /// 每隔 log_retention_check_interval_ms 检查一次
+    /// 
+    /// # Returns
+    /// * `Result<RetentionHandle, String>` - 成功返回后台任务句柄，丢弃或调用 shutdown 时停止
+    pub fn start_retention(&self, config: &cfg::BrokerConfig) -> Result<RetentionHandle, String> {
+        self.retention.set_default_policy(RetentionPolicy::from_hours(
+            config.log_retention_hours,
+            config.log_retention_bytes,
+        ));
+        self.retention
+            .start(Duration::from_millis(config.log_retention_check_interval_ms as u64))
+            .map_err(|e| format!("启动日志保留任务失败: {}", e))
+    }
+
+    /// 立即对所有分区执行一次保留策略
+    /// 
+    /// # Returns
+    /// * `usize` - 删除的日志段数量
+    pub fn run_retention(&self) -> usize {
+        self.retention.run_once()
+    }
+
+    /// 按存储配置启动后台刷盘任务
+    /// 
+    /// 默认策略来自 flush_policy / flush_messages / flush_interval_ms，主题可以通过 flush.messages / flush.ms 覆盖，
+    /// 每隔 flush_scheduler_interval_ms 检查一次按时间刷盘到期的分区
+    /// 
+    /// # Returns
+    /// * `Result<FlushHandle, String>` - 成功返回后台任务句柄，丢弃或调用 shutdown 时停止
+    pub fn start_flusher(&self, config: &cfg::StorageConfig) -> Result<FlushHandle, String> {
+        self.flusher.set_default_policy(FlushPolicy::from_config(
+            &config.flush_policy,
+            config.flush_messages,
+            config.flush_interval_ms as u64,
+        )?);
+        self.flusher
+            .start(Duration::from_millis(config.flush_scheduler_interval_ms as u64))
+            .map_err(|e| format!("启动刷盘任务失败: {}", e))
+    }
+
+    /// 每个分区的恢复点，格式为: 主题-分区号 -> offset，小于该 offset 的记录都已 fsync
+    /// 
+    /// # Returns
+    /// * `HashMap<String, u64>` - 所有分区的恢复点
+    pub fn recovery_points(&self) -> HashMap<String, u64> {
+        self.flusher.recovery_points()
+    }
+
+    /// 正常关闭：所有分区刷盘，写入恢复点检查点并创建正常关闭标记，下次启动时跳过日志校验
+    /// 
+    /// # Returns
+    /// * `Result<(), String>` - 成功返回 Ok(()), 失败返回错误信息
+    pub fn shutdown(&self) -> Result<(), String> {
+        // 离线目录上的分区无法刷盘，不阻止其他目录正常关闭
+        if let Some(log_dirs) = &self.log_dirs {
+            for partition in log_dirs.offline_partitions() {
+                self.flusher.unregister(&partition);
+                self.retention.unregister(&partition);
+            }
+        }
+        self.flusher.shutdown().map_err(|e| format!("关闭日志失败: {}", e))
     }
 
     /// 创建一个新的主题
@@ -32,12 +283,24 @@ impl Broker {
     /// 
     /// # Returns
     /// * `Result<(), String>` - 创建成功返回 Ok(()), 失败返回错误信息
-    pub fn create_topic(&self, topic: &str, config: TopicConfig) -> Result<(), String> {
+    pub fn create_topic(&self, topic: &str, mut config: TopicConfig) -> Result<(), String> {
+        config.configs
+            .entry("compression.type".to_string())
+            .or_insert_with(|| self.compression_type.clone());
+        let name = topic;
+        let mut topic = Topic::new(name.to_string(), config.clone());
+        if let Some(log_dirs) = &self.log_dirs {
+            topic.set_log_dirs(log_dirs.clone());
+        }
+        if let Some(master_key) = &self.master_key {
+            topic.set_master_key(master_key.clone())?;
+        }
+        // 先校验压缩类型和存储类型，避免写入无效的元数据
+        topic.compression_type()?;
+        topic.is_memory()?;
 
-        let topic_metadata = TopicMetadata::new(topic.to_string(), config.clone());
+        let topic_metadata = TopicMetadata::new(name.to_string(), config.clone());
         self.metadata_manager.add_topic(topic_metadata)?;
-
-        let mut topic = Topic::new(topic.to_string(), config.clone());
         for i in 0..config.partitions {
             let metadata = PartitionMetadata {
                 id: i,
@@ -48,8 +311,99 @@ impl Broker {
             topic.create_partition(i, metadata)?;
         }
 
-        let mut topics = self.topics.lock().map_err(|e| e.to_string())?;
-        topics.insert(topic.to_string(), topic);
+        self.add_topic(topic)
+    }
+
+    /// 启动时加载数据目录下已有的分区，按 `主题-分区号` 组合成主题，之后无需再调用 create_topic
+    /// 
+    /// 上次正常关闭时信任恢复点检查点，直接打开所有日志段；否则只修复恢复点之后的日志段。
+    /// 每个数据目录使用 `num_recovery_threads` 个线程并行恢复，全部加载完成后删除正常关闭标记。
+    /// 配置了多个日志目录时加载所有在线目录，分区在原目录打开，同一主题的分区可以分布在不同目录上。
+    /// 主题级配置没有持久化，加载的主题使用默认配置，分区数为目录中最大的分区号加一
+    /// 
+    /// # Arguments
+    /// * `log_dir` - 数据目录，加载的主题以它作为 base_dir；配置了多个日志目录时只作为 base_dir
+    /// * `segment_size` - 单个日志段的最大大小（字节）
+    /// * `num_recovery_threads` - 每个数据目录的恢复线程数（num_recovery_threads_per_data_dir）
+    /// 
+    /// # Returns
+    /// * `Result<Vec<String>, String>` - 成功返回加载的主题名称（按名称排序），失败返回错误信息
+    pub fn load_logs(&self, log_dir: &str, segment_size: usize, num_recovery_threads: usize) -> Result<Vec<String>, String> {
+        // 与按默认配置创建的分区使用相同的日志段配置
+        let segment_config = SegmentConfig {
+            max_segment_size: segment_size,
+            ..SegmentConfig::default()
+        };
+        let data_dirs = match &self.log_dirs {
+            Some(log_dirs) => log_dirs.describe()
+                .into_iter()
+                .filter(|dir| dir.online)
+                .map(|dir| dir.path)
+                .collect(),
+            None => vec![log_dir.to_string()],
+        };
+        let mut topics: BTreeMap<String, BTreeMap<usize, LogQueue>> = BTreeMap::new();
+        for data_dir in data_dirs {
+            let logs = recovery::load_logs(&data_dir, &segment_config, num_recovery_threads)
+                .map_err(|e| format!("加载数据目录 {} 失败: {}", data_dir, e))?;
+            for (name, queue) in logs {
+                // 启动时发现的分区只在一个目录中，迁移中断后另一个目录里的副本不加载
+                if let Some(log_dirs) = &self.log_dirs {
+                    if log_dirs.dir_of(&name).as_deref() != Some(data_dir.as_str()) {
+                        continue;
+                    }
+                }
+                let (topic, partition_id) = name.rsplit_once('-')
+                    .filter(|(topic, _)| !topic.is_empty())
+                    .and_then(|(topic, partition)| Some((topic, partition.parse::<usize>().ok()?)))
+                    .ok_or_else(|| format!("无法识别的分区目录: {}/{}", data_dir, name))?;
+                topics.entry(topic.to_string()).or_default().insert(partition_id, queue);
+            }
+        }
+
+        let mut loaded = Vec::with_capacity(topics.len());
+        for (name, mut partitions) in topics {
+            let config = TopicConfig {
+                name: name.clone(),
+                partitions: partitions.keys().max().map_or(1, |id| id + 1),
+                segment_size,
+                base_dir: log_dir.to_string(),
+                configs: HashMap::from([("compression.type".to_string(), self.compression_type.clone())]),
+                ..Default::default()
+            };
+            let mut topic = Topic::new(name.clone(), config.clone());
+            if let Some(log_dirs) = &self.log_dirs {
+                topic.set_log_dirs(log_dirs.clone());
+            }
+            self.metadata_manager.add_topic(TopicMetadata::new(name.clone(), config.clone()))?;
+            for i in 0..config.partitions {
+                match partitions.remove(&i) {
+                    Some(queue) => topic.open_partition(i, queue)?,
+                    // 分区目录不存在时重新创建为空分区
+                    None => topic.create_partition(i, PartitionMetadata {
+                        id: i,
+                        leader: 1,
+                        replicas: vec![1],
+                        isr: vec![1],
+                    })?,
+                }
+            }
+            self.add_topic(topic)?;
+            loaded.push(name);
+        }
+        Ok(loaded)
+    }
+
+    /// 启用分层存储，把分区注册到保留和刷盘管理器后加入主题表
+    fn add_topic(&self, topic: Topic) -> Result<(), String> {
+        if let Some(storage) = &self.remote_storage {
+            topic.enable_remote_storage(storage)?;
+        }
+        topic.register_retention(&self.retention)?;
+        topic.register_flusher(&self.flusher)?;
+
+        let mut topics = self.topics.write().map_err(|e| e.to_string())?;
+        topics.insert(topic.get_name().to_string(), topic);
         Ok(())
     }
 
@@ -62,12 +416,84 @@ impl Broker {
     /// # Returns
     /// * `Result<u64, String>` - 成功返回消息的偏移量，失败返回错误信息
     pub fn send_message(&self, topic: &str, message: Vec<u8>) -> Result<u64, String> {
-        let mut topics = self.topics.lock().map_err(|e| e.to_string())?;
-        let topic = topics.get_mut(topic)
+        let appender = {
+            let topics = self.topics.read().map_err(|e| e.to_string())?;
+            let topic = topics.get(topic)
+                .ok_or_else(|| "Topic not found".to_string())?;
+            let partition_id = message.len() % topic.get_partition_count();
+            topic.appender(partition_id).map_err(|e| e.message)?
+        };
+        // 追加可能等待分区释放空间，不能持有主题表的锁
+        appender.append_message(message)
+    }
+
+    /// 发送记录批次到指定主题的分区
+    /// 
+    /// # Arguments
+    /// * `topic` - 目标主题
+    /// * `partition` - 分区 ID
+    /// * `batch` - 记录批次
+    /// 
+    /// # Returns
+    /// * `Result<u64, String>` - 成功返回批次的起始偏移量，失败返回错误信息
+    pub fn send_batch(&self, topic: &str, partition: usize, batch: RecordBatch) -> Result<u64, String> {
+        self.appender(topic, partition)
+            .map_err(|(_, message)| message)?
+            .append_batch(batch)
+            .map_err(|e| e.message)
+    }
+
+    /// 处理生产请求并构造响应，失败时响应带有错误码（分区写满时为 `error_code::QUEUE_FULL`）
+    /// 
+    /// # Arguments
+    /// * `req` - 生产请求
+    /// 
+    /// # Returns
+    /// * `ProduceResponse` - 成功时带有批次的起始偏移量，失败时偏移量为 -1
+    pub fn produce(&self, req: &ProduceRequest) -> ProduceResponse {
+        let result = req.record_batch()
+            .map_err(|e| error_code::from_storage_error(&e))
+            .and_then(|batch| {
+                self.appender(&req.topic, req.partition as usize)
+                    .map_err(|(code, _)| code)?
+                    .append_batch(batch)
+                    .map_err(|e| e.error_code)
+            });
+        let (offset, error_code) = match result {
+            Ok(offset) => (offset as i64, error_code::NONE),
+            Err(code) => (-1, code),
+        };
+        ProduceResponse {
+            topic: req.topic.clone(),
+            partition: req.partition,
+            offset,
+            error_code,
+        }
+    }
+
+    /// 获取主题分区的追加句柄，返回时已经释放主题表的锁，追加等待分区空间时不阻塞其他请求
+    fn appender(&self, topic: &str, partition: usize) -> Result<PartitionAppender, (i16, String)> {
+        let topics = self.topics.read().map_err(|e| (error_code::UNKNOWN_SERVER_ERROR, e.to_string()))?;
+        let topic = topics.get(topic)
+            .ok_or_else(|| (error_code::UNKNOWN_TOPIC_OR_PARTITION, "Topic not found".to_string()))?;
+        topic.appender(partition).map_err(|e| (e.error_code, e.message))
+    }
+
+    /// 删除指定主题分区中 offset 小于 `offset` 的记录，推进日志起始 offset
+    /// 
+    /// # Arguments
+    /// * `topic` - 主题名称
+    /// * `partition` - 分区 ID
+    /// * `offset` - 新的日志起始 offset
+    /// 
+    /// # Returns
+    /// * `Result<u64, String>` - 成功返回新的日志起始 offset，失败返回错误信息
+    pub fn delete_records(&self, topic: &str, partition: usize, offset: u64) -> Result<u64, String> {
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
             .ok_or_else(|| "Topic not found".to_string())?;
-        
-        let partition_id = message.len() % topic.get_partition_count();
-        topic.append_message(partition_id, message)
+
+        topic.delete_records_before(partition, offset)
     }
 
     /// 从指定主题的分区获取消息
@@ -80,13 +506,49 @@ impl Broker {
     /// # Returns
     /// * `Result<Option<Vec<u8>>, String>` - 成功返回消息内容，失败返回错误信息
     pub fn fetch_message(&self, topic: &str, partition: usize, offset: u32) -> Result<Option<Vec<u8>>, String> {
-        let mut topics = self.topics.lock().map_err(|e| e.to_string())?;
-        let topic = topics.get_mut(topic)
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
             .ok_or_else(|| "Topic not found".to_string())?;
         
         topic.read_message(partition, offset as u64)
     }
 
+    /// 从指定主题的分区范围读取连续记录
+    /// 
+    /// # Arguments
+    /// * `topic` - 主题名称
+    /// * `partition` - 分区 ID
+    /// * `offset` - 起始偏移量
+    /// * `max_bytes` - 本次读取的最大字节数
+    /// 
+    /// # Returns
+    /// * `Result<ReadRange, String>` - 成功返回读取到的记录及下一次读取的 offset，失败返回错误信息
+    pub fn fetch_range(&self, topic: &str, partition: usize, offset: u64, max_bytes: usize) -> Result<ReadRange, String> {
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
+            .ok_or_else(|| "Topic not found".to_string())?;
+
+        topic.read_range(partition, offset, max_bytes, usize::MAX)
+    }
+
+    /// 定位指定主题分区从 offset 开始的日志文件区域，交给网络层通过 sendfile 直接发送
+    /// 
+    /// # Arguments
+    /// * `topic` - 主题名称
+    /// * `partition` - 分区 ID
+    /// * `offset` - 起始偏移量
+    /// * `max_bytes` - 区域的最大字节数
+    /// 
+    /// # Returns
+    /// * `Result<Option<FileSlice>, String>` - 成功返回文件区域，没有可读数据时返回 None，失败返回错误信息
+    pub fn fetch_slice(&self, topic: &str, partition: usize, offset: u64, max_bytes: usize) -> Result<Option<FileSlice>, String> {
+        let topics = self.topics.read().map_err(|e| e.to_string())?;
+        let topic = topics.get(topic)
+            .ok_or_else(|| "Topic not found".to_string())?;
+
+        topic.read_slice(partition, offset, max_bytes)
+    }
+
     /// 提交消费者组的偏移量
     /// 
     /// # Arguments
@@ -123,13 +585,14 @@ impl Broker {
     // 内部方法
     /// 处理生产者请求
     fn handle_produce_request(&self, req: ProduceRequest) -> Result<(), String> {
-        self.send_message(&req.topic, req.message)
+        let batch = req.record_batch().map_err(|e| e.to_string())?;
+        self.send_batch(&req.topic, req.partition as usize, batch)
             .map(|_| ())
     }
 
     /// 处理消费者请求
     fn handle_fetch_request(&self, req: FetchRequest) -> Result<(), String> {
-        self.fetch_message(&req.topic, req.partition, req.offset)
+        self.fetch_range(&req.topic, req.partition as usize, req.offset as u64, req.max_bytes.max(0) as usize)
             .map(|_| ())
     }
 
@@ -141,8 +604,16 @@ impl Broker {
 
     /// 处理偏移量获取请求
     fn handle_offset_fetch_request(&self, req: OffsetFetchRequest) -> Result<(), String> {
-        self.get_offset(&req.group_id, &req.topic, req.partition)
-            .map(|_| ())
+        for topic in &req.topics {
+            let partition_count = self.topics.read().map_err(|e| e.to_string())?
+                .get(topic)
+                .map(|t| t.get_partition_count())
+                .unwrap_or(0);
+            for partition in 0..partition_count {
+                self.get_offset(&req.group_id, topic, partition)?;
+            }
+        }
+        Ok(())
     }
 
     /// 处理加入消费者组请求
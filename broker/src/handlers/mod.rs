@@ -31,7 +31,7 @@ pub use leave_group::LeaveGroupHandler;
 use protocol::message::MessageType;
 
 /// 注册所有必需的消息处理器
-pub async fn register_all_handlers(server: &dyn network::NetworkServer) {
+pub async fn register_all_handlers(server: &network::NetworkServer) {
     server.register_handler(MessageType::Produce, Box::new(ProduceHandler)).await;
     server.register_handler(MessageType::Fetch, Box::new(FetchHandler)).await;
     server.register_handler(MessageType::Metadata, Box::new(MetadataHandler)).await;
@@ -3,12 +3,15 @@ pub mod topic;
 pub mod broker;
 pub mod request;
 pub mod handlers;
+pub mod log_dirs;
+pub mod snapshot;
 
 // 对外暴露的核心接口
 pub use broker::Broker;
 pub use request::RequestHandler;
 pub use metadata::{TopicConfig, PartitionMetadata, TopicMetadata, MetadataManager};
 pub use topic::Topic;
+pub use log_dirs::{LogDirInfo, LogDirs, PlacementPolicy};
 
 // 重新导出协议类型
 pub use protocol::{
@@ -0,0 +1,275 @@
+//! 多日志目录（JBOD）
+//!
+//! broker 可以配置多个日志目录，新分区放在分区数最少（或占用字节数最少）的在线目录上，
+//! 启动时扫描所有目录发现已有分区。某个目录发生 I/O 错误后被标记为离线，
+//! 只有该目录上的分区不可用，其他目录上的分区继续服务。
+
+use queue::MOVING_DIR_SUFFIX;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// 新分区的放置策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlacementPolicy {
+    /// 放在分区数最少的目录
+    #[default]
+    FewestPartitions,
+    /// 放在已占用字节数最少的目录
+    LeastBytes,
+}
+
+impl FromStr for PlacementPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "partitions" => Ok(PlacementPolicy::FewestPartitions),
+            "bytes" => Ok(PlacementPolicy::LeastBytes),
+            other => Err(format!("未知的日志目录放置策略: {}", other)),
+        }
+    }
+}
+
+/// 日志目录的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogDirInfo {
+    /// 目录路径
+    pub path: String,
+    /// 是否在线
+    pub online: bool,
+    /// 目录上的分区（`主题-分区号`）
+    pub partitions: Vec<String>,
+}
+
+#[derive(Debug)]
+struct LogDir {
+    path: String,
+    online: bool,
+    partitions: BTreeSet<String>,
+}
+
+/// broker 的全部日志目录，记录每个分区所在的目录，由所有主题共享
+#[derive(Debug)]
+pub struct LogDirs {
+    dirs: Mutex<Vec<LogDir>>,
+    policy: PlacementPolicy,
+}
+
+impl LogDirs {
+    /// 打开日志目录并发现已有分区，无法创建或读取的目录标记为离线
+    ///
+    /// # Arguments
+    /// * `paths` - 日志目录列表
+    /// * `policy` - 新分区的放置策略
+    ///
+    /// # Returns
+    /// * `Result<Self, String>` - 成功返回日志目录，目录列表为空时返回错误信息
+    pub fn new(paths: &[String], policy: PlacementPolicy) -> Result<Self, String> {
+        if paths.is_empty() {
+            return Err("至少需要一个日志目录".to_string());
+        }
+        let mut dirs: Vec<LogDir> = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.trim_end_matches('/').to_string();
+            if dirs.iter().any(|dir| dir.path == path) {
+                return Err(format!("日志目录 {} 重复", path));
+            }
+            let (online, partitions) = match discover_partitions(&path) {
+                Ok(partitions) => (true, partitions),
+                Err(e) => {
+                    eprintln!("日志目录 {} 不可用，标记为离线: {}", path, e);
+                    (false, BTreeSet::new())
+                }
+            };
+            // 迁移在删除旧目录前中断时两个目录中都有该分区，两份数据相同，使用先发现的
+            let partitions = partitions
+                .into_iter()
+                .filter(|partition| match dirs.iter().find(|dir| dir.partitions.contains(partition)) {
+                    Some(other) => {
+                        eprintln!("分区 {} 同时存在于日志目录 {} 和 {}，使用前者", partition, other.path, path);
+                        false
+                    }
+                    None => true,
+                })
+                .collect();
+            dirs.push(LogDir { path, online, partitions });
+        }
+        Ok(Self { dirs: Mutex::new(dirs), policy })
+    }
+
+    /// 按存储配置创建：log_dirs 为逗号分隔的目录列表，未配置时使用 log_dir
+    pub fn from_config(config: &cfg::StorageConfig) -> Result<Self, String> {
+        let paths: Vec<String> = config.log_dirs
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect();
+        let paths = if paths.is_empty() { vec![config.log_dir.clone()] } else { paths };
+        Self::new(&paths, config.log_dir_placement.parse()?)
+    }
+
+    /// 分区所在的日志目录
+    pub fn dir_of(&self, partition: &str) -> Option<String> {
+        let dirs = self.dirs.lock().unwrap();
+        dirs.iter()
+            .find(|dir| dir.partitions.contains(partition))
+            .map(|dir| dir.path.clone())
+    }
+
+    /// 为分区分配日志目录并返回分区目录：已有的分区使用原目录，新分区按放置策略选择在线目录
+    ///
+    /// # Returns
+    /// * `Result<String, String>` - 成功返回分区目录，分区所在目录离线或没有在线目录时返回错误信息
+    pub fn assign(&self, partition: &str) -> Result<String, String> {
+        let mut dirs = self.dirs.lock().unwrap();
+        if let Some(dir) = dirs.iter().find(|dir| dir.partitions.contains(partition)) {
+            if !dir.online {
+                return Err(format!("分区 {} 所在的日志目录 {} 已离线", partition, dir.path));
+            }
+            return Ok(format!("{}/{}", dir.path, partition));
+        }
+
+        let dir = match self.policy {
+            PlacementPolicy::FewestPartitions => dirs.iter_mut()
+                .filter(|dir| dir.online)
+                .min_by_key(|dir| dir.partitions.len()),
+            PlacementPolicy::LeastBytes => dirs.iter_mut()
+                .filter(|dir| dir.online)
+                .min_by_key(|dir| dir_size(Path::new(&dir.path))),
+        }
+        .ok_or_else(|| "没有在线的日志目录".to_string())?;
+        dir.partitions.insert(partition.to_string());
+        Ok(format!("{}/{}", dir.path, partition))
+    }
+
+    /// 分区目录删除后释放分配
+    pub fn release(&self, partition: &str) {
+        let mut dirs = self.dirs.lock().unwrap();
+        for dir in dirs.iter_mut() {
+            dir.partitions.remove(partition);
+        }
+    }
+
+    /// 把分区的分配改到另一个目录，分区数据迁移完成后调用
+    pub fn reassign(&self, partition: &str, dest: &str) -> Result<(), String> {
+        let dest = dest.trim_end_matches('/');
+        let mut dirs = self.dirs.lock().unwrap();
+        if !dirs.iter().any(|dir| dir.path == dest) {
+            return Err(format!("日志目录 {} 未配置", dest));
+        }
+        for dir in dirs.iter_mut() {
+            if dir.path == dest {
+                dir.partitions.insert(partition.to_string());
+            } else {
+                dir.partitions.remove(partition);
+            }
+        }
+        Ok(())
+    }
+
+    /// 日志目录是否已配置且在线
+    pub fn is_online(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        let dirs = self.dirs.lock().unwrap();
+        dirs.iter().any(|dir| dir.path == path && dir.online)
+    }
+
+    /// 把日志目录标记为离线，返回该目录上的分区
+    pub fn mark_offline(&self, path: &str) -> Vec<String> {
+        let path = path.trim_end_matches('/');
+        let mut dirs = self.dirs.lock().unwrap();
+        match dirs.iter_mut().find(|dir| dir.path == path) {
+            Some(dir) => {
+                if dir.online {
+                    eprintln!("日志目录 {} 发生 I/O 错误，{} 个分区下线", dir.path, dir.partitions.len());
+                    dir.online = false;
+                }
+                dir.partitions.iter().cloned().collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// 离线目录上的全部分区
+    pub fn offline_partitions(&self) -> Vec<String> {
+        let dirs = self.dirs.lock().unwrap();
+        dirs.iter()
+            .filter(|dir| !dir.online)
+            .flat_map(|dir| dir.partitions.iter().cloned())
+            .collect()
+    }
+
+    /// 所有日志目录的状态
+    pub fn describe(&self) -> Vec<LogDirInfo> {
+        let dirs = self.dirs.lock().unwrap();
+        dirs.iter()
+            .map(|dir| LogDirInfo {
+                path: dir.path.clone(),
+                online: dir.online,
+                partitions: dir.partitions.iter().cloned().collect(),
+            })
+            .collect()
+    }
+}
+
+/// 是否为存储设备错误：只有 I/O 错误、空间写满、只读文件系统、权限错误以及日志目录不再是目录时让目录离线，
+/// 参数错误、数据损坏、文件不存在等逻辑错误不会让目录离线
+pub fn is_storage_error(e: &io::Error) -> bool {
+    if let Some(errno) = e.raw_os_error() {
+        return matches!(
+            errno,
+            libc::EIO | libc::ENOSPC | libc::EDQUOT | libc::EROFS | libc::EACCES | libc::EPERM | libc::ENOTDIR
+        );
+    }
+    // 没有 errno 的错误（例如经过包装）按错误类型判断
+    matches!(
+        e.kind(),
+        io::ErrorKind::StorageFull
+            | io::ErrorKind::QuotaExceeded
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::NotADirectory
+    )
+}
+
+/// 扫描日志目录下的分区目录（`主题-分区号`），清理迁移中途遗留的目录
+fn discover_partitions(path: &str) -> io::Result<BTreeSet<String>> {
+    fs::create_dir_all(path)?;
+    let mut partitions = BTreeSet::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(MOVING_DIR_SUFFIX) {
+            fs::remove_dir_all(entry.path())?;
+            continue;
+        }
+        let is_partition = !name.starts_with('.')
+            && name.rsplit_once('-').is_some_and(|(topic, partition)| {
+                !topic.is_empty() && partition.parse::<usize>().is_ok()
+            });
+        if is_partition {
+            partitions.insert(name);
+        }
+    }
+    Ok(partitions)
+}
+
+/// 目录下全部文件的字节数
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else { return 0 };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        })
+        .sum()
+}
@@ -38,6 +38,31 @@ pub struct TopicConfig {
     pub segment_size: usize,
     /// 基础目录
     pub base_dir: String,
+    /// 其他主题级配置项，键名与 Kafka 一致（如 cleanup.policy、delete.retention.ms）
+    pub configs: HashMap<String, String>,
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            partitions: 1,
+            replication_factor: 1,
+            segment_size: 1024 * 1024,
+            base_dir: String::new(),
+            configs: HashMap::new(),
+        }
+    }
+}
+
+impl TopicConfig {
+    /// 读取并解析主题级配置项，未配置时返回 None
+    pub fn get_config<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>, String> {
+        self.configs
+            .get(key)
+            .map(|value| value.parse().map_err(|_| format!("配置项 {} 的值 {} 无效", key, value)))
+            .transpose()
+    }
 }
 
 impl TopicMetadata {
@@ -89,6 +114,12 @@ pub struct MetadataManager {
     topics: Arc<Mutex<HashMap<String, TopicMetadata>>>,
 }
 
+impl Default for MetadataManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MetadataManager {
     /// 创建新的元数据管理器
     pub fn new() -> Self {
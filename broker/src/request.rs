@@ -1,7 +1,8 @@
-use protocol::message::{BinaryMessage, MessageType};
+use protocol::message::BinaryMessage;
 use crate::broker::Broker;
 
 pub struct RequestHandler {
+    #[allow(dead_code)] // 请求分发尚未实现
     broker: Broker,
 }
 
@@ -10,7 +11,7 @@ impl RequestHandler {
         Self { broker }
     }
 
-    pub fn handle_request(&self, request: BinaryMessage) -> BinaryMessage {
+    pub fn handle_request(&self, _request: BinaryMessage) -> BinaryMessage {
         /* match request.msg_type {
             MessageType::Produce => {  // 生产者请求
                 let msg_id = self.broker.send_message("default_topic", request.payload);
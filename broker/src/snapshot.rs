@@ -0,0 +1,248 @@
+//! 主题快照
+//!
+//! 快照目录包含 `snapshot.json`（主题配置、各分区的 offset 范围与消费者组 offset）以及每个分区一个与分区目录
+//! 布局相同的子目录 `主题-分区号`。目标路径以 `.tar` 结尾时快照打包为 tar 文件。
+//! 恢复时把分区子目录复制到新 broker 的日志目录，随后创建主题，分区由 `LogQueue` 打开时的 `load_segments` 加载。
+//! 加密主题的日志段保持加密，恢复到的 broker 需要使用相同的主密钥。
+
+use crate::metadata::TopicConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 快照清单文件名
+pub const MANIFEST_FILE: &str = "snapshot.json";
+/// tar 格式快照的扩展名
+pub const ARCHIVE_SUFFIX: &str = ".tar";
+
+/// 快照清单
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    /// 主题名称
+    pub topic: String,
+    /// 主题的分区数量，快照可能只包含其中一部分分区
+    pub partitions: usize,
+    /// 副本因子
+    pub replication_factor: usize,
+    /// 单个日志段的最大大小（字节）
+    pub segment_size: usize,
+    /// 主题级配置项
+    pub configs: HashMap<String, String>,
+    /// 快照包含的分区
+    pub snapshots: Vec<PartitionSnapshot>,
+    /// 消费者组在快照分区上提交的 offset，不超过分区快照的结束 offset
+    pub consumer_offsets: Vec<ConsumerOffset>,
+    /// 快照创建时间（毫秒）
+    pub created_ms: u64,
+}
+
+/// 分区快照的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionSnapshot {
+    /// 分区 ID
+    pub partition: usize,
+    /// 日志起始 offset
+    pub log_start_offset: u64,
+    /// 快照包含 offset 小于该值的全部记录
+    pub end_offset: u64,
+}
+
+/// 消费者组提交的 offset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsumerOffset {
+    /// 消费者组 ID
+    pub group: String,
+    /// 分区 ID
+    pub partition: usize,
+    /// 提交的 offset
+    pub offset: u32,
+}
+
+impl SnapshotManifest {
+    /// 由主题配置创建清单，分区快照与消费者组 offset 之后填入
+    pub fn new(config: &TopicConfig, topic: &str) -> Self {
+        Self {
+            topic: topic.to_string(),
+            partitions: config.partitions,
+            replication_factor: config.replication_factor,
+            segment_size: config.segment_size,
+            configs: config.configs.clone(),
+            snapshots: Vec::new(),
+            consumer_offsets: Vec::new(),
+            created_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        }
+    }
+
+    /// 恢复主题使用的配置
+    ///
+    /// # Arguments
+    /// * `base_dir` - 恢复到的 broker 的基础目录
+    pub fn topic_config(&self, base_dir: &str) -> TopicConfig {
+        TopicConfig {
+            name: self.topic.clone(),
+            partitions: self.partitions,
+            replication_factor: self.replication_factor,
+            segment_size: self.segment_size,
+            base_dir: base_dir.to_string(),
+            configs: self.configs.clone(),
+        }
+    }
+}
+
+/// 分区在快照中的子目录名，与分区目录名相同
+pub fn partition_dir_name(topic: &str, partition: usize) -> String {
+    format!("{}-{}", topic, partition)
+}
+
+/// 创建快照
+///
+/// `build` 把各分区写入给定的临时目录并返回清单。全部写入后清单保存为 `snapshot.json`，
+/// 临时目录 rename 为 `dest`，或者在 `dest` 以 `.tar` 结尾时打包为 tar 文件，失败时不会留下不完整的快照
+///
+/// # Arguments
+/// * `dest` - 快照目录或 tar 文件路径，不能已存在
+/// * `build` - 写入分区快照并返回清单
+///
+/// # Returns
+/// * `Result<SnapshotManifest, String>` - 成功返回快照清单，失败返回错误信息
+pub fn create<F>(dest: &str, build: F) -> Result<SnapshotManifest, String>
+where
+    F: FnOnce(&str) -> Result<SnapshotManifest, String>,
+{
+    let dest = dest.trim_end_matches('/');
+    if Path::new(dest).exists() {
+        return Err(format!("快照 {} 已存在", dest));
+    }
+    let work_dir = format!("{}{}", dest, queue::MOVING_DIR_SUFFIX);
+    if Path::new(&work_dir).exists() {
+        fs::remove_dir_all(&work_dir).map_err(|e| format!("清理临时目录 {} 失败: {}", work_dir, e))?;
+    }
+    fs::create_dir_all(&work_dir).map_err(|e| format!("创建快照目录 {} 失败: {}", work_dir, e))?;
+
+    let result = build(&work_dir).and_then(|manifest| {
+        write_manifest(&work_dir, &manifest)?;
+        if dest.ends_with(ARCHIVE_SUFFIX) {
+            pack(&work_dir, dest)?;
+            fs::remove_dir_all(&work_dir).map_err(|e| format!("清理临时目录 {} 失败: {}", work_dir, e))?;
+        } else {
+            fs::rename(&work_dir, dest).map_err(|e| format!("保存快照 {} 失败: {}", dest, e))?;
+        }
+        Ok(manifest)
+    });
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&work_dir);
+    }
+    result
+}
+
+/// 打开的快照，tar 格式的快照解包到临时目录，丢弃时删除
+#[derive(Debug)]
+pub struct OpenSnapshot {
+    dir: String,
+    unpacked: bool,
+    manifest: SnapshotManifest,
+}
+
+impl OpenSnapshot {
+    /// 打开快照目录或 tar 文件并读取清单
+    ///
+    /// # Arguments
+    /// * `src` - 快照目录或 tar 文件路径
+    ///
+    /// # Returns
+    /// * `Result<Self, String>` - 成功返回打开的快照，快照不存在或清单无效时返回错误信息
+    pub fn open(src: &str) -> Result<Self, String> {
+        let src = src.trim_end_matches('/');
+        let path = Path::new(src);
+        let (dir, unpacked) = if path.is_file() {
+            let dir = format!("{}.unpacked-{}", src, std::process::id());
+            let _ = fs::remove_dir_all(&dir);
+            let file = fs::File::open(path).map_err(|e| format!("打开快照 {} 失败: {}", src, e))?;
+            if let Err(e) = tar::Archive::new(file).unpack(&dir) {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(format!("解包快照 {} 失败: {}", src, e));
+            }
+            (dir, true)
+        } else {
+            (src.to_string(), false)
+        };
+        match read_manifest(&dir) {
+            Ok(manifest) => Ok(Self { dir, unpacked, manifest }),
+            Err(e) => {
+                if unpacked {
+                    let _ = fs::remove_dir_all(&dir);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 快照清单
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// 把快照中的分区恢复为分区目录，之后创建主题时加载
+    ///
+    /// # Arguments
+    /// * `partition_dir` - 返回分区应恢复到的分区目录（参数为分区目录名）
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - 成功返回 Ok(())，分区目录已存在或复制失败时返回错误信息
+    pub fn restore_partitions<F>(&self, mut partition_dir: F) -> Result<(), String>
+    where
+        F: FnMut(&str) -> Result<String, String>,
+    {
+        for snapshot in &self.manifest.snapshots {
+            let name = partition_dir_name(&self.manifest.topic, snapshot.partition);
+            let target = partition_dir(&name)?;
+            queue::restore_snapshot(&format!("{}/{}", self.dir, name), &target)
+                .map_err(|e| format!("恢复分区 {} 到 {} 失败: {}", snapshot.partition, target, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OpenSnapshot {
+    fn drop(&mut self) {
+        if self.unpacked {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+fn read_manifest(dir: &str) -> Result<SnapshotManifest, String> {
+    let path = Path::new(dir).join(MANIFEST_FILE);
+    let content = fs::read(&path).map_err(|e| format!("读取快照清单 {} 失败: {}", path.display(), e))?;
+    serde_json::from_slice(&content).map_err(|e| format!("解析快照清单 {} 失败: {}", path.display(), e))
+}
+
+fn write_manifest(dir: &str, manifest: &SnapshotManifest) -> Result<(), String> {
+    let content = serde_json::to_vec_pretty(manifest).map_err(|e| format!("序列化快照清单失败: {}", e))?;
+    let path = Path::new(dir).join(MANIFEST_FILE);
+    fs::write(&path, content)
+        .and_then(|_| fs::File::open(&path)?.sync_all())
+        .map_err(|e| format!("写入快照清单失败: {}", e))
+}
+
+/// 把目录打包为 tar 文件，先写入临时文件再 rename
+fn pack(dir: &str, dest: &str) -> Result<(), String> {
+    let tmp = format!("{}.tmp", dest);
+    let result = fs::File::create(&tmp)
+        .and_then(|file| {
+            let mut builder = tar::Builder::new(file);
+            builder.append_dir_all(".", dir)?;
+            builder.into_inner()?.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, dest));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("打包快照 {} 失败: {}", dest, e));
+    }
+    Ok(())
+}
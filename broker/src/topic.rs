@@ -1,9 +1,21 @@
 use std::sync::{Arc, Mutex};
-use queue::LogQueue;
+use queue::{CleanerConfig, CleanupPolicy, FileSlice, LogCleaner, LogQueue, LogReader, MemoryLog, PartitionLog, ReadRange};
+use queue::{FlushPolicy, LogFlusher, RemoteStorage, RetentionManager, RetentionOverrides, SegmentConfig};
+use queue::{EncryptionKeys, MasterKey};
+use queue::{LogIter, LogStream, StorageError};
+use queue::{CapacityLimit, OverflowPolicy};
+use queue::stream::READ_CHUNK_BYTES;
+use protocol::{CompressionType, Record, RecordBatch};
+use protocol::response::error_code;
+use crate::log_dirs::{self, LogDirs};
 use crate::metadata::{TopicConfig, PartitionMetadata};
+use crate::snapshot::PartitionSnapshot;
 use std::fmt;
 use std::collections::HashMap;
+use std::io;
 use std::time::Instant;
+use std::future::Future;
+use tokio::sync::watch;
 
 /// 分区状态
 #[derive(Debug)]
@@ -12,6 +24,137 @@ enum PartitionState {
     Deleted(Instant), // 记录删除时间
 }
 
+/// 内存分区默认保留的最大字节数
+const DEFAULT_MEMORY_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// 溢出策略为 block 时默认的最长等待时间（毫秒）
+const DEFAULT_CAPACITY_BLOCK_TIMEOUT_MS: u64 = 30_000;
+
+/// 追加失败的原因，错误码用于构造生产响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProduceError {
+    pub error_code: i16,
+    pub message: String,
+}
+
+impl ProduceError {
+    fn new(error_code: i16, message: String) -> Self {
+        Self { error_code, message }
+    }
+
+    fn unknown(message: String) -> Self {
+        Self::new(error_code::UNKNOWN_SERVER_ERROR, message)
+    }
+}
+
+impl fmt::Display for ProduceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// 分区
+#[derive(Debug)]
+struct Partition {
+    log: Arc<Mutex<dyn PartitionLog>>,  // 分区日志
+    file: Option<Arc<Mutex<LogQueue>>>, // 基于文件的分区日志，与 log 是同一个对象；内存分区为 None
+    reader: Option<LogReader>,          // 基于文件的分区日志的只读句柄，读取本地日志段不需要加锁
+    state: PartitionState,
+}
+
+impl Partition {
+    fn file(queue: LogQueue) -> Self {
+        let reader = queue.reader();
+        let queue = Arc::new(Mutex::new(queue));
+        Self { log: queue.clone(), file: Some(queue), reader: Some(reader), state: PartitionState::Active }
+    }
+
+    fn memory(log: MemoryLog) -> Self {
+        Self { log: Arc::new(Mutex::new(log)), file: None, reader: None, state: PartitionState::Active }
+    }
+
+    /// 读取 offset 时可以不加锁使用的 `LogReader`：基于文件的分区且 offset 不早于本地日志起始 offset，
+    /// 更早的记录可能在远程存储中，需要通过 `LogQueue` 读取
+    fn reader_for(&self, offset: u64) -> Option<&LogReader> {
+        self.reader.as_ref().filter(|reader| offset >= reader.local_log_start_offset())
+    }
+
+    /// 不借用分区的范围读取函数，供迭代器与记录流使用：能用 `LogReader` 时不加锁读取，否则通过分区日志读取
+    fn range_reader(&self) -> impl FnMut(u64) -> Result<ReadRange, StorageError> + Send + 'static {
+        let log = self.log.clone();
+        let reader = self.reader.clone();
+        move |offset| match reader.as_ref().filter(|reader| offset >= reader.local_log_start_offset()) {
+            Some(reader) => reader.read_range(offset, READ_CHUNK_BYTES, usize::MAX),
+            None => log.lock()
+                .map_err(|e| StorageError::Io(io::Error::other(format!("获取队列锁失败: {}", e))))?
+                .read_range(offset, READ_CHUNK_BYTES, usize::MAX),
+        }
+    }
+
+    /// 基于文件的分区日志，内存分区不支持依赖日志文件的操作
+    fn file_log(&self, partition_id: usize, action: &str) -> Result<&Arc<Mutex<LogQueue>>, String> {
+        self.file.as_ref()
+            .ok_or_else(|| format!("分区 {} 保存在内存中，不支持{}", partition_id, action))
+    }
+}
+
+/// 指定分区的追加句柄，不借用主题
+/// 
+/// 容量上限的 block 策略可能长时间等待空间释放，调用方先取得句柄并释放主题表等上层的锁再追加，
+/// 等待期间不阻塞释放空间的操作（删除记录、保留策略、压缩）与主题的创建和删除；
+/// 等待是同步的，在异步任务中追加时应放到 `tokio::task::spawn_blocking` 中执行
+pub struct PartitionAppender {
+    log: Arc<Mutex<dyn PartitionLog>>,       // 分区日志
+    log_dirs: Option<Arc<LogDirs>>,          // 写入失败时判断是否让日志目录离线
+    partition_name: String,                  // 分区名称：`主题-分区号`
+    compression: Option<CompressionType>,    // 主题指定的压缩编码
+}
+
+impl PartitionAppender {
+    /// 追加记录批次，分区达到容量上限时按主题的溢出策略处理
+    /// 
+    /// # Arguments
+    /// * `batch` - 记录批次
+    /// 
+    /// # Returns
+    /// * `Result<u64, ProduceError>` - 成功返回批次的起始偏移量，失败返回错误码与错误信息
+    pub fn append_batch(&self, mut batch: RecordBatch) -> Result<u64, ProduceError> {
+        // 主题指定的压缩编码与生产者不同时重新压缩，相同或为 producer 时保留生产者的压缩内容，只重写批次头部
+        if let Some(compression) = self.compression.filter(|&compression| compression != batch.compression()) {
+            batch.set_compression(compression);
+        }
+        queue::append_with_backpressure(&*self.log, &mut batch).map_err(|e| {
+            let code = if StorageError::is_queue_full(&e) { error_code::QUEUE_FULL } else { error_code::UNKNOWN_SERVER_ERROR };
+            ProduceError::new(code, storage_error(self.log_dirs.as_deref(), &self.partition_name, "写入消息失败", e))
+        })
+    }
+
+    /// 追加一条只有内容的消息
+    /// 
+    /// # Arguments
+    /// * `message` - 消息内容
+    /// 
+    /// # Returns
+    /// * `Result<u64, String>` - 成功返回消息的偏移量，失败返回错误信息
+    pub fn append_message(&self, message: Vec<u8>) -> Result<u64, String> {
+        self.append_batch(RecordBatch::new(vec![Record::new(None, Some(message))]))
+            .map_err(|e| e.message)
+    }
+}
+
+/// 转换分区操作的错误，存储设备错误会让分区所在的日志目录离线，该目录上的其他分区随之不可用
+fn storage_error(log_dirs: Option<&LogDirs>, partition_name: &str, action: &str, e: impl Into<io::Error>) -> String {
+    let e = e.into();
+    if let Some(log_dirs) = log_dirs {
+        if log_dirs::is_storage_error(&e) {
+            if let Some(log_dir) = log_dirs.dir_of(partition_name) {
+                log_dirs.mark_offline(&log_dir);
+            }
+        }
+    }
+    format!("{}: {}", action, e)
+}
+
 /// 主题，代表一个消息主题，包含多个分区
 #[derive(Debug)]
 pub struct Topic {
@@ -20,7 +163,11 @@ pub struct Topic {
     /// 主题的配置信息
     config: TopicConfig,
     /// 主题的分区列表
-    partitions: HashMap<usize, (Arc<Mutex<LogQueue>>, PartitionState)>,
+    partitions: HashMap<usize, Partition>,
+    /// broker 的日志目录，未设置时分区都放在 base_dir 下
+    log_dirs: Option<Arc<LogDirs>>,
+    /// 主题的数据密钥，设置后新建的日志段加密保存
+    encryption: Option<Arc<EncryptionKeys>>,
 }
 
 impl Topic {
@@ -34,9 +181,50 @@ impl Topic {
             name,
             config,
             partitions: HashMap::new(),
+            log_dirs: None,
+            encryption: None,
         }
     }
 
+    /// 使用 broker 的多个日志目录放置分区，需要在创建分区之前调用
+    /// 
+    /// # Arguments
+    /// * `log_dirs` - 所有主题共享的日志目录
+    pub fn set_log_dirs(&mut self, log_dirs: Arc<LogDirs>) {
+        self.log_dirs = Some(log_dirs);
+    }
+
+    /// 使用 broker 的主密钥加密主题的日志段，只有设置 encryption.enable=true 的主题加密，需要在创建分区之前调用
+    /// 
+    /// 主题生成自己的数据密钥，由主密钥包装后写入每个日志段的段头部
+    /// 
+    /// # Arguments
+    /// * `master_key` - broker 的主密钥
+    /// 
+    /// # Returns
+    /// * `Result<(), String>` - 成功返回 Ok(()), 配置无效时返回错误信息
+    pub fn set_master_key(&mut self, master_key: Arc<MasterKey>) -> Result<(), String> {
+        if self.config.get_config::<bool>("encryption.enable")?.unwrap_or(false) {
+            self.encryption = Some(Arc::new(EncryptionKeys::new(master_key)));
+        }
+        Ok(())
+    }
+
+    /// 主题的日志段是否加密
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// 轮换主题的数据密钥，之后新建的日志段使用新密钥，已有日志段不需要重写
+    /// 
+    /// # Returns
+    /// * `Result<u32, String>` - 成功返回新密钥的 ID，主题未加密时返回错误信息
+    pub fn rotate_encryption_key(&self) -> Result<u32, String> {
+        let encryption = self.encryption.as_ref()
+            .ok_or_else(|| format!("主题 {} 未启用加密", self.name))?;
+        Ok(encryption.rotate())
+    }
+
     /// 获取主题的名称
     pub fn get_name(&self) -> &str {
         &self.name
@@ -65,19 +253,99 @@ impl Topic {
             return Err(format!("分区号 {} 错误", partition_id));
         }
 
-        // 创建分区目录
-        let partition_dir = self.get_partition_dir(partition_id);
+        // storage.type=memory 的主题只在内存中保存最近 memory.max.bytes 字节的记录，重启后丢失
+        if self.is_memory()? {
+            let max_bytes = self.config.get_config::<usize>("memory.max.bytes")?
+                .unwrap_or(DEFAULT_MEMORY_MAX_BYTES);
+            let mut log = MemoryLog::new(max_bytes);
+            log.set_capacity(self.capacity_limit()?);
+            self.partitions.insert(partition_id, Partition::memory(log));
+            return Ok(());
+        }
+
+        // 创建分区目录，配置了多个日志目录时按放置策略选择（已有的分区使用原目录）
+        let partition_dir = match &self.log_dirs {
+            Some(log_dirs) => log_dirs.assign(&self.partition_name(partition_id))?,
+            None => self.get_partition_dir(partition_id),
+        };
 
         if !std::path::Path::new(&partition_dir).exists() {
             std::fs::create_dir_all(&partition_dir).map_err(|e| format!("创建分区目录失败: {}", e))?;
         }
 
-        let queue = LogQueue::new(&partition_dir, self.config.segment_size)
+        let mut queue = LogQueue::with_config(&partition_dir, self.segment_config()?)
             .map_err(|e| format!("创建消息队列失败: {}", e))?;
-        self.partitions.insert(partition_id, (Arc::new(Mutex::new(queue)), PartitionState::Active));
+        queue.set_capacity(self.capacity_limit()?);
+        self.partitions.insert(partition_id, Partition::file(queue));
+        Ok(())
+    }
+
+    /// 使用启动时已经加载的日志作为分区，分区目录保持不变
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `queue` - 从分区目录加载的队列
+    /// 
+    /// # Returns
+    /// * `Result<(), String>` - 成功返回 Ok(()), 分区已存在或分区号错误时返回错误信息
+    pub fn open_partition(&mut self, partition_id: usize, mut queue: LogQueue) -> Result<(), String> {
+        if self.partitions.contains_key(&partition_id) {
+            return Err(format!("分区 {} 已存在", partition_id));
+        }
+        if partition_id >= self.config.partitions {
+            return Err(format!("分区号 {} 错误", partition_id));
+        }
+        queue.set_capacity(self.capacity_limit()?);
+        self.partitions.insert(partition_id, Partition::file(queue));
         Ok(())
     }
 
+    /// 主题的分区是否只保存在内存中（storage.type=memory），未设置或为 file 时保存在日志文件中
+    pub fn is_memory(&self) -> Result<bool, String> {
+        match self.config.get_config::<String>("storage.type")?.as_deref() {
+            None | Some("file") => Ok(false),
+            Some("memory") => Ok(true),
+            Some(other) => Err(format!("未知的存储类型: {}", other)),
+        }
+    }
+
+    /// 主题的压缩编码（compression.type），未设置或为 producer 时返回 None，保留生产者的压缩编码
+    pub fn compression_type(&self) -> Result<Option<CompressionType>, String> {
+        match self.config.get_config::<String>("compression.type")? {
+            Some(compression) if compression != "producer" => compression.parse().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// 分区容量上限：capacity.max.bytes、capacity.max.records，写满后按 capacity.overflow.policy 处理，
+    /// 取值为 reject（默认，拒绝写入）、block（最多等待 capacity.block.timeout.ms 毫秒）或 drop_oldest（丢弃最旧的记录）
+    pub fn capacity_limit(&self) -> Result<CapacityLimit, String> {
+        let block_timeout_ms = self.config.get_config::<u64>("capacity.block.timeout.ms")?
+            .unwrap_or(DEFAULT_CAPACITY_BLOCK_TIMEOUT_MS);
+        let overflow = match self.config.get_config::<String>("capacity.overflow.policy")? {
+            Some(policy) => OverflowPolicy::from_config(&policy, block_timeout_ms)?,
+            None => OverflowPolicy::default(),
+        };
+        Ok(CapacityLimit {
+            max_bytes: self.config.get_config("capacity.max.bytes")?,
+            max_records: self.config.get_config("capacity.max.records")?,
+            overflow,
+        })
+    }
+
+    /// 分区日志段配置，segment.ms 覆盖按时间滚动的间隔，启用加密时带上主题的数据密钥
+    fn segment_config(&self) -> Result<SegmentConfig, String> {
+        let mut config = SegmentConfig {
+            max_segment_size: self.config.segment_size,
+            ..SegmentConfig::default()
+        };
+        if let Some(segment_ms) = self.config.get_config::<i64>("segment.ms")? {
+            config.segment_ms = segment_ms;
+        }
+        config.encryption = self.encryption.clone();
+        Ok(config)
+    }
+
     //初始化分区
     pub fn init_partitions(&mut self) -> Result<(), String> {
         for i in 0..self.config.partitions {
@@ -104,10 +372,10 @@ impl Topic {
         }
 
         // 标记分区为已删除状态
-        if let Some((_queue, state)) = self.partitions.get_mut(&partition_id) {
-            match state {
+        if let Some(partition) = self.partitions.get_mut(&partition_id) {
+            match partition.state {
                 PartitionState::Active => {
-                    *state = PartitionState::Deleted(Instant::now());
+                    partition.state = PartitionState::Deleted(Instant::now());
                     self.partitions.remove(&partition_id); //移除分区信息
                     //let _ = self.cleanup_deleted_partitions(100);
                     Ok(())
@@ -126,11 +394,18 @@ impl Topic {
     pub fn delete_topic(&mut self) -> Result<(), String> {
         let partition_ids: Vec<usize> = self.partitions.keys().copied().collect();
         for partition_id in partition_ids {
+            let in_memory = self.partitions.get(&partition_id).is_some_and(|partition| partition.file.is_none());
             self.delete_partition(partition_id)?;
+            if in_memory {
+                continue;
+            }
             let partition_dir = self.get_partition_dir(partition_id);
             if let Err(e) = std::fs::remove_dir_all(&partition_dir) {
                 return Err(format!("删除分区目录失败: {}", e));
             }
+            if let Some(log_dirs) = &self.log_dirs {
+                log_dirs.release(&self.partition_name(partition_id));
+            }
         }
         Ok(())
     }
@@ -152,9 +427,9 @@ impl Topic {
         let now = Instant::now();
         let mut to_remove = Vec::new();
 
-        for (partition_id, (_queue, state)) in self.partitions.iter() {
-            if let PartitionState::Deleted(deleted_time) = state {
-                if now.duration_since(*deleted_time).as_secs() >= max_age_seconds {
+        for (partition_id, partition) in self.partitions.iter() {
+            if let PartitionState::Deleted(deleted_time) = partition.state {
+                if now.duration_since(deleted_time).as_secs() >= max_age_seconds {
                     to_remove.push(*partition_id);
                 }
             }
@@ -167,6 +442,9 @@ impl Topic {
                 eprintln!("删除分区目录失败: {}", e);
                 continue;
             }
+            if let Some(log_dirs) = &self.log_dirs {
+                log_dirs.release(&self.partition_name(partition_id));
+            }
             self.partitions.remove(&partition_id);
         }
 
@@ -175,55 +453,527 @@ impl Topic {
 
     /// 向指定分区追加消息
     /// 
+    /// 分区达到容量上限时按主题的溢出策略处理，block 策略在不持有分区锁的情况下等待空间释放
+    /// 
     /// # Arguments
     /// * `partition_id` - 目标分区 ID
     /// * `message` - 消息内容
     /// 
     /// # Returns
     /// * `Result<u64, String>` - 成功返回消息的偏移量，失败返回错误信息
-    pub fn append_message(&mut self, partition_id: usize, message: Vec<u8>) -> Result<u64, String> {
-        let (queue, state) = self.partitions.get(&partition_id)
+    pub fn append_message(&self, partition_id: usize, message: Vec<u8>) -> Result<u64, String> {
+        self.appender(partition_id).map_err(|e| e.message)?.append_message(message)
+    }
+
+    /// 从指定分区读取消息
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 消息偏移量
+    /// 
+    /// # Returns
+    /// * `Result<Option<Vec<u8>>, String>` - 成功返回消息内容，失败返回错误信息
+    pub fn read_message(&self, partition_id: usize, offset: u64) -> Result<Option<Vec<u8>>, String> {
+        let partition = self.partitions.get(&partition_id)
             .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
             
-        match state {
+        match partition.state {
             PartitionState::Active => {
-                let mut queue = queue.lock()
-                    .map_err(|e| format!("获取队列锁失败: {}", e))?;
-                    
-                queue.append_message(&message)
-                    .map_err(|e| format!("写入消息失败: {}", e))
+                let result = match partition.reader_for(offset) {
+                    Some(reader) => reader.read_message(offset),
+                    None => partition.log.lock()
+                        .map_err(|e| format!("获取队列锁失败: {}", e))?
+                        .read_message(offset),
+                };
+                result.map_err(|e| self.storage_error(partition_id, "读取消息失败", e))
             }
             PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
         }
     }
 
-    /// 从指定分区读取消息
+    /// 向指定分区追加记录批次
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 目标分区 ID
+    /// * `batch` - 记录批次
+    /// 
+    /// # Returns
+    /// * `Result<u64, String>` - 成功返回批次的起始偏移量，失败返回错误信息
+    pub fn append_batch(&self, partition_id: usize, batch: RecordBatch) -> Result<u64, String> {
+        self.produce_batch(partition_id, batch).map_err(|e| e.message)
+    }
+
+    /// 向指定分区追加记录批次，失败时给出生产响应的错误码
+    /// 
+    /// 分区达到容量上限时按主题的溢出策略处理：reject 与 block 等待超时返回 `error_code::QUEUE_FULL`，
+    /// drop_oldest 丢弃最旧的记录后写入
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 目标分区 ID
+    /// * `batch` - 记录批次
+    /// 
+    /// # Returns
+    /// * `Result<u64, ProduceError>` - 成功返回批次的起始偏移量，失败返回错误码与错误信息
+    pub fn produce_batch(&self, partition_id: usize, batch: RecordBatch) -> Result<u64, ProduceError> {
+        self.appender(partition_id)?.append_batch(batch)
+    }
+
+    /// 获取指定分区的追加句柄，句柄不借用主题，可以在释放主题表的锁之后追加
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 目标分区 ID
+    /// 
+    /// # Returns
+    /// * `Result<PartitionAppender, ProduceError>` - 成功返回追加句柄，失败返回错误码与错误信息
+    pub fn appender(&self, partition_id: usize) -> Result<PartitionAppender, ProduceError> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| ProduceError::new(error_code::UNKNOWN_TOPIC_OR_PARTITION, format!("分区 {} 不存在", partition_id)))?;
+        self.check_online(partition_id).map_err(ProduceError::unknown)?;
+
+        match partition.state {
+            PartitionState::Active => Ok(PartitionAppender {
+                log: partition.log.clone(),
+                log_dirs: self.log_dirs.clone(),
+                partition_name: self.partition_name(partition_id),
+                compression: self.compression_type().map_err(ProduceError::unknown)?,
+            }),
+            PartitionState::Deleted(_) => Err(ProduceError::new(
+                error_code::UNKNOWN_TOPIC_OR_PARTITION,
+                format!("分区 {} 已被标记为删除", partition_id),
+            )),
+        }
+    }
+
+    /// 从指定分区读取包含指定偏移量的记录批次
     /// 
     /// # Arguments
     /// * `partition_id` - 分区 ID
     /// * `offset` - 消息偏移量
     /// 
     /// # Returns
-    /// * `Result<Option<Vec<u8>>, String>` - 成功返回消息内容，失败返回错误信息
-    pub fn read_message(&mut self, partition_id: usize, offset: u64) -> Result<Option<Vec<u8>>, String> {
-        let (queue, state) = self.partitions.get(&partition_id)
+    /// * `Result<Option<RecordBatch>, String>` - 成功返回记录批次，失败返回错误信息
+    pub fn read_batch(&self, partition_id: usize, offset: u64) -> Result<Option<RecordBatch>, String> {
+        let partition = self.partitions.get(&partition_id)
             .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
-            
-        match state {
+        self.check_online(partition_id)?;
+
+        match partition.state {
             PartitionState::Active => {
-                let mut queue = queue.lock()
+                let result = match partition.reader_for(offset) {
+                    Some(reader) => reader.read_batch(offset),
+                    None => partition.log.lock()
+                        .map_err(|e| format!("获取队列锁失败: {}", e))?
+                        .read_batch(offset),
+                };
+                result.map_err(|e| self.storage_error(partition_id, "读取消息失败", e))
+            }
+            PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
+        }
+    }
+
+    /// 从指定分区的 offset 开始范围读取连续记录
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 起始偏移量
+    /// * `max_bytes` - 本次读取的最大字节数
+    /// * `max_records` - 本次读取的最大记录数
+    /// 
+    /// # Returns
+    /// * `Result<ReadRange, String>` - 成功返回读取到的记录及下一次读取的 offset，失败返回错误信息
+    pub fn read_range(&self, partition_id: usize, offset: u64, max_bytes: usize, max_records: usize) -> Result<ReadRange, String> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
+
+        match partition.state {
+            PartitionState::Active => {
+                let result = match partition.reader_for(offset) {
+                    Some(reader) => reader.read_range(offset, max_bytes, max_records),
+                    None => partition.log.lock()
+                        .map_err(|e| format!("获取队列锁失败: {}", e))?
+                        .read_range(offset, max_bytes, max_records),
+                };
+                result.map_err(|e| self.storage_error(partition_id, "读取消息失败", e))
+            }
+            PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
+        }
+    }
+
+    /// 订阅指定分区的日志结束 offset，读到日志末尾后用于等待新记录写入（长轮询 fetch）
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// 
+    /// # Returns
+    /// * `Result<watch::Receiver<u64>, String>` - 成功返回日志结束 offset 的接收端，失败返回错误信息
+    pub fn subscribe(&self, partition_id: usize) -> Result<watch::Receiver<u64>, String> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
+
+        match partition.state {
+            PartitionState::Active => match &partition.reader {
+                Some(reader) => Ok(reader.subscribe()),
+                None => Ok(partition.log.lock()
+                    .map_err(|e| format!("获取队列锁失败: {}", e))?
+                    .subscribe()),
+            },
+            PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
+        }
+    }
+
+    /// 等待指定分区的日志结束 offset 超过 `offset`，即 `offset` 处的记录已写入
+    /// 
+    /// 返回的 future 不借用主题，可以在释放主题表的锁之后与超时一起等待
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 等待写入的 offset，通常为上一次读取返回的下一个 offset
+    /// 
+    /// # Returns
+    /// * `Result<impl Future<Output = u64>, String>` - 成功返回完成时给出日志结束 offset 的 future，失败返回错误信息
+    pub fn wait_past(&self, partition_id: usize, offset: u64) -> Result<impl Future<Output = u64> + Send + 'static, String> {
+        Ok(queue::wait_past(self.subscribe(partition_id)?, offset))
+    }
+
+    /// 从指定分区的 offset 开始遍历到日志末尾，产出 `(offset, record)`，供进程内的消费者使用
+    /// 
+    /// 迭代器不借用主题，被压缩删除的 offset 自动跳过，读取出错时产出错误后结束
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 起始偏移量
+    /// 
+    /// # Returns
+    /// * `Result<LogIter<'static>, String>` - 成功返回记录迭代器，失败返回错误信息
+    pub fn iter_from(&self, partition_id: usize, offset: u64) -> Result<LogIter<'static>, String> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
+
+        match partition.state {
+            PartitionState::Active => Ok(LogIter::new(offset, partition.range_reader())),
+            PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
+        }
+    }
+
+    /// 从指定分区的 offset 开始跟随日志尾部的记录流，读到末尾后等待新记录写入，供进程内的消费者使用
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 起始偏移量
+    /// 
+    /// # Returns
+    /// * `Result<LogStream, String>` - 成功返回不借用主题的记录流，失败返回错误信息
+    pub fn stream_from(&self, partition_id: usize, offset: u64) -> Result<LogStream, String> {
+        let receiver = self.subscribe(partition_id)?;
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        Ok(LogStream::new(offset, partition.range_reader(), receiver))
+    }
+
+    /// 定位指定分区从 offset 开始的连续批次在日志文件中的区域，用于零拷贝发送
+    /// 
+    /// 加密主题的批次需要解密后发送，返回错误，调用方改用 `read_range`
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 起始偏移量
+    /// * `max_bytes` - 区域的最大字节数
+    /// 
+    /// # Returns
+    /// * `Result<Option<FileSlice>, String>` - 成功返回文件区域，没有可读数据时返回 None，失败返回错误信息
+    pub fn read_slice(&self, partition_id: usize, offset: u64, max_bytes: usize) -> Result<Option<FileSlice>, String> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
+
+        match partition.state {
+            PartitionState::Active => {
+                partition.file_log(partition_id, "零拷贝读取")?;
+                if self.is_encrypted() {
+                    return Err(format!("主题 {} 已加密，不支持零拷贝读取", self.name));
+                }
+                let reader = partition.reader.as_ref().expect("file partition has a reader");
+                reader.read_slice(offset, max_bytes)
+                    .map_err(|e| self.storage_error(partition_id, "读取消息失败", e))
+            }
+            PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
+        }
+    }
+
+    /// 截断指定分区，删除 offset 不小于 `offset` 的全部记录
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 截断位置，之后的写入从该 offset 开始
+    /// 
+    /// # Returns
+    /// * `Result<(), String>` - 成功返回 Ok(()), 失败返回错误信息
+    pub fn truncate_to(&self, partition_id: usize, offset: u64) -> Result<(), String> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
+
+        match partition.state {
+            PartitionState::Active => {
+                let mut queue = partition.log.lock()
                     .map_err(|e| format!("获取队列锁失败: {}", e))?;
-                    
-                queue.read_message(offset)
-                    .map_err(|e| format!("读取消息失败: {}", e))
+
+                queue.truncate_to(offset)
+                    .map_err(|e| self.storage_error(partition_id, "截断分区失败", e))
             }
             PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
         }
     }
 
+    /// 删除指定分区中 offset 小于 `offset` 的记录
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `offset` - 新的日志起始偏移量
+    /// 
+    /// # Returns
+    /// * `Result<u64, String>` - 成功返回新的日志起始偏移量，失败返回错误信息
+    pub fn delete_records_before(&self, partition_id: usize, offset: u64) -> Result<u64, String> {
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        self.check_online(partition_id)?;
+
+        match partition.state {
+            PartitionState::Active => {
+                let mut queue = partition.log.lock()
+                    .map_err(|e| format!("获取队列锁失败: {}", e))?;
+
+                queue.delete_records_before(offset)
+                    .map_err(|e| self.storage_error(partition_id, "删除记录失败", e))
+            }
+            PartitionState::Deleted(_) => Err(format!("分区 {} 已被标记为删除", partition_id)),
+        }
+    }
+
+    /// 按 key 压缩所有分区（仅对 cleanup.policy=compact 的主题生效）
+    /// 
+    /// 墓碑记录的保留时间由主题配置 delete.retention.ms 指定
+    /// 
+    /// # Returns
+    /// * `Result<usize, String>` - 成功返回删除的记录数，失败返回错误信息
+    pub fn compact(&self) -> Result<usize, String> {
+        let policy: CleanupPolicy = self.config.get_config("cleanup.policy")?.unwrap_or_default();
+        if policy != CleanupPolicy::Compact {
+            return Ok(0);
+        }
+        let mut cleaner_config = CleanerConfig::default();
+        if let Some(delete_retention_ms) = self.config.get_config("delete.retention.ms")? {
+            cleaner_config.delete_retention_ms = delete_retention_ms;
+        }
+        let cleaner = LogCleaner::new(cleaner_config);
+
+        let mut removed = 0;
+        for (partition_id, partition) in &self.partitions {
+            // 离线目录上的分区跳过，不影响其他分区；内存分区不压缩
+            let Some(queue) = &partition.file else { continue };
+            if let PartitionState::Active = partition.state {
+                if self.check_online(*partition_id).is_err() {
+                    continue;
+                }
+                let mut queue = queue.lock()
+                    .map_err(|e| format!("获取队列锁失败: {}", e))?;
+                removed += queue.compact(&cleaner)
+                    .map_err(|e| self.storage_error(*partition_id, &format!("压缩分区 {} 失败", partition_id), e))?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// 主题级保留策略覆盖（retention.ms、retention.bytes、local.retention.ms、local.retention.bytes）
+    pub fn retention_overrides(&self) -> Result<RetentionOverrides, String> {
+        Ok(RetentionOverrides {
+            retention_ms: self.config.get_config("retention.ms")?,
+            retention_bytes: self.config.get_config("retention.bytes")?,
+            local_retention_ms: self.config.get_config("local.retention.ms")?,
+            local_retention_bytes: self.config.get_config("local.retention.bytes")?,
+        })
+    }
+
+    /// 为所有分区启用分层存储（仅对 remote.storage.enable=true 的主题生效）
+    /// 
+    /// 分区在远程存储中的 key 前缀为 `主题-分区号`，本地副本的保留时间和大小
+    /// 由 local.retention.ms / local.retention.bytes 指定
+    /// 
+    /// # Returns
+    /// * `Result<bool, String>` - 成功返回是否启用，失败返回错误信息
+    pub fn enable_remote_storage(&self, storage: &Arc<dyn RemoteStorage>) -> Result<bool, String> {
+        if !self.config.get_config::<bool>("remote.storage.enable")?.unwrap_or(false) {
+            return Ok(false);
+        }
+        for (partition_id, partition) in &self.partitions {
+            let Some(queue) = &partition.file else { continue };
+            if let PartitionState::Active = partition.state {
+                let mut queue = queue.lock()
+                    .map_err(|e| format!("获取队列锁失败: {}", e))?;
+                queue.set_remote_storage(storage.clone(), &format!("{}-{}", self.name, partition_id))
+                    .map_err(|e| format!("分区 {} 启用分层存储失败: {}", partition_id, e))?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// 把所有分区注册到保留管理器，分区名称为 `主题-分区号`
+    /// 
+    /// cleanup.policy=compact 的主题只做压缩，不按时间和大小删除
+    pub fn register_retention(&self, manager: &RetentionManager) -> Result<(), String> {
+        let policy: CleanupPolicy = self.config.get_config("cleanup.policy")?.unwrap_or_default();
+        if policy != CleanupPolicy::Delete {
+            return Ok(());
+        }
+        let overrides = self.retention_overrides()?;
+        for (partition_id, partition) in &self.partitions {
+            let Some(queue) = &partition.file else { continue };
+            if let PartitionState::Active = partition.state {
+                manager.register(&format!("{}-{}", self.name, partition_id), queue, overrides);
+            }
+        }
+        Ok(())
+    }
+
+    /// 主题级刷盘策略覆盖：flush.messages 为 1 时每次写入刷盘，大于 1 时按消息数刷盘，
+    /// 否则 flush.ms 指定按时间刷盘；都未设置时返回 None，使用 broker 默认策略
+    pub fn flush_policy(&self) -> Result<Option<FlushPolicy>, String> {
+        if let Some(messages) = self.config.get_config::<u64>("flush.messages")? {
+            return FlushPolicy::from_config(if messages == 1 { "write" } else { "messages" }, messages, 0).map(Some);
+        }
+        match self.config.get_config::<u64>("flush.ms")? {
+            Some(interval_ms) => FlushPolicy::from_config("interval", 0, interval_ms).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 把所有分区注册到刷盘管理器，分区名称为 `主题-分区号`
+    pub fn register_flusher(&self, flusher: &LogFlusher) -> Result<(), String> {
+        let policy = self.flush_policy()?;
+        for (partition_id, partition) in &self.partitions {
+            let Some(queue) = &partition.file else { continue };
+            if let PartitionState::Active = partition.state {
+                flusher.register(&format!("{}-{}", self.name, partition_id), queue, policy);
+            }
+        }
+        Ok(())
+    }
+
+    /// 主题的配置
+    pub fn config(&self) -> &TopicConfig {
+        &self.config
+    }
+
+    /// 把分区中 offset 小于 `up_to` 的记录写入快照目录，每个分区写入子目录 `主题-分区号`
+    /// 
+    /// 快照期间该分区的写入被阻塞，包含 `up_to` 的日志段在快照中截断
+    /// 
+    /// # Arguments
+    /// * `dest_dir` - 快照目录
+    /// * `partition_id` - 只快照指定分区，为 None 时快照所有分区
+    /// * `up_to` - 每个分区的结束 offset，为 None 时到日志结束 offset
+    /// 
+    /// # Returns
+    /// * `Result<Vec<PartitionSnapshot>, String>` - 成功返回各分区快照的范围，内存分区或分区不可用时返回错误信息
+    pub fn snapshot(&self, dest_dir: &str, partition_id: Option<usize>, up_to: Option<u64>) -> Result<Vec<PartitionSnapshot>, String> {
+        let partition_ids = match partition_id {
+            Some(partition_id) => vec![partition_id],
+            None => {
+                let mut ids: Vec<usize> = self.partitions.iter()
+                    .filter(|(_, partition)| matches!(partition.state, PartitionState::Active))
+                    .map(|(id, _)| *id)
+                    .collect();
+                ids.sort_unstable();
+                ids
+            }
+        };
+
+        let mut snapshots = Vec::with_capacity(partition_ids.len());
+        for partition_id in partition_ids {
+            let partition = self.partitions.get(&partition_id)
+                .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+            if let PartitionState::Deleted(_) = partition.state {
+                return Err(format!("分区 {} 已被标记为删除", partition_id));
+            }
+            self.check_online(partition_id)?;
+            let mut queue = partition.file_log(partition_id, "快照")?.lock()
+                .map_err(|e| format!("获取队列锁失败: {}", e))?;
+            let snapshot = queue.snapshot(&format!("{}/{}", dest_dir, self.partition_name(partition_id)), up_to)
+                .map_err(|e| self.storage_error(partition_id, &format!("快照分区 {} 失败", partition_id), e))?;
+            snapshots.push(PartitionSnapshot {
+                partition: partition_id,
+                log_start_offset: snapshot.log_start_offset,
+                end_offset: snapshot.end_offset,
+            });
+        }
+        Ok(snapshots)
+    }
+
     //返回分区目录
     pub fn get_partition_dir(&self, partition_id: usize) -> String {
-        format!("{}/{}-{}", self.config.base_dir, self.name, partition_id)
+        let name = self.partition_name(partition_id);
+        match self.log_dirs.as_ref().and_then(|log_dirs| log_dirs.dir_of(&name)) {
+            Some(log_dir) => format!("{}/{}", log_dir, name),
+            None => format!("{}/{}", self.config.base_dir, name),
+        }
+    }
+
+    /// 分区名称，也是分区目录名：`主题-分区号`
+    fn partition_name(&self, partition_id: usize) -> String {
+        format!("{}-{}", self.name, partition_id)
+    }
+
+    /// 分区所在的日志目录离线时返回错误
+    fn check_online(&self, partition_id: usize) -> Result<(), String> {
+        let Some(log_dirs) = &self.log_dirs else { return Ok(()) };
+        match log_dirs.dir_of(&self.partition_name(partition_id)) {
+            Some(log_dir) if !log_dirs.is_online(&log_dir) => {
+                Err(format!("分区 {} 所在的日志目录 {} 已离线", partition_id, log_dir))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 转换分区操作的错误，存储设备错误会让分区所在的日志目录离线
+    fn storage_error(&self, partition_id: usize, action: &str, e: impl Into<io::Error>) -> String {
+        storage_error(self.log_dirs.as_deref(), &self.partition_name(partition_id), action, e)
+    }
+
+    /// 把分区迁移到另一个日志目录，迁移期间该分区的读写被阻塞
+    /// 
+    /// # Arguments
+    /// * `partition_id` - 分区 ID
+    /// * `dest_dir` - 目标日志目录，必须是已配置的在线目录
+    /// 
+    /// # Returns
+    /// * `Result<(), String>` - 迁移成功返回 Ok(()), 失败返回错误信息，失败时分区仍在原目录
+    pub fn move_partition(&self, partition_id: usize, dest_dir: &str) -> Result<(), String> {
+        let log_dirs = self.log_dirs.as_ref()
+            .ok_or_else(|| format!("主题 {} 未配置多个日志目录", self.name))?;
+        let partition = self.partitions.get(&partition_id)
+            .ok_or_else(|| format!("分区 {} 不存在", partition_id))?;
+        if let PartitionState::Deleted(_) = partition.state {
+            return Err(format!("分区 {} 已被标记为删除", partition_id));
+        }
+        self.check_online(partition_id)?;
+        let dest_dir = dest_dir.trim_end_matches('/');
+        if !log_dirs.is_online(dest_dir) {
+            return Err(format!("日志目录 {} 未配置或已离线", dest_dir));
+        }
+        let name = self.partition_name(partition_id);
+        if log_dirs.dir_of(&name).as_deref() == Some(dest_dir) {
+            return Ok(());
+        }
+
+        let mut queue = partition.file_log(partition_id, "迁移")?.lock()
+            .map_err(|e| format!("获取队列锁失败: {}", e))?;
+        queue.move_to(&format!("{}/{}", dest_dir, name))
+            .map_err(|e| format!("迁移分区 {} 到 {} 失败: {}", partition_id, dest_dir, e))?;
+        log_dirs.reassign(&name, dest_dir)
     }
 }
 
@@ -3,6 +3,7 @@ mod tests {
     use broker::metadata::{TopicConfig, PartitionMetadata, TopicMetadata};
     use broker::topic::Topic;
     use broker::metadata::MetadataManager;
+    use protocol::{Record, RecordBatch};
     const LOD_DIR :&str = "target/topics";
     const TEST_TOPIC: &str = "test-topic";
     #[test]
@@ -13,6 +14,7 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         };
         let topic = Topic::new(TEST_TOPIC.to_string(), config.clone());
         assert_eq!(topic.get_name(), TEST_TOPIC);
@@ -27,6 +29,7 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         });
 
         let metadata = PartitionMetadata {
@@ -51,6 +54,7 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         });
 
         let metadata = PartitionMetadata {
@@ -79,6 +83,7 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         });
 
         let metadata = PartitionMetadata {
@@ -114,6 +119,7 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         };
         
         let topic_metadata = TopicMetadata::new(TEST_TOPIC.to_string(), config);
@@ -146,6 +152,7 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         });
 
         let metadata = PartitionMetadata {
@@ -171,10 +178,722 @@ mod tests {
             replication_factor: 1,
             segment_size: 1024 * 1024,
             base_dir: LOD_DIR.to_string(),
+            ..Default::default()
         };
         let mut topic = Topic::new(TEST_TOPIC.to_string(), config.clone());
         topic.init_partitions().unwrap(); //初始化所有分区
         topic.delete_topic().unwrap(); //删除主题
         assert_eq!(topic.get_partition_count(), 0);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_topic_compact() {
+        let base_dir = "target/topics-compact";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("cleanup.policy".to_string(), "compact".to_string());
+        let mut topic = Topic::new(TEST_TOPIC.to_string(), TopicConfig {
+            name: TEST_TOPIC.to_string(),
+            partitions: 1,
+            segment_size: 1024,
+            base_dir: base_dir.to_string(),
+            configs,
+            ..Default::default()
+        });
+        topic.init_partitions().unwrap();
+
+        for i in 0..100 {
+            let record = Record::new(Some(format!("key-{}", i % 5).into_bytes()), Some(vec![i as u8]));
+            topic.append_batch(0, RecordBatch::new(vec![record])).unwrap();
+        }
+        let removed = topic.compact().unwrap();
+        assert!(removed > 0);
+        assert_eq!(topic.read_message(0, 0).unwrap(), None);
+        assert_eq!(topic.read_message(0, 99).unwrap(), Some(vec![99]));
+
+        // 默认 delete 策略的主题不做压缩
+        let mut topic = Topic::new(TEST_TOPIC.to_string(), TopicConfig {
+            name: TEST_TOPIC.to_string(),
+            partitions: 1,
+            segment_size: 1024,
+            base_dir: format!("{}-delete", base_dir),
+            ..Default::default()
+        });
+        topic.init_partitions().unwrap();
+        assert_eq!(topic.compact().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_topic_remote_storage() {
+        use queue::{LocalDirRemoteStorage, RemoteStorage, RetentionManager};
+        use std::sync::Arc;
+
+        let base_dir = "target/topics-remote";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let storage: Arc<dyn RemoteStorage> =
+            Arc::new(LocalDirRemoteStorage::new(format!("{}/remote", base_dir)).unwrap());
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("remote.storage.enable".to_string(), "true".to_string());
+        configs.insert("local.retention.bytes".to_string(), "0".to_string());
+        let mut topic = Topic::new(TEST_TOPIC.to_string(), TopicConfig {
+            name: TEST_TOPIC.to_string(),
+            partitions: 1,
+            segment_size: 1024,
+            base_dir: format!("{}/local", base_dir),
+            configs,
+            ..Default::default()
+        });
+        topic.init_partitions().unwrap();
+        assert!(topic.enable_remote_storage(&storage).unwrap());
+
+        for i in 0..100u8 {
+            topic.append_batch(0, RecordBatch::new(vec![Record::new(None, Some(vec![i]))])).unwrap();
+        }
+        let manager = RetentionManager::default();
+        topic.register_retention(&manager).unwrap();
+        manager.run_once();
+
+        // 本地只剩活跃段，旧记录从远程读取
+        let local_logs = std::fs::read_dir(topic.get_partition_dir(0))
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_str().unwrap().ends_with(".log"))
+            .count();
+        assert_eq!(local_logs, 1);
+        assert_eq!(topic.read_message(0, 0).unwrap(), Some(vec![0]));
+        assert_eq!(topic.read_message(0, 99).unwrap(), Some(vec![99]));
+    }
+
+    #[test]
+    fn test_topic_compression_type() {
+        use protocol::CompressionType;
+
+        let base_dir = "target/topics-compression";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let records = || (0..10u8).map(|i| Record::new(None, Some(vec![i; 64]))).collect::<Vec<_>>();
+        let topic_with = |compression_type: &str| {
+            let mut configs = std::collections::HashMap::new();
+            configs.insert("compression.type".to_string(), compression_type.to_string());
+            let mut topic = Topic::new(TEST_TOPIC.to_string(), TopicConfig {
+                name: TEST_TOPIC.to_string(),
+                partitions: 1,
+                base_dir: format!("{}/{}", base_dir, compression_type),
+                configs,
+                ..Default::default()
+            });
+            topic.init_partitions().unwrap();
+            topic
+        };
+
+        // producer 保留生产者的压缩编码
+        let topic = topic_with("producer");
+        topic.append_batch(0, RecordBatch::new(records()).with_compression(CompressionType::Gzip)).unwrap();
+        let batch = topic.read_batch(0, 0).unwrap().unwrap();
+        assert_eq!(batch.compression(), CompressionType::Gzip);
+        assert_eq!(batch.records[9].value, Some(vec![9; 64]));
+
+        // 主题指定的编码与生产者不同时重新压缩
+        let topic = topic_with("zstd");
+        topic.append_batch(0, RecordBatch::new(records()).with_compression(CompressionType::Gzip)).unwrap();
+        topic.append_batch(0, RecordBatch::new(records())).unwrap();
+        assert_eq!(topic.read_batch(0, 0).unwrap().unwrap().compression(), CompressionType::Zstd);
+        assert_eq!(topic.read_batch(0, 10).unwrap().unwrap().compression(), CompressionType::Zstd);
+        assert_eq!(topic.read_message(0, 15).unwrap(), Some(vec![5; 64]));
+
+        let topic = topic_with("brotli");
+        assert!(topic.compression_type().is_err());
+    }
+
+    #[test]
+    fn test_broker_log_dirs() {
+        use broker::{Broker, LogDirs, PlacementPolicy};
+        use std::path::Path;
+
+        let base_dir = "target/broker-log-dirs";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let dirs = vec![format!("{}/a", base_dir), format!("{}/b", base_dir)];
+        let topic_config = |name: &str, partitions: usize| TopicConfig {
+            name: name.to_string(),
+            partitions,
+            segment_size: 1024,
+            ..Default::default()
+        };
+        let batch = |value: u8| RecordBatch::new(vec![Record::new(None, Some(vec![value]))]);
+
+        // 目录 b 上已有分区 existing-0
+        {
+            let mut topic = Topic::new("existing".to_string(), TopicConfig {
+                base_dir: dirs[1].clone(),
+                ..topic_config("existing", 1)
+            });
+            topic.init_partitions().unwrap();
+            topic.append_batch(0, batch(42)).unwrap();
+        }
+
+        // 启动时发现已有分区，在原目录打开；新分区放在分区数最少的目录
+        let broker = Broker::new().with_log_dirs(LogDirs::new(&dirs, PlacementPolicy::FewestPartitions).unwrap());
+        broker.create_topic("existing", topic_config("existing", 1)).unwrap();
+        assert_eq!(broker.fetch_message("existing", 0, 0).unwrap(), Some(vec![42]));
+        broker.create_topic("events", topic_config("events", 3)).unwrap();
+        let log_dirs = broker.describe_log_dirs();
+        assert_eq!(log_dirs[0].partitions, vec!["events-0", "events-1"]);
+        assert_eq!(log_dirs[1].partitions, vec!["events-2", "existing-0"]);
+        for partition in 0..3 {
+            broker.send_batch("events", partition, batch(partition as u8)).unwrap();
+        }
+
+        // 迁移分区后数据保留，原目录被删除，可以继续写入
+        broker.move_partition("events", 0, &dirs[1]).unwrap();
+        assert!(!Path::new(&format!("{}/events-0", dirs[0])).exists());
+        assert_eq!(broker.fetch_message("events", 0, 0).unwrap(), Some(vec![0]));
+        assert_eq!(broker.send_batch("events", 0, batch(10)).unwrap(), 1);
+        assert!(broker.move_partition("events", 0, "target/not-a-log-dir").is_err());
+
+        // 目录 a 故障（被替换成普通文件，滚动新段时无法创建文件），只有其上的分区下线
+        std::fs::remove_dir_all(&dirs[0]).unwrap();
+        std::fs::write(&dirs[0], b"").unwrap();
+        assert!(broker.send_batch("events", 1, RecordBatch::new(vec![Record::new(None, Some(vec![0; 2048]))])).is_err());
+        let log_dirs = broker.describe_log_dirs();
+        assert!(!log_dirs[0].online);
+        assert!(log_dirs[1].online);
+        assert!(broker.fetch_message("events", 1, 0).unwrap_err().contains("离线"));
+        assert_eq!(broker.send_batch("events", 2, batch(2)).unwrap(), 1);
+        assert_eq!(broker.fetch_message("events", 0, 1).unwrap(), Some(vec![10]));
+        assert_eq!(broker.fetch_message("existing", 0, 0).unwrap(), Some(vec![42]));
+        broker.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_broker_load_logs() {
+        use broker::Broker;
+        use queue::recovery::CLEAN_SHUTDOWN_FILE;
+        use std::path::Path;
+
+        let base_dir = "target/broker-load-logs";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let marker = format!("{}/{}", base_dir, CLEAN_SHUTDOWN_FILE);
+        let batch = |value: u8| RecordBatch::new(vec![Record::new(None, Some(vec![value; 100]))]);
+
+        let broker = Broker::new();
+        broker.create_topic("events", TopicConfig {
+            name: "events".to_string(),
+            partitions: 2,
+            segment_size: 1024,
+            base_dir: base_dir.to_string(),
+            ..Default::default()
+        }).unwrap();
+        for i in 0..30 {
+            broker.send_batch("events", i % 2, batch(i as u8)).unwrap();
+        }
+        broker.shutdown().unwrap();
+        drop(broker);
+        assert!(Path::new(&marker).exists());
+
+        // 正常关闭后重启：不调用 create_topic 直接读写已有分区，加载完成后删除正常关闭标记
+        let broker = Broker::new();
+        assert_eq!(broker.load_logs(base_dir, 1024, 2).unwrap(), vec!["events"]);
+        assert!(!Path::new(&marker).exists());
+        assert_eq!(broker.fetch_message("events", 0, 0).unwrap(), Some(vec![0; 100]));
+        assert_eq!(broker.fetch_message("events", 1, 14).unwrap(), Some(vec![29; 100]));
+        assert_eq!(broker.recovery_points()["events-1"], 15);
+        assert_eq!(broker.send_batch("events", 0, batch(30)).unwrap(), 15);
+        drop(broker);
+
+        // 非正常关闭后重启：没有正常关闭标记，损坏的尾部被截断
+        let partition_dir = format!("{}/events-0", base_dir);
+        let mut logs: Vec<_> = std::fs::read_dir(&partition_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect();
+        logs.sort();
+        let mut file = std::fs::OpenOptions::new().append(true).open(logs.last().unwrap()).unwrap();
+        std::io::Write::write_all(&mut file, &[0xff; 10]).unwrap();
+        drop(file);
+        let broker = Broker::new();
+        assert_eq!(broker.load_logs(base_dir, 1024, 2).unwrap(), vec!["events"]);
+        assert_eq!(broker.fetch_message("events", 0, 15).unwrap(), Some(vec![30; 100]));
+        assert_eq!(broker.send_batch("events", 0, batch(31)).unwrap(), 16);
+        assert_eq!(broker.send_batch("events", 1, batch(32)).unwrap(), 15);
+        broker.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_broker_restart_over_log_dirs() {
+        use broker::{Broker, LogDirs, PlacementPolicy};
+        use queue::recovery::CLEAN_SHUTDOWN_FILE;
+        use std::path::Path;
+
+        let base_dir = "target/broker-restart-log-dirs";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let dirs = vec![format!("{}/a", base_dir), format!("{}/b", base_dir)];
+        let open_broker = || Broker::new().with_log_dirs(LogDirs::new(&dirs, PlacementPolicy::FewestPartitions).unwrap());
+        let batch = |value: u8| RecordBatch::new(vec![Record::new(None, Some(vec![value]))]);
+
+        let broker = open_broker();
+        broker.create_topic("events", TopicConfig {
+            name: "events".to_string(),
+            partitions: 3,
+            segment_size: 1024,
+            ..Default::default()
+        }).unwrap();
+        for partition in 0..3 {
+            broker.send_batch("events", partition, batch(partition as u8)).unwrap();
+        }
+        broker.shutdown().unwrap();
+        drop(broker);
+
+        // 重启后发现两个目录上的分区，组合成主题并在原目录打开，不需要 create_topic
+        let broker = open_broker();
+        assert_eq!(broker.load_logs(base_dir, 1024, 1).unwrap(), vec!["events"]);
+        for dir in &dirs {
+            assert!(!Path::new(&format!("{}/{}", dir, CLEAN_SHUTDOWN_FILE)).exists());
+        }
+        let log_dirs = broker.describe_log_dirs();
+        assert_eq!(log_dirs[0].partitions, vec!["events-0", "events-2"]);
+        assert_eq!(log_dirs[1].partitions, vec!["events-1"]);
+        for partition in 0..3 {
+            assert_eq!(broker.fetch_message("events", partition, 0).unwrap(), Some(vec![partition as u8]));
+            assert_eq!(broker.send_batch("events", partition, batch(10)).unwrap(), 1);
+        }
+        assert!(broker.create_topic("events", TopicConfig { name: "events".to_string(), ..Default::default() }).is_err());
+        broker.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_is_storage_error() {
+        use broker::log_dirs::is_storage_error;
+        use std::io::{Error, ErrorKind};
+
+        // 设备错误让日志目录离线
+        for errno in [libc::EIO, libc::ENOSPC, libc::EROFS, libc::EACCES, libc::ENOTDIR] {
+            assert!(is_storage_error(&Error::from_raw_os_error(errno)), "errno {}", errno);
+        }
+        for kind in [ErrorKind::StorageFull, ErrorKind::ReadOnlyFilesystem, ErrorKind::PermissionDenied] {
+            assert!(is_storage_error(&Error::new(kind, "device")), "{:?}", kind);
+        }
+
+        // 逻辑错误不影响日志目录
+        assert!(!is_storage_error(&Error::other("lock poisoned")));
+        assert!(!is_storage_error(&Error::from_raw_os_error(libc::ENOENT)));
+        assert!(!is_storage_error(&Error::from_raw_os_error(libc::EEXIST)));
+        for kind in [
+            ErrorKind::Unsupported,
+            ErrorKind::AlreadyExists,
+            ErrorKind::NotFound,
+            ErrorKind::InvalidInput,
+            ErrorKind::InvalidData,
+            ErrorKind::UnexpectedEof,
+        ] {
+            assert!(!is_storage_error(&Error::new(kind, "logic")), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_memory_topic() {
+        use broker::Broker;
+
+        let base_dir = "target/topics-memory";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("storage.type".to_string(), "memory".to_string());
+        configs.insert("memory.max.bytes".to_string(), "4096".to_string());
+        let mut topic = Topic::new("telemetry".to_string(), TopicConfig {
+            name: "telemetry".to_string(),
+            partitions: 2,
+            base_dir: base_dir.to_string(),
+            configs: configs.clone(),
+            ..Default::default()
+        });
+        assert!(topic.is_memory().unwrap());
+        topic.init_partitions().unwrap();
+        for i in 0..100u8 {
+            topic.append_message(0, vec![i; 100]).unwrap();
+        }
+        topic.append_batch(1, RecordBatch::new(vec![Record::new(None, Some(b"a".to_vec()))])).unwrap();
+
+        // 不创建分区目录，只保留最近的记录
+        assert!(!std::path::Path::new(base_dir).exists());
+        assert!(topic.read_message(0, 0).is_err());
+        assert_eq!(topic.read_message(0, 99).unwrap(), Some(vec![99; 100]));
+        assert_eq!(topic.read_range(0, 90, usize::MAX, 5).unwrap().records.len(), 5);
+        assert_eq!(topic.read_message(1, 0).unwrap(), Some(b"a".to_vec()));
+        assert!(topic.read_slice(0, 99, 1024).is_err());
+        topic.delete_topic().unwrap();
+        assert_eq!(topic.get_partition_count(), 0);
+
+        let broker = Broker::new();
+        broker.create_topic("telemetry", TopicConfig {
+            name: "telemetry".to_string(),
+            partitions: 1,
+            base_dir: base_dir.to_string(),
+            configs,
+            ..Default::default()
+        }).unwrap();
+        broker.send_message("telemetry", b"m".to_vec()).unwrap();
+        assert_eq!(broker.fetch_message("telemetry", 0, 0).unwrap(), Some(b"m".to_vec()));
+
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("storage.type".to_string(), "tape".to_string());
+        assert!(broker.create_topic("invalid", TopicConfig {
+            name: "invalid".to_string(),
+            configs,
+            ..Default::default()
+        }).is_err());
+    }
+
+    #[test]
+    fn test_topic_encryption() {
+        use broker::Broker;
+        use queue::MasterKey;
+        use std::sync::Arc;
+
+        let base_dir = "target/topics-encryption";
+        let _ = std::fs::remove_dir_all(base_dir);
+        std::fs::create_dir_all(base_dir).unwrap();
+        let key_file = format!("{}/master.key", base_dir);
+        MasterKey::generate(&key_file).unwrap();
+        let topic_config = |name: &str, encryption: &str| {
+            let mut configs = std::collections::HashMap::new();
+            configs.insert("encryption.enable".to_string(), encryption.to_string());
+            TopicConfig {
+                name: name.to_string(),
+                partitions: 1,
+                segment_size: 1024,
+                base_dir: base_dir.to_string(),
+                configs,
+                ..Default::default()
+            }
+        };
+        let log_contents = |dir: &str| {
+            let mut contents = Vec::new();
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "log") {
+                    contents.extend(std::fs::read(path).unwrap());
+                }
+            }
+            contents
+        };
+        let contains = |contents: &[u8], pattern: &[u8]| contents.windows(pattern.len()).any(|w| w == pattern);
+
+        let broker = Broker::new().with_master_key_file(&key_file).unwrap();
+        broker.create_topic("secure", topic_config("secure", "true")).unwrap();
+        broker.create_topic("plain", topic_config("plain", "false")).unwrap();
+        for i in 0..20 {
+            broker.send_message("secure", format!("card-number-{:04}", i).into_bytes()).unwrap();
+            broker.send_message("plain", format!("card-number-{:04}", i).into_bytes()).unwrap();
+            if i == 9 {
+                broker.rotate_encryption_key("secure").unwrap();
+            }
+        }
+        assert!(broker.rotate_encryption_key("plain").is_err());
+        assert_eq!(broker.fetch_message("secure", 0, 15).unwrap(), Some(b"card-number-0015".to_vec()));
+        assert!(!contains(&log_contents(&format!("{}/secure-0", base_dir)), b"card-number"));
+        assert!(contains(&log_contents(&format!("{}/plain-0", base_dir)), b"card-number"));
+        broker.shutdown().unwrap();
+        drop(broker);
+
+        // 重启后使用同一个主密钥读取轮换前后写入的日志段
+        let mut topic = Topic::new("secure".to_string(), topic_config("secure", "true"));
+        topic.set_master_key(Arc::new(MasterKey::load(&key_file).unwrap())).unwrap();
+        assert!(topic.is_encrypted());
+        topic.init_partitions().unwrap();
+        assert_eq!(topic.read_message(0, 3).unwrap(), Some(b"card-number-0003".to_vec()));
+        assert_eq!(topic.read_message(0, 19).unwrap(), Some(b"card-number-0019".to_vec()));
+        assert_eq!(topic.append_message(0, b"after restart".to_vec()).unwrap(), 20);
+
+        // 没有主密钥时无法打开加密分区
+        let mut topic = Topic::new("secure".to_string(), topic_config("secure", "true"));
+        assert!(topic.init_partitions().is_err());
+
+        // 加密需要主题显式开启，未设置 encryption.enable 的主题不加密
+        let mut topic = Topic::new("default".to_string(), TopicConfig { name: "default".to_string(), ..Default::default() });
+        topic.set_master_key(Arc::new(MasterKey::load(&key_file).unwrap())).unwrap();
+        assert!(!topic.is_encrypted());
+    }
+
+    #[test]
+    fn test_encrypted_slice_keeps_log_dir_online() {
+        use broker::{Broker, LogDirs, PlacementPolicy};
+        use queue::MasterKey;
+
+        let base_dir = "target/topics-encrypted-slice";
+        let _ = std::fs::remove_dir_all(base_dir);
+        std::fs::create_dir_all(base_dir).unwrap();
+        let key_file = format!("{}/master.key", base_dir);
+        MasterKey::generate(&key_file).unwrap();
+        let dirs = vec![format!("{}/data", base_dir)];
+        let topic_config = |name: &str, encryption: &str| {
+            let mut configs = std::collections::HashMap::new();
+            configs.insert("encryption.enable".to_string(), encryption.to_string());
+            TopicConfig {
+                name: name.to_string(),
+                partitions: 1,
+                configs,
+                ..Default::default()
+            }
+        };
+
+        let broker = Broker::new()
+            .with_log_dirs(LogDirs::new(&dirs, PlacementPolicy::FewestPartitions).unwrap())
+            .with_master_key_file(&key_file)
+            .unwrap();
+        broker.create_topic("secure", topic_config("secure", "true")).unwrap();
+        broker.create_topic("plain", topic_config("plain", "false")).unwrap();
+        broker.send_message("secure", b"secret".to_vec()).unwrap();
+        broker.send_message("plain", b"public".to_vec()).unwrap();
+
+        // 加密主题不支持零拷贝读取，但这不是设备故障，日志目录保持在线
+        assert!(broker.fetch_slice("secure", 0, 0, 1024).is_err());
+        assert!(broker.describe_log_dirs().iter().all(|dir| dir.online));
+        assert_eq!(broker.fetch_range("secure", 0, 0, 1024).unwrap().records[0].value, Some(b"secret".to_vec()));
+        assert!(broker.fetch_slice("plain", 0, 0, 1024).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_topic_snapshot_and_restore() {
+        use broker::Broker;
+
+        let base_dir = "target/topics-snapshot";
+        let restore_dir = "target/topics-snapshot-restored";
+        let archive = "target/topics-snapshot.tar";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let _ = std::fs::remove_dir_all(restore_dir);
+        let _ = std::fs::remove_file(archive);
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("cleanup.policy".to_string(), "delete".to_string());
+        let config = TopicConfig {
+            name: "orders".to_string(),
+            partitions: 2,
+            segment_size: 1024,
+            base_dir: base_dir.to_string(),
+            configs,
+            ..Default::default()
+        };
+
+        let broker = Broker::new();
+        broker.create_topic("orders", config).unwrap();
+        for i in 0..60 {
+            let batch = RecordBatch::new(vec![Record::new(None, Some(format!("order-{}", i).into_bytes()))]);
+            broker.send_batch("orders", i % 2, batch).unwrap();
+        }
+        broker.commit_offset("billing", "orders", 0, 10).unwrap();
+        broker.commit_offset("billing", "orders", 1, 29).unwrap();
+        broker.commit_offset("billing", "other", 0, 5).unwrap();
+
+        let manifest = broker.snapshot_topic("orders", None, Some(20), archive).unwrap();
+        assert_eq!(manifest.snapshots.len(), 2);
+        assert!(manifest.snapshots.iter().all(|p| p.log_start_offset == 0 && p.end_offset == 20));
+        // 超过快照结束 offset 的提交按结束 offset 保存
+        let offsets: Vec<_> = manifest.consumer_offsets.iter().map(|o| (o.partition, o.offset)).collect();
+        assert_eq!(offsets, vec![(0, 10), (1, 20)]);
+        assert!(std::path::Path::new(archive).is_file());
+        assert!(broker.snapshot_topic("orders", None, None, archive).is_err());
+        assert!(broker.snapshot_topic("missing", None, None, "target/topics-snapshot-missing").is_err());
+
+        // 快照之后的写入不影响快照
+        broker.send_batch("orders", 0, RecordBatch::new(vec![Record::new(None, Some(b"late".to_vec()))])).unwrap();
+        assert!(broker.restore_topic(archive, base_dir).is_err());
+
+        let restored = Broker::new();
+        let manifest = restored.restore_topic(archive, restore_dir).unwrap();
+        assert_eq!(manifest.configs.get("cleanup.policy").map(String::as_str), Some("delete"));
+        assert!(std::path::Path::new(&format!("{}/orders-1", restore_dir)).is_dir());
+        assert_eq!(restored.get_offset("billing", "orders", 0).unwrap(), Some(10));
+        assert_eq!(restored.get_offset("billing", "orders", 1).unwrap(), Some(20));
+        assert_eq!(restored.get_offset("billing", "other", 0).unwrap(), None);
+        let range = restored.fetch_range("orders", 0, 0, usize::MAX).unwrap();
+        assert_eq!(range.records.len(), 20);
+        assert_eq!(range.records[19].value.as_deref(), Some("order-38".as_bytes()));
+        assert_eq!(range.next_offset, 20);
+        assert_eq!(restored.fetch_message("orders", 1, 20).unwrap(), None);
+        assert_eq!(restored.send_batch("orders", 1, RecordBatch::new(vec![Record::new(None, Some(b"next".to_vec()))])).unwrap(), 20);
+        assert!(restored.restore_topic(archive, restore_dir).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_topic_tail_subscription() {
+        use std::time::Duration;
+
+        let base_dir = "target/topics-tail";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let mut topic = Topic::new(TEST_TOPIC.to_string(), TopicConfig {
+            name: TEST_TOPIC.to_string(),
+            partitions: 2,
+            base_dir: base_dir.to_string(),
+            ..Default::default()
+        });
+        topic.init_partitions().unwrap();
+        topic.append_message(0, b"a".to_vec()).unwrap();
+
+        // 读到日志末尾后等待下一条记录，而不是轮询 read_message
+        assert_eq!(topic.read_message(0, 1).unwrap(), None);
+        let waiter = tokio::spawn(topic.wait_past(0, 1).unwrap());
+        let other = topic.wait_past(1, 0).unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(50), topic.wait_past(0, 1).unwrap()).await.is_err());
+        topic.append_message(0, b"b".to_vec()).unwrap();
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap(), 2);
+        assert_eq!(topic.read_message(0, 1).unwrap(), Some(b"b".to_vec()));
+
+        // 分区之间互不影响
+        assert!(tokio::time::timeout(Duration::from_millis(50), other).await.is_err());
+        assert!(topic.subscribe(5).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_topic_iter_and_stream() {
+        use futures::StreamExt;
+        use std::time::Duration;
+
+        let base_dir = "target/topics-stream";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("storage.type".to_string(), "memory".to_string());
+        let mut file_topic = Topic::new(TEST_TOPIC.to_string(), TopicConfig {
+            name: TEST_TOPIC.to_string(),
+            partitions: 1,
+            base_dir: base_dir.to_string(),
+            ..Default::default()
+        });
+        let mut memory_topic = Topic::new("telemetry".to_string(), TopicConfig {
+            name: "telemetry".to_string(),
+            partitions: 1,
+            base_dir: base_dir.to_string(),
+            configs,
+            ..Default::default()
+        });
+        for topic in [&mut file_topic, &mut memory_topic] {
+            topic.init_partitions().unwrap();
+            for i in 0..5u8 {
+                topic.append_message(0, vec![i]).unwrap();
+            }
+
+            // 迭代器读到日志末尾结束
+            let offsets: Vec<u64> = topic.iter_from(0, 2).unwrap().map(|item| item.unwrap().0).collect();
+            assert_eq!(offsets, vec![2, 3, 4]);
+            assert!(topic.iter_from(1, 0).is_err());
+
+            // 记录流跟随日志尾部
+            let mut stream = topic.stream_from(0, 3).unwrap();
+            assert_eq!(stream.next().await.unwrap().unwrap().0, 3);
+            assert_eq!(stream.next().await.unwrap().unwrap().0, 4);
+            assert!(tokio::time::timeout(Duration::from_millis(50), stream.next()).await.is_err());
+            topic.append_message(0, vec![5]).unwrap();
+            let (offset, record) = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap().unwrap().unwrap();
+            assert_eq!(offset, 5);
+            assert_eq!(record.value, Some(vec![5]));
+        }
+    }
+
+    #[test]
+    fn test_topic_capacity() {
+        use broker::Broker;
+        use protocol::response::error_code;
+        use protocol::ProduceRequest;
+
+        let base_dir = "target/topics-capacity";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let topic_config = |name: &str, policy: &str| {
+            let mut configs = std::collections::HashMap::new();
+            configs.insert("capacity.max.records".to_string(), "3".to_string());
+            configs.insert("capacity.overflow.policy".to_string(), policy.to_string());
+            TopicConfig {
+                name: name.to_string(),
+                partitions: 1,
+                base_dir: base_dir.to_string(),
+                configs,
+                ..Default::default()
+            }
+        };
+
+        // reject：写满后生产者收到 QUEUE_FULL 错误码
+        let broker = Broker::new();
+        broker.create_topic("jobs", topic_config("jobs", "reject")).unwrap();
+        let request = ProduceRequest::new("jobs".to_string(), 0, &RecordBatch::new(vec![Record::new(None, Some(b"job".to_vec()))])).unwrap();
+        for offset in 0..3 {
+            let response = broker.produce(&request);
+            assert_eq!((response.offset, response.error_code), (offset, error_code::NONE));
+        }
+        let response = broker.produce(&request);
+        assert_eq!((response.offset, response.error_code), (-1, error_code::QUEUE_FULL));
+        assert!(broker.send_message("jobs", b"job".to_vec()).is_err());
+        // 写满不是存储设备错误，分区仍然可以读取
+        assert_eq!(broker.fetch_message("jobs", 0, 0).unwrap(), Some(b"job".to_vec()));
+        let unknown = ProduceRequest::new("missing".to_string(), 0, &RecordBatch::new(vec![Record::new(None, Some(b"job".to_vec()))])).unwrap();
+        assert_eq!(broker.produce(&unknown).error_code, error_code::UNKNOWN_TOPIC_OR_PARTITION);
+
+        // drop_oldest：推进日志起始 offset，只保留最新的记录
+        let mut topic = Topic::new("buffer".to_string(), topic_config("buffer", "drop_oldest"));
+        topic.init_partitions().unwrap();
+        for i in 0..5u8 {
+            topic.append_message(0, vec![i]).unwrap();
+        }
+        assert!(topic.read_message(0, 1).is_err());
+        assert_eq!(topic.read_message(0, 2).unwrap(), Some(vec![2]));
+
+        // block：等待超时后返回 QUEUE_FULL
+        let mut config = topic_config("blocking", "block");
+        config.configs.insert("capacity.block.timeout.ms".to_string(), "20".to_string());
+        let mut topic = Topic::new("blocking".to_string(), config);
+        topic.init_partitions().unwrap();
+        for _ in 0..3 {
+            topic.append_message(0, b"job".to_vec()).unwrap();
+        }
+        let err = topic.produce_batch(0, RecordBatch::new(vec![Record::new(None, Some(b"job".to_vec()))])).unwrap_err();
+        assert_eq!(err.error_code, error_code::QUEUE_FULL);
+
+        assert!(Topic::new("invalid".to_string(), topic_config("invalid", "grow")).capacity_limit().is_err());
+    }
+
+    #[test]
+    fn test_blocked_producer_releases_topics() {
+        use broker::Broker;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let base_dir = "target/topics-blocked-producer";
+        let _ = std::fs::remove_dir_all(base_dir);
+        let mut configs = std::collections::HashMap::new();
+        configs.insert("capacity.max.records".to_string(), "2".to_string());
+        configs.insert("capacity.overflow.policy".to_string(), "block".to_string());
+        configs.insert("capacity.block.timeout.ms".to_string(), "10000".to_string());
+        let broker = Arc::new(Broker::new());
+        broker.create_topic("jobs", TopicConfig {
+            name: "jobs".to_string(),
+            partitions: 1,
+            base_dir: base_dir.to_string(),
+            configs,
+            ..Default::default()
+        }).unwrap();
+        broker.send_message("jobs", b"a".to_vec()).unwrap();
+        broker.send_message("jobs", b"b".to_vec()).unwrap();
+
+        let started = Instant::now();
+        let producer = {
+            let broker = broker.clone();
+            std::thread::spawn(move || {
+                broker.send_batch("jobs", 0, RecordBatch::new(vec![Record::new(None, Some(b"c".to_vec()))]))
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!producer.is_finished());
+
+        // 生产者等待期间不持有主题表的锁：创建主题（写锁）与释放空间的删除都能立即完成
+        let creator = {
+            let broker = broker.clone();
+            std::thread::spawn(move || {
+                broker.create_topic("other", TopicConfig {
+                    name: "other".to_string(),
+                    partitions: 1,
+                    base_dir: base_dir.to_string(),
+                    ..Default::default()
+                })
+            })
+        };
+        creator.join().unwrap().unwrap();
+        assert_eq!(broker.delete_records("jobs", 0, 1).unwrap(), 1);
+        assert_eq!(producer.join().unwrap().unwrap(), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(broker.fetch_message("jobs", 0, 2).unwrap(), Some(b"c".to_vec()));
+    }
+}
@@ -55,8 +55,22 @@ pub struct BrokerConfig {
 pub struct StorageConfig {
     /// 日志文件存储目录
     pub log_dir: String,
+    /// 多个日志目录（逗号分隔），未配置时只使用 log_dir
+    pub log_dirs: String,
+    /// 新分区的日志目录放置策略（partitions: 分区数最少, bytes: 占用字节数最少）
+    pub log_dir_placement: String,
     /// 单个日志段的大小（字节）
     pub segment_size: usize,
+    /// 日志段按时间滚动的间隔（毫秒），负数表示不按时间滚动
+    pub segment_ms: i64,
+    /// 每写入多少字节追加一个稀疏索引条目
+    pub index_interval_bytes: usize,
+    /// 索引文件预分配的最大大小（字节）
+    pub segment_index_bytes: usize,
+    /// 刷盘策略（write: 每次写入, messages: 每 flush_messages 条消息, interval: 每 flush_interval_ms 毫秒, os: 由操作系统回写）
+    pub flush_policy: String,
+    /// 刷盘策略为 messages 时，累计多少条消息后刷盘
+    pub flush_messages: u64,
     /// 日志刷新到磁盘的时间间隔（毫秒）
     pub flush_interval_ms: u32,
     /// 日志刷新调度器的时间间隔（毫秒）
@@ -73,8 +87,10 @@ pub struct StorageConfig {
     pub background_threads_enable: bool,
     /// 后台线程数量
     pub num_background_threads: u32,
-    /// 消息压缩类型（none, gzip, snappy, lz4, zstd）
+    /// 消息压缩类型（producer: 保留生产者的压缩编码, none, gzip, snappy, lz4, zstd），主题可以通过 compression.type 覆盖
     pub compression_type: String,
+    /// 静态数据加密的主密钥文件（64 个十六进制字符），为空时不加密；主题通过 encryption.enable=true 开启
+    pub encryption_master_key_file: String,
     /// 单条消息的最大大小（字节）
     pub message_max_bytes: i32,
     /// 副本获取数据的最大大小（字节）
@@ -138,7 +154,14 @@ impl ConfigStruct {
             .set_default("broker.group_max_session_timeout_ms", 300000)?
             // 存储配置默认值
             .set_default("storage.log_dir", "/var/lib/rust_kafka")?
+            .set_default("storage.log_dirs", "")?
+            .set_default("storage.log_dir_placement", "partitions")?
             .set_default("storage.segment_size", 1048576)?
+            .set_default("storage.segment_ms", 604800000)?
+            .set_default("storage.index_interval_bytes", 4096)?
+            .set_default("storage.segment_index_bytes", 10485760)?
+            .set_default("storage.flush_policy", "os")?
+            .set_default("storage.flush_messages", 10000)?
             .set_default("storage.flush_interval_ms", 1000)?
             .set_default("storage.flush_scheduler_interval_ms", 3000)?
             .set_default("storage.num_recovery_threads_per_data_dir", 1)?
@@ -148,6 +171,7 @@ impl ConfigStruct {
             .set_default("storage.background_threads_enable", true)?
             .set_default("storage.num_background_threads", 10)?
             .set_default("storage.compression_type", "producer")?
+            .set_default("storage.encryption_master_key_file", "")?
             .set_default("storage.message_max_bytes", 1000012)?
             .set_default("storage.replica_fetch_max_bytes", 1048576)?
             .set_default("storage.replica_fetch_min_bytes", 1)?
@@ -15,7 +15,7 @@ mod tests {
         assert!(settings.is_ok());
         match settings {
             Ok(cfg) => {
-                cfg.broker.host;
+                assert!(!cfg.broker.host.is_empty());
                 assert_eq!(cfg.storage.segment_size, 1048576);
             },
             Err(e) => {
@@ -55,10 +55,13 @@ pub struct PartitionInfo {
 /// 管理客户端
 pub struct AdminClient {
     /// 客户端ID
+    #[allow(dead_code)] // 请求发送尚未实现
     client_id: String,
     /// 集群配置
+    #[allow(dead_code)] // 请求发送尚未实现
     configs: HashMap<String, String>,
     /// 网络服务器
+    #[allow(dead_code)] // 请求发送尚未实现
     network_server: NetworkServer,
     /// Broker地址
     broker_addr: String,
@@ -83,7 +86,7 @@ impl AdminClient {
     /// # Returns
     /// * `Result<(), String>` - 创建成功返回 Ok(()), 失败返回错误信息
     pub async fn create_topic(&self, config: TopicConfig) -> Result<(), String> {
-        let request = ClientRequest::CreateTopic(CreateTopicRequest {
+        let _request = ClientRequest::CreateTopic(CreateTopicRequest {
             name: config.name,
             num_partitions: config.num_partitions,
             replication_factor: config.replication_factor,
@@ -109,7 +112,7 @@ impl AdminClient {
     /// # Returns
     /// * `Result<(), String>` - 删除成功返回 Ok(()), 失败返回错误信息
     pub async fn delete_topic(&self, topic_name: &str) -> Result<(), String> {
-        let request = ClientRequest::DeleteTopic(DeleteTopicRequest {
+        let _request = ClientRequest::DeleteTopic(DeleteTopicRequest {
             name: topic_name.to_string(),
         });
         
@@ -131,7 +134,7 @@ impl AdminClient {
     /// # Returns
     /// * `Result<TopicDescription, String>` - 成功返回主题描述，失败返回错误信息
     pub async fn describe_topic(&self, topic_name: &str) -> Result<TopicDescription, String> {
-        let request = ClientRequest::DescribeTopic(DescribeTopicRequest {
+        let _request = ClientRequest::DescribeTopic(DescribeTopicRequest {
             name: topic_name.to_string(),
         });
         
@@ -155,7 +158,7 @@ impl AdminClient {
     /// # Returns
     /// * `Result<Vec<String>, String>` - 成功返回主题列表，失败返回错误信息
     pub async fn list_topics(&self) -> Result<Vec<String>, String> {
-        let request = ClientRequest::ListTopics(ListTopicsRequest {});
+        let _request = ClientRequest::ListTopics(ListTopicsRequest {});
         
         // let response = self.network_server.send_request(&self.broker_addr, request).await?;
         
@@ -181,12 +184,12 @@ impl AdminClient {
     /// # Returns
     /// * `Result<(), String>` - 更新成功返回 Ok(()), 失败返回错误信息
     pub async fn update_topic_config(&self, topic_name: &str, configs: HashMap<String, String>) -> Result<(), String> {
-        let request = ClientRequest::UpdateTopicConfig(UpdateTopicConfigRequest {
+        let _request = ClientRequest::UpdateTopicConfig(UpdateTopicConfigRequest {
             name: topic_name.to_string(),
             configs,
         });
 
-        let stream = std::net::TcpStream::connect(&self.broker_addr).unwrap();
+        let _stream = std::net::TcpStream::connect(&self.broker_addr).unwrap();
         // send_message(&mut stream, &request);
         // let response = receive_message(&mut stream).await?;
         
@@ -203,7 +206,7 @@ impl AdminClient {
     /// # Returns
     /// * `Result<Vec<i32>, String>` - 成功返回broker ID列表，失败返回错误信息
     pub async fn get_cluster_info(&self) -> Result<Vec<i32>, String> {
-        let request = ClientRequest::GetClusterInfo(GetClusterInfoRequest {});
+        let _request = ClientRequest::GetClusterInfo(GetClusterInfoRequest {});
         
         // let response = self.network_server.send_request(&self.broker_addr, request).await?;
         
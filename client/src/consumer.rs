@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use crate::group::ConsumerGroup;
+use protocol::response::FetchResponse;
+use protocol::Record;
 
 /// 消费者
 pub struct Consumer {
@@ -48,4 +50,30 @@ impl Consumer {
     pub fn update_offset(&mut self, partition_id: usize, offset: u64) {
         self.offsets.insert(partition_id, offset);
     }
+
+    /// 处理获取响应：解码（并解压）其中的记录批次，跳过已消费的记录，并把分区偏移量推进到最后一条记录之后
+    /// 
+    /// # Arguments
+    /// * `response` - 获取消息响应
+    /// 
+    /// # Returns
+    /// * `Result<Vec<Record>, String>` - 成功返回新记录，批次损坏时返回错误信息
+    pub fn handle_fetch_response(&mut self, response: &FetchResponse) -> Result<Vec<Record>, String> {
+        let partition_id = response.partition as usize;
+        let mut offset = self.get_offset(partition_id);
+        let batches = response.record_batches()
+            .map_err(|e| format!("解码记录批次失败: {}", e))?;
+        let mut records = Vec::new();
+        for batch in batches {
+            // 返回的第一个批次可能包含 offset 之前的记录
+            for record in batch.records {
+                if record.offset >= offset {
+                    offset = record.offset + 1;
+                    records.push(record);
+                }
+            }
+        }
+        self.update_offset(partition_id, offset);
+        Ok(records)
+    }
 }
@@ -1,3 +1,4 @@
+use protocol::{CompressionType, ProduceRequest, Record, RecordBatch};
 
 /// 生产者配置
 #[derive(Debug, Clone)]
@@ -6,6 +7,8 @@ pub struct ProducerConfig {
     pub auto_select_partition: bool,
     /// 分区数量
     pub partition_count: usize,
+    /// 记录批次的压缩编码
+    pub compression_type: CompressionType,
 }
 
 impl Default for ProducerConfig {
@@ -13,6 +16,7 @@ impl Default for ProducerConfig {
         Self {
             auto_select_partition: true,
             partition_count: 1,
+            compression_type: CompressionType::None,
         }
     }
 }
@@ -76,6 +80,21 @@ impl Producer {
         partition_id
     }
 
+    /// 构造生产请求，按第一条记录的 key 选择分区，并使用配置的压缩编码压缩批次
+    /// 
+    /// # Arguments
+    /// * `topic` - 目标主题
+    /// * `records` - 同一批次发送的记录
+    /// 
+    /// # Returns
+    /// * `Result<ProduceRequest, String>` - 成功返回包含已编码批次的生产请求，记录无法编码时返回错误信息
+    pub fn build_request(&mut self, topic: &str, records: Vec<Record>) -> Result<ProduceRequest, String> {
+        let key = records.first().and_then(|record| record.key.clone());
+        let partition_id = self.select_partition(key.as_deref());
+        let batch = RecordBatch::new(records).with_compression(self.config.compression_type);
+        ProduceRequest::new(topic.to_string(), partition_id as i32, &batch).map_err(|e| format!("编码批次失败: {}", e))
+    }
+
     /// 发送消息
     /// 
     /// # Arguments
@@ -84,7 +103,7 @@ impl Producer {
     /// 
     /// # Returns
     /// * `Result<(usize, u64), String>` - 成功返回(分区ID, 偏移量)，失败返回错误信息
-    pub fn send_message(&mut self, message: Vec<u8>, key: Option<Vec<u8>>) -> Result<(usize, u64), String> {
+    pub fn send_message(&mut self, _message: Vec<u8>, key: Option<Vec<u8>>) -> Result<(usize, u64), String> {
         let partition_id = self.select_partition(key.as_deref());
         
         // TODO: 实际发送消息到broker
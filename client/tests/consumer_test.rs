@@ -1,4 +1,4 @@
-use client::{Consumer, ConsumerGroup};
+use client::Consumer;
 
 #[test]
 fn test_consumer_creation() {
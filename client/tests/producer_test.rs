@@ -1,4 +1,6 @@
-use client::{Producer, ProducerConfig};
+use client::{Consumer, Producer, ProducerConfig};
+use protocol::response::FetchResponse;
+use protocol::{CompressionType, Record};
 
 #[test]
 fn test_producer_creation() {
@@ -12,8 +14,10 @@ fn test_producer_creation() {
 
 #[test]
 fn test_partition_selection() {
-    let mut config = ProducerConfig::default();
-    config.partition_count = 3;
+    let config = ProducerConfig {
+        partition_count: 3,
+        ..Default::default()
+    };
     let mut producer = Producer::new("test_producer".to_string(), config);
     
     // 测试无key时的轮询分配
@@ -52,3 +56,33 @@ fn test_message_sending() {
     assert_eq!(partition_id, 0); // 默认配置下只有一个分区
     assert_eq!(offset, 0); // 临时返回值
 }
+
+#[test]
+fn test_compressed_produce_and_fetch() {
+    let config = ProducerConfig {
+        compression_type: CompressionType::Lz4,
+        ..ProducerConfig::default()
+    };
+    let mut producer = Producer::new("test_producer".to_string(), config);
+    let records = (0..100)
+        .map(|i| Record::new(None, Some(format!(r#"{{"id":{},"status":"ok"}}"#, i).into_bytes())))
+        .collect();
+    let request = producer.build_request("events", records).unwrap();
+    let batch = request.record_batch().unwrap();
+    assert_eq!(batch.compression(), CompressionType::Lz4);
+
+    // 消费者解压批次并跳过已消费的记录
+    let mut consumer = Consumer::new("test_consumer".to_string(), "test_group".to_string());
+    consumer.update_offset(0, 40);
+    let response = FetchResponse {
+        topic: "events".to_string(),
+        partition: 0,
+        error_code: 0,
+        high_watermark: 100,
+        messages: vec![request.messages.clone()],
+    };
+    let records = consumer.handle_fetch_response(&response).unwrap();
+    assert_eq!(records.len(), 60);
+    assert_eq!(records[0].value, Some(br#"{"id":40,"status":"ok"}"#.to_vec()));
+    assert_eq!(consumer.get_offset(0), 100);
+}
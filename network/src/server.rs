@@ -10,6 +10,10 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use std::io;
 use tokio::time::timeout;
+use storage::FileSlice;
+
+/// 消息体的最小长度：1字节类型 + 4字节ID + 4字节correlation_id + 4字节client_id
+const MIN_FRAME_LENGTH: usize = 13;
 
 pub struct NetworkServer {
     address: String,
@@ -46,6 +50,13 @@ impl NetworkServer {
             .await
             .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Read timeout"))??;
         let length = u32::from_be_bytes(length_buf) as usize;
+        // 长度不足消息头的帧是无效数据，直接拒绝，不等待后续数据直到超时
+        if length < MIN_FRAME_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Frame too short: {} bytes", length),
+            ));
+        }
 
         // 读取消息内容
         let mut buffer = vec![0u8; length];
@@ -69,6 +80,73 @@ impl NetworkServer {
         Ok(())
     }
 
+    /// 发送消息，并在消息之后直接发送日志文件区域（零拷贝）
+    ///
+    /// 帧长度包含文件区域，接收方解码得到的 payload 为 `message.payload` 后接文件区域内容
+    pub async fn send_message_with_slice(
+        &self,
+        stream: &mut TcpStream,
+        message: &BinaryMessage,
+        slice: &FileSlice,
+    ) -> io::Result<()> {
+        let header = message.encode_with_trailing(slice.len());
+        timeout(self.connection_timeout, stream.write_all(&header))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Write timeout"))??;
+        timeout(self.connection_timeout, Self::write_slice(stream, slice))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Write timeout"))??;
+        timeout(self.connection_timeout, stream.flush())
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Flush timeout"))??;
+        Ok(())
+    }
+
+    /// 使用 sendfile 把文件区域从页缓存直接写入 socket，不经过用户态缓冲区
+    #[cfg(target_os = "linux")]
+    async fn write_slice(stream: &mut TcpStream, slice: &FileSlice) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        use tokio::io::Interest;
+
+        let mut offset = slice.position() as libc::off_t;
+        let end = offset + slice.len() as libc::off_t;
+        while offset < end {
+            stream.writable().await?;
+            let result = stream.try_io(Interest::WRITABLE, || {
+                let sent = unsafe {
+                    libc::sendfile(
+                        stream.as_raw_fd(),
+                        slice.file().as_raw_fd(),
+                        &mut offset,
+                        (end - offset) as usize,
+                    )
+                };
+                if sent < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(sent as usize)
+                }
+            });
+            match result {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file slice truncated")),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// 不支持 sendfile 的平台通过 mmap 映射文件区域后直接写入 socket
+    #[cfg(not(target_os = "linux"))]
+    async fn write_slice(stream: &mut TcpStream, slice: &FileSlice) -> io::Result<()> {
+        if slice.is_empty() {
+            return Ok(());
+        }
+        let mmap = slice.map()?;
+        stream.write_all(&mmap).await
+    }
+
     pub async fn start(&self) -> tokio::io::Result<()> {
         let listener = TcpListener::bind(&self.address).await?;
         println!("🚀 Server running on {}", self.address);
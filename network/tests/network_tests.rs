@@ -5,6 +5,7 @@ use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use std::time::Duration;
+use storage::{LogSegment, Record, RecordBatch};
 
 async fn setup_test_server() -> (TcpStream, TcpStream) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -15,11 +16,19 @@ async fn setup_test_server() -> (TcpStream, TcpStream) {
     (server, client)
 }
 
+#[allow(dead_code)]
 async fn setup_test_server_1() {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
     let network_server = NetworkServer::new("127.0.0.1:0").with_timeout(Duration::from_secs(5));
-    let client = TcpStream::connect(addr).await.unwrap();
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let test_message = BinaryMessage {
+        msg_id: 1,
+        msg_type: MessageType::Produce,
+        payload: vec![1, 2, 3, 4],
+        client_id: 1,
+        correlation_id: 1,
+    };
     network_server.send_message(&mut client, &test_message).await.unwrap();
 
 }
@@ -116,8 +125,8 @@ async fn test_timeout() {
     let (mut server, mut client) = setup_test_server().await;
     let network_server = NetworkServer::new("127.0.0.1:0").with_timeout(Duration::from_millis(100));
 
-    // 发送部分数据
-    client.write_all(&[0, 0, 0, 4]).await.unwrap();
+    // 发送部分数据：长度有效，消息体未发送完
+    client.write_all(&[0, 0, 0, 13, 1, 0, 0]).await.unwrap();
     client.flush().await.unwrap();
 
     // 应该超时
@@ -126,3 +135,50 @@ async fn test_timeout() {
     let err = result.unwrap_err();
     assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
 }
+
+#[tokio::test]
+async fn test_send_message_with_slice() {
+    let (mut server, mut client) = setup_test_server().await;
+    let network_server = NetworkServer::new("127.0.0.1:0").with_timeout(Duration::from_secs(5));
+
+    // 准备日志段，写入足够多的数据以覆盖 socket 缓冲区写满后的重试
+    let dir = "../target/test_send_message_with_slice";
+    let _ = std::fs::remove_dir_all(dir);
+    let mut segment = LogSegment::new(dir, 0, 16 * 1024 * 1024).unwrap();
+    for i in 0..500 {
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(vec![i as u8; 1000]))]);
+        segment.append_batch(&mut batch).unwrap();
+    }
+    let slice = segment.read_slice(3, usize::MAX).unwrap().unwrap();
+    assert_eq!(slice.next_offset(), 500);
+
+    let message = BinaryMessage {
+        msg_id: 7,
+        msg_type: MessageType::Fetch,
+        payload: b"header".to_vec(),
+        client_id: 1,
+        correlation_id: 7,
+    };
+    let (sent, received) = tokio::join!(
+        network_server.send_message_with_slice(&mut client, &message, &slice),
+        network_server.receive_message(&mut server)
+    );
+    sent.unwrap();
+    let received = received.unwrap();
+
+    // payload 为消息头部之后紧跟文件区域，可以直接按批次解码
+    assert_eq!(received.msg_id, 7);
+    assert_eq!(&received.payload[..6], b"header");
+    let mut body = &received.payload[6..];
+    assert_eq!(body, &slice.to_vec().unwrap()[..]);
+    let mut offset = 3;
+    while !body.is_empty() {
+        let size = 16 + u32::from_be_bytes(body[8..12].try_into().unwrap()) as usize;
+        let batch = RecordBatch::decode(&body[..size]).unwrap();
+        assert_eq!(batch.base_offset, offset);
+        assert_eq!(batch.records[0].value, Some(vec![offset as u8; 1000]));
+        offset += 1;
+        body = &body[size..];
+    }
+    assert_eq!(offset, 500);
+}
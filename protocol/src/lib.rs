@@ -4,6 +4,7 @@
 //! - 消息类型定义
 //! - 请求/响应处理
 //! - 二进制消息编解码
+//! - 记录批次格式（与 `storage` 共用）
 
 pub mod message;
 pub mod request;
@@ -11,8 +12,17 @@ pub mod response;
 
 // 导出常用类型
 pub use message::{MessageType, BinaryMessage};
-pub use request::{ClientRequest, ProduceRequest, FetchRequest};
+pub use request::{
+    ClientRequest, ProduceRequest, FetchRequest, MetadataRequest, OffsetFetchRequest,
+    JoinGroupRequest, SyncGroupRequest, HeartbeatRequest, LeaveGroupRequest,
+    CreateTopicRequest, DeleteTopicRequest, DescribeTopicRequest, ListTopicsRequest,
+    UpdateTopicConfigRequest, GetClusterInfoRequest,
+};
 pub use response::ServerResponse;
+// 记录批次格式由 storage 定义，协议层直接复用
+pub use storage::record;
+pub use storage::record::{Header, Record, RecordBatch, TimestampType};
+pub use storage::CompressionType;
 // 导出错误类型
 pub mod error {
     use thiserror::Error;
@@ -80,10 +80,16 @@ impl BinaryMessage {
 
     /// 将消息序列化成二进制格式
     pub fn encode(&self) -> Vec<u8> {
+        self.encode_with_trailing(0)
+    }
+
+    /// 序列化消息，长度字段额外计入紧随其后单独发送的 `trailing_len` 字节，
+    /// 用于在消息之后直接发送日志文件区域（零拷贝），接收方解码得到的 payload 包含这部分数据
+    pub fn encode_with_trailing(&self, trailing_len: usize) -> Vec<u8> {
         let mut buffer: Vec<u8> = Vec::new();
 
-        // 计算消息总长度 (1字节类型 + 4字节ID + 4字节correlation_id + 4字节client_id + payload长度)
-        let msg_length = 1 + 4 + 4 + 4 + self.payload.len();
+        // 计算消息总长度 (1字节类型 + 4字节ID + 4字节correlation_id + 4字节client_id + payload长度 + 尾随数据长度)
+        let msg_length = 1 + 4 + 4 + 4 + self.payload.len() + trailing_len;
 
         buffer.write_all(&(msg_length as u32).to_be_bytes()).unwrap(); // 4字节 长度
         buffer.write_all(&[self.msg_type.into()]).unwrap();           // 1字节 类型
@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use storage::record::RecordBatch;
 
 /// 客户端请求类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,9 +39,26 @@ pub enum ClientRequest {
 pub struct ProduceRequest {
     pub topic: String,
     pub partition: i32,
+    /// 编码后的记录批次，格式与磁盘一致
     pub messages: Vec<u8>,
 }
 
+impl ProduceRequest {
+    /// 使用记录批次创建生产请求
+    pub fn new(topic: String, partition: i32, batch: &RecordBatch) -> storage::Result<Self> {
+        Ok(Self {
+            topic,
+            partition,
+            messages: batch.encode()?,
+        })
+    }
+
+    /// 解码请求中的记录批次
+    pub fn record_batch(&self) -> storage::Result<RecordBatch> {
+        RecordBatch::decode(&self.messages)
+    }
+}
+
 /// 获取消息请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FetchRequest {
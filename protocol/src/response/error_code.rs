@@ -0,0 +1,25 @@
+//! 响应错误码
+//!
+//! 与 Kafka 含义相同的错误使用 Kafka 的错误码，本项目特有的错误从 1000 开始编号，避免冲突。
+
+use storage::StorageError;
+
+/// 没有错误
+pub const NONE: i16 = 0;
+/// 服务端未知错误
+pub const UNKNOWN_SERVER_ERROR: i16 = -1;
+/// 请求的 offset 不在分区日志范围内
+pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+/// 主题或分区不存在
+pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+/// 分区已达到容量上限，拒绝写入（或等待空间释放超时）
+pub const QUEUE_FULL: i16 = 1000;
+
+/// 存储层错误对应的错误码
+pub fn from_storage_error(err: &StorageError) -> i16 {
+    match err {
+        StorageError::OffsetOutOfRange { .. } => OFFSET_OUT_OF_RANGE,
+        StorageError::QueueFull { .. } => QUEUE_FULL,
+        _ => UNKNOWN_SERVER_ERROR,
+    }
+}
@@ -1,3 +1,4 @@
 mod types;
+pub mod error_code;
 
 pub use types::*;
@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use storage::record::RecordBatch;
 
 /// 服务器响应类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,9 +38,17 @@ pub struct FetchResponse {
     pub partition: i32,
     pub error_code: i16,
     pub high_watermark: i64,
+    /// 编码后的记录批次，格式与磁盘一致
     pub messages: Vec<Vec<u8>>,
 }
 
+impl FetchResponse {
+    /// 解码响应中的全部记录批次
+    pub fn record_batches(&self) -> storage::Result<Vec<RecordBatch>> {
+        self.messages.iter().map(|m| RecordBatch::decode(m)).collect()
+    }
+}
+
 /// 获取元数据响应
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetadataResponse {
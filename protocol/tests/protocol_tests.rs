@@ -4,6 +4,7 @@ use protocol::request::ClientRequest;
 use protocol::request::ProduceRequest;
 use protocol::request::GetClusterInfoRequest;
 use protocol::request::FetchRequest;
+use protocol::{Record, RecordBatch};
 #[test]
 fn test_message_type_conversion() {
     // 测试 MessageType 到 u8 的转换
@@ -273,4 +274,27 @@ fn test_message_stream_decode() {
     assert_eq!(decoded_msg.correlation_id, original_msg.correlation_id);
     assert_eq!(decoded_msg.client_id, original_msg.client_id);
     assert_eq!(decoded_msg.payload, original_msg.payload);
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_produce_request_record_batch() {
+    // 创建带 key 和 header 的记录批次
+    let batch = RecordBatch::new(vec![
+        Record::new(Some(b"order-1".to_vec()), Some(b"paid".to_vec()))
+            .with_header("trace-id", Some(b"t-1".to_vec())),
+        Record::new(Some(b"order-2".to_vec()), None),
+    ]);
+    let produce_request = ClientRequest::Produce(ProduceRequest::new("test-topic".to_string(), 0, &batch).unwrap());
+
+    // 经过二进制消息编解码后记录批次保持不变
+    let binary_msg = BinaryMessage::from_request(&produce_request, 1, 2, 3);
+    match binary_msg.to_request().unwrap() {
+        ClientRequest::Produce(req) => {
+            let decoded = req.record_batch().unwrap();
+            assert_eq!(decoded, batch);
+            assert_eq!(decoded.records[0].key, Some(b"order-1".to_vec()));
+            assert_eq!(decoded.records[1].value, None);
+        }
+        _ => panic!("Expected ProduceRequest"),
+    }
+}
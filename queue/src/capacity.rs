@@ -0,0 +1,68 @@
+//! 分区容量上限与写入背压
+//!
+//! 分区日志在追加时按 `CapacityLimit` 检查是否写满：Reject 与 Block 返回 `StorageError::QueueFull`，
+//! DropOldest 推进日志起始 offset 后继续写入。Block 的等待不能在持有分区日志的锁时进行，否则释放空间的
+//! 操作（`delete_records_before`、保留策略、压缩）拿不到锁，所以由 [`append_with_backpressure`] 在锁外等待
+//! `SpaceNotifier` 的通知后重试。
+
+use crate::PartitionLog;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use storage::{OverflowPolicy, RecordBatch, StorageError};
+
+/// 分区空间释放的通知：日志起始 offset 推进、段被删除或日志被截断后唤醒等待的写入方
+#[derive(Debug, Clone, Default)]
+pub struct SpaceNotifier {
+    shared: Arc<(Mutex<u64>, Condvar)>, // 释放次数与等待的条件变量
+}
+
+impl SpaceNotifier {
+    /// 通知等待方空间可能已经释放，由分区日志的写入方调用
+    pub(crate) fn notify(&self) {
+        let (generation, condvar) = &*self.shared;
+        *generation.lock().unwrap() += 1;
+        condvar.notify_all();
+    }
+
+    /// 当前的释放次数，在持有分区日志的锁时读取，之后的释放都会让它增加
+    pub fn generation(&self) -> u64 {
+        *self.shared.0.lock().unwrap()
+    }
+
+    /// 等待释放次数超过 `generation`，超时返回 false
+    pub fn wait_timeout(&self, generation: u64, timeout: Duration) -> bool {
+        let (current, condvar) = &*self.shared;
+        let current = current.lock().unwrap();
+        let (current, _) = condvar.wait_timeout_while(current, timeout, |current| *current <= generation).unwrap();
+        *current > generation
+    }
+}
+
+/// 按分区的容量上限追加记录批次，返回批次的 base_offset
+///
+/// 溢出策略为 Block 时在锁外等待空间释放后重试，超过等待时间仍然写满时返回 `StorageError::QueueFull`；
+/// 记录数超过上限的批次不会等待，其他策略与直接调用 `append_batch` 相同
+pub fn append_with_backpressure<L: PartitionLog + ?Sized>(log: &Mutex<L>, batch: &mut RecordBatch) -> io::Result<u64> {
+    let mut deadline = None;
+    loop {
+        let (result, space, generation, capacity) = {
+            let mut log = log.lock().map_err(|e| io::Error::other(format!("failed to lock partition log: {}", e)))?;
+            let space = log.space_notifier();
+            let generation = space.generation();
+            (log.append_batch(batch), space, generation, log.capacity())
+        };
+        // 记录数超过上限的批次等到空间释放也无法写入
+        let oversized = capacity.exceeds(batch.records.len() as u64);
+        let (error, timeout_ms) = match (result, capacity.overflow) {
+            (Err(e), OverflowPolicy::Block { timeout_ms }) if !oversized && StorageError::is_queue_full(&e) => (e, timeout_ms),
+            (result, _) => return result,
+        };
+        let deadline = *deadline.get_or_insert_with(|| Instant::now() + Duration::from_millis(timeout_ms));
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // 等待期间没有释放空间，返回最后一次的写满错误
+        if remaining.is_zero() || !space.wait_timeout(generation, remaining) {
+            return Err(error);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! 后台刷盘任务（group commit）
+//!
+//! 每个分区以名称注册并设置刷盘策略；后台线程每隔调度间隔检查所有分区，
+//! 对按时间刷盘到期的分区统一执行 fsync，同一轮中多个分区的刷盘合并完成，
+//! 并记录每个分区的恢复点，每轮结束后按数据目录写入恢复点检查点。
+
+use crate::recovery;
+use crate::task::{self, TaskHandle};
+use crate::LogQueue;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use storage::record::now_ms;
+use storage::FlushPolicy;
+
+/// 已注册的分区，`policy` 为 None 时使用默认策略
+struct FlushTarget {
+    queue: Weak<Mutex<LogQueue>>,
+    policy: Option<FlushPolicy>,
+}
+
+struct FlusherState {
+    default_policy: FlushPolicy,
+    targets: HashMap<String, FlushTarget>,
+}
+
+/// 日志刷盘管理器
+#[derive(Clone)]
+pub struct LogFlusher {
+    state: Arc<Mutex<FlusherState>>,
+}
+
+impl Default for LogFlusher {
+    fn default() -> Self {
+        Self::new(FlushPolicy::default())
+    }
+}
+
+impl LogFlusher {
+    pub fn new(default_policy: FlushPolicy) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FlusherState {
+                default_policy,
+                targets: HashMap::new(),
+            })),
+        }
+    }
+
+    /// 更新 broker 级默认刷盘策略，没有主题级覆盖的分区立即生效
+    pub fn set_default_policy(&self, policy: FlushPolicy) {
+        let mut state = self.state.lock().unwrap();
+        state.default_policy = policy;
+        for target in state.targets.values().filter(|target| target.policy.is_none()) {
+            if let Some(queue) = target.queue.upgrade() {
+                if let Ok(mut queue) = queue.lock() {
+                    queue.set_flush_policy(policy);
+                }
+            }
+        }
+    }
+
+    /// 注册分区并设置其刷盘策略，同名分区会被替换
+    pub fn register(&self, name: &str, queue: &Arc<Mutex<LogQueue>>, policy: Option<FlushPolicy>) {
+        let mut state = self.state.lock().unwrap();
+        if let Ok(mut queue) = queue.lock() {
+            queue.set_flush_policy(policy.unwrap_or(state.default_policy));
+        }
+        state.targets.insert(
+            name.to_string(),
+            FlushTarget {
+                queue: Arc::downgrade(queue),
+                policy,
+            },
+        );
+    }
+
+    /// 取消注册分区
+    pub fn unregister(&self, name: &str) {
+        self.state.lock().unwrap().targets.remove(name);
+    }
+
+    /// 已注册的分区数量
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 对所有到期的分区执行一次刷盘，返回刷盘的分区数，单个分区失败不影响其他分区
+    pub fn run_once(&self) -> usize {
+        let mut flushed = 0;
+        for (name, queue) in self.live_targets() {
+            let mut queue = match queue.lock() {
+                Ok(queue) => queue,
+                Err(e) => {
+                    eprintln!("分区 {} 获取队列锁失败: {}", name, e);
+                    continue;
+                }
+            };
+            if !queue.needs_flush(now_ms()) {
+                continue;
+            }
+            match queue.flush() {
+                Ok(_) => flushed += 1,
+                Err(e) => eprintln!("分区 {} 刷盘失败: {}", name, e),
+            }
+        }
+        flushed
+    }
+
+    /// 每个分区的恢复点：小于该 offset 的记录都已 fsync
+    pub fn recovery_points(&self) -> HashMap<String, u64> {
+        self.live_targets()
+            .into_iter()
+            .filter_map(|(name, queue)| Some((name, queue.lock().ok()?.recovery_point())))
+            .collect()
+    }
+
+    /// 按数据目录（分区目录的上级目录）写入各分区的恢复点检查点
+    pub fn checkpoint_recovery_points(&self) -> io::Result<()> {
+        let mut data_dirs: HashMap<String, HashMap<String, u64>> = HashMap::new();
+        for (name, queue) in self.live_targets() {
+            let queue = queue
+                .lock()
+                .map_err(|e| io::Error::other(format!("分区 {} 获取队列锁失败: {}", name, e)))?;
+            let log_dir = Path::new(queue.log_dir());
+            let (Some(data_dir), Some(partition)) = (log_dir.parent(), log_dir.file_name()) else {
+                continue;
+            };
+            data_dirs
+                .entry(data_dir.to_string_lossy().into_owned())
+                .or_default()
+                .insert(partition.to_string_lossy().into_owned(), queue.recovery_point());
+        }
+        for (data_dir, points) in &data_dirs {
+            recovery::write_recovery_checkpoint(data_dir, points)?;
+        }
+        Ok(())
+    }
+
+    /// 正常关闭：所有分区刷盘，写入最终的恢复点检查点并创建正常关闭标记
+    pub fn shutdown(&self) -> io::Result<()> {
+        let mut data_dirs = Vec::new();
+        for (name, queue) in self.live_targets() {
+            let mut queue = queue
+                .lock()
+                .map_err(|e| io::Error::other(format!("分区 {} 获取队列锁失败: {}", name, e)))?;
+            queue.flush()?;
+            if let Some(data_dir) = Path::new(queue.log_dir()).parent() {
+                data_dirs.push(data_dir.to_string_lossy().into_owned());
+            }
+        }
+        self.checkpoint_recovery_points()?;
+        data_dirs.sort();
+        data_dirs.dedup();
+        for data_dir in &data_dirs {
+            recovery::mark_clean_shutdown(data_dir)?;
+        }
+        Ok(())
+    }
+
+    /// 移除已删除的分区，返回仍然存在的分区
+    fn live_targets(&self) -> Vec<(String, Arc<Mutex<LogQueue>>)> {
+        let mut state = self.state.lock().unwrap();
+        state.targets.retain(|_, target| target.queue.strong_count() > 0);
+        state
+            .targets
+            .iter()
+            .filter_map(|(name, target)| Some((name.clone(), target.queue.upgrade()?)))
+            .collect()
+    }
+
+    /// 启动后台线程，每隔 `scheduler_interval` 检查一次需要刷盘的分区
+    pub fn start(&self, scheduler_interval: Duration) -> io::Result<FlushHandle> {
+        let flusher = self.clone();
+        task::spawn_periodic("log-flusher", scheduler_interval, move || {
+            flusher.run_once();
+            if let Err(e) = flusher.checkpoint_recovery_points() {
+                eprintln!("写入恢复点检查点失败: {}", e);
+            }
+        })
+    }
+}
+
+/// 后台刷盘任务的句柄，`shutdown` 或丢弃时停止线程并等待其退出
+pub type FlushHandle = TaskHandle;
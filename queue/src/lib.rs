@@ -1,3 +1,32 @@
 pub mod queue;
+pub mod retention;
+pub mod flusher;
+pub mod recovery;
+pub mod partition_log;
+pub mod memory;
+pub mod snapshot;
+pub mod reader;
+pub mod tail;
+pub mod stream;
+pub mod capacity;
+mod task;
 
-pub use queue::LogQueue;
\ No newline at end of file
+pub use queue::{LogQueue, MOVING_DIR_SUFFIX};
+pub use reader::{LogReader, OffsetLookup};
+pub use tail::wait_past;
+pub use stream::{LogIter, LogStream};
+pub use capacity::{append_with_backpressure, SpaceNotifier};
+pub use partition_log::PartitionLog;
+pub use memory::MemoryLog;
+pub use snapshot::{restore_snapshot, LogSnapshot};
+pub use retention::{RetentionHandle, RetentionManager};
+pub use flusher::{FlushHandle, LogFlusher};
+pub use task::TaskHandle;
+pub use storage::FlushPolicy;
+pub use storage::{CapacityLimit, OverflowPolicy};
+pub use storage::{RollReason, SegmentConfig};
+pub use storage::{CleanerConfig, CleanupPolicy, FileSlice, LogCleaner, ReadRange};
+pub use storage::{RetentionOverrides, RetentionPolicy};
+pub use storage::{LocalDirRemoteStorage, RemoteStorage};
+pub use storage::{EncryptionKeys, MasterKey};
+pub use storage::StorageError;
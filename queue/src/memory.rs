@@ -0,0 +1,238 @@
+//! 内存分区日志
+//!
+//! 记录批次只保存在内存中，总字节数（按编码后的大小计算）超过上限时从头部淘汰最早的批次并推进日志起始 offset，
+//! 进程退出后数据丢失。适合测试以及不需要持久化的低价值数据（如遥测）。
+
+use crate::capacity::SpaceNotifier;
+use crate::tail::EndOffsetNotifier;
+use crate::PartitionLog;
+use std::collections::VecDeque;
+use std::io;
+use storage::record::now_ms;
+use storage::Result as StorageResult;
+use storage::{CapacityLimit, OverflowPolicy, ReadRange, RecordBatch, StorageError, TimestampType};
+use tokio::sync::watch;
+
+/// 内存分区日志，按字节数上限淘汰旧批次的环形缓冲
+#[derive(Debug)]
+pub struct MemoryLog {
+    batches: VecDeque<(RecordBatch, usize)>, // 批次及其编码后的字节数，按 offset 递增
+    max_bytes: usize,                        // 保留的最大字节数
+    bytes: usize,                            // 当前保存的字节数
+    log_start_offset: u64,                   // 日志起始 offset
+    next_offset: u64,                        // 下一条写入记录的 offset
+    end_offset: EndOffsetNotifier,           // 日志结束 offset 的订阅
+    capacity: CapacityLimit,                 // 分区容量上限与写满后的处理方式
+    space: SpaceNotifier,                    // 空间释放的通知
+}
+
+impl MemoryLog {
+    /// 创建内存日志，保存的批次总字节数超过 `max_bytes` 时淘汰最早的批次（至少保留最新的一个批次）
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            batches: VecDeque::new(),
+            max_bytes,
+            bytes: 0,
+            log_start_offset: 0,
+            next_offset: 0,
+            end_offset: EndOffsetNotifier::default(),
+            capacity: CapacityLimit::default(),
+            space: SpaceNotifier::default(),
+        }
+    }
+
+    /// 当前保存的字节数
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// 当前保存的批次数
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    fn offset_out_of_range(&self, offset: u64) -> StorageError {
+        StorageError::OffsetOutOfRange {
+            offset,
+            log_start_offset: self.log_start_offset,
+            log_end_offset: self.next_offset,
+        }
+    }
+
+    /// 第一个包含不小于 `offset` 的记录的批次下标
+    fn batch_index(&self, offset: u64) -> usize {
+        self.batches.partition_point(|(batch, _)| batch.last_offset() < offset)
+    }
+
+    /// 追加 `incoming` 条记录前按容量上限检查：写满时 DropOldest 丢弃最旧的记录，其他策略返回 `StorageError::QueueFull`
+    fn ensure_capacity(&mut self, incoming: u64) -> io::Result<()> {
+        let bytes = self.bytes as u64;
+        let records = self.next_offset - self.log_start_offset;
+        if self.capacity.exceeds(incoming) {
+            return Err(StorageError::QueueFull { records, bytes }.into());
+        }
+        if !self.capacity.would_overflow(bytes, records, incoming) {
+            return Ok(());
+        }
+        match self.capacity.overflow {
+            OverflowPolicy::DropOldest => {
+                let count = self.capacity.records_to_drop(records, incoming);
+                self.log_start_offset += count;
+                self.remove_batches_before(self.log_start_offset);
+                // 超过字节上限时从头部丢弃整个批次
+                while self.capacity.max_bytes.is_some_and(|max_bytes| self.bytes as u64 >= max_bytes) {
+                    let Some((batch, size)) = self.batches.pop_front() else { break };
+                    self.bytes -= size;
+                    self.log_start_offset = self.log_start_offset.max(batch.next_offset());
+                }
+                Ok(())
+            }
+            OverflowPolicy::Reject | OverflowPolicy::Block { .. } => Err(StorageError::QueueFull { records, bytes }.into()),
+        }
+    }
+
+    /// 从头部删除最后一个 offset 小于 `offset` 的批次
+    fn remove_batches_before(&mut self, offset: u64) {
+        while self.batches.front().is_some_and(|(batch, _)| batch.last_offset() < offset) {
+            if let Some((_, size)) = self.batches.pop_front() {
+                self.bytes -= size;
+            }
+        }
+    }
+}
+
+impl PartitionLog for MemoryLog {
+    fn append_batch(&mut self, batch: &mut RecordBatch) -> io::Result<u64> {
+        if batch.records.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty record batch"));
+        }
+        self.ensure_capacity(batch.records.len() as u64)?;
+        batch.assign_offsets(self.next_offset);
+        if batch.timestamp_type() == TimestampType::LogAppendTime {
+            batch.set_append_time(now_ms());
+        }
+        let size = batch.encode()?.len();
+        self.next_offset = batch.next_offset();
+        self.bytes += size;
+        let mut stored = batch.clone();
+        stored.discard_compressed();
+        self.batches.push_back((stored, size));
+
+        // 超过上限时淘汰最早的批次，日志起始 offset 随之推进
+        while self.bytes > self.max_bytes && self.batches.len() > 1 {
+            if let Some((_, size)) = self.batches.pop_front() {
+                self.bytes -= size;
+            }
+        }
+        if let Some((first, _)) = self.batches.front() {
+            self.log_start_offset = self.log_start_offset.max(first.base_offset);
+        }
+        self.end_offset.notify(self.next_offset);
+        Ok(batch.base_offset)
+    }
+
+    fn read_batch(&self, offset: u64) -> StorageResult<Option<RecordBatch>> {
+        if offset < self.log_start_offset {
+            return Err(self.offset_out_of_range(offset));
+        }
+        Ok(self
+            .batches
+            .get(self.batch_index(offset))
+            .map(|(batch, _)| batch)
+            .filter(|batch| batch.base_offset <= offset)
+            .cloned())
+    }
+
+    fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> StorageResult<ReadRange> {
+        if start_offset < self.log_start_offset {
+            return Err(self.offset_out_of_range(start_offset));
+        }
+        let mut range = ReadRange {
+            records: Vec::new(),
+            next_offset: start_offset,
+            bytes: 0,
+        };
+        for (batch, size) in self.batches.iter().skip(self.batch_index(start_offset)) {
+            if range.records.len() >= max_records || (!range.records.is_empty() && range.bytes + size > max_bytes) {
+                break;
+            }
+            range.bytes += size;
+            for record in batch.records.iter().filter(|record| record.offset >= start_offset) {
+                range.next_offset = record.offset + 1;
+                range.records.push(record.clone());
+                if range.records.len() >= max_records {
+                    break;
+                }
+            }
+        }
+        Ok(range)
+    }
+
+    fn truncate_to(&mut self, offset: u64) -> StorageResult<()> {
+        if offset < self.log_start_offset {
+            return Err(self.offset_out_of_range(offset));
+        }
+        if offset >= self.next_offset {
+            return Ok(());
+        }
+        while self.batches.back().is_some_and(|(batch, _)| batch.base_offset >= offset) {
+            if let Some((_, size)) = self.batches.pop_back() {
+                self.bytes -= size;
+            }
+        }
+        // 截断点落在批次中间时保留该批次中 offset 更小的记录
+        if let Some((batch, size)) = self.batches.back_mut() {
+            if batch.last_offset() >= offset {
+                batch.records.retain(|record| record.offset < offset);
+                self.bytes -= *size;
+                *size = batch.encode()?.len();
+                self.bytes += *size;
+            }
+        }
+        self.next_offset = offset;
+        self.end_offset.notify(self.next_offset);
+        self.space.notify();
+        Ok(())
+    }
+
+    fn delete_records_before(&mut self, offset: u64) -> StorageResult<u64> {
+        if offset > self.next_offset {
+            return Err(self.offset_out_of_range(offset));
+        }
+        if offset > self.log_start_offset {
+            self.log_start_offset = offset;
+            self.remove_batches_before(offset);
+            self.space.notify();
+        }
+        Ok(self.log_start_offset)
+    }
+
+    fn log_start_offset(&self) -> u64 {
+        self.log_start_offset
+    }
+
+    fn log_end_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    /// 内存日志没有需要持久化的数据
+    fn flush(&mut self) -> io::Result<u64> {
+        Ok(self.next_offset)
+    }
+
+    fn subscribe(&self) -> watch::Receiver<u64> {
+        self.end_offset.subscribe()
+    }
+
+    fn set_capacity(&mut self, capacity: CapacityLimit) {
+        self.capacity = capacity;
+    }
+
+    fn capacity(&self) -> CapacityLimit {
+        self.capacity
+    }
+
+    fn space_notifier(&self) -> SpaceNotifier {
+        self.space.clone()
+    }
+}
@@ -0,0 +1,130 @@
+//! 分区日志抽象
+//!
+//! `PartitionLog` 覆盖分区日志的追加、读取、截断、offset 与刷盘。`LogQueue` 是基于日志段文件的实现，
+//! `MemoryLog` 是只保存在内存中的环形缓冲实现，用于测试和不需要持久化的主题。
+
+use crate::capacity::SpaceNotifier;
+use crate::LogQueue;
+use std::fmt;
+use std::io;
+use tokio::sync::watch;
+use storage::Result as StorageResult;
+use storage::{CapacityLimit, ReadRange, Record, RecordBatch};
+
+/// 分区日志
+pub trait PartitionLog: Send + fmt::Debug {
+    /// 追加记录批次，返回批次的 base_offset，批次中的记录 offset 会被重新分配
+    fn append_batch(&mut self, batch: &mut RecordBatch) -> io::Result<u64>;
+
+    /// 读取包含指定 offset 的记录批次，offset 早于日志起始 offset 时返回 `StorageError::OffsetOutOfRange`
+    fn read_batch(&self, offset: u64) -> StorageResult<Option<RecordBatch>>;
+
+    /// 从 `start_offset` 开始读取连续记录，批次字节数累计不超过 `max_bytes`（至少返回一个批次），
+    /// 记录数不超过 `max_records`
+    fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> StorageResult<ReadRange>;
+
+    /// 截断日志，删除 offset 不小于 `offset` 的全部记录
+    fn truncate_to(&mut self, offset: u64) -> StorageResult<()>;
+
+    /// 把日志起始 offset 推进到 `offset`，返回新的日志起始 offset
+    fn delete_records_before(&mut self, offset: u64) -> StorageResult<u64>;
+
+    /// 日志起始 offset
+    fn log_start_offset(&self) -> u64;
+
+    /// 下一条写入记录的 offset
+    fn log_end_offset(&self) -> u64;
+
+    /// 把已写入的记录持久化，返回新的恢复点
+    fn flush(&mut self) -> io::Result<u64>;
+
+    /// 订阅日志结束 offset，配合 [`crate::wait_past`] 在新记录写入时被唤醒
+    fn subscribe(&self) -> watch::Receiver<u64>;
+
+    /// 设置分区容量上限，之后的追加按上限检查
+    fn set_capacity(&mut self, capacity: CapacityLimit);
+
+    /// 分区容量上限
+    fn capacity(&self) -> CapacityLimit;
+
+    /// 空间释放的通知，配合 [`crate::append_with_backpressure`] 实现 Block 策略
+    fn space_notifier(&self) -> SpaceNotifier;
+
+    /// 追加一条只有内容的消息
+    fn append_message(&mut self, message: &[u8]) -> io::Result<u64> {
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(message.to_vec()))]);
+        self.append_batch(&mut batch)
+    }
+
+    /// 读取指定 offset 的记录
+    fn read_record(&self, offset: u64) -> StorageResult<Option<Record>> {
+        Ok(self.read_batch(offset)?.and_then(|batch| batch.record(offset).cloned()))
+    }
+
+    /// 读取指定 offset 的消息内容，offset 不存在或为墓碑记录（value 为空）时返回 None
+    fn read_message(&self, offset: u64) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.read_record(offset)?.and_then(|record| record.value))
+    }
+}
+
+impl PartitionLog for LogQueue {
+    fn append_batch(&mut self, batch: &mut RecordBatch) -> io::Result<u64> {
+        LogQueue::append_batch(self, batch)
+    }
+
+    fn read_batch(&self, offset: u64) -> StorageResult<Option<RecordBatch>> {
+        LogQueue::read_batch(self, offset)
+    }
+
+    fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> StorageResult<ReadRange> {
+        LogQueue::read_range(self, start_offset, max_bytes, max_records)
+    }
+
+    fn truncate_to(&mut self, offset: u64) -> StorageResult<()> {
+        LogQueue::truncate_to(self, offset)
+    }
+
+    fn delete_records_before(&mut self, offset: u64) -> StorageResult<u64> {
+        LogQueue::delete_records_before(self, offset)
+    }
+
+    fn log_start_offset(&self) -> u64 {
+        LogQueue::log_start_offset(self)
+    }
+
+    fn log_end_offset(&self) -> u64 {
+        LogQueue::log_end_offset(self)
+    }
+
+    fn flush(&mut self) -> io::Result<u64> {
+        LogQueue::flush(self)
+    }
+
+    fn subscribe(&self) -> watch::Receiver<u64> {
+        LogQueue::subscribe(self)
+    }
+
+    fn set_capacity(&mut self, capacity: CapacityLimit) {
+        LogQueue::set_capacity(self, capacity)
+    }
+
+    fn capacity(&self) -> CapacityLimit {
+        LogQueue::capacity(self)
+    }
+
+    fn space_notifier(&self) -> SpaceNotifier {
+        LogQueue::space_notifier(self)
+    }
+
+    fn append_message(&mut self, message: &[u8]) -> io::Result<u64> {
+        LogQueue::append_message(self, message)
+    }
+
+    fn read_record(&self, offset: u64) -> StorageResult<Option<Record>> {
+        LogQueue::read_record(self, offset)
+    }
+
+    fn read_message(&self, offset: u64) -> StorageResult<Option<Vec<u8>>> {
+        LogQueue::read_message(self, offset)
+    }
+}
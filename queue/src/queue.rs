@@ -1,41 +1,290 @@
-use std::collections::BTreeMap;
 use std::collections::VecDeque;
+use std::future::Future;
+use std::fs;
 use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use storage::cleaner;
+use storage::FileSlice;
+use storage::FlushPolicy;
+use storage::{CapacityLimit, OverflowPolicy};
+use storage::LogCleaner;
 use storage::IoResult;
 use storage::LogSegment;
+use storage::ReadRange;
+use storage::{RemoteLog, RemoteStorage};
+use storage::RetentionPolicy;
+use storage::SegmentConfig;
+use storage::SegmentReader;
+use storage::Result as StorageResult;
+use storage::StorageError;
+use storage::record::now_ms;
+use storage::{Record, RecordBatch};
 use storage::LOG_FILE_SUFFIX;
+use storage::{INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX};
+use crate::capacity::SpaceNotifier;
+use crate::reader::{LogReader, OffsetLookup};
+use crate::stream::{LogIter, LogStream, READ_CHUNK_BYTES};
+use crate::snapshot::{self, LogSnapshot};
+use tokio::sync::watch;
+
+/// 持久化的日志起始 offset 文件名
+pub const LOG_START_OFFSET_FILE: &str = "log-start-offset";
+/// 迁移中的分区目录后缀，复制完成后 rename 为目标目录
+pub const MOVING_DIR_SUFFIX: &str = ".moving";
+
+/// 打开队列时已有日志段的恢复方式
+#[derive(Debug, Clone, Copy)]
+enum SegmentRecovery {
+    /// 校验索引，只恢复每个段最后一个索引项之后的数据
+    Tail,
+    /// 按数据目录的恢复点检查点和正常关闭标记决定哪些段可以信任
+    Checkpoint { recovery_point: u64, clean_shutdown: bool },
+}
 
 #[derive(Debug)]
 pub struct LogQueue {
-    segments: VecDeque<LogSegment>, // 存储多个日志段  //后续考虑优化，是否会存在并发访问的情况？
+    segments: VecDeque<LogSegment>, // 存储多个日志段，最后一个为活跃的写入段
     log_dir: String,                // 日志存储路径
-    max_segment_size: usize,        // 每个日志段的最大大小
-    //max_queue_size: usize,          // 队列的最大大小
-    active_write_segment_index: usize,   // 当前活跃的写入 segment
-    active_read_segment_index: usize,    // 当前活跃的读取 segment
-    segment_index: BTreeMap<u64, usize>, // 存储每个base_offset -> segment_index
+    config: SegmentConfig,          // 日志段配置（段大小、索引间隔等）
+    reader: LogReader,                   // 发布给并发读取方的段表
+    capacity: CapacityLimit,             // 分区容量上限与写满后的处理方式
+    space: SpaceNotifier,                // 空间释放的通知，唤醒等待写入的生产者
+    remote: Option<RemoteLog>,           // 分层存储，启用后已关闭的段上传到远程
+    log_start_offset: u64,               // 通过 delete_records_before 推进的日志起始 offset
+    flush_policy: FlushPolicy,           // 刷盘策略
+    recovery_point: u64,                 // 小于该 offset 的记录都已 fsync 到磁盘
+    unflushed_messages: u64,             // 上次刷盘后写入的消息数
+    last_flush_ms: i64,                  // 上次刷盘的时间
 }
 
 impl LogQueue {
     pub fn new(
         log_dir: &str,
-        max_segment_size: usize, /*, max_queue_size:usize*/
+        max_segment_size: usize,
+    ) -> io::Result<Self> {
+        let config = SegmentConfig {
+            max_segment_size,
+            ..SegmentConfig::default()
+        };
+        Self::with_config(log_dir, config)
+    }
+
+    /// 使用指定的日志段配置创建队列，已有的日志段只校验索引并恢复最后一个索引项之后的数据
+    pub fn with_config(log_dir: &str, config: SegmentConfig) -> io::Result<Self> {
+        Self::open(log_dir, config, SegmentRecovery::Tail)
+    }
+
+    /// 按恢复点打开队列
+    ///
+    /// 正常关闭（`clean_shutdown`）时信任所有日志段，不做校验；否则只有全部数据都在 `recovery_point`
+    /// 之前的日志段被信任，之后的段逐个修复（从头校验、截断损坏的尾部、重建索引）并 fsync
+    pub fn open_with_recovery(
+        log_dir: &str,
+        config: SegmentConfig,
+        recovery_point: u64,
+        clean_shutdown: bool,
     ) -> io::Result<Self> {
+        Self::open(log_dir, config, SegmentRecovery::Checkpoint { recovery_point, clean_shutdown })
+    }
+
+    fn open(log_dir: &str, config: SegmentConfig, recovery: SegmentRecovery) -> io::Result<Self> {
         let mut queue = Self {
             segments: VecDeque::new(),
             log_dir: log_dir.to_string(),
-            max_segment_size,
-            // /max_queue_size,
-            active_write_segment_index: 0,
-            active_read_segment_index: 0,
-            segment_index: BTreeMap::new(),
+            config,
+            reader: LogReader::default(),
+            capacity: CapacityLimit::default(),
+            space: SpaceNotifier::default(),
+            remote: None,
+            log_start_offset: 0,
+            flush_policy: FlushPolicy::default(),
+            recovery_point: 0,
+            unflushed_messages: 0,
+            last_flush_ms: now_ms(),
         };
-        queue.load_segments()?;
+        queue.load_segments(recovery)?;
+        // 恢复点之后的段在加载时已经 fsync，尾部恢复时沿用原来的做法，视为已落盘
+        queue.recovery_point = queue.log_end_offset();
         Ok(queue)
     }
 
-    /// 加载已有的日志段
-    fn load_segments(&mut self) -> io::Result<()> {
+    /// 启用分层存储，`prefix` 为该分区在远程存储中的 key 前缀
+    pub fn set_remote_storage(&mut self, storage: Arc<dyn RemoteStorage>, prefix: &str) -> io::Result<()> {
+        self.remote = Some(RemoteLog::open(&self.log_dir, prefix, storage, self.config.clone())?);
+        Ok(())
+    }
+
+    /// 日志目录
+    pub fn log_dir(&self) -> &str {
+        &self.log_dir
+    }
+
+    /// 把分区日志迁移到 `new_dir`：刷盘后复制全部文件并在新目录重新打开，成功后删除旧目录
+    ///
+    /// 文件先复制到 `{new_dir}.moving`，全部 fsync 后 rename 为 `new_dir`，中途失败时旧目录不受影响
+    pub fn move_to(&mut self, new_dir: &str) -> io::Result<()> {
+        let new_dir = new_dir.trim_end_matches('/');
+        if Path::new(new_dir).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("目标目录 {} 已存在", new_dir),
+            ));
+        }
+        self.flush()?;
+
+        let moving_dir = format!("{}{}", new_dir, MOVING_DIR_SUFFIX);
+        if Path::new(&moving_dir).exists() {
+            fs::remove_dir_all(&moving_dir)?;
+        }
+        // 远程段缓存与压缩临时目录不复制
+        snapshot::copy_dir_files(&self.log_dir, &moving_dir)?;
+        fs::rename(&moving_dir, new_dir)?;
+
+        // 已经刷盘，新目录中的日志段都可以信任
+        let mut moved = Self::open_with_recovery(new_dir, self.config.clone(), self.log_end_offset(), true)?;
+        if let Some(remote) = &self.remote {
+            moved.set_remote_storage(remote.storage().clone(), remote.prefix())?;
+        }
+        moved.flush_policy = self.flush_policy;
+        moved.capacity = self.capacity;
+        // 已有的 LogReader 与等待空间的写入方继续有效，改为读取新目录中的日志段
+        moved.reader = self.reader.clone();
+        moved.space = self.space.clone();
+        moved.publish_segments();
+        let old = std::mem::replace(self, moved);
+        let old_dir = old.log_dir.clone();
+        drop(old);
+        fs::remove_dir_all(old_dir)
+    }
+
+    /// 把 offset 小于 `up_to`（默认为日志结束 offset）的记录写入快照目录 `dest_dir`
+    ///
+    /// 刷盘后复制本地日志段，包含 `up_to` 的日志段在快照中截断；已经转移到远程存储的日志段不包含在快照中，
+    /// 快照的日志起始 offset 不早于第一个本地日志段
+    pub fn snapshot(&mut self, dest_dir: &str, up_to: Option<u64>) -> io::Result<LogSnapshot> {
+        let dest_dir = dest_dir.trim_end_matches('/');
+        let end_offset = up_to.map_or(self.log_end_offset(), |offset| offset.min(self.log_end_offset()));
+        if end_offset < self.log_start_offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("快照结束 offset {} 早于日志起始 offset {}", end_offset, self.log_start_offset),
+            ));
+        }
+        if Path::new(dest_dir).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("快照目录 {} 已存在", dest_dir),
+            ));
+        }
+        self.flush()?;
+        fs::create_dir_all(dest_dir)?;
+
+        let mut segments = 0;
+        let mut log_start_offset = self.log_start_offset;
+        for segment in self.segments.iter() {
+            let base_offset = segment.get_base_offset();
+            // 完全早于日志起始 offset 的段等待删除，不需要复制
+            if segment.get_next_offset() <= self.log_start_offset && base_offset < end_offset {
+                continue;
+            }
+            if base_offset >= end_offset {
+                break;
+            }
+            if segments == 0 {
+                log_start_offset = log_start_offset.max(base_offset);
+            }
+            for suffix in [LOG_FILE_SUFFIX, INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX] {
+                let source = segment.file_path(suffix);
+                fs::copy(&source, Path::new(dest_dir).join(source.file_name().unwrap()))?;
+            }
+            if segment.get_next_offset() > end_offset {
+                let mut copy = LogSegment::open_clean(dest_dir, base_offset, self.config.clone())?;
+                copy.truncate_to(end_offset)?;
+                copy.seal()?;
+            }
+            segments += 1;
+        }
+        // 快照中没有日志段时创建一个空段，恢复后从 end_offset 继续写入
+        if segments == 0 {
+            log_start_offset = end_offset;
+            LogSegment::with_config(dest_dir, end_offset, self.config.clone())?.seal()?;
+        }
+        fs::write(Path::new(dest_dir).join(LOG_START_OFFSET_FILE), log_start_offset.to_string())?;
+        for entry in fs::read_dir(dest_dir)? {
+            fs::File::open(entry?.path())?.sync_all()?;
+        }
+        Ok(LogSnapshot { log_start_offset, end_offset, segments })
+    }
+
+    /// 分区日志的只读句柄，可以在不持有 `LogQueue` 的情况下与追加并发读取本地日志段
+    pub fn reader(&self) -> LogReader {
+        self.reader.clone()
+    }
+
+    /// 订阅日志结束 offset，追加记录（或截断日志）后接收方收到新的值
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.reader.subscribe()
+    }
+
+    /// 返回在日志结束 offset 超过 `offset`（即 `offset` 处的记录已写入）时完成的 future，
+    /// 完成时给出当时的日志结束 offset；future 不借用 `LogQueue`，可以在释放锁之后等待
+    pub fn wait_past(&self, offset: u64) -> impl Future<Output = u64> + Send + 'static {
+        self.reader.wait_past(offset)
+    }
+
+    pub fn has_remote_storage(&self) -> bool {
+        self.remote.is_some()
+    }
+
+    /// 修复所有本地日志段，返回截断的字节数
+    pub fn repair(&mut self) -> io::Result<u64> {
+        let mut truncated = 0;
+        for segment in self.segments.iter_mut() {
+            truncated += segment.repair()?;
+        }
+        self.recovery_point = self.recovery_point.min(self.log_end_offset());
+        Ok(truncated)
+    }
+
+    /// 本地最早的 offset，更早的记录只存在于远程
+    pub fn local_log_start_offset(&self) -> u64 {
+        self.segments.front().map(|s| s.get_base_offset()).unwrap_or(0)
+    }
+
+    /// 日志起始 offset，早于它的读取返回 `StorageError::OffsetOutOfRange`
+    ///
+    /// 由 `delete_records_before` 与保留策略推进并持久化，日志压缩删除的空段不影响起始 offset
+    pub fn log_start_offset(&self) -> u64 {
+        self.log_start_offset
+    }
+
+    /// 下一条写入记录的 offset
+    pub fn log_end_offset(&self) -> u64 {
+        self.get_next_base_offset()
+    }
+
+    fn offset_out_of_range(&self, offset: u64) -> StorageError {
+        StorageError::OffsetOutOfRange {
+            offset,
+            log_start_offset: self.log_start_offset(),
+            log_end_offset: self.log_end_offset(),
+        }
+    }
+
+    /// 读取前检查 offset 不早于日志起始 offset
+    fn check_log_start(&self, offset: u64) -> StorageResult<()> {
+        if offset < self.log_start_offset() {
+            return Err(self.offset_out_of_range(offset));
+        }
+        Ok(())
+    }
+
+    /// 加载已有的日志段，按 `recovery` 决定每个段的恢复方式
+    fn load_segments(&mut self, recovery: SegmentRecovery) -> io::Result<()> {
+        // 上次压缩中途退出时遗留的新段文件直接丢弃，原段文件始终完整
+        cleaner::remove_cleaned_dir(&self.log_dir)?;
+
         // 获取目录下所有日志段
         let mut segment_offsets: Vec<u64> = if std::path::Path::new(&self.log_dir).exists() {
             std::fs::read_dir(&self.log_dir)?
@@ -56,85 +305,510 @@ impl LogQueue {
 
         segment_offsets.sort();
 
+        let log_start_path = format!("{}/{}", self.log_dir, LOG_START_OFFSET_FILE);
+        if std::path::Path::new(&log_start_path).exists() {
+            self.log_start_offset = std::fs::read_to_string(&log_start_path)?
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("日志起始 offset 文件格式错误: {}", e)))?;
+        }
+
         // 加载所有日志段
         for (index, offset) in segment_offsets.iter().enumerate() {
-            let segment = LogSegment::new(&self.log_dir, *offset, self.max_segment_size)?;
+            let segment = match recovery {
+                SegmentRecovery::Tail => LogSegment::with_config(&self.log_dir, *offset, self.config.clone())?,
+                SegmentRecovery::Checkpoint { recovery_point, clean_shutdown } => {
+                    // 下一个段的起始 offset 不超过恢复点时，该段的全部数据都已刷盘
+                    let trusted = clean_shutdown || segment_offsets.get(index + 1).is_some_and(|&next| next <= recovery_point);
+                    if trusted {
+                        LogSegment::open_clean(&self.log_dir, *offset, self.config.clone())?
+                    } else {
+                        let segment = LogSegment::open_repaired(&self.log_dir, *offset, self.config.clone())?;
+                        segment.flush()?;
+                        segment
+                    }
+                }
+            };
             self.segments.push_back(segment);
-            self.segment_index.insert(*offset, index);
         }
 
         // 如果没有找到任何日志段，创建一个新的
         if self.segments.is_empty() {
             let new_offset = 0;
-            let new_segment = LogSegment::new(&self.log_dir, new_offset, self.max_segment_size)?;
+            let new_segment = LogSegment::with_config(&self.log_dir, new_offset, self.config.clone())?;
             self.segments.push_back(new_segment);
-            self.segment_index.insert(0, 0);
         }
-
-        // 写入 segment 指向最后一个
-        self.active_write_segment_index = self.segments.len() - 1;
-        // 读取 segment 默认从第一个开始
-        self.active_read_segment_index = 0;
+        self.publish_segments();
 
         Ok(())
     }
 
     /// 追加消息，自动选择合适的日志段
     pub fn append_message(&mut self, message: &[u8]) -> io::Result<u64> {
-        if let Some(segment) = self.segments.get_mut(self.active_write_segment_index) {
-            match segment.append_message(message) {
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(message.to_vec()))]);
+        self.append_batch(&mut batch)
+    }
+
+    /// 追加记录批次，返回批次的 base_offset，批次中的记录 offset 会被重新分配
+    ///
+    /// 刷盘策略为 `EveryWrite` 时返回前记录已 fsync，`EveryMessages` 达到消息数时同样在返回前刷盘
+    pub fn append_batch(&mut self, batch: &mut RecordBatch) -> io::Result<u64> {
+        self.ensure_capacity(batch.records.len() as u64)?;
+        let offset = self.append_to_segments(batch)?;
+        self.reader.notify_log_end_offset(self.log_end_offset());
+        self.unflushed_messages += batch.records.len() as u64;
+        // 追加路径只按消息数判断，按时间刷盘由后台刷盘任务执行
+        if self.flush_policy.should_flush(self.unflushed_messages, 0) {
+            self.flush()?;
+        }
+        Ok(offset)
+    }
+
+    /// 设置分区容量上限
+    ///
+    /// 记录数按 [log_start_offset, log_end_offset) 计算；字节数按包含未删除记录的日志段计算，
+    /// DropOldest 按日志段整体丢弃，活跃段不会被丢弃，因此段大小应小于字节上限
+    pub fn set_capacity(&mut self, capacity: CapacityLimit) {
+        self.capacity = capacity;
+    }
+
+    pub fn capacity(&self) -> CapacityLimit {
+        self.capacity
+    }
+
+    /// 空间释放的通知，Block 策略的写入方在释放锁之后等待
+    pub fn space_notifier(&self) -> SpaceNotifier {
+        self.space.clone()
+    }
+
+    /// 包含未删除记录的本地日志段的字节数，全部记录都早于日志起始 offset 的活跃段不计入
+    fn retained_bytes(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter(|segment| segment.get_next_offset() > self.log_start_offset)
+            .map(|segment| segment.get_size() as u64)
+            .sum()
+    }
+
+    /// 追加 `incoming` 条记录前按容量上限检查：写满时 DropOldest 丢弃最旧的记录，其他策略返回 `StorageError::QueueFull`
+    fn ensure_capacity(&mut self, incoming: u64) -> io::Result<()> {
+        let bytes = self.retained_bytes();
+        let records = self.log_end_offset() - self.log_start_offset;
+        if self.capacity.exceeds(incoming) {
+            return Err(StorageError::QueueFull { records, bytes }.into());
+        }
+        if !self.capacity.would_overflow(bytes, records, incoming) {
+            return Ok(());
+        }
+        match self.capacity.overflow {
+            OverflowPolicy::DropOldest => self.drop_oldest(records, incoming),
+            OverflowPolicy::Reject | OverflowPolicy::Block { .. } => Err(StorageError::QueueFull { records, bytes }.into()),
+        }
+    }
+
+    /// 推进日志起始 offset 丢弃最旧的记录，使写入 `incoming` 条记录后不超过容量上限
+    fn drop_oldest(&mut self, records: u64, incoming: u64) -> io::Result<()> {
+        let count = self.capacity.records_to_drop(records, incoming);
+        if count > 0 {
+            self.delete_records_before(self.log_start_offset + count)?;
+        }
+        // 超过字节上限时整体丢弃最旧的已关闭段
+        while self.capacity.max_bytes.is_some_and(|max_bytes| self.retained_bytes() >= max_bytes) {
+            let closed = self.segments.len() - 1;
+            let Some(next_offset) = self
+                .segments
+                .iter()
+                .take(closed)
+                .map(LogSegment::get_next_offset)
+                .find(|&next_offset| next_offset > self.log_start_offset)
+            else {
+                break;
+            };
+            self.delete_records_before(next_offset)?;
+        }
+        Ok(())
+    }
+
+    fn append_to_segments(&mut self, batch: &mut RecordBatch) -> io::Result<u64> {
+        if let Some(segment) = self.segments.back_mut() {
+            match segment.append_batch(batch) {
                 Ok(IoResult::Success(offset)) => return Ok(offset),
-                Ok(IoResult::SegmentFull) => { /*当前日志段已满，不做任何处理，后续处理段和消息写入*/
+                Ok(IoResult::SegmentFull(_)) => { /*当前日志段已满，不做任何处理，后续处理段和消息写入*/
                 }
                 Err(e) => return Err(e),
             }
         }
         // 如果当前日志段已满，则创建新段
         let new_offset = self.get_next_base_offset();
-        let mut new_segment = LogSegment::new(&self.log_dir, new_offset, self.max_segment_size)?;
-        let result = new_segment.append_message(message)?;
+        let mut new_segment = LogSegment::with_config(&self.log_dir, new_offset, self.config.clone())?;
+        let result = new_segment.append_batch(batch)?;
         self.segments.push_back(new_segment);
-        let index = self.segments.len() - 1;
-        self.active_write_segment_index = index;
-        self.segment_index.insert(new_offset, index);
+        self.publish_segments();
         Ok(match result {
             IoResult::Success(offset) => offset,
             //抛出IO异常
-            IoResult::SegmentFull => panic!("SegmentFull should not happen here"),
+            IoResult::SegmentFull(_) => panic!("SegmentFull should not happen here"),
         })
     }
 
-    /// 读取指定 offset 的消息 ，首次读取使用索引快速查找log segment,后续则通过active_read_segment_index查找读取
-    pub fn read_message(&mut self, offset: u64) -> io::Result<Option<Vec<u8>>> {
-        if self.active_read_segment_index == 0 {
-            // 1. **使用索引快速查找 segment**
-            if let Some((_, &index)) = self.segment_index.range(..=offset).next_back() {
-                if let Some(segment) = self.segments.get_mut(index) {
-                    self.active_read_segment_index = index;
-                    return segment.read_message(offset);
-                }
+    /// 设置刷盘策略
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flush_policy = policy;
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    /// 恢复点：小于该 offset 的记录都已 fsync，内核崩溃后仍然存在
+    pub fn recovery_point(&self) -> u64 {
+        self.recovery_point
+    }
+
+    /// 按刷盘策略判断距上次刷盘 `now` 时是否需要刷盘
+    pub fn needs_flush(&self, now: i64) -> bool {
+        self.flush_policy.should_flush(self.unflushed_messages, now - self.last_flush_ms)
+    }
+
+    /// fsync 恢复点之后的所有日志段，返回新的恢复点
+    pub fn flush(&mut self) -> io::Result<u64> {
+        let log_end_offset = self.log_end_offset();
+        if self.recovery_point < log_end_offset {
+            for segment in self.segments.iter().filter(|s| s.get_next_offset() > self.recovery_point) {
+                segment.flush()?;
             }
+            self.recovery_point = log_end_offset;
+        }
+        self.unflushed_messages = 0;
+        self.last_flush_ms = now_ms();
+        Ok(self.recovery_point)
+    }
+
+    /// 查找指定 offset 的记录，区分早于日志起始 offset、尚未写入、已被压缩删除与找到，
+    /// 早于本地起始 offset 的记录从远程段读取
+    pub fn lookup(&self, offset: u64) -> StorageResult<OffsetLookup> {
+        if offset < self.log_start_offset || offset >= self.local_log_start_offset() {
+            return self.reader.lookup(offset);
         }
-        // **从当前的 active_read_segment 开始读**
-        if let Some(segment) = self.segments.get_mut(self.active_read_segment_index) {
-            if let Some(message) = segment.read_message(offset)? {
-                return Ok(Some(message));
+        let record = match &self.remote {
+            Some(remote) => remote.segment_for(offset)?.map(|segment| segment.read_record(offset)).transpose()?.flatten(),
+            None => None,
+        };
+        Ok(record.map_or(OffsetLookup::Removed, OffsetLookup::Found))
+    }
+
+    /// 读取指定 offset 的消息内容，墓碑记录返回 None
+    pub fn read_message(&self, offset: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.read_from_segments(offset, |segment, offset| segment.read_message(offset), LogReader::read_message)
+    }
+
+    /// 读取指定 offset 的记录
+    pub fn read_record(&self, offset: u64) -> StorageResult<Option<Record>> {
+        self.read_from_segments(offset, |segment, offset| segment.read_record(offset), LogReader::read_record)
+    }
+
+    /// 读取包含指定 offset 的记录批次
+    pub fn read_batch(&self, offset: u64) -> StorageResult<Option<RecordBatch>> {
+        self.read_from_segments(offset, |segment, offset| segment.read_batch(offset), LogReader::read_batch)
+    }
+
+    /// 从 `start_offset` 开始读取连续记录，跨日志段边界时继续读取下一个段，
+    /// 批次字节数累计不超过 `max_bytes`，记录数不超过 `max_records`，返回结果中带有下一次读取的 offset
+    pub fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> StorageResult<ReadRange> {
+        self.check_log_start(start_offset)?;
+        // 早于本地起始 offset 时只读取对应的远程段，后续段由下一次读取继续
+        if start_offset < self.local_log_start_offset() {
+            if let Some(segment) = self.remote.as_ref().map(|remote| remote.segment_for(start_offset)).transpose()?.flatten() {
+                return segment.read_range(start_offset, max_bytes, max_records);
             }
         }
-        // **如果 offset 超出了当前的 active_read_segment，向前查找**
-        for (index, segment) in self
+        self.reader.read_range(start_offset, max_bytes, max_records)
+    }
+
+    /// 从 `offset` 开始遍历到日志末尾的迭代器，产出 `(offset, record)`，包括只存在于远程的记录
+    ///
+    /// 被压缩删除的 offset 自动跳过；offset 早于日志起始 offset 时产出 `StorageError::OffsetOutOfRange` 后结束
+    pub fn iter_from(&self, offset: u64) -> LogIter<'_> {
+        LogIter::new(offset, move |offset| self.read_range(offset, READ_CHUNK_BYTES, usize::MAX))
+    }
+
+    /// 从 `offset` 开始跟随日志尾部的记录流，读到末尾后等待新记录写入
+    ///
+    /// 记录流通过 `LogReader` 读取，不借用 `LogQueue`，只包含本地日志段中的记录
+    pub fn stream_from(&self, offset: u64) -> LogStream {
+        self.reader.stream_from(offset)
+    }
+
+    /// 定位从 `start_offset` 开始的连续批次在日志文件中的区域，用于零拷贝发送
+    ///
+    /// 区域不会跨越日志段，读完一个区域后使用 `FileSlice::next_offset` 继续读取
+    pub fn read_slice(&self, start_offset: u64, max_bytes: usize) -> StorageResult<Option<FileSlice>> {
+        self.reader.read_slice(start_offset, max_bytes)
+    }
+
+    /// 查找时间戳不小于 `timestamp` 的第一条记录的 offset，不存在时返回 None
+    ///
+    /// 按 base_offset 顺序遍历日志段，跳过最大时间戳小于目标的段，再通过段内时间索引定位；
+    /// 结果不会早于日志起始 offset
+    pub fn offset_for_timestamp(&self, timestamp: i64) -> StorageResult<Option<u64>> {
+        self.reader.offset_for_timestamp(timestamp)
+    }
+
+    /// 读取 offset 所在的日志段：早于本地起始 offset 的记录从远程段读取，其余通过 `LogReader` 读取本地段
+    fn read_from_segments<T>(
+        &self,
+        offset: u64,
+        read_remote: impl FnOnce(&SegmentReader, u64) -> StorageResult<Option<T>>,
+        read_local: impl FnOnce(&LogReader, u64) -> StorageResult<Option<T>>,
+    ) -> StorageResult<Option<T>> {
+        self.check_log_start(offset)?;
+        // 早于本地起始 offset 的记录从远程读取
+        if offset < self.local_log_start_offset() {
+            return match &self.remote {
+                Some(remote) => match remote.segment_for(offset)? {
+                    Some(segment) => read_remote(&segment, offset),
+                    None => Ok(None),
+                },
+                None => Ok(None),
+            };
+        }
+        read_local(&self.reader, offset)
+    }
+
+    /// 按 key 压缩已关闭的日志段（cleanup.policy=compact），返回删除的记录数
+    ///
+    /// 每个 key 的最新 offset 包含活跃段中的记录，但活跃段本身不会被改写。
+    /// 所有段先写入临时目录，全部成功后再逐个替换文件并原地替换 `segments`，最后发布新的段表
+    pub fn compact(&mut self, cleaner: &LogCleaner) -> StorageResult<usize> {
+        let closed = self.segments.len().saturating_sub(1);
+        if closed == 0 {
+            return Ok(0);
+        }
+        let offset_map = cleaner.build_offset_map(self.segments.iter())?;
+        let cleaned = self
             .segments
-            .iter_mut()
-            .enumerate()
-            // index是从0开始的，所以skip要加1。避免重复读不到消息浪费IO
-            .skip(self.active_read_segment_index + 1)
-        {
-            if let Some(message) = segment.read_message(offset)? {
-                self.active_read_segment_index = index; // 更新 active_read_segment
-                return Ok(Some(message));
+            .iter()
+            .take(closed)
+            .map(|segment| cleaner.clean_segment(segment, &offset_map, &self.log_dir, self.config.clone()))
+            .collect::<StorageResult<Vec<_>>>();
+        let cleaned = match cleaned {
+            Ok(cleaned) => cleaned,
+            Err(e) => {
+                cleaner::remove_cleaned_dir(&self.log_dir)?;
+                return Err(e);
+            }
+        };
+
+        for (index, result) in cleaned.iter().enumerate() {
+            if result.records_removed == 0 {
+                continue;
+            }
+            let retained = result.records_retained > 0;
+            cleaner::swap_cleaned_segment(&self.log_dir, result.base_offset, retained)?;
+            if retained {
+                self.segments[index] = LogSegment::with_config(&self.log_dir, result.base_offset, self.config.clone())?;
+            }
+        }
+        cleaner::remove_cleaned_dir(&self.log_dir)?;
+
+        // 移除压缩后为空的段并发布新的段表
+        let mut index = 0;
+        self.segments.retain(|_| {
+            let keep = cleaned.get(index).is_none_or(|result| result.records_retained > 0);
+            index += 1;
+            keep
+        });
+        self.publish_segments();
+
+        Ok(cleaned.iter().map(|result| result.records_removed).sum())
+    }
+
+    /// 按保留策略删除最旧的已关闭日志段，活跃段永远不会被删除，返回删除的段数
+    ///
+    /// 启用分层存储时保留策略作用于整个分区：只存在于远程的段排在本地段之前参与判断，
+    /// 本地段被删除时其远程副本一并删除；日志起始 offset 推进到剩余最早的段
+    pub fn apply_retention(&mut self, policy: &RetentionPolicy, now: i64) -> io::Result<usize> {
+        let remote_only = self
+            .remote
+            .as_ref()
+            .map(|remote| remote.manifest().segments_before(self.local_log_start_offset()))
+            .unwrap_or_default();
+        let mut closed: Vec<(u64, i64)> = remote_only
+            .iter()
+            .map(|metadata| (metadata.size, metadata.max_timestamp))
+            .collect();
+        closed.extend(
+            self.segments
+                .iter()
+                .take(self.segments.len().saturating_sub(1))
+                .map(|segment| (segment.get_size() as u64, segment.get_max_timestamp())),
+        );
+        let total_size = closed.iter().map(|&(size, _)| size).sum::<u64>()
+            + self.segments.back().map_or(0, |segment| segment.get_size() as u64);
+        let count = policy.segments_to_delete(&closed, total_size, now);
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let remote_count = count.min(remote_only.len());
+        if let Some(remote) = self.remote.as_mut() {
+            for metadata in &remote_only[..remote_count] {
+                remote.delete_segment(metadata.base_offset)?;
+            }
+        }
+        let result = (remote_count..count).try_for_each(|_| {
+            let segment = self.segments.pop_front().expect("closed segment");
+            if let Some(remote) = self.remote.as_mut() {
+                remote.delete_segment(segment.get_base_offset())?;
+            }
+            segment.delete()
+        });
+        // 即使中途删除失败，也要保证发布的段表与 segments 一致
+        self.publish_segments();
+        result?;
+        // 被删除的记录不再可读，日志起始 offset 推进到剩余最早的段
+        let remaining_start = self
+            .remote
+            .as_ref()
+            .and_then(|remote| remote.manifest().log_start_offset())
+            .map_or(self.local_log_start_offset(), |remote| remote.min(self.local_log_start_offset()));
+        self.advance_log_start_offset(remaining_start)?;
+        Ok(count)
+    }
+
+    /// 把已关闭的日志段上传到远程存储，再按本地保留策略删除已上传段的本地副本，返回删除的本地段数
+    ///
+    /// 未启用分层存储时不做任何处理；上传失败时直接返回错误，不删除任何本地段
+    pub fn offload_segments(&mut self, local_policy: &RetentionPolicy, now: i64) -> io::Result<usize> {
+        let Some(remote) = self.remote.as_mut() else {
+            return Ok(0);
+        };
+        let closed = self.segments.len().saturating_sub(1);
+        for segment in self.segments.iter_mut().take(closed) {
+            if !remote.manifest().contains(segment.get_base_offset()) {
+                segment.seal()?;
+                remote.upload(segment)?;
+            }
+        }
+
+        let candidates: Vec<(u64, i64)> = self
+            .segments
+            .iter()
+            .take(closed)
+            .map(|segment| (segment.get_size() as u64, segment.get_max_timestamp()))
+            .collect();
+        let total_size = self.segments.iter().map(|segment| segment.get_size() as u64).sum();
+        let count = local_policy.segments_to_delete(&candidates, total_size, now);
+        if count == 0 {
+            return Ok(0);
+        }
+        let result = (0..count).try_for_each(|_| self.segments.pop_front().expect("closed segment").delete());
+        self.publish_segments();
+        result.map(|_| count)
+    }
+
+    /// 截断日志，删除 offset 不小于 `offset` 的全部记录，之后的写入从 `offset` 开始
+    ///
+    /// 起始 offset 不小于 `offset` 的段整体删除（至少保留一个段），截断点所在的段就地截断；
+    /// 已上传的远程副本与本地内容不再一致，一并删除。截断点不能早于本地日志起始 offset
+    pub fn truncate_to(&mut self, offset: u64) -> StorageResult<()> {
+        if offset < self.log_start_offset.max(self.local_log_start_offset()) {
+            return Err(self.offset_out_of_range(offset));
+        }
+        if offset >= self.log_end_offset() {
+            return Ok(());
+        }
+        let mut result = Ok(());
+        while self.segments.len() > 1 && self.segments.back().is_some_and(|s| s.get_base_offset() >= offset) {
+            let segment = self.segments.pop_back().expect("segment");
+            if let Some(remote) = self.remote.as_mut() {
+                result = remote.delete_segment(segment.get_base_offset());
+            }
+            result = result.and_then(|_| segment.delete());
+            if result.is_err() {
+                break;
             }
         }
-        Ok(None)
+        if result.is_ok() {
+            let segment = self.segments.back_mut().expect("segment");
+            let base_offset = segment.get_base_offset();
+            result = segment.truncate_to(offset).map_err(io::Error::from);
+            if let Some(remote) = self.remote.as_mut() {
+                result = result.and_then(|_| remote.delete_segment(base_offset));
+            }
+        }
+        // 即使中途失败，也要保证发布的段表与 segments 一致
+        self.publish_segments();
+        self.recovery_point = self.recovery_point.min(offset);
+        Ok(result?)
+    }
+
+    /// 把日志起始 offset 推进到 `offset` 并持久化，之后早于它的读取返回 `StorageError::OffsetOutOfRange`，
+    /// 返回新的日志起始 offset
+    ///
+    /// 所有记录都早于 `offset` 的已关闭段（包括远程段）随即删除，活跃段保留；`offset` 不能超过日志末尾
+    pub fn delete_records_before(&mut self, offset: u64) -> StorageResult<u64> {
+        if offset > self.log_end_offset() {
+            return Err(self.offset_out_of_range(offset));
+        }
+        if offset <= self.log_start_offset {
+            return Ok(self.log_start_offset);
+        }
+        // 先持久化起始 offset，之后删除段时崩溃也不会重新暴露已删除的记录
+        self.advance_log_start_offset(offset)?;
+
+        if let Some(remote) = self.remote.as_mut() {
+            for metadata in remote.manifest().segments_before(offset) {
+                if metadata.next_offset <= offset {
+                    remote.delete_segment(metadata.base_offset)?;
+                }
+            }
+        }
+        let mut result = Ok(());
+        while self.segments.len() > 1 && self.segments.front().is_some_and(|s| s.get_next_offset() <= offset) {
+            let segment = self.segments.pop_front().expect("segment");
+            if let Some(remote) = self.remote.as_mut() {
+                result = remote.delete_segment(segment.get_base_offset());
+            }
+            result = result.and_then(|_| segment.delete());
+            if result.is_err() {
+                break;
+            }
+        }
+        self.publish_segments();
+        result?;
+        Ok(self.log_start_offset)
+    }
+
+    /// 推进日志起始 offset 并原子地写入 `LOG_START_OFFSET_FILE`
+    fn advance_log_start_offset(&mut self, offset: u64) -> io::Result<()> {
+        if offset <= self.log_start_offset {
+            return Ok(());
+        }
+        let path = format!("{}/{}", self.log_dir, LOG_START_OFFSET_FILE);
+        let tmp = format!("{}.tmp", path);
+        {
+            let mut file = std::fs::File::create(&tmp)?;
+            io::Write::write_all(&mut file, offset.to_string().as_bytes())?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, &path)?;
+        self.log_start_offset = offset;
+        self.reader.set_log_start_offset(offset);
+        self.space.notify();
+        Ok(())
+    }
+
+    /// 把当前的日志段列表与日志起始 offset 发布给 `LogReader`
+    fn publish_segments(&self) {
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| (segment.get_base_offset(), segment.reader()))
+            .collect();
+        self.reader.publish(segments, self.log_start_offset);
+        self.reader.notify_log_end_offset(self.log_end_offset());
+        self.space.notify();
     }
 
     /// 获取下一个日志段的起始 offset
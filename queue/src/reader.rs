@@ -0,0 +1,241 @@
+//! 分区日志的并发读取
+//!
+//! `LogQueue` 是分区唯一的写入方，日志段列表变化（滚动、压缩、保留、截断）后把各段的 `SegmentReader`
+//! 发布到共享的段表。`LogReader` 只持有共享段表，可以 clone 给多个消费者，读取不需要 `LogQueue` 的锁，
+//! 能与追加同时进行；读取期间被删除的日志段在读取结束前保持打开。
+//!
+//! `LogReader` 只读取本地日志段，早于本地起始 offset 的远程记录需要通过 `LogQueue` 读取。
+//! 读到日志末尾时可以通过 `subscribe`/`wait_past` 等待新记录写入。
+
+use crate::stream::{LogIter, LogStream, READ_CHUNK_BYTES};
+use crate::tail::{self, EndOffsetNotifier};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use storage::Result as StorageResult;
+use storage::{FileSlice, ReadRange, Record, RecordBatch, SegmentReader, StorageError};
+use tokio::sync::watch;
+
+/// 按 offset 查找记录的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OffsetLookup {
+    /// offset 早于日志起始 offset，记录已被删除
+    BelowLogStart { log_start_offset: u64 },
+    /// offset 不小于日志结束 offset，记录尚未写入
+    NotYetWritten { log_end_offset: u64 },
+    /// offset 在日志范围内，但所在的日志段中没有该记录（已被日志压缩删除）
+    Removed,
+    /// 找到的记录
+    Found(Record),
+}
+
+#[derive(Debug, Default)]
+struct SharedLog {
+    segments: RwLock<BTreeMap<u64, SegmentReader>>, // base_offset -> 本地日志段
+    log_start_offset: AtomicU64,                    // 日志起始 offset
+    end_offset: EndOffsetNotifier,                  // 日志结束 offset 的订阅
+}
+
+/// 分区日志的只读句柄
+#[derive(Debug, Clone, Default)]
+pub struct LogReader {
+    shared: Arc<SharedLog>,
+}
+
+impl LogReader {
+    /// 发布新的日志段列表与日志起始 offset，由 `LogQueue` 在段列表变化后调用
+    pub(crate) fn publish(&self, segments: BTreeMap<u64, SegmentReader>, log_start_offset: u64) {
+        *self.shared.segments.write().unwrap() = segments;
+        self.set_log_start_offset(log_start_offset);
+    }
+
+    pub(crate) fn set_log_start_offset(&self, offset: u64) {
+        self.shared.log_start_offset.store(offset, Ordering::Release);
+    }
+
+    /// 通知订阅方新的日志结束 offset，由 `LogQueue` 在追加与段列表变化后调用
+    pub(crate) fn notify_log_end_offset(&self, log_end_offset: u64) {
+        self.shared.end_offset.notify(log_end_offset);
+    }
+
+    /// 订阅日志结束 offset，追加记录（或截断日志）后接收方收到新的值
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.shared.end_offset.subscribe()
+    }
+
+    /// 返回在日志结束 offset 超过 `offset` 时完成的 future，见 [`tail::wait_past`]
+    pub fn wait_past(&self, offset: u64) -> impl Future<Output = u64> + Send + 'static {
+        tail::wait_past(self.subscribe(), offset)
+    }
+
+    /// 从 `offset` 开始遍历到日志末尾的迭代器，产出 `(offset, record)`，不借用 `LogReader`
+    pub fn iter_from(&self, offset: u64) -> LogIter<'static> {
+        let reader = self.clone();
+        LogIter::new(offset, move |offset| reader.read_range(offset, READ_CHUNK_BYTES, usize::MAX))
+    }
+
+    /// 从 `offset` 开始跟随日志尾部的记录流，读到末尾后等待新记录写入
+    pub fn stream_from(&self, offset: u64) -> LogStream {
+        let reader = self.clone();
+        let receiver = self.subscribe();
+        LogStream::new(offset, move |offset| reader.read_range(offset, READ_CHUNK_BYTES, usize::MAX), receiver)
+    }
+
+    /// 日志起始 offset，早于它的读取返回 `StorageError::OffsetOutOfRange`
+    pub fn log_start_offset(&self) -> u64 {
+        self.shared.log_start_offset.load(Ordering::Acquire)
+    }
+
+    /// 本地最早的 offset，更早的记录只存在于远程
+    pub fn local_log_start_offset(&self) -> u64 {
+        self.shared.segments.read().unwrap().keys().next().copied().unwrap_or(0)
+    }
+
+    /// 已完整写入的最后一条记录之后的 offset
+    pub fn log_end_offset(&self) -> u64 {
+        self.shared
+            .segments
+            .read()
+            .unwrap()
+            .values()
+            .next_back()
+            .map_or(0, SegmentReader::get_next_offset)
+    }
+
+    /// 读取前检查 offset 不早于日志起始 offset
+    fn check_log_start(&self, offset: u64) -> StorageResult<()> {
+        let log_start_offset = self.log_start_offset();
+        if offset < log_start_offset {
+            return Err(StorageError::OffsetOutOfRange {
+                offset,
+                log_start_offset,
+                log_end_offset: self.log_end_offset(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 包含 offset 的日志段：base_offset 不大于 offset 的最后一个段
+    fn segment_for(&self, offset: u64) -> Option<SegmentReader> {
+        let segments = self.shared.segments.read().unwrap();
+        segments.range(..=offset).next_back().map(|(_, segment)| segment.clone())
+    }
+
+    /// 包含 offset 的日志段及其之后的所有段，复制出来后释放段表的锁，读取期间不阻塞写入方发布
+    fn segments_from(&self, offset: u64) -> Vec<SegmentReader> {
+        let segments = self.shared.segments.read().unwrap();
+        let first = segments.range(..=offset).next_back().map_or(0, |(&base_offset, _)| base_offset);
+        segments.range(first..).map(|(_, segment)| segment.clone()).collect()
+    }
+
+    /// 查找指定 offset 的记录，区分早于日志起始 offset、尚未写入、已被压缩删除与找到
+    ///
+    /// 总是通过 base_offset 映射定位 offset 所在的日志段，向前或向后跳转读取的结果相同
+    pub fn lookup(&self, offset: u64) -> StorageResult<OffsetLookup> {
+        let log_start_offset = self.log_start_offset();
+        if offset < log_start_offset {
+            return Ok(OffsetLookup::BelowLogStart { log_start_offset });
+        }
+        let log_end_offset = self.log_end_offset();
+        if offset >= log_end_offset {
+            return Ok(OffsetLookup::NotYetWritten { log_end_offset });
+        }
+        let record = match self.segment_for(offset) {
+            Some(segment) => segment.read_record(offset)?,
+            None => None,
+        };
+        Ok(record.map_or(OffsetLookup::Removed, OffsetLookup::Found))
+    }
+
+    /// 读取指定 offset 的消息内容
+    pub fn read_message(&self, offset: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.read_from_segments(offset, |segment, offset| segment.read_message(offset))
+    }
+
+    /// 读取指定 offset 的记录
+    pub fn read_record(&self, offset: u64) -> StorageResult<Option<Record>> {
+        self.read_from_segments(offset, |segment, offset| segment.read_record(offset))
+    }
+
+    /// 读取包含指定 offset 的记录批次
+    pub fn read_batch(&self, offset: u64) -> StorageResult<Option<RecordBatch>> {
+        self.read_from_segments(offset, |segment, offset| segment.read_batch(offset))
+    }
+
+    /// 从 `start_offset` 开始读取连续记录，跨日志段边界时继续读取下一个段，
+    /// 批次字节数累计不超过 `max_bytes`，记录数不超过 `max_records`，返回结果中带有下一次读取的 offset
+    pub fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> StorageResult<ReadRange> {
+        let mut range = ReadRange {
+            records: Vec::new(),
+            next_offset: start_offset,
+            bytes: 0,
+        };
+        self.check_log_start(start_offset)?;
+        for segment in self.segments_from(start_offset) {
+            if range.records.len() >= max_records {
+                break;
+            }
+            let part = segment.read_range(
+                range.next_offset,
+                max_bytes.saturating_sub(range.bytes),
+                max_records - range.records.len(),
+            )?;
+            // 段内总会返回至少一个批次，已有数据时超出 max_bytes 的批次留给下一次读取
+            if !range.records.is_empty() && range.bytes + part.bytes > max_bytes {
+                break;
+            }
+            range.records.extend(part.records);
+            range.next_offset = part.next_offset;
+            range.bytes += part.bytes;
+            // 当前段未读完说明已达到读取上限
+            if range.next_offset < segment.get_next_offset() {
+                break;
+            }
+        }
+        Ok(range)
+    }
+
+    /// 定位从 `start_offset` 开始的连续批次在日志文件中的区域，用于零拷贝发送
+    ///
+    /// 区域不会跨越日志段，读完一个区域后使用 `FileSlice::next_offset` 继续读取
+    pub fn read_slice(&self, start_offset: u64, max_bytes: usize) -> StorageResult<Option<FileSlice>> {
+        self.check_log_start(start_offset)?;
+        for segment in self.segments_from(start_offset) {
+            if let Some(slice) = segment.read_slice(start_offset, max_bytes)? {
+                return Ok(Some(slice));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 查找时间戳不小于 `timestamp` 的第一条记录的 offset，不存在时返回 None
+    ///
+    /// 从日志起始 offset 所在的段开始按 base_offset 顺序遍历，跳过最大时间戳小于目标的段，再通过段内时间索引定位；
+    /// 段内早于日志起始 offset 的记录已被删除，找到这些记录时返回日志起始 offset
+    pub fn offset_for_timestamp(&self, timestamp: i64) -> StorageResult<Option<u64>> {
+        let log_start_offset = self.log_start_offset();
+        for segment in self.segments_from(log_start_offset) {
+            if segment.get_max_timestamp() < timestamp {
+                continue;
+            }
+            if let Some(offset) = segment.find_offset_by_timestamp(timestamp)? {
+                return Ok(Some(offset.max(log_start_offset)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 在包含 offset 的日志段中读取，offset 早于日志起始 offset 时返回 `StorageError::OffsetOutOfRange`
+    fn read_from_segments<T>(
+        &self,
+        offset: u64,
+        read: impl FnOnce(&SegmentReader, u64) -> StorageResult<Option<T>>,
+    ) -> StorageResult<Option<T>> {
+        self.check_log_start(offset)?;
+        match self.segment_for(offset) {
+            Some(segment) => read(&segment, offset),
+            None => Ok(None),
+        }
+    }
+}
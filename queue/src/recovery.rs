@@ -0,0 +1,133 @@
+//! 启动恢复
+//!
+//! 每个数据目录下的 `RECOVERY_POINT_CHECKPOINT_FILE` 记录各分区的恢复点，刷盘任务定期写入；
+//! 正常关闭时所有分区刷盘后写入最终的检查点并创建 `CLEAN_SHUTDOWN_FILE`。
+//! 启动时存在该标记则信任全部日志段，否则只恢复各分区恢复点之后的日志段，
+//! 多个分区使用 `num_threads` 个线程并行加载。
+
+use crate::queue::MOVING_DIR_SUFFIX;
+use crate::LogQueue;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use storage::SegmentConfig;
+
+/// 恢复点检查点文件名
+pub const RECOVERY_POINT_CHECKPOINT_FILE: &str = "recovery-point-offset-checkpoint";
+/// 正常关闭标记文件名
+pub const CLEAN_SHUTDOWN_FILE: &str = ".clean_shutdown";
+/// 检查点文件格式版本
+const CHECKPOINT_VERSION: u32 = 0;
+
+/// 读取数据目录下的恢复点检查点（分区目录名 -> 恢复点），文件不存在时为空
+///
+/// 文件格式：第一行为版本号，第二行为条目数，之后每行为 `分区目录名 恢复点`
+pub fn read_recovery_checkpoint(data_dir: &str) -> io::Result<HashMap<String, u64>> {
+    let path = Path::new(data_dir).join(RECOVERY_POINT_CHECKPOINT_FILE);
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let content = fs::read_to_string(&path)?;
+    let invalid = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("恢复点检查点 {} 格式错误: {}", path.display(), reason),
+        )
+    };
+    let mut lines = content.lines();
+    match lines.next().map(|line| line.trim().parse::<u32>()) {
+        Some(Ok(CHECKPOINT_VERSION)) => {}
+        _ => return Err(invalid("版本号不支持")),
+    }
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(|| invalid("缺少条目数"))?;
+    let mut points = HashMap::with_capacity(count);
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let (name, offset) = line.rsplit_once(' ').ok_or_else(|| invalid(line))?;
+        points.insert(name.to_string(), offset.parse().map_err(|_| invalid(line))?);
+    }
+    if points.len() != count {
+        return Err(invalid("条目数不一致"));
+    }
+    Ok(points)
+}
+
+/// 原子地写入数据目录下的恢复点检查点
+pub fn write_recovery_checkpoint(data_dir: &str, points: &HashMap<String, u64>) -> io::Result<()> {
+    let path = Path::new(data_dir).join(RECOVERY_POINT_CHECKPOINT_FILE);
+    let tmp = path.with_extension("tmp");
+    let mut entries: Vec<_> = points.iter().collect();
+    entries.sort();
+    {
+        let mut file = fs::File::create(&tmp)?;
+        writeln!(file, "{}", CHECKPOINT_VERSION)?;
+        writeln!(file, "{}", entries.len())?;
+        for (name, offset) in entries {
+            writeln!(file, "{} {}", name, offset)?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, &path)
+}
+
+/// 创建正常关闭标记，调用前所有分区必须已经刷盘并写入检查点
+pub fn mark_clean_shutdown(data_dir: &str) -> io::Result<()> {
+    fs::File::create(Path::new(data_dir).join(CLEAN_SHUTDOWN_FILE))?.sync_all()
+}
+
+/// 加载数据目录下的所有分区（分区目录名 -> 队列），使用 `num_threads` 个线程并行恢复，
+/// 对应 num_recovery_threads_per_data_dir
+///
+/// 全部分区加载完成后删除正常关闭标记，之后再崩溃按非正常关闭处理
+pub fn load_logs(data_dir: &str, config: &SegmentConfig, num_threads: usize) -> io::Result<HashMap<String, LogQueue>> {
+    if !Path::new(data_dir).exists() {
+        return Ok(HashMap::new());
+    }
+    let clean_shutdown = Path::new(data_dir).join(CLEAN_SHUTDOWN_FILE).exists();
+    let checkpoint = read_recovery_checkpoint(data_dir)?;
+    let mut partitions = Vec::new();
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_dir() || name.starts_with('.') {
+            continue;
+        }
+        // 迁移中途退出时遗留的目录，原分区目录仍然完整
+        if name.ends_with(MOVING_DIR_SUFFIX) {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            partitions.push(name);
+        }
+    }
+
+    let num_threads = num_threads.clamp(1, partitions.len().max(1));
+    let pending = Mutex::new(partitions.into_iter());
+    let loaded = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| loop {
+                let Some(name) = pending.lock().unwrap().next() else { break };
+                // 检查点中没有的分区从头恢复
+                let recovery_point = checkpoint.get(&name).copied().unwrap_or(0);
+                let log_dir = format!("{}/{}", data_dir.trim_end_matches('/'), name);
+                let queue = LogQueue::open_with_recovery(&log_dir, config.clone(), recovery_point, clean_shutdown);
+                loaded.lock().unwrap().push((name, queue));
+            });
+        }
+    });
+
+    let mut logs = HashMap::new();
+    for (name, queue) in loaded.into_inner().unwrap() {
+        let queue = queue.map_err(|e| io::Error::new(e.kind(), format!("加载分区 {} 失败: {}", name, e)))?;
+        logs.insert(name, queue);
+    }
+    if clean_shutdown {
+        fs::remove_file(Path::new(data_dir).join(CLEAN_SHUTDOWN_FILE))?;
+    }
+    Ok(logs)
+}
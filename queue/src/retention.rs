@@ -0,0 +1,128 @@
+//! 日志保留任务
+//!
+//! 每个分区以名称注册，携带各自的主题级覆盖；后台线程按检查间隔对所有分区执行保留策略，
+//! 通过 `LogQueue::apply_retention` 删除段，保证发布给 `LogReader` 的段表与磁盘一致。
+//! 启用分层存储的分区随后按本地保留策略把已关闭的段转移到远程。
+
+use crate::task::{self, TaskHandle};
+use crate::LogQueue;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use storage::record::now_ms;
+use storage::{RetentionOverrides, RetentionPolicy};
+
+/// 已注册的分区，分区被删除后弱引用失效，下次检查时自动移除
+struct RetentionTarget {
+    queue: Weak<Mutex<LogQueue>>,
+    overrides: RetentionOverrides,
+}
+
+struct RetentionState {
+    default_policy: RetentionPolicy,
+    targets: HashMap<String, RetentionTarget>,
+}
+
+/// 日志保留管理器
+#[derive(Clone)]
+pub struct RetentionManager {
+    state: Arc<Mutex<RetentionState>>,
+}
+
+impl Default for RetentionManager {
+    fn default() -> Self {
+        Self::new(RetentionPolicy::default())
+    }
+}
+
+impl RetentionManager {
+    pub fn new(default_policy: RetentionPolicy) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RetentionState {
+                default_policy,
+                targets: HashMap::new(),
+            })),
+        }
+    }
+
+    /// 更新 broker 级默认保留策略
+    pub fn set_default_policy(&self, policy: RetentionPolicy) {
+        self.state.lock().unwrap().default_policy = policy;
+    }
+
+    /// 注册分区，同名分区会被替换
+    pub fn register(&self, name: &str, queue: &Arc<Mutex<LogQueue>>, overrides: RetentionOverrides) {
+        self.state.lock().unwrap().targets.insert(
+            name.to_string(),
+            RetentionTarget {
+                queue: Arc::downgrade(queue),
+                overrides,
+            },
+        );
+    }
+
+    /// 取消注册分区
+    pub fn unregister(&self, name: &str) {
+        self.state.lock().unwrap().targets.remove(name);
+    }
+
+    /// 已注册的分区数量
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 对所有分区执行一次保留策略，返回删除的段数，单个分区失败不影响其他分区
+    pub fn run_once(&self) -> usize {
+        let targets: Vec<(String, Weak<Mutex<LogQueue>>, RetentionPolicy, RetentionPolicy)> = {
+            let mut state = self.state.lock().unwrap();
+            state.targets.retain(|_, target| target.queue.strong_count() > 0);
+            let default_policy = state.default_policy;
+            state
+                .targets
+                .iter()
+                .map(|(name, target)| {
+                    let policy = default_policy.with_overrides(&target.overrides);
+                    (name.clone(), target.queue.clone(), policy, policy.local_policy(&target.overrides))
+                })
+                .collect()
+        };
+
+        let mut deleted = 0;
+        for (name, queue, policy, local_policy) in targets {
+            let Some(queue) = queue.upgrade() else { continue };
+            let mut queue = match queue.lock() {
+                Ok(queue) => queue,
+                Err(e) => {
+                    eprintln!("分区 {} 获取队列锁失败: {}", name, e);
+                    continue;
+                }
+            };
+            match queue.apply_retention(&policy, now_ms()) {
+                Ok(count) => deleted += count,
+                Err(e) => eprintln!("分区 {} 执行保留策略失败: {}", name, e),
+            }
+            if queue.has_remote_storage() {
+                if let Err(e) = queue.offload_segments(&local_policy, now_ms()) {
+                    eprintln!("分区 {} 转移日志段到远程存储失败: {}", name, e);
+                }
+            }
+        }
+        deleted
+    }
+
+    /// 启动后台线程，每隔 `check_interval` 执行一次保留策略
+    pub fn start(&self, check_interval: Duration) -> io::Result<RetentionHandle> {
+        let manager = self.clone();
+        task::spawn_periodic("log-retention", check_interval, move || {
+            manager.run_once();
+        })
+    }
+}
+
+/// 后台保留任务的句柄，`shutdown` 或丢弃时停止线程并等待其退出
+pub type RetentionHandle = TaskHandle;
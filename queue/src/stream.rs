@@ -0,0 +1,167 @@
+//! 按 offset 顺序遍历分区日志
+//!
+//! `LogIter` 从指定 offset 开始按批读取记录，读到日志末尾时结束；`LogStream` 是对应的异步 `Stream`，
+//! 读到末尾后等待日志结束 offset 超过下一个 offset 再继续，一直跟随日志尾部。两者都自己维护下一次读取的
+//! offset（跳过被压缩删除的空洞），嵌入式使用时不需要围绕 `read_message` 手动记录 offset。
+//!
+//! 读取出错（例如 offset 早于日志起始 offset）时产出该错误后结束。
+
+use crate::tail;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use storage::Result as StorageResult;
+use storage::{ReadRange, Record};
+use tokio::sync::watch;
+
+/// 每次从日志读取的最大字节数
+pub const READ_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// 从 offset 开始读取一批连续记录的函数，参数与 `read_range` 的 `start_offset` 相同
+type ReadFn<'a> = Box<dyn FnMut(u64) -> StorageResult<ReadRange> + Send + 'a>;
+
+/// 已读取的记录与下一次读取的 offset
+struct Cursor<'a> {
+    read: ReadFn<'a>,
+    next_offset: u64,
+    buffered: VecDeque<Record>,
+    done: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(offset: u64, read: ReadFn<'a>) -> Self {
+        Self { read, next_offset: offset, buffered: VecDeque::new(), done: false }
+    }
+
+    fn pop(&mut self) -> Option<(u64, Record)> {
+        self.buffered.pop_front().map(|record| (record.offset, record))
+    }
+
+    /// 读取下一批记录，返回是否读到了记录；出错后不再读取
+    fn fill(&mut self) -> StorageResult<bool> {
+        match (self.read)(self.next_offset) {
+            Ok(range) => {
+                self.next_offset = self.next_offset.max(range.next_offset);
+                self.buffered.extend(range.records);
+                Ok(!self.buffered.is_empty())
+            }
+            Err(e) => {
+                self.done = true;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// 从 offset 开始到日志末尾的记录迭代器，产出 `(offset, record)`
+pub struct LogIter<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> LogIter<'a> {
+    /// 创建从 `offset` 开始的迭代器，`read` 从给定 offset 读取一批连续记录
+    pub fn new(offset: u64, read: impl FnMut(u64) -> StorageResult<ReadRange> + Send + 'a) -> Self {
+        Self { cursor: Cursor::new(offset, Box::new(read)) }
+    }
+
+    /// 下一条要产出的记录的 offset（之后读取开始的位置）
+    pub fn next_offset(&self) -> u64 {
+        self.cursor.buffered.front().map_or(self.cursor.next_offset, |record| record.offset)
+    }
+}
+
+impl Iterator for LogIter<'_> {
+    type Item = StorageResult<(u64, Record)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.cursor.pop() {
+            return Some(Ok(item));
+        }
+        if self.cursor.done {
+            return None;
+        }
+        match self.cursor.fill() {
+            Ok(true) => self.cursor.pop().map(Ok),
+            // 没有更多记录，已到日志末尾
+            Ok(false) => {
+                self.cursor.done = true;
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl fmt::Debug for LogIter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogIter").field("next_offset", &self.next_offset()).finish()
+    }
+}
+
+/// 从 offset 开始跟随日志尾部的记录流，产出 `(offset, record)`，读到末尾时等待新记录写入而不结束
+pub struct LogStream {
+    cursor: Cursor<'static>,
+    receiver: watch::Receiver<u64>,
+    waiting: Option<Pin<Box<dyn Future<Output = u64> + Send>>>,
+}
+
+impl LogStream {
+    /// 创建从 `offset` 开始的记录流，`read` 从给定 offset 读取一批连续记录，`receiver` 为日志结束 offset 的订阅
+    pub fn new(
+        offset: u64,
+        read: impl FnMut(u64) -> StorageResult<ReadRange> + Send + 'static,
+        receiver: watch::Receiver<u64>,
+    ) -> Self {
+        Self { cursor: Cursor::new(offset, Box::new(read)), receiver, waiting: None }
+    }
+
+    /// 下一条要产出的记录的 offset
+    pub fn next_offset(&self) -> u64 {
+        self.cursor.buffered.front().map_or(self.cursor.next_offset, |record| record.offset)
+    }
+}
+
+impl Stream for LogStream {
+    type Item = StorageResult<(u64, Record)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.cursor.pop() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+            if this.cursor.done {
+                return Poll::Ready(None);
+            }
+            if let Some(waiting) = this.waiting.as_mut() {
+                if waiting.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.waiting = None;
+                // 分区日志已被释放，不会再有新记录
+                if this.receiver.has_changed().is_err() && *this.receiver.borrow() <= this.cursor.next_offset {
+                    this.cursor.done = true;
+                    continue;
+                }
+            }
+            match this.cursor.fill() {
+                Ok(true) => {}
+                // 读到日志末尾，等待下一个 offset 写入
+                Ok(false) => {
+                    let wait = tail::wait_past(this.receiver.clone(), this.cursor.next_offset);
+                    this.waiting = Some(Box::pin(wait));
+                }
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for LogStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogStream").field("next_offset", &self.next_offset()).finish()
+    }
+}
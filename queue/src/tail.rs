@@ -0,0 +1,45 @@
+//! 日志尾部订阅
+//!
+//! 分区日志通过 `tokio::sync::watch` 发布日志结束 offset。消费者读到日志末尾后订阅并等待新记录写入，
+//! 不需要轮询；长轮询 fetch（`max_wait_ms`/`min_bytes`）在等待结果与超时之间选择即可。
+
+use tokio::sync::watch;
+
+/// 日志结束 offset 的发布端，由分区日志的写入方持有
+#[derive(Debug)]
+pub(crate) struct EndOffsetNotifier {
+    sender: watch::Sender<u64>,
+}
+
+impl EndOffsetNotifier {
+    pub(crate) fn new(log_end_offset: u64) -> Self {
+        Self { sender: watch::Sender::new(log_end_offset) }
+    }
+
+    /// 发布新的日志结束 offset，没有变化时不唤醒订阅方
+    pub(crate) fn notify(&self, log_end_offset: u64) {
+        self.sender.send_if_modified(|current| {
+            let modified = *current != log_end_offset;
+            *current = log_end_offset;
+            modified
+        });
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<u64> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EndOffsetNotifier {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// 等待日志结束 offset 超过 `offset`（即 `offset` 处的记录已写入），返回当时的日志结束 offset
+///
+/// 已经超过时立即完成；分区日志被释放后不会再有新记录，返回最后发布的日志结束 offset
+pub async fn wait_past(mut receiver: watch::Receiver<u64>, offset: u64) -> u64 {
+    let result = receiver.wait_for(|&log_end_offset| log_end_offset > offset).await.map(|end| *end);
+    result.unwrap_or_else(|_| *receiver.borrow())
+}
@@ -0,0 +1,53 @@
+//! 后台周期任务
+
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 后台周期任务的句柄，`shutdown` 或丢弃时停止线程并等待其退出
+pub struct TaskHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TaskHandle {
+    /// 停止后台线程，正在执行的一轮任务会先完成
+    pub fn shutdown(mut self) {
+        self.stop_and_join();
+    }
+
+    fn stop_and_join(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
+
+/// 启动名为 `name` 的后台线程，每隔 `interval` 执行一次 `task`
+pub(crate) fn spawn_periodic(
+    name: &str,
+    interval: Duration,
+    mut task: impl FnMut() + Send + 'static,
+) -> io::Result<TaskHandle> {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = thread::Builder::new().name(name.to_string()).spawn(move || {
+        // 收到停止信号或句柄被丢弃时退出
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            task();
+        }
+    })?;
+    Ok(TaskHandle {
+        stop: Some(stop),
+        thread: Some(thread),
+    })
+}
@@ -3,6 +3,12 @@ use queue::LogQueue;
 mod tests {
     use super::*;
     use std::fs;
+    use std::collections::HashMap;
+    use queue::{OffsetLookup, RetentionManager};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use queue::{FlushPolicy, LocalDirRemoteStorage, LogFlusher, RemoteStorage};
+    use storage::{CleanerConfig, LogCleaner, Record, RecordBatch, RetentionOverrides, RetentionPolicy, StorageError};
 
     const TEST_LOG_DIR: &str = "test_log_queue";
 
@@ -95,4 +101,1065 @@ mod tests {
             };
         }
     }
+
+    /// **为单个测试准备独立目录，避免并行测试互相干扰**
+    fn setup_dir(dir: &str) -> String {
+        let dir = format!("target/{}", dir);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_offset_for_timestamp() {
+        let dir = setup_dir("test_offset_for_timestamp");
+        let mut queue = LogQueue::new(&dir, 4096).expect("Failed to create LogQueue");
+        for i in 0..1000i64 {
+            let mut batch = RecordBatch::new(vec![
+                Record::new(None, Some(format!("hello kafka {}", i).into_bytes())).with_timestamp(1_000 + i),
+            ]);
+            queue.append_batch(&mut batch).unwrap();
+        }
+
+        assert_eq!(queue.offset_for_timestamp(0).unwrap(), Some(0));
+        assert_eq!(queue.offset_for_timestamp(1_500).unwrap(), Some(500));
+        assert_eq!(queue.offset_for_timestamp(1_999).unwrap(), Some(999));
+        assert_eq!(queue.offset_for_timestamp(2_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_offset_for_timestamp_after_delete_records() {
+        let dir = setup_dir("test_offset_for_timestamp_after_delete_records");
+        let mut queue = LogQueue::new(&dir, 4096).expect("Failed to create LogQueue");
+        for i in 0..1000i64 {
+            let mut batch = RecordBatch::new(vec![
+                Record::new(None, Some(format!("hello kafka {}", i).into_bytes())).with_timestamp(1_000 + i),
+            ]);
+            queue.append_batch(&mut batch).unwrap();
+        }
+        // 日志起始 offset 落在段中间，段内仍保留已删除的记录
+        queue.delete_records_before(505).unwrap();
+        assert!(queue.local_log_start_offset() < 505);
+
+        // 早于日志起始 offset 的记录不会被返回
+        assert_eq!(queue.offset_for_timestamp(0).unwrap(), Some(505));
+        assert_eq!(queue.offset_for_timestamp(1_500).unwrap(), Some(505));
+        assert_eq!(queue.offset_for_timestamp(1_600).unwrap(), Some(600));
+        assert_eq!(queue.reader().offset_for_timestamp(0).unwrap(), Some(505));
+        assert_eq!(queue.offset_for_timestamp(2_000).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_range_across_segments() {
+        let dir = setup_dir("test_read_range_across_segments");
+        let mut queue = LogQueue::new(&dir, 4096).expect("Failed to create LogQueue");
+        for i in 0..100 {
+            let mut batch = RecordBatch::new(
+                (0..5)
+                    .map(|j| Record::new(None, Some(format!("msg-{}", i * 5 + j).into_bytes())))
+                    .collect(),
+            );
+            queue.append_batch(&mut batch).unwrap();
+        }
+
+        // 一次读取跨越多个日志段，按 next_offset 继续读取直到末尾
+        let mut offset = 3;
+        let mut values = Vec::new();
+        loop {
+            let range = queue.read_range(offset, 8192, 120).unwrap();
+            if range.records.is_empty() {
+                break;
+            }
+            assert!(range.records.len() <= 120);
+            assert_eq!(range.records[0].offset, offset);
+            values.extend(range.records.into_iter().map(|r| r.value.unwrap()));
+            offset = range.next_offset;
+        }
+        assert_eq!(offset, 500);
+        let expected: Vec<Vec<u8>> = (3..500).map(|i| format!("msg-{}", i).into_bytes()).collect();
+        assert_eq!(values, expected);
+
+        // max_bytes 很小时仍至少返回一个批次
+        let range = queue.read_range(250, 1, usize::MAX).unwrap();
+        assert_eq!(range.records.len(), 5);
+        assert_eq!(range.next_offset, 255);
+    }
+
+    fn append_keyed(queue: &mut LogQueue, key: Option<&str>, value: Option<&str>, timestamp: i64) -> u64 {
+        let mut batch = RecordBatch::new(vec![Record::new(
+            key.map(|k| k.as_bytes().to_vec()),
+            value.map(|v| v.as_bytes().to_vec()),
+        )
+        .with_timestamp(timestamp)]);
+        queue.append_batch(&mut batch).unwrap()
+    }
+
+    fn read_all(queue: &mut LogQueue) -> Vec<Record> {
+        let start = queue.log_start_offset();
+        queue.read_range(start, usize::MAX, usize::MAX).unwrap().records
+    }
+
+    #[test]
+    fn test_compact_keeps_latest_per_key() {
+        let dir = setup_dir("test_compact_keeps_latest_per_key");
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to create LogQueue");
+        for i in 0..300 {
+            let key = format!("key-{}", i % 10);
+            append_keyed(&mut queue, Some(&key), Some(&format!("value-{}", i)), 1_000 + i);
+        }
+        append_keyed(&mut queue, None, Some("no-key"), 2_000);
+
+        let before = read_all(&mut queue);
+        let latest: HashMap<_, _> = before.iter().map(|r| (r.key.clone(), r.offset)).collect();
+
+        let cleaner = LogCleaner::new(CleanerConfig::default());
+        let removed = queue.compact(&cleaner).unwrap();
+        assert!(removed > 0);
+
+        let after = read_all(&mut queue);
+        assert_eq!(after.len(), before.len() - removed);
+        assert!(after.windows(2).all(|w| w[0].offset < w[1].offset));
+        // 每个 key 的最新记录都被保留
+        for offset in latest.values() {
+            assert!(after.iter().any(|r| r.offset == *offset));
+        }
+        assert_eq!(queue.read_message(300).unwrap(), Some(b"no-key".to_vec()));
+        // 被压缩掉的 offset 读不到
+        assert_eq!(queue.read_message(0).unwrap(), None);
+
+        // 重新打开后数据一致，新写入的 offset 继续递增
+        drop(queue);
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to reopen LogQueue");
+        assert_eq!(read_all(&mut queue), after);
+        assert_eq!(append_keyed(&mut queue, Some("key-0"), Some("new"), 3_000), 301);
+    }
+
+    #[test]
+    fn test_compact_removes_expired_tombstones() {
+        let dir = setup_dir("test_compact_removes_expired_tombstones");
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to create LogQueue");
+        // 记录写入于 10 秒前
+        let timestamp = storage::record::now_ms() - 10_000;
+        append_keyed(&mut queue, Some("user-1"), Some("created"), timestamp);
+        append_keyed(&mut queue, Some("user-2"), Some("created"), timestamp);
+        let tombstone = append_keyed(&mut queue, Some("user-1"), None, timestamp);
+        // 没有 key 的记录用于滚动日志段，压缩时原样保留
+        for _ in 0..50 {
+            append_keyed(&mut queue, None, Some("filler"), timestamp);
+        }
+
+        // 保留时间未到：墓碑保留，之前的值被删除
+        let cleaner = LogCleaner::new(CleanerConfig::default());
+        assert_eq!(queue.compact(&cleaner).unwrap(), 1);
+        let record = queue.read_record(tombstone).unwrap().unwrap();
+        assert_eq!(record.value, None);
+
+        // 保留时间已过：墓碑也被删除
+        let cleaner = LogCleaner::new(CleanerConfig { delete_retention_ms: 5_000 });
+        assert_eq!(queue.compact(&cleaner).unwrap(), 1);
+        assert_eq!(queue.read_record(tombstone).unwrap(), None);
+        let keys: Vec<_> = read_all(&mut queue).into_iter().filter_map(|r| r.key).collect();
+        assert_eq!(keys, vec![b"user-2".to_vec()]);
+    }
+
+    fn segment_files(dir: &str) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_str().unwrap().ends_with(".log"))
+            .count()
+    }
+
+    #[test]
+    fn test_time_based_rolling() {
+        let dir = setup_dir("test_time_based_rolling");
+        let config = storage::SegmentConfig {
+            segment_ms: 1_000,
+            ..storage::SegmentConfig::default()
+        };
+        let mut queue = LogQueue::with_config(&dir, config).unwrap();
+        for (i, timestamp) in [10_000, 10_500, 11_000, 11_001, 11_500, 12_002].into_iter().enumerate() {
+            assert_eq!(append_keyed(&mut queue, None, Some("v"), timestamp), i as u64);
+        }
+        // 11_001 和 12_002 分别与所在段第一个批次相差超过 1 秒，触发滚动
+        assert_eq!(segment_files(&dir), 3);
+        assert_eq!(read_all(&mut queue).len(), 6);
+    }
+
+    #[test]
+    fn test_move_to() {
+        let dir = setup_dir("test_move_to");
+        let dest = format!("{}-moved", dir);
+        let _ = fs::remove_dir_all(&dest);
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        for i in 0..50 {
+            append_keyed(&mut queue, None, Some(&format!("value-{}", i)), i);
+        }
+        queue.delete_records_before(10).unwrap();
+        queue.set_flush_policy(FlushPolicy::EveryWrite);
+
+        // 已存在的目标目录不能覆盖
+        fs::create_dir_all(&dest).unwrap();
+        assert!(queue.move_to(&dest).is_err());
+        fs::remove_dir(&dest).unwrap();
+
+        queue.move_to(&dest).unwrap();
+        assert!(!std::path::Path::new(&dir).exists());
+        assert_eq!(queue.log_dir(), dest);
+        assert_eq!(queue.log_start_offset(), 10);
+        assert_eq!(queue.flush_policy(), FlushPolicy::EveryWrite);
+        assert_eq!(append_keyed(&mut queue, None, Some("value-50"), 50), 50);
+        assert_eq!(read_all(&mut queue).len(), 41);
+    }
+
+    #[test]
+    fn test_apply_retention() {
+        let dir = setup_dir("test_apply_retention");
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to create LogQueue");
+        for i in 0..200 {
+            append_keyed(&mut queue, None, Some("hello kafka"), 1_000 + i);
+        }
+        let segments = segment_files(&dir);
+        assert!(segments > 3);
+
+        // 时间保留：最大时间戳早于 1100 的段被删除
+        let deleted = queue.apply_retention(&RetentionPolicy::new(100, -1), 1_200).unwrap();
+        assert!(deleted > 0);
+        assert_eq!(segment_files(&dir), segments - deleted);
+        let first = read_all(&mut queue)[0].offset;
+        assert!(first > 0 && first <= 100);
+        assert_eq!(queue.log_start_offset(), first);
+        assert!(matches!(queue.read_message(0), Err(StorageError::OffsetOutOfRange { .. })));
+
+        // 大小保留为 0 时也不会删除活跃段
+        queue.apply_retention(&RetentionPolicy::new(-1, 0), 1_200).unwrap();
+        assert_eq!(segment_files(&dir), 1);
+        assert_eq!(queue.read_message(199).unwrap(), Some(b"hello kafka".to_vec()));
+        assert_eq!(queue.append_message(b"next").unwrap(), 200);
+
+        // 重新打开后保持一致
+        drop(queue);
+        let queue = LogQueue::new(&dir, 1024).expect("Failed to reopen LogQueue");
+        assert_eq!(queue.read_message(200).unwrap(), Some(b"next".to_vec()));
+    }
+
+    #[test]
+    fn test_retention_manager() {
+        let dir = setup_dir("test_retention_manager");
+        let queue = Arc::new(Mutex::new(LogQueue::new(&dir, 1024).expect("Failed to create LogQueue")));
+        for _ in 0..100 {
+            // 时间戳为 0，按默认 7 天保留已经过期
+            append_keyed(&mut queue.lock().unwrap(), None, Some("hello kafka"), 0);
+        }
+
+        let manager = RetentionManager::default();
+        // 主题级覆盖为不限制时不删除
+        manager.register(
+            "topic-0",
+            &queue,
+            RetentionOverrides {
+                retention_ms: Some(-1),
+                ..Default::default()
+            },
+        );
+        assert_eq!(manager.run_once(), 0);
+
+        manager.register("topic-0", &queue, RetentionOverrides::default());
+        let handle = manager.start(Duration::from_millis(10)).unwrap();
+        let start = std::time::Instant::now();
+        while segment_files(&dir) > 1 && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle.shutdown();
+        assert_eq!(segment_files(&dir), 1);
+
+        // 分区被释放后自动移除
+        drop(queue);
+        assert_eq!(manager.run_once(), 0);
+        assert!(manager.is_empty());
+    }
+
+    #[test]
+    fn test_tiered_storage() {
+        let dir = setup_dir("test_tiered_storage");
+        let remote_dir = setup_dir("test_tiered_storage_remote");
+        let remote: Arc<dyn RemoteStorage> = Arc::new(LocalDirRemoteStorage::new(&remote_dir).unwrap());
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to create LogQueue");
+        queue.set_remote_storage(remote.clone(), "topic-0").unwrap();
+        for i in 0..200 {
+            append_keyed(&mut queue, None, Some(&format!("message-{}", i)), 1_000 + i);
+        }
+        let segments = segment_files(&dir);
+        assert!(segments > 3);
+
+        // 本地只保留活跃段，已关闭的段全部上传后删除本地副本
+        let deleted = queue.offload_segments(&RetentionPolicy::new(-1, 0), 1_200).unwrap();
+        assert_eq!(deleted, segments - 1);
+        assert_eq!(segment_files(&dir), 1);
+        assert_eq!(segment_files(&format!("{}/topic-0", remote_dir)), segments - 1);
+        assert!(queue.local_log_start_offset() > 0);
+        assert_eq!(queue.log_start_offset(), 0);
+
+        // 早于本地起始 offset 的记录透明地从远程读取
+        for offset in [0, 57, queue.local_log_start_offset() - 1, 199] {
+            assert_eq!(queue.read_message(offset).unwrap(), Some(format!("message-{}", offset).into_bytes()));
+        }
+        let range = queue.read_range(0, usize::MAX, 10).unwrap();
+        assert_eq!(range.records.len(), 10);
+        assert_eq!(range.next_offset, 10);
+
+        // 重新打开后从清单恢复远程段
+        drop(queue);
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to reopen LogQueue");
+        queue.set_remote_storage(remote, "topic-0").unwrap();
+        assert_eq!(queue.read_message(3).unwrap(), Some(b"message-3".to_vec()));
+
+        // 总保留策略同时删除远程段
+        let deleted = queue.apply_retention(&RetentionPolicy::new(100, -1), 1_200).unwrap();
+        assert!(deleted > 0);
+        assert!(matches!(queue.read_message(0), Err(StorageError::OffsetOutOfRange { .. })));
+        assert!(queue.log_start_offset() > 0 && queue.log_start_offset() <= 100);
+        assert_eq!(segment_files(&format!("{}/topic-0", remote_dir)), segments - 1 - deleted);
+        assert_eq!(queue.read_message(150).unwrap(), Some(b"message-150".to_vec()));
+    }
+
+    #[test]
+    fn test_truncate_and_delete_records() {
+        let dir = setup_dir("test_truncate_and_delete_records");
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to create LogQueue");
+        for i in 0..200 {
+            append_keyed(&mut queue, None, Some(&format!("message-{}", i)), 1_000 + i);
+        }
+        let segments = segment_files(&dir);
+
+        // 截断删除之后的段，之后的写入从截断点开始
+        queue.truncate_to(50).unwrap();
+        assert_eq!(queue.log_end_offset(), 50);
+        assert!(segment_files(&dir) < segments);
+        assert_eq!(queue.read_message(49).unwrap(), Some(b"message-49".to_vec()));
+        assert_eq!(queue.read_message(50).unwrap(), None);
+        assert_eq!(queue.append_message(b"rewritten").unwrap(), 50);
+
+        // 推进日志起始 offset，早于它的读取失败
+        assert_eq!(queue.delete_records_before(30).unwrap(), 30);
+        assert!(matches!(queue.read_message(29), Err(StorageError::OffsetOutOfRange { .. })));
+        assert!(matches!(queue.read_range(0, usize::MAX, 10), Err(StorageError::OffsetOutOfRange { .. })));
+        assert_eq!(queue.read_message(30).unwrap(), Some(b"message-30".to_vec()));
+        assert!(matches!(queue.delete_records_before(1_000), Err(StorageError::OffsetOutOfRange { .. })));
+        assert!(matches!(queue.truncate_to(10), Err(StorageError::OffsetOutOfRange { .. })));
+
+        // 重新打开后起始 offset 保持不变
+        drop(queue);
+        let queue = LogQueue::new(&dir, 1024).expect("Failed to reopen LogQueue");
+        assert_eq!(queue.log_start_offset(), 30);
+        assert_eq!(queue.log_end_offset(), 51);
+        assert!(matches!(queue.read_message(0), Err(StorageError::OffsetOutOfRange { .. })));
+        assert_eq!(queue.read_message(50).unwrap(), Some(b"rewritten".to_vec()));
+    }
+
+    #[test]
+    fn test_flush_policy_and_recovery_point() {
+        let dir = setup_dir("test_flush_policy_and_recovery_point");
+        let mut queue = LogQueue::new(&dir, 1024).expect("Failed to create LogQueue");
+        assert_eq!(queue.recovery_point(), 0);
+
+        // 由操作系统回写时追加不推进恢复点
+        queue.append_message(b"os").unwrap();
+        assert_eq!(queue.recovery_point(), 0);
+
+        // 每次写入刷盘，返回时 offset 已持久化
+        queue.set_flush_policy(FlushPolicy::EveryWrite);
+        let offset = queue.append_message(b"write").unwrap();
+        assert_eq!(queue.recovery_point(), offset + 1);
+
+        // 按消息数刷盘，跨越多个日志段
+        queue.set_flush_policy(FlushPolicy::EveryMessages(50));
+        for _ in 0..49 {
+            queue.append_message(b"hello kafka").unwrap();
+        }
+        assert_eq!(queue.recovery_point(), 2);
+        queue.append_message(b"hello kafka").unwrap();
+        assert_eq!(queue.recovery_point(), 52);
+
+        // 截断后恢复点不超过日志末尾
+        queue.truncate_to(10).unwrap();
+        assert_eq!(queue.recovery_point(), 10);
+    }
+
+    #[test]
+    fn test_log_flusher() {
+        let dir = setup_dir("test_log_flusher");
+        let first = Arc::new(Mutex::new(LogQueue::new(&format!("{}/topic-0", dir), 1024).unwrap()));
+        let second = Arc::new(Mutex::new(LogQueue::new(&format!("{}/topic-1", dir), 1024).unwrap()));
+
+        let flusher = LogFlusher::new(FlushPolicy::Interval(60_000));
+        flusher.register("topic-0", &first, None);
+        flusher.register("topic-1", &second, Some(FlushPolicy::OsManaged));
+        for _ in 0..10 {
+            first.lock().unwrap().append_message(b"hello kafka").unwrap();
+            second.lock().unwrap().append_message(b"hello kafka").unwrap();
+        }
+        // 间隔未到期时不刷盘
+        assert_eq!(flusher.run_once(), 0);
+
+        // 默认策略变更只作用于没有覆盖的分区
+        flusher.set_default_policy(FlushPolicy::Interval(1));
+        assert_eq!(second.lock().unwrap().flush_policy(), FlushPolicy::OsManaged);
+        let handle = flusher.start(Duration::from_millis(5)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        handle.shutdown();
+        let recovery_points = flusher.recovery_points();
+        assert_eq!(recovery_points["topic-0"], 10);
+        assert_eq!(recovery_points["topic-1"], 0);
+
+        drop(first);
+        assert_eq!(flusher.run_once(), 0);
+        assert_eq!(flusher.len(), 1);
+    }
+
+    #[test]
+    fn test_recovery_checkpoint_and_clean_shutdown() {
+        use queue::recovery::{self, CLEAN_SHUTDOWN_FILE};
+        use storage::SegmentConfig;
+
+        let dir = setup_dir("test_recovery_checkpoint_and_clean_shutdown");
+        let config = SegmentConfig {
+            max_segment_size: 1024,
+            ..SegmentConfig::default()
+        };
+        let flusher = LogFlusher::default();
+        let mut queues = Vec::new();
+        for partition in 0..4 {
+            let name = format!("topic-{}", partition);
+            let queue = Arc::new(Mutex::new(LogQueue::with_config(&format!("{}/{}", dir, name), config.clone()).unwrap()));
+            for i in 0..100 {
+                queue.lock().unwrap().append_message(format!("message-{}", i).as_bytes()).unwrap();
+            }
+            flusher.register(&name, &queue, None);
+            queues.push(queue);
+        }
+
+        // 检查点格式往返，由操作系统回写时尚未刷盘
+        flusher.checkpoint_recovery_points().unwrap();
+        let checkpoint = recovery::read_recovery_checkpoint(&dir).unwrap();
+        assert_eq!(checkpoint.len(), 4);
+        assert_eq!(checkpoint["topic-0"], 0);
+
+        // 正常关闭后信任全部日志段
+        flusher.shutdown().unwrap();
+        assert_eq!(recovery::read_recovery_checkpoint(&dir).unwrap()["topic-3"], 100);
+        assert!(std::path::Path::new(&format!("{}/{}", dir, CLEAN_SHUTDOWN_FILE)).exists());
+        drop(queues);
+        let mut logs = recovery::load_logs(&dir, &config, 2).unwrap();
+        assert_eq!(logs.len(), 4);
+        assert!(!std::path::Path::new(&format!("{}/{}", dir, CLEAN_SHUTDOWN_FILE)).exists());
+        for queue in logs.values_mut() {
+            assert_eq!(queue.log_end_offset(), 100);
+            assert_eq!(queue.read_message(99).unwrap(), Some(b"message-99".to_vec()));
+        }
+        let queue = logs.get_mut("topic-1").unwrap();
+        assert_eq!(queue.append_message(b"after restart").unwrap(), 100);
+        drop(logs);
+
+        // 非正常关闭：恢复点之后损坏的尾部被截断，之前的段不校验
+        let log_dir = format!("{}/topic-1", dir);
+        let mut last = fs::read_dir(&log_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect::<Vec<_>>();
+        last.sort();
+        let last = last.pop().unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&last).unwrap();
+        std::io::Write::write_all(&mut file, &[0xff; 10]).unwrap();
+        drop(file);
+        let mut logs = recovery::load_logs(&dir, &config, 3).unwrap();
+        let queue = logs.get_mut("topic-1").unwrap();
+        assert_eq!(queue.log_end_offset(), 101);
+        assert_eq!(queue.read_message(100).unwrap(), Some(b"after restart".to_vec()));
+        assert_eq!(queue.recovery_point(), 101);
+    }
+
+    #[test]
+    fn test_with_config_only_recovers_tail() {
+        use storage::SegmentConfig;
+
+        let dir = setup_dir("test_with_config_only_recovers_tail");
+        let config = SegmentConfig {
+            max_segment_size: 1024,
+            index_interval_bytes: 1,
+            ..SegmentConfig::default()
+        };
+        let mut queue = LogQueue::with_config(&dir, config.clone()).unwrap();
+        for i in 0..100 {
+            queue.append_message(format!("message-{}", i).as_bytes()).unwrap();
+        }
+        queue.flush().unwrap();
+        drop(queue);
+
+        // 第一个段开头的批次损坏，不在最后一个索引项之后，普通打开不校验也不截断
+        let first = format!("{}/{:020}.log", dir, 0);
+        let mut data = fs::read(&first).unwrap();
+        data[70] ^= 0xff;
+        fs::write(&first, &data).unwrap();
+        let queue = LogQueue::with_config(&dir, config).unwrap();
+        assert_eq!(queue.log_end_offset(), 100);
+        assert_eq!(queue.read_message(1).unwrap(), Some(b"message-1".to_vec()));
+        assert_eq!(queue.read_message(99).unwrap(), Some(b"message-99".to_vec()));
+    }
+
+    #[test]
+    fn test_repair_only_segments_past_recovery_point() {
+        use storage::SegmentConfig;
+
+        let dir = setup_dir("test_repair_only_segments_past_recovery_point");
+        let config = SegmentConfig {
+            max_segment_size: 1024,
+            index_interval_bytes: 1,
+            ..SegmentConfig::default()
+        };
+        let mut queue = LogQueue::with_config(&dir, config.clone()).unwrap();
+        for i in 0..100 {
+            queue.append_message(format!("message-{}", i).as_bytes()).unwrap();
+        }
+        queue.flush().unwrap();
+        drop(queue);
+        let mut segments: Vec<u64> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.unwrap().file_name().to_str()?.strip_suffix(".log")?.parse().ok())
+            .collect();
+        segments.sort();
+        let (first, last) = (segments[0], *segments.last().unwrap());
+
+        // 普通打开：索引截断成不完整的条目时从日志重建
+        let index = format!("{}/{:020}.index", dir, first);
+        let len = fs::metadata(&index).unwrap().len();
+        fs::OpenOptions::new().write(true).open(&index).unwrap().set_len(len - 5).unwrap();
+        let queue = LogQueue::with_config(&dir, config.clone()).unwrap();
+        assert_eq!(queue.read_message(1).unwrap(), Some(b"message-1".to_vec()));
+        drop(queue);
+
+        // 非正常关闭：只修复恢复点之后的段，之前的段即使有损坏的批次也不校验
+        for base_offset in [first, last] {
+            let log = format!("{}/{:020}.log", dir, base_offset);
+            let mut data = fs::read(&log).unwrap();
+            data[70] ^= 0xff;
+            fs::write(&log, &data).unwrap();
+        }
+        let queue = LogQueue::open_with_recovery(&dir, config, last, false).unwrap();
+        assert_eq!(queue.read_message(1).unwrap(), Some(b"message-1".to_vec()));
+        assert_eq!(queue.log_end_offset(), last);
+    }
+
+    #[test]
+    fn test_partition_log_tombstone() {
+        use queue::{MemoryLog, PartitionLog};
+
+        // 文件日志和内存日志对墓碑记录的读取结果一致
+        fn check(log: &mut impl PartitionLog) {
+            let mut batch = RecordBatch::new(vec![
+                Record::new(Some(b"key".to_vec()), Some(b"value".to_vec())),
+                Record::new(Some(b"key".to_vec()), None),
+            ]);
+            assert_eq!(log.append_batch(&mut batch).unwrap(), 0);
+            assert_eq!(log.read_message(0).unwrap(), Some(b"value".to_vec()));
+            assert_eq!(log.read_message(1).unwrap(), None);
+            assert_eq!(log.read_record(1).unwrap().unwrap().value, None);
+        }
+
+        let dir = setup_dir("test_partition_log_tombstone");
+        check(&mut LogQueue::new(&dir, 1024).unwrap());
+        check(&mut MemoryLog::new(1024));
+    }
+
+    #[test]
+    fn test_memory_log() {
+        use queue::{MemoryLog, PartitionLog};
+
+        let batch = |n: u8| RecordBatch::new((0..n).map(|i| Record::new(None, Some(vec![i; 100]))).collect());
+        let batch_size = batch(10).encode().unwrap().len();
+        let mut log = MemoryLog::new(batch_size * 3);
+        assert_eq!(log.append_message(b"first").unwrap(), 0);
+        for i in 0..3 {
+            assert_eq!(log.append_batch(&mut batch(10)).unwrap(), 1 + i * 10);
+        }
+        assert_eq!(log.log_end_offset(), 31);
+
+        // 超过字节数上限时淘汰最早的批次
+        assert_eq!(log.batch_count(), 3);
+        assert_eq!(log.log_start_offset(), 1);
+        assert!(matches!(log.read_message(0), Err(StorageError::OffsetOutOfRange { .. })));
+        assert_eq!(log.read_message(15).unwrap(), Some(vec![4; 100]));
+        assert_eq!(log.read_batch(15).unwrap().unwrap().base_offset, 11);
+        assert_eq!(log.read_message(31).unwrap(), None);
+
+        // 范围读取与 LogQueue 相同：至少返回一个批次，记录数达到上限时停在批次中间
+        let range = log.read_range(5, batch_size, usize::MAX).unwrap();
+        assert_eq!(range.records.len(), 6);
+        assert_eq!(range.next_offset, 11);
+        let range = log.read_range(5, usize::MAX, 10).unwrap();
+        assert_eq!(range.records.first().unwrap().offset, 5);
+        assert_eq!(range.next_offset, 15);
+
+        // 截断到批次中间后从截断位置继续写入
+        log.truncate_to(25).unwrap();
+        assert_eq!(log.log_end_offset(), 25);
+        assert_eq!(log.read_message(24).unwrap(), Some(vec![3; 100]));
+        assert_eq!(log.read_message(25).unwrap(), None);
+        assert_eq!(log.append_message(b"next").unwrap(), 25);
+
+        assert_eq!(log.delete_records_before(21).unwrap(), 21);
+        assert_eq!(log.batch_count(), 2);
+        assert!(log.read_message(20).is_err());
+        assert_eq!(log.read_message(21).unwrap(), Some(vec![0; 100]));
+        assert_eq!(log.flush().unwrap(), 26);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        use queue::restore_snapshot;
+
+        let dir = setup_dir("test_snapshot_and_restore");
+        let snapshot_dir = format!("{}-snapshot", dir);
+        let restored_dir = format!("{}-restored", dir);
+        let _ = fs::remove_dir_all(&snapshot_dir);
+        let _ = fs::remove_dir_all(&restored_dir);
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        for i in 0..100 {
+            append_keyed(&mut queue, None, Some(&format!("value-{}", i)), i);
+        }
+        queue.delete_records_before(5).unwrap();
+        assert!(segment_files(&dir) > 2);
+
+        // 结束 offset 早于日志起始 offset 时失败，快照目录已存在时不覆盖
+        assert!(queue.snapshot(&snapshot_dir, Some(3)).is_err());
+        let snapshot = queue.snapshot(&snapshot_dir, Some(60)).unwrap();
+        assert_eq!(snapshot.log_start_offset, 5);
+        assert_eq!(snapshot.end_offset, 60);
+        assert_eq!(snapshot.segments, segment_files(&snapshot_dir));
+        assert!(queue.snapshot(&snapshot_dir, None).is_err());
+
+        // 快照之后的写入不影响快照
+        append_keyed(&mut queue, None, Some("value-100"), 100);
+        drop(queue);
+
+        restore_snapshot(&snapshot_dir, &restored_dir).unwrap();
+        assert!(restore_snapshot(&snapshot_dir, &restored_dir).is_err());
+        let mut restored = LogQueue::new(&restored_dir, 1024).unwrap();
+        assert_eq!(restored.log_start_offset(), 5);
+        assert_eq!(restored.log_end_offset(), 60);
+        let records = read_all(&mut restored);
+        assert_eq!(records.len(), 55);
+        assert_eq!(records[0].value.as_deref(), Some("value-5".as_bytes()));
+        assert_eq!(records[54].value.as_deref(), Some("value-59".as_bytes()));
+        assert_eq!(append_keyed(&mut restored, None, Some("value-60"), 60), 60);
+
+        // 没有超过结束 offset 的快照包含全部记录
+        let full_dir = format!("{}-full", dir);
+        let _ = fs::remove_dir_all(&full_dir);
+        let snapshot = restored.snapshot(&full_dir, None).unwrap();
+        assert_eq!((snapshot.log_start_offset, snapshot.end_offset), (5, 61));
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        let dir = setup_dir("test_concurrent_readers");
+        let config = storage::SegmentConfig {
+            max_segment_size: 4096,
+            index_interval_bytes: 256,
+            ..storage::SegmentConfig::default()
+        };
+        let mut queue = LogQueue::with_config(&dir, config).unwrap();
+        let reader = queue.reader();
+        const MESSAGES: u64 = 2000;
+
+        // 多个读取方与追加同时进行，只会读到完整写入的记录，且 offset 与内容一致
+        let readers: Vec<_> = (0..4)
+            .map(|id| {
+                let reader = reader.clone();
+                std::thread::spawn(move || {
+                    let deadline = std::time::Instant::now() + Duration::from_secs(30);
+                    let mut next_offset = 0;
+                    while next_offset < MESSAGES {
+                        assert!(std::time::Instant::now() < deadline, "reader {} stalled at {}", id, next_offset);
+                        let end = reader.log_end_offset();
+                        let range = reader.read_range(next_offset, 1024, usize::MAX).unwrap();
+                        for record in &range.records {
+                            assert_eq!(record.offset, next_offset);
+                            assert_eq!(record.value.as_deref(), Some(format!("message-{}", record.offset).as_bytes()));
+                            next_offset += 1;
+                        }
+                        // 发布的结束 offset 之前的记录都可以按 offset 读取
+                        if end > 0 {
+                            let offset = (next_offset * 7 + id) % end;
+                            let message = reader.read_message(offset).unwrap();
+                            assert_eq!(message, Some(format!("message-{}", offset).into_bytes()));
+                        }
+                    }
+                    next_offset
+                })
+            })
+            .collect();
+
+        for i in 0..MESSAGES {
+            assert_eq!(queue.append_message(format!("message-{}", i).as_bytes()).unwrap(), i);
+        }
+        for handle in readers {
+            assert_eq!(handle.join().unwrap(), MESSAGES);
+        }
+        assert!(segment_files(&dir) > 1);
+        assert_eq!(reader.log_end_offset(), MESSAGES);
+        assert_eq!(reader.offset_for_timestamp(0).unwrap(), Some(0));
+
+        // 段列表变化后读取方读取新的段表，记录删除后返回 OffsetOutOfRange
+        queue.delete_records_before(1500).unwrap();
+        assert!(reader.local_log_start_offset() > 0);
+        assert!(matches!(reader.read_message(100), Err(StorageError::OffsetOutOfRange { .. })));
+        assert_eq!(reader.read_message(1500).unwrap(), Some(b"message-1500".to_vec()));
+    }
+
+    /// 测试用的伪随机数（xorshift），保证失败时可以复现
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_random_seeks_across_segments() {
+        let dir = setup_dir("test_random_seeks_across_segments");
+        let config = storage::SegmentConfig {
+            max_segment_size: 1024,
+            index_interval_bytes: 128,
+            ..storage::SegmentConfig::default()
+        };
+        let mut queue = LogQueue::with_config(&dir, config).unwrap();
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        // 每个批次 1~5 条记录，部分记录带有重复的 key，压缩后留下 offset 空洞
+        while queue.log_end_offset() < 1500 {
+            let count = next_random(&mut seed) % 5 + 1;
+            let records = (0..count)
+                .map(|_| {
+                    let key = next_random(&mut seed) % 4;
+                    let key = (key == 0).then(|| format!("key-{}", next_random(&mut seed) % 20).into_bytes());
+                    Record::new(key, Some(format!("value-{}", next_random(&mut seed)).into_bytes()))
+                })
+                .collect();
+            queue.append_batch(&mut RecordBatch::new(records)).unwrap();
+        }
+        assert!(queue.compact(&LogCleaner::new(CleanerConfig::default())).unwrap() > 0);
+        queue.delete_records_before(200).unwrap();
+        assert!(segment_files(&dir) > 10);
+
+        let expected: HashMap<u64, Record> = read_all(&mut queue).into_iter().map(|r| (r.offset, r)).collect();
+        let log_start_offset = queue.log_start_offset();
+        let log_end_offset = queue.log_end_offset();
+        assert!(expected.len() < (log_end_offset - log_start_offset) as usize);
+
+        let reader = queue.reader();
+        for _ in 0..5000 {
+            let offset = next_random(&mut seed) % (log_end_offset + 50);
+            let lookup = if offset < log_start_offset {
+                assert!(matches!(queue.read_message(offset), Err(StorageError::OffsetOutOfRange { .. })));
+                OffsetLookup::BelowLogStart { log_start_offset }
+            } else if offset >= log_end_offset {
+                assert_eq!(queue.read_message(offset).unwrap(), None);
+                OffsetLookup::NotYetWritten { log_end_offset }
+            } else {
+                match expected.get(&offset) {
+                    Some(record) => {
+                        assert_eq!(queue.read_message(offset).unwrap(), record.value);
+                        assert_eq!(reader.read_message(offset).unwrap(), record.value);
+                        OffsetLookup::Found(record.clone())
+                    }
+                    None => {
+                        assert_eq!(queue.read_message(offset).unwrap(), None);
+                        OffsetLookup::Removed
+                    }
+                }
+            };
+            assert_eq!(queue.lookup(offset).unwrap(), lookup, "offset {}", offset);
+            assert_eq!(reader.lookup(offset).unwrap(), lookup, "offset {}", offset);
+        }
+
+        // 先读较新的 offset 再回退读取较旧的 offset
+        let mut offsets: Vec<_> = expected.keys().copied().collect();
+        offsets.sort_unstable();
+        for &offset in offsets.iter().rev().step_by(37) {
+            assert_eq!(queue.read_record(offset).unwrap().as_ref(), expected.get(&offset));
+        }
+        assert_eq!(queue.read_record(offsets[offsets.len() - 1]).unwrap().as_ref(), expected.get(&offsets[offsets.len() - 1]));
+        assert_eq!(queue.read_record(offsets[0]).unwrap().as_ref(), expected.get(&offsets[0]));
+    }
+
+    #[tokio::test]
+    async fn test_tail_subscription() {
+        let dir = setup_dir("test_tail_subscription");
+        let queue = Arc::new(Mutex::new(LogQueue::new(&dir, 1024).unwrap()));
+        let (end, waiter) = {
+            let mut queue = queue.lock().unwrap();
+            queue.append_message(b"first").unwrap();
+            (queue.log_end_offset(), queue.wait_past(1))
+        };
+        assert_eq!(end, 1);
+        // 已写入的 offset 立即完成；future 不借用队列，释放锁之后再等待
+        let written = queue.lock().unwrap().wait_past(0);
+        assert_eq!(written.await, 1);
+        // 尚未写入时一直等待
+        let waiter = tokio::spawn(waiter);
+        let pending = queue.lock().unwrap().wait_past(1);
+        assert!(tokio::time::timeout(Duration::from_millis(50), pending).await.is_err());
+
+        let writer = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            writer.lock().unwrap().append_message(b"second").unwrap();
+        });
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap(), 2);
+        assert_eq!(queue.lock().unwrap().read_message(1).unwrap(), Some(b"second".to_vec()));
+
+        // 接收端跟随截断与后续写入
+        let reader = queue.lock().unwrap().reader();
+        let mut receiver = reader.subscribe();
+        assert_eq!(*receiver.borrow_and_update(), 2);
+        queue.lock().unwrap().truncate_to(1).unwrap();
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(*receiver.borrow_and_update(), 1);
+        queue.lock().unwrap().append_message(b"third").unwrap();
+        assert_eq!(reader.wait_past(1).await, 2);
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let dir = setup_dir("test_iter_from");
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        for i in 0..200 {
+            let key = format!("key-{}", i % 20);
+            append_keyed(&mut queue, Some(&key), Some(&format!("value-{}", i)), 1_000 + i);
+        }
+        // 跨越多个日志段，产出的 offset 与记录一一对应，读到日志末尾结束
+        let all: Vec<(u64, Record)> = queue.iter_from(0).collect::<Result<_, _>>().unwrap();
+        assert_eq!(all.len(), 200);
+        assert!(all.iter().enumerate().all(|(i, (offset, record))| *offset == i as u64 && record.offset == *offset));
+        let tail: Vec<u64> = queue.iter_from(195).map(|item| item.unwrap().0).collect();
+        assert_eq!(tail, vec![195, 196, 197, 198, 199]);
+        assert_eq!(queue.iter_from(200).count(), 0);
+
+        // 压缩后自动跳过空洞，与范围读取的结果一致
+        queue.compact(&LogCleaner::new(CleanerConfig::default())).unwrap();
+        let compacted: Vec<Record> = queue.iter_from(0).map(|item| item.unwrap().1).collect();
+        assert_eq!(compacted, read_all(&mut queue));
+        assert!(compacted.len() < 200);
+
+        // 早于日志起始 offset 时产出错误后结束
+        queue.delete_records_before(100).unwrap();
+        let mut iter = queue.iter_from(50);
+        assert!(matches!(iter.next(), Some(Err(StorageError::OffsetOutOfRange { .. }))));
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        // LogReader 的迭代器不借用队列，之后追加的记录也能读到
+        let mut iter = queue.reader().iter_from(199);
+        assert_eq!(iter.next().unwrap().unwrap().0, 199);
+        append_keyed(&mut queue, Some("key-0"), Some("new"), 3_000);
+        assert_eq!(iter.next().unwrap().unwrap().0, 200);
+        assert!(iter.next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_from() {
+        use futures::StreamExt;
+
+        let dir = setup_dir("test_stream_from");
+        let queue = Arc::new(Mutex::new(LogQueue::new(&dir, 1024).unwrap()));
+        let mut stream = {
+            let mut queue = queue.lock().unwrap();
+            for i in 0..3 {
+                queue.append_message(format!("message-{}", i).as_bytes()).unwrap();
+            }
+            queue.stream_from(1)
+        };
+        for offset in 1..3 {
+            let (next, record) = stream.next().await.unwrap().unwrap();
+            assert_eq!(next, offset);
+            assert_eq!(record.value, Some(format!("message-{}", offset).into_bytes()));
+        }
+        // 读到日志末尾后等待新记录，而不是结束
+        assert!(tokio::time::timeout(Duration::from_millis(50), stream.next()).await.is_err());
+
+        let writer = queue.clone();
+        std::thread::spawn(move || {
+            for i in 3..50 {
+                writer.lock().unwrap().append_message(format!("message-{}", i).as_bytes()).unwrap();
+            }
+        });
+        for offset in 3..50 {
+            let (next, _) = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap().unwrap().unwrap();
+            assert_eq!(next, offset);
+        }
+        assert_eq!(stream.next_offset(), 50);
+    }
+
+    #[test]
+    fn test_capacity_reject() {
+        use queue::{CapacityLimit, OverflowPolicy};
+
+        let dir = setup_dir("test_capacity_reject");
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        queue.set_capacity(CapacityLimit { max_records: Some(10), ..Default::default() });
+        for i in 0..10 {
+            queue.append_message(format!("message-{}", i).as_bytes()).unwrap();
+        }
+        let err = queue.append_message(b"overflow").unwrap_err();
+        assert!(StorageError::is_queue_full(&err));
+        // 一个批次超过剩余空间时整体拒绝
+        queue.delete_records_before(8).unwrap();
+        let mut batch = RecordBatch::new((0..9).map(|_| Record::new(None, Some(b"x".to_vec()))).collect());
+        assert!(StorageError::is_queue_full(&queue.append_batch(&mut batch).unwrap_err()));
+        assert_eq!(queue.log_end_offset(), 10);
+        // 消费者推进日志起始 offset 后腾出空间
+        queue.delete_records_before(10).unwrap();
+        assert_eq!(queue.append_batch(&mut batch).unwrap(), 10);
+        // 记录数超过上限的批次即使日志为空也无法写入
+        queue.delete_records_before(19).unwrap();
+        let mut oversized = RecordBatch::new((0..11).map(|_| Record::new(None, Some(b"x".to_vec()))).collect());
+        assert!(StorageError::is_queue_full(&queue.append_batch(&mut oversized).unwrap_err()));
+        assert_eq!(queue.log_end_offset(), 19);
+
+        // 字节上限按包含未删除记录的日志段计算，全部消费后活跃段不再占用
+        let dir = setup_dir("test_capacity_reject_bytes");
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        queue.set_capacity(CapacityLimit { max_bytes: Some(2048), overflow: OverflowPolicy::Reject, ..Default::default() });
+        let full = loop {
+            if let Err(e) = queue.append_message(&[7u8; 100]) {
+                break e;
+            }
+        };
+        assert!(StorageError::is_queue_full(&full));
+        let end = queue.log_end_offset();
+        queue.delete_records_before(end).unwrap();
+        assert_eq!(queue.append_message(b"after").unwrap(), end);
+    }
+
+    #[test]
+    fn test_capacity_drop_oldest() {
+        use queue::{CapacityLimit, OverflowPolicy};
+
+        let dir = setup_dir("test_capacity_drop_oldest");
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        queue.set_capacity(CapacityLimit { max_records: Some(10), overflow: OverflowPolicy::DropOldest, ..Default::default() });
+        for i in 0..25 {
+            queue.append_message(format!("message-{}", i).as_bytes()).unwrap();
+        }
+        assert_eq!(queue.log_start_offset(), 15);
+        assert_eq!(queue.log_end_offset(), 25);
+        assert!(matches!(queue.read_message(14), Err(StorageError::OffsetOutOfRange { .. })));
+        assert_eq!(queue.read_message(15).unwrap(), Some(b"message-15".to_vec()));
+        // 记录数超过上限的批次直接拒绝，不丢弃已有的记录
+        let mut oversized = RecordBatch::new((0..11).map(|_| Record::new(None, Some(b"x".to_vec()))).collect());
+        assert!(StorageError::is_queue_full(&queue.append_batch(&mut oversized).unwrap_err()));
+        assert_eq!(queue.log_start_offset(), 15);
+        assert_eq!(queue.log_end_offset(), 25);
+
+        // 字节上限整体丢弃最旧的段，磁盘占用不超过上限加一个段
+        let dir = setup_dir("test_capacity_drop_oldest_bytes");
+        let mut queue = LogQueue::new(&dir, 1024).unwrap();
+        queue.set_capacity(CapacityLimit { max_bytes: Some(4096), overflow: OverflowPolicy::DropOldest, ..Default::default() });
+        for _ in 0..500 {
+            queue.append_message(&[7u8; 100]).unwrap();
+        }
+        let size: u64 = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".log"))
+            .map(|entry| entry.metadata().unwrap().len())
+            .sum();
+        assert!(size <= 4096 + 1024 + 200, "size {}", size);
+        assert!(queue.log_start_offset() > 0);
+        assert_eq!(queue.log_end_offset(), 500);
+        let start = queue.log_start_offset();
+        assert_eq!(queue.read_message(start).unwrap(), Some(vec![7u8; 100]));
+    }
+
+    #[test]
+    fn test_capacity_block() {
+        use queue::{append_with_backpressure, CapacityLimit, OverflowPolicy};
+
+        let dir = setup_dir("test_capacity_block");
+        let queue = Arc::new(Mutex::new(LogQueue::new(&dir, 1024).unwrap()));
+        queue.lock().unwrap().set_capacity(CapacityLimit {
+            max_records: Some(5),
+            overflow: OverflowPolicy::Block { timeout_ms: 5_000 },
+            ..Default::default()
+        });
+        for _ in 0..5 {
+            queue.lock().unwrap().append_message(b"work").unwrap();
+        }
+        // 写满后生产者在锁外等待，消费者仍然可以读取并推进日志起始 offset
+        let producer = {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                let mut batch = RecordBatch::new(vec![Record::new(None, Some(b"blocked".to_vec()))]);
+                append_with_backpressure(&*queue, &mut batch)
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!producer.is_finished());
+        assert_eq!(queue.lock().unwrap().read_message(0).unwrap(), Some(b"work".to_vec()));
+        queue.lock().unwrap().delete_records_before(1).unwrap();
+        assert_eq!(producer.join().unwrap().unwrap(), 5);
+        assert_eq!(queue.lock().unwrap().read_message(5).unwrap(), Some(b"blocked".to_vec()));
+
+        // 等待超时后返回写满错误
+        queue.lock().unwrap().set_capacity(CapacityLimit {
+            max_records: Some(5),
+            overflow: OverflowPolicy::Block { timeout_ms: 50 },
+            ..Default::default()
+        });
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(b"late".to_vec()))]);
+        let started = std::time::Instant::now();
+        let err = append_with_backpressure(&*queue, &mut batch).unwrap_err();
+        assert!(StorageError::is_queue_full(&err));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(queue.lock().unwrap().log_end_offset(), 6);
+
+        // 记录数超过上限的批次不等待，直接返回写满错误
+        queue.lock().unwrap().set_capacity(CapacityLimit {
+            max_records: Some(5),
+            overflow: OverflowPolicy::Block { timeout_ms: 5_000 },
+            ..Default::default()
+        });
+        let mut oversized = RecordBatch::new((0..6).map(|_| Record::new(None, Some(b"x".to_vec()))).collect());
+        let started = std::time::Instant::now();
+        let err = append_with_backpressure(&*queue, &mut oversized).unwrap_err();
+        assert!(StorageError::is_queue_full(&err));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(queue.lock().unwrap().log_end_offset(), 6);
+    }
+
+    #[test]
+    fn test_memory_log_capacity() {
+        use queue::{CapacityLimit, MemoryLog, OverflowPolicy, PartitionLog};
+
+        let mut log = MemoryLog::new(1024 * 1024);
+        log.set_capacity(CapacityLimit { max_records: Some(3), ..Default::default() });
+        for _ in 0..3 {
+            log.append_message(b"m").unwrap();
+        }
+        assert!(StorageError::is_queue_full(&log.append_message(b"m").unwrap_err()));
+        log.delete_records_before(1).unwrap();
+        assert_eq!(log.append_message(b"m").unwrap(), 3);
+
+        log.set_capacity(CapacityLimit { max_records: Some(3), overflow: OverflowPolicy::DropOldest, ..Default::default() });
+        for _ in 0..4 {
+            log.append_message(b"m").unwrap();
+        }
+        assert_eq!(log.log_start_offset(), 5);
+        assert_eq!(log.log_end_offset(), 8);
+        assert_eq!(log.batch_count(), 3);
+        let mut oversized = RecordBatch::new((0..4).map(|_| Record::new(None, Some(b"m".to_vec()))).collect());
+        assert!(StorageError::is_queue_full(&log.append_batch(&mut oversized).unwrap_err()));
+        assert_eq!(log.log_start_offset(), 5);
+        assert_eq!(log.batch_count(), 3);
+    }
 }
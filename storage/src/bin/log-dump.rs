@@ -0,0 +1,180 @@
+//! 日志段检查工具
+//!
+//! ```text
+//! log-dump [--no-records] [--no-index] [--payload-bytes <n>] [--master-key <file>] <path>...
+//! ```
+//!
+//! `path` 可以是 `.log`/`.index`/`.timeindex` 文件或分区目录，文件只读打开，可用于复制出来的分区目录。
+//! 打印批次、记录与索引条目，并列出检查发现的问题；存在错误（offset 间隔之外的问题）时退出码为 1。
+
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use storage::record::{COMPRESSION_CODEC_MASK, CONTROL_FLAG, ENCRYPTED_FLAG, TIMESTAMP_TYPE_FLAG, TRANSACTIONAL_FLAG};
+use storage::{dump_dir, dump_segment, CompressionType, EncryptionKeys, MasterKey, Record, SegmentDump};
+
+const USAGE: &str = "用法: log-dump [--no-records] [--no-index] [--payload-bytes <n>] [--master-key <file>] <path>...";
+
+struct Options {
+    records: bool,
+    index: bool,
+    payload_bytes: usize,
+    keys: Option<EncryptionKeys>,
+    paths: Vec<String>,
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let mut consistent = true;
+    for path in &options.paths {
+        let dumps = if Path::new(path).is_dir() {
+            dump_dir(path, options.keys.as_ref())
+        } else {
+            dump_segment(path, options.keys.as_ref()).map(|dump| vec![dump])
+        };
+        match dumps {
+            Ok(dumps) => {
+                for dump in &dumps {
+                    print_dump(dump, &options);
+                    consistent &= dump.is_consistent();
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                consistent = false;
+            }
+        }
+    }
+    if !consistent {
+        process::exit(1);
+    }
+}
+
+fn parse_options(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options { records: true, index: true, payload_bytes: 64, keys: None, paths: Vec::new() };
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-records" => options.records = false,
+            "--no-index" => options.index = false,
+            "--payload-bytes" => {
+                let value = args.next().ok_or("参数 --payload-bytes 缺少值")?;
+                options.payload_bytes = value.parse().map_err(|_| format!("参数 --payload-bytes 的值 {} 无效", value))?;
+            }
+            "--master-key" => {
+                let path = args.next().ok_or("参数 --master-key 缺少值")?;
+                let master_key = MasterKey::load(&path).map_err(|e| format!("加载主密钥 {} 失败: {}", path, e))?;
+                options.keys = Some(EncryptionKeys::new(Arc::new(master_key)));
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("未知参数 {}\n{}", arg, USAGE)),
+            _ => options.paths.push(arg),
+        }
+    }
+    if options.paths.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    Ok(options)
+}
+
+fn print_dump(dump: &SegmentDump, options: &Options) {
+    print!("Dumping {} (base offset {}, {} bytes", dump.log_path.display(), dump.base_offset, dump.file_len);
+    if let Some(key_id) = dump.encryption_key_id {
+        print!(", encrypted with key {}", key_id);
+    }
+    println!(")");
+
+    for batch in &dump.batches {
+        println!(
+            "baseOffset: {} lastOffset: {} count: {} position: {} size: {} crc: {:#010x}{} maxTimestamp: {} {}",
+            batch.base_offset,
+            batch.last_offset,
+            batch.record_count,
+            batch.position,
+            batch.size,
+            batch.crc,
+            if batch.crc_valid { "" } else { " (invalid)" },
+            batch.max_timestamp,
+            describe_attributes(batch.attributes),
+        );
+        if let (true, Some(records)) = (options.records, &batch.records) {
+            for record in records {
+                println!("  | {}", describe_record(record, options.payload_bytes));
+            }
+        }
+    }
+
+    if options.index {
+        println!("Index entries: {}", dump.index.len());
+        for (offset, position) in &dump.index {
+            println!("  offset: {} position: {}", offset, position);
+        }
+        println!("Time index entries: {}", dump.time_index.len());
+        for (timestamp, offset) in &dump.time_index {
+            println!("  timestamp: {} offset: {}", timestamp, offset);
+        }
+    }
+
+    if dump.issues.is_empty() {
+        println!("No issues found, next offset {}", dump.next_offset());
+    }
+    for issue in &dump.issues {
+        println!("{}: {}", if issue.is_warning() { "WARN" } else { "ERROR" }, issue);
+    }
+    println!();
+}
+
+fn describe_attributes(attributes: u8) -> String {
+    let compression = CompressionType::from_codec(attributes & COMPRESSION_CODEC_MASK)
+        .map(|compression| compression.name().to_string())
+        .unwrap_or_else(|_| format!("codec-{}", attributes & COMPRESSION_CODEC_MASK));
+    let mut description = format!("compression: {}", compression);
+    if attributes & TIMESTAMP_TYPE_FLAG != 0 {
+        description.push_str(" logAppendTime");
+    }
+    if attributes & TRANSACTIONAL_FLAG != 0 {
+        description.push_str(" transactional");
+    }
+    if attributes & CONTROL_FLAG != 0 {
+        description.push_str(" control");
+    }
+    if attributes & ENCRYPTED_FLAG != 0 {
+        description.push_str(" encrypted");
+    }
+    description
+}
+
+fn describe_record(record: &Record, payload_bytes: usize) -> String {
+    let mut description = format!("offset: {} timestamp: {}", record.offset, record.timestamp);
+    if let Some(key) = &record.key {
+        description.push_str(&format!(" key: {}", preview(key, payload_bytes)));
+    }
+    match &record.value {
+        Some(value) => description.push_str(&format!(" payload({}): {}", value.len(), preview(value, payload_bytes))),
+        None => description.push_str(" payload: <tombstone>"),
+    }
+    for header in &record.headers {
+        description.push_str(&format!(" header: {}", header.key));
+    }
+    description
+}
+
+/// 内容预览：可打印的 UTF-8 按字符串显示，否则显示十六进制，超过 `max_bytes` 的部分省略
+fn preview(data: &[u8], max_bytes: usize) -> String {
+    let shown = &data[..data.len().min(max_bytes)];
+    let ellipsis = if data.len() > max_bytes { "..." } else { "" };
+    match std::str::from_utf8(shown) {
+        Ok(text) if !text.chars().any(char::is_control) => format!("{:?}{}", text, ellipsis),
+        _ => {
+            let hex: String = shown.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("0x{}{}", hex, ellipsis)
+        }
+    }
+}
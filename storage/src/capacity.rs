@@ -0,0 +1,62 @@
+//! 分区容量上限
+//!
+//! 限制分区日志 [log_start_offset, log_end_offset) 范围内保存的字节数和/或记录数，写满后按溢出策略处理：
+//! 拒绝写入、让写入方等待空间释放（带超时），或推进日志起始 offset 丢弃最旧的记录。
+//! 用于把小主题当作有界的工作缓冲，避免失控的生产者写满磁盘。
+//!
+//! 记录数按写入后计算，不会超过上限，记录数本身超过上限的批次按任何策略都直接拒绝；
+//! 字节数在写入前检查，已达到上限时才算写满，因此最多超出一个批次。
+
+/// 分区写满后的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// 拒绝写入，返回 `StorageError::QueueFull`
+    #[default]
+    Reject,
+    /// 写入方等待空间释放（日志起始 offset 推进、段被删除等），超过 `timeout_ms` 毫秒仍然写满时返回 `StorageError::QueueFull`
+    Block { timeout_ms: u64 },
+    /// 推进日志起始 offset，丢弃最旧的记录腾出空间
+    DropOldest,
+}
+
+impl OverflowPolicy {
+    /// 由配置创建：`policy` 为 reject / block / drop_oldest，block 的等待时间为 `block_timeout_ms`
+    pub fn from_config(policy: &str, block_timeout_ms: u64) -> Result<Self, String> {
+        match policy.trim() {
+            "reject" => Ok(OverflowPolicy::Reject),
+            "block" if block_timeout_ms > 0 => Ok(OverflowPolicy::Block { timeout_ms: block_timeout_ms }),
+            "block" => Err("溢出策略 block 的等待时间必须大于 0".to_string()),
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            other => Err(format!("未知的溢出策略: {}", other)),
+        }
+    }
+}
+
+/// 分区容量上限，未设置的上限不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CapacityLimit {
+    /// 最大字节数
+    pub max_bytes: Option<u64>,
+    /// 最大记录数（按 offset 范围计算，被压缩删除的 offset 同样占用）
+    pub max_records: Option<u64>,
+    /// 写满后的处理方式
+    pub overflow: OverflowPolicy,
+}
+
+impl CapacityLimit {
+    /// 单个批次的 `incoming` 条记录是否超过记录数上限，这样的批次按任何溢出策略都无法写入
+    pub fn exceeds(&self, incoming: u64) -> bool {
+        self.max_records.is_some_and(|max_records| incoming > max_records)
+    }
+
+    /// 当前保存 `bytes` 字节、`records` 条记录时，再写入 `incoming` 条记录是否超出上限
+    pub fn would_overflow(&self, bytes: u64, records: u64, incoming: u64) -> bool {
+        self.max_bytes.is_some_and(|max_bytes| bytes >= max_bytes)
+            || self.max_records.is_some_and(|max_records| records + incoming > max_records)
+    }
+
+    /// 写入 `incoming` 条记录前需要丢弃的最旧记录数，使写入后的记录数不超过上限
+    pub fn records_to_drop(&self, records: u64, incoming: u64) -> u64 {
+        self.max_records.map_or(0, |max_records| (records + incoming).saturating_sub(max_records).min(records))
+    }
+}
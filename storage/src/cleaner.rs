@@ -0,0 +1,193 @@
+//! 基于 key 的日志压缩（cleanup.policy=compact）
+//!
+//! 对已关闭的日志段，每个 key 只保留最新的一条记录，没有 key 的记录原样保留；
+//! 墓碑记录（value 为 None）在所在段的最大时间戳超过 `delete_retention_ms` 后删除。
+//!
+//! 压缩后的段先写入日志目录下的 `CLEANED_DIR` 子目录，全部写完后再由 `swap_cleaned_segment`
+//! 逐个替换原文件。替换时先删除原索引、再用 rename 替换 `.log`，任何时刻崩溃都只会留下
+//! 一个完整的 `.log`（原内容或压缩后的内容），缺失的索引在重新打开时按日志内容处理。
+
+use crate::error::Result;
+use crate::record::{now_ms, RecordBatch};
+use crate::segment::{LogSegment, SegmentConfig};
+use super::{INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// 压缩过程中新段文件所在的子目录
+pub const CLEANED_DIR: &str = ".cleaned";
+
+/// 日志清理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CleanupPolicy {
+    /// 按时间/大小删除旧段
+    #[default]
+    Delete,
+    /// 按 key 压缩
+    Compact,
+}
+
+impl FromStr for CleanupPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "delete" => Ok(CleanupPolicy::Delete),
+            "compact" => Ok(CleanupPolicy::Compact),
+            other => Err(format!("未知的清理策略: {}", other)),
+        }
+    }
+}
+
+/// 日志压缩配置
+#[derive(Debug, Clone)]
+pub struct CleanerConfig {
+    /// 墓碑记录的保留时间（毫秒），对应 delete.retention.ms
+    pub delete_retention_ms: i64,
+}
+
+impl Default for CleanerConfig {
+    fn default() -> Self {
+        Self {
+            delete_retention_ms: 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+/// 单个日志段的压缩结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanedSegment {
+    /// 日志段的起始 offset
+    pub base_offset: u64,
+    /// 保留的记录数
+    pub records_retained: usize,
+    /// 删除的记录数
+    pub records_removed: usize,
+}
+
+/// 日志压缩器
+#[derive(Debug, Clone, Default)]
+pub struct LogCleaner {
+    config: CleanerConfig,
+}
+
+impl LogCleaner {
+    pub fn new(config: CleanerConfig) -> Self {
+        Self { config }
+    }
+
+    /// 扫描日志段，记录每个 key 最新一条记录的 offset
+    pub fn build_offset_map<'a>(
+        &self,
+        segments: impl IntoIterator<Item = &'a LogSegment>,
+    ) -> Result<HashMap<Vec<u8>, u64>> {
+        let mut offset_map = HashMap::new();
+        for segment in segments {
+            segment.for_each_batch(|batch| {
+                for record in batch.records {
+                    if let Some(key) = record.key {
+                        offset_map.insert(key, record.offset);
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(offset_map)
+    }
+
+    /// 把日志段中需要保留的记录写入 `log_dir/CLEANED_DIR` 下起始 offset 相同的新段，
+    /// 记录保持原有 offset 与批次属性
+    pub fn clean_segment(
+        &self,
+        segment: &LogSegment,
+        offset_map: &HashMap<Vec<u8>, u64>,
+        log_dir: &str,
+        config: SegmentConfig,
+    ) -> Result<CleanedSegment> {
+        let base_offset = segment.get_base_offset();
+        let cleaned_dir = format!("{}/{}", log_dir.trim_end_matches('/'), CLEANED_DIR);
+        remove_segment_files(&cleaned_dir, base_offset)?;
+
+        // 段内最大时间戳超过保留时间后，墓碑记录可以删除
+        let delete_tombstones = segment.get_max_timestamp().saturating_add(self.config.delete_retention_ms) <= now_ms();
+        let mut cleaned = LogSegment::with_config(&cleaned_dir, base_offset, config)?;
+        let mut result = CleanedSegment {
+            base_offset,
+            records_retained: 0,
+            records_removed: 0,
+        };
+        segment.for_each_batch(|batch| {
+            let attributes = batch.attributes;
+            let total = batch.records.len();
+            let records: Vec<_> = batch
+                .records
+                .into_iter()
+                .filter(|record| match &record.key {
+                    None => true,
+                    Some(key) => {
+                        offset_map.get(key) == Some(&record.offset) && !(record.value.is_none() && delete_tombstones)
+                    }
+                })
+                .collect();
+            result.records_removed += total - records.len();
+            result.records_retained += records.len();
+            if let Some(first) = records.first() {
+                let batch = RecordBatch::with_records(first.offset, attributes, records);
+                cleaned.append_retained(&batch)?;
+            }
+            Ok(())
+        })?;
+        cleaned.seal()?;
+        Ok(result)
+    }
+}
+
+/// 用 `CLEANED_DIR` 中压缩后的段文件替换原段文件；`retained` 为 false 时直接删除原段
+pub fn swap_cleaned_segment(log_dir: &str, base_offset: u64, retained: bool) -> io::Result<()> {
+    let log_dir = log_dir.trim_end_matches('/');
+    let cleaned_dir = format!("{}/{}", log_dir, CLEANED_DIR);
+    let name = format!("{:020}", base_offset);
+
+    // 先删除原索引，此时崩溃只会留下没有索引的原日志
+    for suffix in [INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX] {
+        remove_if_exists(&format!("{}/{}{}", log_dir, name, suffix))?;
+    }
+    if !retained {
+        remove_if_exists(&format!("{}/{}{}", log_dir, name, LOG_FILE_SUFFIX))?;
+        return remove_segment_files(&cleaned_dir, base_offset);
+    }
+    // rename 原子地替换日志文件，之后再放入新索引
+    for suffix in [LOG_FILE_SUFFIX, INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX] {
+        fs::rename(
+            format!("{}/{}{}", cleaned_dir, name, suffix),
+            format!("{}/{}{}", log_dir, name, suffix),
+        )?;
+    }
+    Ok(())
+}
+
+/// 删除压缩中途遗留的 `CLEANED_DIR`，原段文件始终完整，可以直接丢弃
+pub fn remove_cleaned_dir(log_dir: &str) -> io::Result<()> {
+    let cleaned_dir = format!("{}/{}", log_dir.trim_end_matches('/'), CLEANED_DIR);
+    if Path::new(&cleaned_dir).exists() {
+        fs::remove_dir_all(&cleaned_dir)?;
+    }
+    Ok(())
+}
+
+fn remove_segment_files(dir: &str, base_offset: u64) -> io::Result<()> {
+    for suffix in [INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX] {
+        remove_if_exists(&format!("{}/{:020}{}", dir, base_offset, suffix))?;
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
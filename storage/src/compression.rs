@@ -0,0 +1,140 @@
+//! 批次压缩
+//!
+//! 压缩编码保存在批次 attributes 的低 3 位，压缩范围为批次头部之后的全部记录，
+//! 批次头部（offset、时间戳、记录数）保持明文，遍历日志时无需解压。
+//! 解压后的大小不能超过 [`MAX_DECOMPRESSED_BYTES`]，防止很小的压缩批次解压出大量数据耗尽内存。
+
+use crate::error::{Result, StorageError};
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// 单个批次解压后的最大字节数，超过时视为无效批次
+pub const MAX_DECOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+
+/// 压缩编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionType {
+    #[default]
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl CompressionType {
+    /// 由 attributes 中的编码值创建
+    pub fn from_codec(codec: u8) -> Result<Self> {
+        match codec {
+            0 => Ok(CompressionType::None),
+            1 => Ok(CompressionType::Gzip),
+            2 => Ok(CompressionType::Snappy),
+            3 => Ok(CompressionType::Lz4),
+            4 => Ok(CompressionType::Zstd),
+            _ => Err(StorageError::InvalidRecordBatch("unknown compression codec")),
+        }
+    }
+
+    /// 写入 attributes 的编码值
+    pub fn codec(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Gzip => 1,
+            CompressionType::Snappy => 2,
+            CompressionType::Lz4 => 3,
+            CompressionType::Zstd => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompressionType::None => "none",
+            CompressionType::Gzip => "gzip",
+            CompressionType::Snappy => "snappy",
+            CompressionType::Lz4 => "lz4",
+            CompressionType::Zstd => "zstd",
+        }
+    }
+
+    /// 压缩编码后的记录
+    pub(crate) fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                // 写入 Vec 不会失败
+                encoder.write_all(data).expect("gzip compress");
+                encoder.finish().expect("gzip compress")
+            }
+            CompressionType::Snappy => snap::raw::Encoder::new().compress_vec(data).expect("snappy compress"),
+            CompressionType::Lz4 => lz4_flex::compress_prepend_size(data),
+            CompressionType::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL).expect("zstd compress"),
+        }
+    }
+
+    /// 解压记录，解压后超过 `max_len` 字节时返回错误
+    ///
+    /// 流式编码最多读取 `max_len + 1` 字节；snappy 与 lz4 在分配缓冲区之前检查数据中记录的解压后大小
+    pub(crate) fn decompress(self, data: &[u8], max_len: usize) -> Result<Vec<u8>> {
+        let decompressed = match self {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Gzip => read_limited(flate2::read::GzDecoder::new(data), max_len)?,
+            CompressionType::Snappy => {
+                check_len(snap::raw::decompress_len(data).map_err(decompress_error)?, max_len)?;
+                snap::raw::Decoder::new().decompress_vec(data).map_err(decompress_error)?
+            }
+            CompressionType::Lz4 => {
+                let size = data.get(..4).ok_or_else(|| decompress_error(()))?;
+                check_len(u32::from_le_bytes(size.try_into().unwrap()) as usize, max_len)?;
+                lz4_flex::decompress_size_prepended(data).map_err(decompress_error)?
+            }
+            CompressionType::Zstd => read_limited(zstd::stream::read::Decoder::new(data).map_err(decompress_error)?, max_len)?,
+        };
+        check_len(decompressed.len(), max_len)?;
+        Ok(decompressed)
+    }
+}
+
+/// 从流式解码器读取，最多读取 `max_len + 1` 字节以判断是否超出上限
+fn read_limited(decoder: impl Read, max_len: usize) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    decoder
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(decompress_error)?;
+    Ok(decompressed)
+}
+
+fn check_len(len: usize, max_len: usize) -> Result<()> {
+    if len > max_len {
+        return Err(StorageError::InvalidRecordBatch("decompressed records exceed size limit"));
+    }
+    Ok(())
+}
+
+fn decompress_error<E>(_: E) -> StorageError {
+    StorageError::InvalidRecordBatch("failed to decompress records")
+}
+
+impl FromStr for CompressionType {
+    type Err = String;
+
+    /// 解析 compression.type，uncompressed 与 none 等价
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" | "uncompressed" => Ok(CompressionType::None),
+            "gzip" => Ok(CompressionType::Gzip),
+            "snappy" => Ok(CompressionType::Snappy),
+            "lz4" => Ok(CompressionType::Lz4),
+            "zstd" => Ok(CompressionType::Zstd),
+            other => Err(format!("未知的压缩类型: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -1,21 +1,68 @@
-use std::fs::OpenOptions;
-use std::sync::{Arc, Mutex};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
 
+/// 日志段的数据文件，只有一个写入方以追加方式写入，读取按位置进行（pread），不依赖共享的文件游标，
+/// 因此多个读取方可以与写入方同时访问，不需要互斥锁
 #[derive(Debug)]
-pub struct MutexFile {
-    pub file: Arc<Mutex<std::fs::File>>,
+pub struct LogFile {
+    file: File,
 }
 
-impl MutexFile {
-    pub fn new(path: &str) -> std::io::Result<Self> {
-        //println!("file_path:{}",file_path.clone().to_string());
+impl LogFile {
+    /// 以追加方式打开（不存在时创建）日志文件
+    pub fn open(path: &str) -> io::Result<Self> {
         let file = OpenOptions::new().create(true).append(true).read(true).open(path)?;
-        Ok(Self {
-            file: Arc::new(Mutex::new(file)),
-        })
+        Ok(Self { file })
     }
 
-    pub fn lock(&self) -> std::sync::MutexGuard<'_, std::fs::File> {
-        self.file.lock().unwrap()
+    /// 从 `pos` 开始读满 `buf`，文件在此之前结束时返回 `UnexpectedEof`
+    #[cfg(unix)]
+    pub fn read_exact_at(&self, buf: &mut [u8], pos: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(&self.file, buf, pos)
+    }
+
+    /// 从 `pos` 开始读满 `buf`，文件在此之前结束时返回 `UnexpectedEof`
+    #[cfg(windows)]
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut pos: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.file.seek_read(buf, pos) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    pos += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// 在文件末尾追加数据，只能由写入方调用
+    pub fn append(&self, buf: &[u8]) -> io::Result<()> {
+        (&self.file).write_all(buf)
+    }
+
+    /// 当前文件长度
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub fn set_len(&self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    /// 复制一个独立的文件句柄（零拷贝发送使用）
+    pub fn try_clone(&self) -> io::Result<File> {
+        self.file.try_clone()
     }
 }
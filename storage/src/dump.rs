@@ -0,0 +1,396 @@
+//! 日志段检查
+//!
+//! 以只读方式解析日志段的 `.log`、`.index` 与 `.timeindex` 文件，列出批次、记录与索引条目，
+//! 并检查 offset 是否连续、单调以及索引是否指向对应的批次。与 `LogSegment::open` 不同，
+//! 这里不会修复、截断或预分配任何文件，可以直接用于复制出来的分区目录。
+
+use crate::encryption::{has_segment_magic, BatchCipher, SEGMENT_HEADER_SIZE};
+use crate::record::{batch_crc, BatchHeader, BATCH_HEADER_SIZE};
+use crate::{EncryptionKeys, Record, RecordBatch};
+use crate::{INDEX_ENTRY_SIZE, INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX, MSG_HEADER_SIZE, TIME_INDEX_FILE_SUFFIX};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 日志中的一个批次
+#[derive(Debug, Clone)]
+pub struct BatchInfo {
+    /// 批次在日志文件中的位置
+    pub position: u64,
+    pub base_offset: u64,
+    pub last_offset: u64,
+    /// 批次占用的字节数（含头部）
+    pub size: usize,
+    pub crc: u32,
+    /// crc 是否正确
+    pub crc_valid: bool,
+    pub attributes: u8,
+    pub max_timestamp: i64,
+    pub record_count: u32,
+    /// 解码后的记录；批次损坏、无法解码或加密段未提供密钥时为 None
+    pub records: Option<Vec<Record>>,
+}
+
+/// 检查发现的问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpIssue {
+    /// 批次的 base_offset 大于上一个批次的 last_offset + 1（日志压缩后的段中属于正常情况）
+    OffsetGap { position: u64, expected: u64, found: u64 },
+    /// 批次的 base_offset 不大于上一个批次的 last_offset，或小于段的起始 offset
+    NonMonotonicOffset { position: u64, previous: u64, found: u64 },
+    /// 批次 crc 不匹配
+    CrcMismatch { position: u64, offset: u64, expected: u32, actual: u32 },
+    /// 批次长度无效或超出文件末尾，之后的内容无法解析
+    TruncatedBatch { position: u64, file_len: u64 },
+    /// 批次内容无法解码
+    InvalidBatch { position: u64, offset: u64, reason: String },
+    /// offset 索引条目无效
+    InvalidIndexEntry { entry: usize, offset: u64, position: u64, reason: &'static str },
+    /// 时间索引条目无效
+    InvalidTimeIndexEntry { entry: usize, timestamp: i64, offset: u64, reason: &'static str },
+    /// 索引文件大小不是条目大小的整数倍
+    MisalignedIndex { file: PathBuf, len: u64 },
+    /// 日志段的起始 offset 与上一个日志段的结束 offset 不相接
+    SegmentGap { base_offset: u64, expected: u64 },
+    /// 日志段与上一个日志段的 offset 范围重叠
+    SegmentOverlap { base_offset: u64, previous_next_offset: u64 },
+}
+
+impl fmt::Display for DumpIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpIssue::OffsetGap { position, expected, found } => write!(
+                f,
+                "offset gap at position {}: expected {}, found {} (normal for compacted segments)",
+                position, expected, found
+            ),
+            DumpIssue::NonMonotonicOffset { position, previous, found } => write!(
+                f,
+                "non-monotonic offset at position {}: {} after {}",
+                position, found, previous
+            ),
+            DumpIssue::CrcMismatch { position, offset, expected, actual } => write!(
+                f,
+                "crc mismatch at position {} (offset {}): expected {:#010x}, actual {:#010x}",
+                position, offset, expected, actual
+            ),
+            DumpIssue::TruncatedBatch { position, file_len } => write!(
+                f,
+                "invalid or truncated batch at position {}, {} trailing bytes not parsed",
+                position,
+                file_len - position
+            ),
+            DumpIssue::InvalidBatch { position, offset, reason } => {
+                write!(f, "cannot decode batch at position {} (offset {}): {}", position, offset, reason)
+            }
+            DumpIssue::InvalidIndexEntry { entry, offset, position, reason } => write!(
+                f,
+                "index entry {} (offset {}, position {}): {}",
+                entry, offset, position, reason
+            ),
+            DumpIssue::InvalidTimeIndexEntry { entry, timestamp, offset, reason } => write!(
+                f,
+                "time index entry {} (timestamp {}, offset {}): {}",
+                entry, timestamp, offset, reason
+            ),
+            DumpIssue::MisalignedIndex { file, len } => write!(
+                f,
+                "{}: size {} is not a multiple of {}",
+                file.display(),
+                len,
+                INDEX_ENTRY_SIZE
+            ),
+            DumpIssue::SegmentGap { base_offset, expected } => write!(
+                f,
+                "segment {} starts after previous segment end {}",
+                base_offset, expected
+            ),
+            DumpIssue::SegmentOverlap { base_offset, previous_next_offset } => write!(
+                f,
+                "segment {} overlaps previous segment ending at {}",
+                base_offset, previous_next_offset
+            ),
+        }
+    }
+}
+
+impl DumpIssue {
+    /// 是否只是提示：offset 间隔可能由日志压缩或保留策略产生，不代表数据损坏
+    pub fn is_warning(&self) -> bool {
+        matches!(self, DumpIssue::OffsetGap { .. } | DumpIssue::SegmentGap { .. })
+    }
+}
+
+/// 一个日志段的检查结果
+#[derive(Debug, Clone)]
+pub struct SegmentDump {
+    /// 日志文件路径
+    pub log_path: PathBuf,
+    /// 由文件名得到的段起始 offset
+    pub base_offset: u64,
+    pub file_len: u64,
+    /// 第一个批次的位置，加密段跳过段头部
+    pub data_start: u64,
+    /// 加密段的数据密钥 ID
+    pub encryption_key_id: Option<u32>,
+    pub batches: Vec<BatchInfo>,
+    /// offset 索引条目 (offset, 物理位置)，不含预分配的零值尾部
+    pub index: Vec<(u64, u64)>,
+    /// 时间索引条目 (时间戳, offset)，不含预分配的零值尾部
+    pub time_index: Vec<(i64, u64)>,
+    pub issues: Vec<DumpIssue>,
+}
+
+impl SegmentDump {
+    /// 段中最后一个批次之后的 offset，没有批次时为段起始 offset
+    pub fn next_offset(&self) -> u64 {
+        self.batches.last().map_or(self.base_offset, |batch| batch.last_offset + 1)
+    }
+
+    /// 是否没有发现错误（offset 间隔等提示不算错误）
+    pub fn is_consistent(&self) -> bool {
+        self.issues.iter().all(DumpIssue::is_warning)
+    }
+}
+
+/// 检查一个日志段，`path` 可以是 `.log`、`.index` 或 `.timeindex` 文件，同名的其他文件自动查找
+///
+/// 加密段提供 `keys` 时解密记录，否则只列出批次头部
+pub fn dump_segment(path: impl AsRef<Path>, keys: Option<&EncryptionKeys>) -> io::Result<SegmentDump> {
+    let log_path = path.as_ref().with_extension(&LOG_FILE_SUFFIX[1..]);
+    let base_offset = log_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.parse::<u64>().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a segment file", log_path.display()),
+            )
+        })?;
+    let log = fs::read(&log_path)?;
+    let mut dump = SegmentDump {
+        log_path: log_path.clone(),
+        base_offset,
+        file_len: log.len() as u64,
+        data_start: 0,
+        encryption_key_id: None,
+        batches: Vec::new(),
+        index: Vec::new(),
+        time_index: Vec::new(),
+        issues: Vec::new(),
+    };
+
+    let mut cipher = None;
+    if has_segment_magic(&log[..log.len().min(SEGMENT_HEADER_SIZE)]) {
+        if log.len() < SEGMENT_HEADER_SIZE {
+            dump.issues.push(DumpIssue::TruncatedBatch { position: 0, file_len: dump.file_len });
+            return Ok(dump);
+        }
+        let header: &[u8; SEGMENT_HEADER_SIZE] = log[..SEGMENT_HEADER_SIZE].try_into().unwrap();
+        dump.data_start = SEGMENT_HEADER_SIZE as u64;
+        dump.encryption_key_id = Some(u32::from_be_bytes(header[8..12].try_into().unwrap()));
+        if let Some(keys) = keys {
+            cipher = Some(keys.open_segment_header(header)?);
+        }
+    }
+    dump_batches(&mut dump, &log, cipher.as_ref());
+
+    // 起始 offset 为 0 的明文段的第一个索引条目可能就是 (0, 0)，不能当作预分配的尾部
+    let keep = usize::from(base_offset == 0 && dump.data_start == 0);
+    let index = read_index(&log_path.with_extension(&INDEX_FILE_SUFFIX[1..]), keep, &mut dump.issues)?;
+    dump.index = index;
+    let time_index = read_index(&log_path.with_extension(&TIME_INDEX_FILE_SUFFIX[1..]), 0, &mut dump.issues)?;
+    dump.time_index = time_index.into_iter().map(|(timestamp, offset)| (timestamp as i64, offset)).collect();
+    check_index(&mut dump);
+    check_time_index(&mut dump);
+    Ok(dump)
+}
+
+/// 按 offset 顺序检查目录中的所有日志段，同时检查相邻日志段的 offset 是否相接
+pub fn dump_dir(dir: impl AsRef<Path>, keys: Option<&EncryptionKeys>) -> io::Result<Vec<SegmentDump>> {
+    let mut logs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == &LOG_FILE_SUFFIX[1..]) {
+            logs.push(path);
+        }
+    }
+    logs.sort();
+
+    let mut dumps: Vec<SegmentDump> = Vec::with_capacity(logs.len());
+    for log in logs {
+        let mut dump = dump_segment(&log, keys)?;
+        if let Some(previous) = dumps.last() {
+            let previous_next_offset = previous.next_offset();
+            if dump.base_offset < previous_next_offset {
+                dump.issues.insert(0, DumpIssue::SegmentOverlap { base_offset: dump.base_offset, previous_next_offset });
+            } else if dump.base_offset > previous_next_offset {
+                dump.issues.insert(0, DumpIssue::SegmentGap { base_offset: dump.base_offset, expected: previous_next_offset });
+            }
+        }
+        dumps.push(dump);
+    }
+    Ok(dumps)
+}
+
+/// 顺序解析批次；长度无效时停止，crc 错误的批次仍按长度跳过，继续检查后面的批次
+fn dump_batches(dump: &mut SegmentDump, log: &[u8], cipher: Option<&BatchCipher>) {
+    let mut position = dump.data_start as usize;
+    let mut previous_last: Option<u64> = None;
+    while position < log.len() {
+        let Some(header_bytes) = log.get(position..position + BATCH_HEADER_SIZE) else {
+            dump.issues.push(DumpIssue::TruncatedBatch { position: position as u64, file_len: dump.file_len });
+            break;
+        };
+        let header_bytes: &[u8; BATCH_HEADER_SIZE] = header_bytes.try_into().unwrap();
+        let header = BatchHeader::decode(header_bytes);
+        let end = position + header.size();
+        if header.length < BATCH_HEADER_SIZE - MSG_HEADER_SIZE || end > log.len() {
+            dump.issues.push(DumpIssue::TruncatedBatch { position: position as u64, file_len: dump.file_len });
+            break;
+        }
+
+        let body = &log[position + MSG_HEADER_SIZE..end];
+        let actual = batch_crc(header_bytes, body);
+        let mut batch = BatchInfo {
+            position: position as u64,
+            base_offset: header.base_offset,
+            last_offset: header.last_offset(),
+            size: header.size(),
+            crc: header.crc,
+            crc_valid: actual == header.crc,
+            attributes: body[0],
+            max_timestamp: header.max_timestamp,
+            record_count: u32::from_be_bytes(body[21..25].try_into().unwrap()),
+            records: None,
+        };
+
+        match previous_last {
+            Some(previous) if header.base_offset <= previous => {
+                dump.issues.push(DumpIssue::NonMonotonicOffset {
+                    position: batch.position,
+                    previous,
+                    found: header.base_offset,
+                });
+            }
+            Some(previous) if header.base_offset > previous + 1 => {
+                dump.issues.push(DumpIssue::OffsetGap {
+                    position: batch.position,
+                    expected: previous + 1,
+                    found: header.base_offset,
+                });
+            }
+            None if header.base_offset < dump.base_offset => {
+                dump.issues.push(DumpIssue::NonMonotonicOffset {
+                    position: batch.position,
+                    previous: dump.base_offset,
+                    found: header.base_offset,
+                });
+            }
+            _ => {}
+        }
+
+        if !batch.crc_valid {
+            dump.issues.push(DumpIssue::CrcMismatch {
+                position: batch.position,
+                offset: header.base_offset,
+                expected: header.crc,
+                actual,
+            });
+        } else if dump.encryption_key_id.is_none() || cipher.is_some() {
+            let decoded = match cipher {
+                Some(cipher) => cipher.decrypt_body(header.base_offset, body.to_vec()),
+                None => Ok(body.to_vec()),
+            }
+            .and_then(|body| RecordBatch::decode_body(header.base_offset, &body));
+            match decoded {
+                Ok(decoded) => batch.records = Some(decoded.records),
+                Err(e) => dump.issues.push(DumpIssue::InvalidBatch {
+                    position: batch.position,
+                    offset: header.base_offset,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        previous_last = Some(previous_last.map_or(batch.last_offset, |previous| previous.max(batch.last_offset)));
+        dump.batches.push(batch);
+        position = end;
+    }
+}
+
+/// 读取索引文件的条目，去掉预分配的零值尾部（至少保留前 `keep` 个条目）；文件不存在时返回空
+fn read_index(path: &Path, keep: usize, issues: &mut Vec<DumpIssue>) -> io::Result<Vec<(u64, u64)>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    if !data.len().is_multiple_of(INDEX_ENTRY_SIZE) {
+        issues.push(DumpIssue::MisalignedIndex { file: path.to_path_buf(), len: data.len() as u64 });
+    }
+    let mut entries: Vec<(u64, u64)> = data
+        .chunks_exact(INDEX_ENTRY_SIZE)
+        .map(|entry| {
+            (
+                u64::from_be_bytes(entry[..8].try_into().unwrap()),
+                u64::from_be_bytes(entry[8..].try_into().unwrap()),
+            )
+        })
+        .collect();
+    while entries.len() > keep && entries.last() == Some(&(0, 0)) {
+        entries.pop();
+    }
+    Ok(entries)
+}
+
+/// offset 索引条目需要严格递增，并指向 offset 相同的批次的起始位置
+fn check_index(dump: &mut SegmentDump) {
+    let batches: HashMap<u64, u64> = dump.batches.iter().map(|batch| (batch.position, batch.base_offset)).collect();
+    let mut previous: Option<(u64, u64)> = None;
+    for (entry, &(offset, position)) in dump.index.iter().enumerate() {
+        let reason = if offset < dump.base_offset {
+            Some("offset below segment base offset")
+        } else if previous.is_some_and(|(prev_offset, prev_position)| offset <= prev_offset || position <= prev_position) {
+            Some("not strictly increasing")
+        } else {
+            match batches.get(&position) {
+                None => Some("position is not the start of a batch"),
+                Some(&base_offset) if base_offset != offset => Some("offset does not match the batch at position"),
+                Some(_) => None,
+            }
+        };
+        if let Some(reason) = reason {
+            dump.issues.push(DumpIssue::InvalidIndexEntry { entry, offset, position, reason });
+        }
+        previous = Some((offset, position));
+    }
+}
+
+/// 时间索引条目需要严格递增，offset 指向批次起始，时间戳等于截至该批次的最大时间戳
+fn check_time_index(dump: &mut SegmentDump) {
+    let mut max_timestamps = HashMap::new();
+    let mut max_timestamp = -1;
+    for batch in &dump.batches {
+        max_timestamp = max_timestamp.max(batch.max_timestamp);
+        max_timestamps.insert(batch.base_offset, max_timestamp);
+    }
+    let mut previous: Option<(i64, u64)> = None;
+    for (entry, &(timestamp, offset)) in dump.time_index.iter().enumerate() {
+        let reason = if previous.is_some_and(|(prev_timestamp, prev_offset)| timestamp <= prev_timestamp || offset <= prev_offset) {
+            Some("not strictly increasing")
+        } else {
+            match max_timestamps.get(&offset) {
+                None => Some("offset is not the start of a batch"),
+                Some(&expected) if expected != timestamp => Some("timestamp does not match max timestamp up to batch"),
+                Some(_) => None,
+            }
+        };
+        if let Some(reason) = reason {
+            dump.issues.push(DumpIssue::InvalidTimeIndexEntry { entry, timestamp, offset, reason });
+        }
+        previous = Some((timestamp, offset));
+    }
+}
@@ -0,0 +1,245 @@
+//! 静态数据加密
+//!
+//! 采用信封加密：每个主题有自己的数据密钥，数据密钥由从本地密钥文件加载的主密钥以 AES-256-GCM 包装。
+//! 加密的日志段以段头部开始，头部记录数据密钥 ID 与包装后的数据密钥：
+//!
+//! ```text
+//! magic        8 字节   "RMQENC01"
+//! key_id       u32      数据密钥 ID
+//! wrapped_key  60 字节  nonce(12) + 以主密钥加密的数据密钥(32) + tag(16)，key_id 作为附加数据
+//! ```
+//!
+//! 段内批次只加密记录部分：批次头部（offset、长度、crc、时间戳、记录数）保持明文，
+//! attributes 的 bit 6 标记批次已加密，记录部分替换为 nonce(12) + 密文 + tag(16)，
+//! 批次头部作为附加数据参与认证。索引只保存 offset、时间戳与文件位置，无需加密。
+//! 轮换数据密钥只影响之后创建的日志段，旧段按头部中的密钥继续读取。
+
+use crate::error::{Result, StorageError};
+use crate::record::{batch_crc, ENCRYPTED_FLAG, BATCH_HEADER_SIZE};
+use crate::{MSG_HEADER_SIZE, MSG_LEN_SIZE, OFFSET_SIZE};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// 密钥长度（AES-256）
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const WRAPPED_KEY_SIZE: usize = NONCE_SIZE + KEY_SIZE + TAG_SIZE;
+const SEGMENT_MAGIC: &[u8; 8] = b"RMQENC01";
+/// 加密日志段头部大小：magic 8 + key_id 4 + 包装后的数据密钥 60 = 72 字节
+pub const SEGMENT_HEADER_SIZE: usize = SEGMENT_MAGIC.len() + 4 + WRAPPED_KEY_SIZE;
+
+/// 主密钥，用于包装各主题的数据密钥
+pub struct MasterKey {
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    pub fn from_bytes(key: &[u8; KEY_SIZE]) -> Self {
+        Self { cipher: Aes256Gcm::new(key.into()) }
+    }
+
+    /// 从密钥文件加载主密钥，文件内容为 64 个十六进制字符（首尾空白忽略）
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let hex = content.trim();
+        if hex.len() != KEY_SIZE * 2 {
+            return Err(invalid_key("master key must be 64 hex characters"));
+        }
+        let mut key = [0u8; KEY_SIZE];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| invalid_key("master key must be 64 hex characters"))?;
+        }
+        Ok(Self::from_bytes(&key))
+    }
+
+    /// 生成随机主密钥并写入密钥文件（仅所有者可读写），文件已存在时返回错误
+    pub fn generate(path: impl AsRef<Path>) -> io::Result<Self> {
+        let key: [u8; KEY_SIZE] = Aes256Gcm::generate_key(OsRng).into();
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+        writeln!(file, "{}", hex)?;
+        file.sync_all()?;
+        Ok(Self::from_bytes(&key))
+    }
+
+    fn wrap(&self, key_id: u32, key: &[u8; KEY_SIZE]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut wrapped = nonce.to_vec();
+        let payload = Payload { msg: key, aad: &key_id.to_be_bytes() };
+        wrapped.extend(self.cipher.encrypt(&nonce, payload).expect("AES-GCM encryption failed"));
+        wrapped
+    }
+
+    fn unwrap(&self, key_id: u32, wrapped: &[u8]) -> io::Result<[u8; KEY_SIZE]> {
+        let (nonce, ciphertext) = wrapped.split_at(NONCE_SIZE);
+        let payload = Payload { msg: ciphertext, aad: &key_id.to_be_bytes() };
+        let key = self.cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| invalid_key("failed to unwrap data key, wrong master key?"))?;
+        key.try_into().map_err(|_| invalid_key("invalid data key length"))
+    }
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterKey(..)")
+    }
+}
+
+/// 数据密钥
+struct DataKey {
+    id: u32,
+    key: [u8; KEY_SIZE],
+}
+
+impl DataKey {
+    /// 生成随机数据密钥，ID 与 `previous` 不同
+    fn generate(previous: Option<u32>) -> Self {
+        let mut id = OsRng.next_u32();
+        while Some(id) == previous {
+            id = OsRng.next_u32();
+        }
+        Self { id, key: Aes256Gcm::generate_key(OsRng).into() }
+    }
+}
+
+/// 主题的加密密钥：主密钥与当前数据密钥，新建的日志段使用当前数据密钥
+pub struct EncryptionKeys {
+    master: Arc<MasterKey>,
+    current: RwLock<DataKey>,
+}
+
+impl EncryptionKeys {
+    /// 使用主密钥创建，并生成第一个数据密钥
+    pub fn new(master: Arc<MasterKey>) -> Self {
+        Self { master, current: RwLock::new(DataKey::generate(None)) }
+    }
+
+    /// 当前数据密钥的 ID
+    pub fn current_key_id(&self) -> u32 {
+        self.current.read().unwrap().id
+    }
+
+    /// 轮换数据密钥，返回新密钥的 ID；已有日志段不需要重写
+    pub fn rotate(&self) -> u32 {
+        let mut current = self.current.write().unwrap();
+        *current = DataKey::generate(Some(current.id));
+        current.id
+    }
+
+    /// 为新日志段生成段头部，返回头部与段内批次使用的密钥
+    pub(crate) fn segment_header(&self) -> (Vec<u8>, BatchCipher) {
+        let current = self.current.read().unwrap();
+        let mut header = Vec::with_capacity(SEGMENT_HEADER_SIZE);
+        header.extend_from_slice(SEGMENT_MAGIC);
+        header.extend_from_slice(&current.id.to_be_bytes());
+        header.extend(self.master.wrap(current.id, &current.key));
+        (header, BatchCipher::new(current.id, &current.key))
+    }
+
+    /// 解析已有日志段的头部，用主密钥解开段的数据密钥
+    pub(crate) fn open_segment_header(&self, header: &[u8; SEGMENT_HEADER_SIZE]) -> io::Result<BatchCipher> {
+        let key_id = u32::from_be_bytes(header[SEGMENT_MAGIC.len()..SEGMENT_MAGIC.len() + 4].try_into().unwrap());
+        let key = self.master.unwrap(key_id, &header[SEGMENT_MAGIC.len() + 4..])?;
+        Ok(BatchCipher::new(key_id, &key))
+    }
+}
+
+impl fmt::Debug for EncryptionKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKeys")
+            .field("current_key_id", &self.current_key_id())
+            .finish()
+    }
+}
+
+/// 日志文件是否以加密段头部（或其前缀，头部写入中断时）开始
+pub(crate) fn has_segment_magic(prefix: &[u8]) -> bool {
+    let len = prefix.len().min(SEGMENT_MAGIC.len());
+    len > 0 && prefix[..len] == SEGMENT_MAGIC[..len]
+}
+
+/// 日志段内批次使用的数据密钥
+#[derive(Clone)]
+pub(crate) struct BatchCipher {
+    key_id: u32,
+    cipher: Aes256Gcm,
+}
+
+impl BatchCipher {
+    fn new(key_id: u32, key: &[u8; KEY_SIZE]) -> Self {
+        Self { key_id, cipher: Aes256Gcm::new(key.into()) }
+    }
+
+    pub(crate) fn key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    /// 加密已编码批次的记录部分，重新计算长度与 crc
+    pub(crate) fn encrypt(&self, buffer: &[u8]) -> Vec<u8> {
+        let mut encrypted = buffer[..BATCH_HEADER_SIZE].to_vec();
+        encrypted[MSG_HEADER_SIZE] |= ENCRYPTED_FLAG;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = batch_aad(&encrypted[..OFFSET_SIZE], &encrypted[MSG_HEADER_SIZE..]);
+        let payload = Payload { msg: &buffer[BATCH_HEADER_SIZE..], aad: &aad };
+        let ciphertext = self.cipher.encrypt(&nonce, payload).expect("AES-GCM encryption failed");
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend(ciphertext);
+
+        let length = (encrypted.len() - MSG_HEADER_SIZE) as u32;
+        encrypted[OFFSET_SIZE..OFFSET_SIZE + MSG_LEN_SIZE].copy_from_slice(&length.to_be_bytes());
+        let crc = batch_crc(&encrypted[..OFFSET_SIZE + MSG_LEN_SIZE], &encrypted[MSG_HEADER_SIZE..]);
+        encrypted[OFFSET_SIZE + MSG_LEN_SIZE..MSG_HEADER_SIZE].copy_from_slice(&crc.to_be_bytes());
+        encrypted
+    }
+
+    /// 解密批次 crc 字段之后的内容，返回与未加密批次相同格式的内容；未加密的批次原样返回
+    pub(crate) fn decrypt_body(&self, base_offset: u64, body: Vec<u8>) -> Result<Vec<u8>> {
+        const FIELDS: usize = BATCH_HEADER_SIZE - MSG_HEADER_SIZE;
+        if body.first().is_none_or(|attributes| attributes & ENCRYPTED_FLAG == 0) {
+            return Ok(body);
+        }
+        if body.len() < FIELDS + NONCE_SIZE + TAG_SIZE {
+            return Err(StorageError::InvalidRecordBatch("encrypted batch too short"));
+        }
+        let aad = batch_aad(&base_offset.to_be_bytes(), &body[..FIELDS]);
+        let nonce = Nonce::from_slice(&body[FIELDS..FIELDS + NONCE_SIZE]);
+        let payload = Payload { msg: &body[FIELDS + NONCE_SIZE..], aad: &aad };
+        let records = self.cipher
+            .decrypt(nonce, payload)
+            .map_err(|_| StorageError::DecryptionFailed { offset: base_offset, key_id: self.key_id })?;
+        let mut decrypted = body[..FIELDS].to_vec();
+        decrypted[0] &= !ENCRYPTED_FLAG;
+        decrypted.extend(records);
+        Ok(decrypted)
+    }
+}
+
+impl fmt::Debug for BatchCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchCipher").field("key_id", &self.key_id).finish()
+    }
+}
+
+/// 批次认证的附加数据：base_offset 与批次头部的定长字段（不含会随密文变化的长度与 crc）
+fn batch_aad(base_offset: &[u8], fields: &[u8]) -> Vec<u8> {
+    let mut aad = base_offset.to_vec();
+    aad.extend_from_slice(&fields[..BATCH_HEADER_SIZE - MSG_HEADER_SIZE]);
+    aad
+}
+
+fn invalid_key(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
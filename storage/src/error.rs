@@ -0,0 +1,59 @@
+use std::io;
+use thiserror::Error;
+
+/// 存储层错误类型
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// 记录校验和不匹配（磁盘位翻转或写入不完整）
+    #[error("Corrupt record at offset {offset} (position {position}): expected crc {expected:#010x}, actual {actual:#010x}")]
+    CorruptRecord {
+        offset: u64,
+        position: u64,
+        expected: u32,
+        actual: u32,
+    },
+    /// 请求的 offset 不在日志范围 [log_start_offset, log_end_offset] 内
+    #[error("Offset {offset} out of range [{log_start_offset}, {log_end_offset}]")]
+    OffsetOutOfRange {
+        offset: u64,
+        log_start_offset: u64,
+        log_end_offset: u64,
+    },
+    /// 加密批次认证失败（数据被篡改或密钥不匹配）
+    #[error("Failed to decrypt record batch at offset {offset} with key {key_id}")]
+    DecryptionFailed {
+        offset: u64,
+        key_id: u32,
+    },
+    /// 分区已达到容量上限，按溢出策略拒绝写入
+    #[error("Partition is full: {records} records, {bytes} bytes")]
+    QueueFull {
+        records: u64,
+        bytes: u64,
+    },
+    /// 记录批次格式错误
+    #[error("Invalid record batch: {0}")]
+    InvalidRecordBatch(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+impl StorageError {
+    /// 追加返回的 `io::Error` 是否由分区写满（`StorageError::QueueFull`）引起
+    pub fn is_queue_full(err: &io::Error) -> bool {
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<StorageError>())
+            .is_some_and(|inner| matches!(inner, StorageError::QueueFull { .. }))
+    }
+}
+
+impl From<StorageError> for io::Error {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::Io(e) => e,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
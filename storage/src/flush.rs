@@ -0,0 +1,45 @@
+//! 刷盘策略
+//!
+//! 决定追加后何时调用 `sync_data`：每次写入、每 N 条消息、每隔 T 毫秒（由后台刷盘任务执行），
+//! 或完全交给操作系统回写。已刷盘的最大 offset 作为分区的恢复点。
+
+/// 日志刷盘策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    /// 每次写入后立即 fsync，返回的 offset 在内核崩溃后仍然存在
+    EveryWrite,
+    /// 累计写入 N 条消息后 fsync，对应 flush.messages
+    EveryMessages(u64),
+    /// 距上次刷盘超过 T 毫秒后由后台刷盘任务 fsync，对应 flush.ms
+    Interval(u64),
+    /// 不主动 fsync，由操作系统回写脏页
+    #[default]
+    OsManaged,
+}
+
+impl FlushPolicy {
+    /// 由配置创建：`policy` 为 write / messages / interval / os
+    pub fn from_config(policy: &str, flush_messages: u64, flush_interval_ms: u64) -> Result<Self, String> {
+        match policy.trim() {
+            "write" => Ok(FlushPolicy::EveryWrite),
+            "messages" if flush_messages > 0 => Ok(FlushPolicy::EveryMessages(flush_messages)),
+            "interval" if flush_interval_ms > 0 => Ok(FlushPolicy::Interval(flush_interval_ms)),
+            "messages" | "interval" => Err(format!("刷盘策略 {} 的参数必须大于 0", policy)),
+            "os" => Ok(FlushPolicy::OsManaged),
+            other => Err(format!("未知的刷盘策略: {}", other)),
+        }
+    }
+
+    /// 距上次刷盘已写入 `unflushed_messages` 条消息、经过 `elapsed_ms` 毫秒时是否需要刷盘
+    pub fn should_flush(&self, unflushed_messages: u64, elapsed_ms: i64) -> bool {
+        if unflushed_messages == 0 {
+            return false;
+        }
+        match *self {
+            FlushPolicy::EveryWrite => true,
+            FlushPolicy::EveryMessages(messages) => unflushed_messages >= messages,
+            FlushPolicy::Interval(interval_ms) => elapsed_ms >= interval_ms as i64,
+            FlushPolicy::OsManaged => false,
+        }
+    }
+}
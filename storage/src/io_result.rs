@@ -1,5 +1,17 @@
+/// 日志段滚动的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollReason {
+    /// 写入该批次后段大小会超过 max_segment_size
+    SegmentSize,
+    /// 批次时间戳与段内第一个批次的时间戳相差超过 segment_ms
+    SegmentAge,
+    /// offset 索引或时间索引已满
+    IndexFull,
+}
+
 /// 追加消息的结果
+#[derive(Debug)]
 pub enum IoResult {
-    Success(u64),  // 成功写入，返回 offset
-    SegmentFull
+    Success(u64),             // 成功写入，返回 offset
+    SegmentFull(RollReason), // 需要滚动到新段，批次未写入
 }
@@ -3,17 +3,39 @@ pub mod mmap;
 pub mod retention;
 pub mod concurrency;
 pub mod io_result;
+pub mod error;
+pub mod record;
+pub mod time_index;
+pub mod slice;
+pub mod cleaner;
+pub mod remote;
+pub mod flush;
+pub mod capacity;
+pub mod compression;
+pub mod encryption;
+pub mod dump;
 
 // 对外暴露核心 API
-pub use segment::LogSegment;
-pub use io_result::IoResult;
-pub use retention::clean_old_segments;
+pub use segment::{LogSegment, ReadRange, SegmentConfig, SegmentReader};
+pub use io_result::{IoResult, RollReason};
+pub use retention::{RetentionOverrides, RetentionPolicy};
+pub use error::{StorageError, Result};
+pub use record::{Header, Record, RecordBatch, TimestampType};
+pub use slice::FileSlice;
+pub use cleaner::{CleanedSegment, CleanerConfig, CleanupPolicy, LogCleaner};
+pub use flush::FlushPolicy;
+pub use capacity::{CapacityLimit, OverflowPolicy};
+pub use compression::CompressionType;
+pub use encryption::{EncryptionKeys, MasterKey};
+pub use dump::{dump_dir, dump_segment, BatchInfo, DumpIssue, SegmentDump};
+pub use remote::{LocalDirRemoteStorage, RemoteLog, RemoteManifest, RemoteSegmentMetadata, RemoteStorage};
 
 const MSG_LEN_SIZE: usize = 4; // 消息长度占 4 字节
 const OFFSET_SIZE: usize = 8; // 相对偏移量 占 8 字节
 const POS_SIZE: usize = 8; // 物理偏移量 占 8 字节
+const CRC_SIZE: usize = 4; // CRC32C 校验和占 4 字节
 const INDEX_ENTRY_SIZE: usize = OFFSET_SIZE + POS_SIZE; // 每个索引条目 8+8=16 字节（相对偏移量 + 物理偏移量）
-const MSG_HEADER_SIZE: usize = OFFSET_SIZE + MSG_LEN_SIZE; // 日志条目头部 8+4=12 字节
+const MSG_HEADER_SIZE: usize = OFFSET_SIZE + MSG_LEN_SIZE + CRC_SIZE; // 日志条目头部 8+4+4=16 字节
 
 //定义日志文件后缀为.log
 pub const LOG_FILE_SUFFIX: &str = ".log";
@@ -21,5 +43,8 @@ pub const LOG_FILE_SUFFIX: &str = ".log";
 //定义日志索引后缀为.index
 pub const INDEX_FILE_SUFFIX: &str = ".index";
 
+//定义时间索引后缀为.timeindex
+pub const TIME_INDEX_FILE_SUFFIX: &str = ".timeindex";
+
 //use std::sync::atomic::AtomicU64;
 //static GLOBAL_OFFSET: AtomicU64 = AtomicU64::new(0);
\ No newline at end of file
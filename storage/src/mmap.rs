@@ -1,104 +1,230 @@
-use memmap2::Mmap;
-use std::fs::File;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
 use std::io::{self};
-use super::{OFFSET_SIZE,INDEX_ENTRY_SIZE,POS_SIZE};
+use super::{OFFSET_SIZE,INDEX_ENTRY_SIZE};
 use std::fmt;
 
+/// 可写的索引文件，每个条目由两个 8 字节大端整数组成
+///
+/// 首次写入时把文件预分配到 `max_size`，之后的写入只修改 mmap，不再重新映射；
+/// 段滚动时通过 `trim` 把文件截断到实际条目大小
+pub(crate) struct IndexEntries {
+    file: File,
+    mmap: MmapMut,
+    entries: usize,  // 有效条目数量
+    max_size: usize, // 预分配大小（条目大小的整数倍）
+    corrupt: bool,   // 文件大小不是条目大小的整数倍，或有效条目之后仍有非零数据
+}
+
+impl IndexEntries {
+    /// 打开索引文件，`is_valid(当前条目, 上一条目)` 用于识别有效条目，
+    /// 预分配文件尾部的零值条目会在第一个无效条目处截止
+    pub(crate) fn open(
+        path: &str,
+        max_size: usize,
+        is_valid: impl Fn((u64, u64), Option<(u64, u64)>) -> bool,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(path)?;
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+        let mut index = Self {
+            file,
+            mmap,
+            entries: 0,
+            max_size: max_size / INDEX_ENTRY_SIZE * INDEX_ENTRY_SIZE,
+            corrupt: false,
+        };
+        let capacity = index.mmap.len() / INDEX_ENTRY_SIZE;
+        let mut prev = None;
+        while index.entries < capacity {
+            let entry = index.entry(index.entries);
+            if !is_valid(entry, prev) {
+                break;
+            }
+            prev = Some(entry);
+            index.entries += 1;
+        }
+        // 第一个无效条目只应是预分配的零值尾部，只检查紧随其后的一个条目，避免读取整个预分配区域
+        let next = index.entries * INDEX_ENTRY_SIZE;
+        let stray = index.mmap[next..(next + INDEX_ENTRY_SIZE).min(index.mmap.len())].iter().any(|&b| b != 0);
+        index.corrupt = !index.mmap.len().is_multiple_of(INDEX_ENTRY_SIZE) || stray;
+        Ok(index)
+    }
+
+    /// 打开时是否发现损坏（大小未对齐或存在无效条目）
+    pub(crate) fn is_corrupt(&self) -> bool {
+        self.corrupt
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub(crate) fn entry(&self, index: usize) -> (u64, u64) {
+        let start = index * INDEX_ENTRY_SIZE;
+        let key = u64::from_be_bytes(self.mmap[start..start + OFFSET_SIZE].try_into().unwrap());
+        let value = u64::from_be_bytes(self.mmap[start + OFFSET_SIZE..start + INDEX_ENTRY_SIZE].try_into().unwrap());
+        (key, value)
+    }
+
+    pub(crate) fn last(&self) -> Option<(u64, u64)> {
+        match self.entries {
+            0 => None,
+            n => Some(self.entry(n - 1)),
+        }
+    }
+
+    /// 满足 `pred` 的前缀条目数量（要求 `pred` 在条目序列上单调：先真后假）
+    pub(crate) fn partition_point(&self, pred: impl Fn((u64, u64)) -> bool) -> usize {
+        let mut low = 0;
+        let mut high = self.entries;
+        while low < high {
+            let mid = (low + high) / 2;
+            if pred(self.entry(mid)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    /// 索引是否已写满
+    pub(crate) fn is_full(&self) -> bool {
+        (self.entries + 1) * INDEX_ENTRY_SIZE > self.max_size
+    }
+
+    /// 追加条目，索引已满时返回 false
+    pub(crate) fn append(&mut self, key: u64, value: u64) -> io::Result<bool> {
+        if self.is_full() {
+            return Ok(false);
+        }
+        let start = self.entries * INDEX_ENTRY_SIZE;
+        if start + INDEX_ENTRY_SIZE > self.mmap.len() {
+            // 首次写入时预分配，避免每个条目都重新映射
+            self.file.set_len(self.max_size as u64)?;
+            self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+        }
+        self.mmap[start..start + OFFSET_SIZE].copy_from_slice(&key.to_be_bytes());
+        self.mmap[start + OFFSET_SIZE..start + INDEX_ENTRY_SIZE].copy_from_slice(&value.to_be_bytes());
+        self.entries += 1;
+        Ok(true)
+    }
+
+    /// 只保留前 `entries` 个条目，并把文件截断到实际大小
+    pub(crate) fn truncate(&mut self, entries: usize) -> io::Result<()> {
+        if entries < self.entries {
+            self.mmap[entries * INDEX_ENTRY_SIZE..self.entries * INDEX_ENTRY_SIZE].fill(0);
+            self.entries = entries;
+        }
+        self.trim()
+    }
+
+    /// 把预分配的文件截断到实际条目大小
+    pub(crate) fn trim(&mut self) -> io::Result<()> {
+        let len = self.entries * INDEX_ENTRY_SIZE;
+        if self.mmap.len() != len {
+            self.mmap.flush()?;
+            self.file.set_len(len as u64)?;
+            self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+        }
+        // 截断后文件中只剩有效条目
+        self.corrupt = false;
+        Ok(())
+    }
+
+    /// 将 mmap 中的修改刷到磁盘
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        self.mmap.flush()
+    }
+}
+
+/// offset 索引：(offset, 物理位置)，offset 与物理位置都严格递增
 pub struct MmapIndex {
-    mmap: Mmap,
+    entries: IndexEntries,
 }
 
 impl fmt::Debug for MmapIndex {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("MmapIndex")
-            .field("mmap_len", &self.mmap.len())
+            .field("entries", &self.entries.len())
+            .field("mmap_len", &self.entries.mmap.len())
             .finish()
     }
 }
 
 impl MmapIndex {
     /// **加载索引文件**
-    pub fn new(index_file: &File) -> io::Result<Self> {
-        let mmap = unsafe { Mmap::map(index_file)? };
-        Ok(Self { mmap })
+    pub fn open(path: &str, base_offset: u64, max_size: usize) -> io::Result<Self> {
+        let entries = IndexEntries::open(path, max_size, |(offset, pos), prev| match prev {
+            None => offset >= base_offset,
+            Some((prev_offset, prev_pos)) => offset > prev_offset && pos > prev_pos,
+        })?;
+        Ok(Self { entries })
     }
 
-    pub fn last_entry(&self) -> Option<(u64,u64)> {
-        let index_len = self.mmap.len();
-        if index_len < INDEX_ENTRY_SIZE {
-            return None; // 索引为空
-        }
+    /// 有效条目数量
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
 
-        // 获取最后 16 字节 (8 字节 offset + 8 字节 pos)
-        let last_offset_bytes = self.mmap.get(index_len - INDEX_ENTRY_SIZE..index_len - POS_SIZE)?;
-        let last_pos_bytes = self.mmap.get(index_len - POS_SIZE..)?;
+    /// 索引是否为空
+    pub fn is_empty(&self) -> bool {
+        self.entries.len() == 0
+    }
 
-        let last_offset = u64::from_be_bytes(last_offset_bytes.try_into().ok()?);
-        let last_pos = u64::from_be_bytes(last_pos_bytes.try_into().ok()?);
+    /// 索引是否已写满
+    pub fn is_full(&self) -> bool {
+        self.entries.is_full()
+    }
 
-        Some((last_offset, last_pos))
+    /// 打开时是否发现损坏（大小不是 16 的整数倍或存在无效条目）
+    pub fn is_corrupt(&self) -> bool {
+        self.entries.is_corrupt()
     }
 
-    /// **查找目标 offset 对应的日志文件位置**
-    pub fn find_position(&self, target_offset: u64) -> Option<u64> {
+    pub fn last_entry(&self) -> Option<(u64,u64)> {
+        self.entries.last()
+    }
 
-        let index_size = self.mmap.len();
-        if index_size == 0 {
-            return None;
-        }
+    /// 追加索引条目，索引已满时返回 false
+    pub fn append(&mut self, offset: u64, position: u64) -> io::Result<bool> {
+        self.entries.append(offset, position)
+    }
 
-        let mut closest_offset = 0;
-        let mut closest_pos = 0;
+    /// 索引中物理位置小于 `position` 的条目数量（条目按物理位置递增）
+    pub fn entries_before_position(&self, position: u64) -> usize {
+        self.entries.partition_point(|(_, pos)| pos < position)
+    }
 
-        let mut index = 0;
-        while index + INDEX_ENTRY_SIZE <= index_size {
-            let stored_offset =
-                u64::from_be_bytes(self.mmap[index..index + OFFSET_SIZE].try_into().unwrap());
-            let log_pos = u64::from_be_bytes(self.mmap[index + OFFSET_SIZE..index + INDEX_ENTRY_SIZE].try_into().unwrap());
+    /// 只保留前 `entries` 个条目
+    pub fn truncate(&mut self, entries: usize) -> io::Result<()> {
+        self.entries.truncate(entries)
+    }
 
-            if stored_offset > target_offset {
-                break;
-            }
+    /// 段滚动时把预分配的索引文件截断到实际大小
+    pub fn trim(&mut self) -> io::Result<()> {
+        self.entries.trim()
+    }
 
-            closest_offset = stored_offset;
-            closest_pos = log_pos;
-            index += INDEX_ENTRY_SIZE;
-        }
+    pub fn flush(&self) -> io::Result<()> {
+        self.entries.flush()
+    }
 
-        if closest_offset > target_offset {
-            None
-        } else {
-            Some(closest_pos)
+    /// **二分查找不大于目标 offset 的最后一个条目对应的日志文件位置**
+    pub fn find_position(&self, target_offset: u64) -> Option<u64> {
+        match self.entries.partition_point(|(offset, _)| offset <= target_offset) {
+            0 => None,
+            n => Some(self.entries.entry(n - 1).1),
         }
     }
 
-    /// **二分查找最近的 offset**
+    /// **二分查找不小于目标 offset 的第一个条目对应的日志文件位置**
     pub fn find_offset_half(&self, target_offset: u64) -> Option<u64> {
-        let num_entries = self.mmap.len() / INDEX_ENTRY_SIZE;
-
-        let mut low = 0;
-        let mut high = num_entries;
-
-        while low < high {
-            let mid = (low + high) / 2;
-            let entry_start = mid * INDEX_ENTRY_SIZE;
-            let stored_offset = u64::from_be_bytes(self.mmap[entry_start..entry_start + OFFSET_SIZE].try_into().unwrap());
-
-            if stored_offset < target_offset {
-                low = mid + 1;
-            } else {
-                high = mid;
-            }
-        }
-
-        if low < num_entries {
-            let entry_start = low * INDEX_ENTRY_SIZE;
-            let position = u64::from_be_bytes(self.mmap[entry_start + POS_SIZE..entry_start + INDEX_ENTRY_SIZE].try_into().unwrap());
-            Some(position)
+        let index = self.entries.partition_point(|(offset, _)| offset < target_offset);
+        if index < self.entries.len() {
+            Some(self.entries.entry(index).1)
         } else {
             None
         }
     }
-    
-    
 }
@@ -0,0 +1,563 @@
+//! 记录批次格式
+//!
+//! 磁盘与网络使用同一种编码，一个批次即日志段中的一个条目：
+//!
+//! ```text
+//! base_offset       u64   批次中第一条记录的 offset
+//! length            u32   crc 字段之后的字节数
+//! crc               u32   CRC32C，覆盖 base_offset、length 以及之后的全部内容
+//! attributes        u8    压缩编码（bit 0-2）、时间戳类型（bit 3）、事务（bit 4）、控制批次（bit 5）、已加密（bit 6）
+//! last_offset_delta u32   最后一条记录相对 base_offset 的增量
+//! base_timestamp    i64   第一条记录的时间戳（毫秒）
+//! max_timestamp     i64   批次内最大时间戳（毫秒）
+//! record_count      u32   记录数量
+//! records           ...   见 `Record`，压缩编码不为 none 时为压缩后的全部记录
+//! ```
+//!
+//! 时间戳类型为 LogAppendTime 时，记录的时间戳增量均为 0，所有记录的时间戳都是批次的 max_timestamp。
+
+use super::{CRC_SIZE, MSG_HEADER_SIZE, MSG_LEN_SIZE, OFFSET_SIZE};
+use crate::compression::{CompressionType, MAX_DECOMPRESSED_BYTES};
+use crate::error::{Result, StorageError};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 批次头部大小：日志条目头部 16 字节 + 1 + 4 + 8 + 8 + 4 = 41 字节
+pub const BATCH_HEADER_SIZE: usize = MSG_HEADER_SIZE + 1 + 4 + 8 + 8 + 4;
+
+/// 压缩编码掩码（bit 0-2）
+pub const COMPRESSION_CODEC_MASK: u8 = 0x07;
+/// 时间戳类型标志（bit 3），置位表示 LogAppendTime
+pub const TIMESTAMP_TYPE_FLAG: u8 = 0x08;
+/// 事务批次标志（bit 4）
+pub const TRANSACTIONAL_FLAG: u8 = 0x10;
+/// 控制批次标志（bit 5）
+pub const CONTROL_FLAG: u8 = 0x20;
+/// 加密批次标志（bit 6），只出现在加密日志段中，见 `encryption`
+pub const ENCRYPTED_FLAG: u8 = 0x40;
+
+/// 时间戳类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampType {
+    /// 由生产者指定的创建时间
+    CreateTime,
+    /// 由 broker 在写入时指定的时间
+    LogAppendTime,
+}
+
+/// 记录头，用于携带追踪上下文等元信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub key: String,
+    pub value: Option<Vec<u8>>,
+}
+
+/// 单条记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// 记录的绝对 offset，写入时由日志段分配
+    pub offset: u64,
+    /// 记录时间戳（毫秒）
+    pub timestamp: i64,
+    /// 可选的 key，用于按 key 分区和压缩
+    pub key: Option<Vec<u8>>,
+    /// 记录内容，None 表示墓碑记录
+    pub value: Option<Vec<u8>>,
+    /// 记录头
+    pub headers: Vec<Header>,
+}
+
+impl Record {
+    /// 创建一条使用当前时间作为时间戳的记录
+    pub fn new(key: Option<Vec<u8>>, value: Option<Vec<u8>>) -> Self {
+        Self {
+            offset: 0,
+            timestamp: now_ms(),
+            key,
+            value,
+            headers: Vec::new(),
+        }
+    }
+
+    /// 设置记录时间戳
+    pub fn with_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// 追加一个记录头
+    pub fn with_header(mut self, key: &str, value: Option<Vec<u8>>) -> Self {
+        self.headers.push(Header {
+            key: key.to_string(),
+            value,
+        });
+        self
+    }
+
+    /// 编码单条记录（timestamp 与 offset 均以相对批次的增量存储）
+    ///
+    /// ```text
+    /// length           u32   之后的字节数
+    /// attributes       u8    保留
+    /// timestamp_delta  i64
+    /// offset_delta     u32
+    /// key_length       i32   -1 表示 null
+    /// key
+    /// value_length     i32   -1 表示 null
+    /// value
+    /// header_count     u32
+    /// headers          key_length u32, key, value_length i32, value
+    /// ```
+    fn encode_into(&self, timestamp_delta: i64, offset_delta: u32, buffer: &mut Vec<u8>) {
+        let start = buffer.len();
+        buffer.extend_from_slice(&[0u8; 4]); // 长度占位
+        buffer.push(0);
+        buffer.extend_from_slice(&timestamp_delta.to_be_bytes());
+        buffer.extend_from_slice(&offset_delta.to_be_bytes());
+        put_nullable_bytes(buffer, self.key.as_deref());
+        put_nullable_bytes(buffer, self.value.as_deref());
+        buffer.extend_from_slice(&(self.headers.len() as u32).to_be_bytes());
+        for header in &self.headers {
+            buffer.extend_from_slice(&(header.key.len() as u32).to_be_bytes());
+            buffer.extend_from_slice(header.key.as_bytes());
+            put_nullable_bytes(buffer, header.value.as_deref());
+        }
+        let length = (buffer.len() - start - 4) as u32;
+        buffer[start..start + 4].copy_from_slice(&length.to_be_bytes());
+    }
+
+    fn decode_from(reader: &mut Reader<'_>, base_offset: u64, base_timestamp: i64) -> Result<Self> {
+        let length = reader.u32()? as usize;
+        let mut reader = Reader::new(reader.bytes(length)?);
+        let _attributes = reader.u8()?;
+        let timestamp = base_timestamp
+            .checked_add(reader.i64()?)
+            .ok_or(StorageError::InvalidRecordBatch("timestamp delta overflow"))?;
+        let offset = base_offset
+            .checked_add(reader.u32()? as u64)
+            .ok_or(StorageError::InvalidRecordBatch("offset delta overflow"))?;
+        let key = reader.nullable_bytes()?;
+        let value = reader.nullable_bytes()?;
+        let header_count = reader.u32()? as usize;
+        let mut headers = Vec::with_capacity(header_count.min(reader.remaining()));
+        for _ in 0..header_count {
+            let key_length = reader.u32()? as usize;
+            let key = String::from_utf8(reader.bytes(key_length)?.to_vec())
+                .map_err(|_| StorageError::InvalidRecordBatch("header key is not valid UTF-8"))?;
+            let value = reader.nullable_bytes()?;
+            headers.push(Header { key, value });
+        }
+        Ok(Self {
+            offset,
+            timestamp,
+            key,
+            value,
+            headers,
+        })
+    }
+}
+
+/// 记录批次
+#[derive(Debug, Clone)]
+pub struct RecordBatch {
+    /// 批次中第一条记录的 offset
+    pub base_offset: u64,
+    /// 批次属性
+    pub attributes: u8,
+    /// 批次中的记录，offset 递增（日志压缩后可能不连续）
+    pub records: Vec<Record>,
+    /// 解码时保留的压缩记录，见 `RecordBatch::decode`
+    compressed: Option<CompressedRecords>,
+}
+
+impl PartialEq for RecordBatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.base_offset == other.base_offset && self.attributes == other.attributes && self.records == other.records
+    }
+}
+
+impl Eq for RecordBatch {}
+
+/// 解码得到的压缩记录与解压后的内容，重新编码出的记录与解压后的内容相同时直接复用压缩结果
+#[derive(Clone)]
+struct CompressedRecords {
+    compression: CompressionType,
+    compressed: Vec<u8>,
+    decompressed: Vec<u8>,
+}
+
+impl fmt::Debug for CompressedRecords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedRecords")
+            .field("compression", &self.compression)
+            .field("compressed_len", &self.compressed.len())
+            .finish()
+    }
+}
+
+impl RecordBatch {
+    /// 创建新的记录批次，offset 在写入日志时分配
+    pub fn new(records: Vec<Record>) -> Self {
+        let mut batch = Self {
+            base_offset: 0,
+            attributes: 0,
+            records,
+            compressed: None,
+        };
+        batch.assign_offsets(0);
+        batch
+    }
+
+    /// 设置时间戳类型
+    pub fn with_timestamp_type(mut self, timestamp_type: TimestampType) -> Self {
+        match timestamp_type {
+            TimestampType::CreateTime => self.attributes &= !TIMESTAMP_TYPE_FLAG,
+            TimestampType::LogAppendTime => self.attributes |= TIMESTAMP_TYPE_FLAG,
+        }
+        self
+    }
+
+    /// 时间戳类型
+    pub fn timestamp_type(&self) -> TimestampType {
+        if self.attributes & TIMESTAMP_TYPE_FLAG != 0 {
+            TimestampType::LogAppendTime
+        } else {
+            TimestampType::CreateTime
+        }
+    }
+
+    /// 是否为事务批次
+    pub fn is_transactional(&self) -> bool {
+        self.attributes & TRANSACTIONAL_FLAG != 0
+    }
+
+    /// 是否为控制批次
+    pub fn is_control(&self) -> bool {
+        self.attributes & CONTROL_FLAG != 0
+    }
+
+    /// 压缩编码（attributes 的低 3 位）
+    pub fn compression_codec(&self) -> u8 {
+        self.attributes & COMPRESSION_CODEC_MASK
+    }
+
+    /// 设置压缩编码，编码时按该编码压缩记录
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.set_compression(compression);
+        self
+    }
+
+    /// 设置压缩编码
+    pub fn set_compression(&mut self, compression: CompressionType) {
+        self.attributes = (self.attributes & !COMPRESSION_CODEC_MASK) | compression.codec();
+    }
+
+    /// 压缩编码，未知编码按不压缩处理（解码时已校验）
+    pub fn compression(&self) -> CompressionType {
+        CompressionType::from_codec(self.compression_codec()).unwrap_or_default()
+    }
+
+    /// 从 `base_offset` 开始为记录重新分配连续的 offset
+    pub fn assign_offsets(&mut self, base_offset: u64) {
+        self.base_offset = base_offset;
+        for (delta, record) in self.records.iter_mut().enumerate() {
+            record.offset = base_offset + delta as u64;
+        }
+    }
+
+    /// 使用写入时间覆盖所有记录的时间戳（LogAppendTime）
+    pub fn set_append_time(&mut self, timestamp: i64) {
+        for record in &mut self.records {
+            record.timestamp = timestamp;
+        }
+    }
+
+    /// 最后一条记录的 offset
+    pub fn last_offset(&self) -> u64 {
+        self.base_offset + self.last_offset_delta() as u64
+    }
+
+    /// 该批次之后的下一个 offset
+    pub fn next_offset(&self) -> u64 {
+        self.last_offset() + 1
+    }
+
+    /// 最后一条记录相对 base_offset 的增量
+    pub fn last_offset_delta(&self) -> u32 {
+        self.records
+            .last()
+            .map(|r| (r.offset - self.base_offset) as u32)
+            .unwrap_or(0)
+    }
+
+    /// 批次内最大时间戳，空批次返回 -1
+    pub fn max_timestamp(&self) -> i64 {
+        self.records.iter().map(|r| r.timestamp).max().unwrap_or(-1)
+    }
+
+    /// 查找指定 offset 的记录
+    pub fn record(&self, offset: u64) -> Option<&Record> {
+        self.records.iter().find(|r| r.offset == offset)
+    }
+
+    /// 丢弃解码时保留的压缩内容，批次需要长期保存在内存中时调用，之后编码会重新压缩
+    pub fn discard_compressed(&mut self) {
+        self.compressed = None;
+    }
+
+    /// 使用已分配好 offset 的记录创建批次（日志压缩时使用）
+    pub(crate) fn with_records(base_offset: u64, attributes: u8, records: Vec<Record>) -> Self {
+        Self { base_offset, attributes, records, compressed: None }
+    }
+
+    /// 编码为磁盘/网络格式
+    ///
+    /// 由 `decode` 得到的压缩批次，记录内容与相对 offset、时间戳未改变且压缩编码相同时复用原来的压缩结果，
+    /// 只重写批次头部（offset、时间戳、长度与 crc），不会重新压缩。
+    /// 记录相对 base_offset 或第一条记录时间戳的增量超出编码范围时返回 `StorageError::InvalidRecordBatch`
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let base_timestamp = match self.timestamp_type() {
+            TimestampType::CreateTime => self.records.first().map(|r| r.timestamp).unwrap_or(-1),
+            TimestampType::LogAppendTime => self.max_timestamp(),
+        };
+        let mut buffer = Vec::with_capacity(BATCH_HEADER_SIZE);
+        buffer.extend_from_slice(&self.base_offset.to_be_bytes());
+        buffer.extend_from_slice(&[0u8; MSG_LEN_SIZE + CRC_SIZE]); // 长度与 crc 占位
+        buffer.push(self.attributes);
+        buffer.extend_from_slice(&self.last_offset_delta().to_be_bytes());
+        buffer.extend_from_slice(&base_timestamp.to_be_bytes());
+        buffer.extend_from_slice(&self.max_timestamp().to_be_bytes());
+        buffer.extend_from_slice(&(self.records.len() as u32).to_be_bytes());
+        match self.compression() {
+            CompressionType::None => self.encode_records(base_timestamp, &mut buffer)?,
+            compression => {
+                let mut records = Vec::new();
+                self.encode_records(base_timestamp, &mut records)?;
+                match &self.compressed {
+                    Some(cached) if cached.compression == compression && cached.decompressed == records => {
+                        buffer.extend_from_slice(&cached.compressed)
+                    }
+                    _ => buffer.extend_from_slice(&compression.compress(&records)),
+                }
+            }
+        }
+
+        let length = (buffer.len() - MSG_HEADER_SIZE) as u32;
+        buffer[OFFSET_SIZE..OFFSET_SIZE + MSG_LEN_SIZE].copy_from_slice(&length.to_be_bytes());
+        let crc = batch_crc(&buffer[..OFFSET_SIZE + MSG_LEN_SIZE], &buffer[MSG_HEADER_SIZE..]);
+        buffer[OFFSET_SIZE + MSG_LEN_SIZE..MSG_HEADER_SIZE].copy_from_slice(&crc.to_be_bytes());
+        Ok(buffer)
+    }
+
+    /// 按相对 base_offset 与 `base_timestamp` 的增量编码全部记录，LogAppendTime 批次的时间戳增量为 0
+    fn encode_records(&self, base_timestamp: i64, buffer: &mut Vec<u8>) -> Result<()> {
+        let append_time = self.timestamp_type() == TimestampType::LogAppendTime;
+        for record in &self.records {
+            let timestamp_delta = match append_time {
+                true => 0,
+                false => record
+                    .timestamp
+                    .checked_sub(base_timestamp)
+                    .ok_or(StorageError::InvalidRecordBatch("timestamp delta overflow"))?,
+            };
+            let offset_delta = record
+                .offset
+                .checked_sub(self.base_offset)
+                .and_then(|delta| u32::try_from(delta).ok())
+                .ok_or(StorageError::InvalidRecordBatch("offset delta out of range"))?;
+            record.encode_into(timestamp_delta, offset_delta, buffer);
+        }
+        Ok(())
+    }
+
+    /// 从磁盘/网络格式解码，并校验 crc
+    ///
+    /// 压缩批次保留原始的压缩内容，之后重新编码（例如 broker 分配 offset 后写入日志）时不需要再次压缩
+    pub fn decode(buffer: &[u8]) -> Result<Self> {
+        if buffer.len() < BATCH_HEADER_SIZE {
+            return Err(StorageError::InvalidRecordBatch("buffer too short"));
+        }
+        let header: &[u8; MSG_HEADER_SIZE] = buffer[..MSG_HEADER_SIZE].try_into().unwrap();
+        let (base_offset, length, crc) = decode_entry_header(header);
+        if buffer.len() < MSG_HEADER_SIZE + length {
+            return Err(StorageError::InvalidRecordBatch("batch length exceeds buffer"));
+        }
+        let body = &buffer[MSG_HEADER_SIZE..MSG_HEADER_SIZE + length];
+        let actual = batch_crc(header, body);
+        if actual != crc {
+            return Err(StorageError::CorruptRecord {
+                offset: base_offset,
+                position: 0,
+                expected: crc,
+                actual,
+            });
+        }
+        Self::decode_records(base_offset, body, true)
+    }
+
+    /// 解码 crc 字段之后的内容（调用方负责校验 crc）
+    pub(crate) fn decode_body(base_offset: u64, body: &[u8]) -> Result<Self> {
+        Self::decode_records(base_offset, body, false)
+    }
+
+    /// 解码批次内容，`keep_compressed` 为 true 时保留压缩批次的原始压缩内容
+    fn decode_records(base_offset: u64, body: &[u8], keep_compressed: bool) -> Result<Self> {
+        let mut reader = Reader::new(body);
+        let attributes = reader.u8()?;
+        let _last_offset_delta = reader.u32()?;
+        let base_timestamp = reader.i64()?;
+        let max_timestamp = reader.i64()?;
+        let record_count = reader.u32()? as usize;
+        if attributes & ENCRYPTED_FLAG != 0 {
+            return Err(StorageError::InvalidRecordBatch("record batch is encrypted"));
+        }
+        let compression = CompressionType::from_codec(attributes & COMPRESSION_CODEC_MASK)?;
+        let (compressed, decompressed) = match compression {
+            CompressionType::None => (&[][..], None),
+            compression => {
+                let compressed = reader.bytes(reader.remaining())?;
+                (compressed, Some(compression.decompress(compressed, MAX_DECOMPRESSED_BYTES)?))
+            }
+        };
+        let mut reader = match &decompressed {
+            Some(decompressed) => Reader::new(decompressed),
+            None => reader,
+        };
+        let mut records = Vec::with_capacity(record_count.min(reader.remaining()));
+        for _ in 0..record_count {
+            records.push(Record::decode_from(&mut reader, base_offset, base_timestamp)?);
+        }
+        if attributes & TIMESTAMP_TYPE_FLAG != 0 {
+            for record in &mut records {
+                record.timestamp = max_timestamp;
+            }
+        }
+        let compressed = match decompressed {
+            Some(decompressed) if keep_compressed => Some(CompressedRecords {
+                compression,
+                compressed: compressed.to_vec(),
+                decompressed,
+            }),
+            _ => None,
+        };
+        Ok(Self {
+            base_offset,
+            attributes,
+            records,
+            compressed,
+        })
+    }
+}
+
+/// 解析日志条目头部，返回 (base_offset, 长度, crc)
+pub(crate) fn decode_entry_header(header: &[u8; MSG_HEADER_SIZE]) -> (u64, usize, u32) {
+    let offset = u64::from_be_bytes(header[0..OFFSET_SIZE].try_into().unwrap());
+    let length =
+        u32::from_be_bytes(header[OFFSET_SIZE..OFFSET_SIZE + MSG_LEN_SIZE].try_into().unwrap()) as usize;
+    let crc = u32::from_be_bytes(header[OFFSET_SIZE + MSG_LEN_SIZE..MSG_HEADER_SIZE].try_into().unwrap());
+    (offset, length, crc)
+}
+
+/// 批次头部的定长字段，用于在不解码记录的情况下遍历日志
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BatchHeader {
+    pub base_offset: u64,
+    pub length: usize,
+    pub crc: u32,
+    pub last_offset_delta: u32,
+    pub max_timestamp: i64,
+}
+
+impl BatchHeader {
+    pub(crate) fn decode(header: &[u8; BATCH_HEADER_SIZE]) -> Self {
+        let (base_offset, length, crc) = decode_entry_header(header[..MSG_HEADER_SIZE].try_into().unwrap());
+        let body = &header[MSG_HEADER_SIZE..];
+        Self {
+            base_offset,
+            length,
+            crc,
+            last_offset_delta: u32::from_be_bytes(body[1..5].try_into().unwrap()),
+            max_timestamp: i64::from_be_bytes(body[13..21].try_into().unwrap()),
+        }
+    }
+
+    pub(crate) fn last_offset(&self) -> u64 {
+        self.base_offset + self.last_offset_delta as u64
+    }
+
+    /// 整个批次在日志中占用的字节数
+    pub(crate) fn size(&self) -> usize {
+        MSG_HEADER_SIZE + self.length
+    }
+}
+
+/// 计算批次的 CRC32C：覆盖 base_offset、长度字段以及批次内容
+pub(crate) fn batch_crc(header: &[u8], body: &[u8]) -> u32 {
+    let crc = crc32c::crc32c(&header[..OFFSET_SIZE + MSG_LEN_SIZE]);
+    crc32c::crc32c_append(crc, body)
+}
+
+/// 当前时间（毫秒）
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn put_nullable_bytes(buffer: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            buffer.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+            buffer.extend_from_slice(bytes);
+        }
+        None => buffer.extend_from_slice(&(-1i32).to_be_bytes()),
+    }
+}
+
+/// 按大端序顺序读取字段的游标
+struct Reader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        Self { buffer }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buffer.len() < len {
+            return Err(StorageError::InvalidRecordBatch("unexpected end of batch"));
+        }
+        let (head, tail) = self.buffer.split_at(len);
+        self.buffer = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn nullable_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.i32()? {
+            len if len < 0 => Ok(None),
+            len => Ok(Some(self.bytes(len as usize)?.to_vec())),
+        }
+    }
+}
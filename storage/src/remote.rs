@@ -0,0 +1,281 @@
+//! 分层存储
+//!
+//! 已关闭的日志段连同索引上传到远程存储，并记录在分区目录下的远程段清单中；
+//! 本地副本按本地保留策略删除后，读取更早的 offset 时把远程段下载到本地缓存目录再读取。
+
+use crate::segment::{LogSegment, SegmentConfig, SegmentReader};
+use super::{INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// 远程段清单文件名
+pub const REMOTE_MANIFEST_FILE: &str = "remote-segments.manifest";
+/// 远程段的本地缓存目录
+pub const REMOTE_CACHE_DIR: &str = ".remote_cache";
+/// 本地缓存的远程段数量上限
+const REMOTE_CACHE_SEGMENTS: usize = 4;
+
+/// 远程存储，以对象存储的方式按 key 读写整个文件
+pub trait RemoteStorage: Send + Sync + fmt::Debug {
+    /// 上传本地文件
+    fn put(&self, key: &str, source: &Path) -> io::Result<()>;
+    /// 下载到本地文件
+    fn get(&self, key: &str, dest: &Path) -> io::Result<()>;
+    /// 删除对象，对象不存在时不报错
+    fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// 使用本地目录模拟对象存储，key 即相对路径
+#[derive(Debug, Clone)]
+pub struct LocalDirRemoteStorage {
+    root: PathBuf,
+}
+
+impl LocalDirRemoteStorage {
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+}
+
+impl RemoteStorage for LocalDirRemoteStorage {
+    fn put(&self, key: &str, source: &Path) -> io::Result<()> {
+        let dest = self.root.join(key);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // 先写临时文件再 rename，避免读到上传了一半的对象
+        let tmp = dest.with_extension("uploading");
+        fs::copy(source, &tmp)?;
+        fs::rename(&tmp, &dest)
+    }
+
+    fn get(&self, key: &str, dest: &Path) -> io::Result<()> {
+        fs::copy(self.root.join(key), dest).map(|_| ())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 远程段元数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteSegmentMetadata {
+    /// 段的起始 offset
+    pub base_offset: u64,
+    /// 段之后的下一个 offset
+    pub next_offset: u64,
+    /// .log 文件大小（字节）
+    pub size: u64,
+    /// 段内最大时间戳
+    pub max_timestamp: i64,
+}
+
+/// 远程段清单，每行一个段：`base_offset next_offset size max_timestamp`
+#[derive(Debug)]
+pub struct RemoteManifest {
+    path: PathBuf,
+    segments: BTreeMap<u64, RemoteSegmentMetadata>,
+}
+
+impl RemoteManifest {
+    /// 加载清单，文件不存在时为空
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut segments = BTreeMap::new();
+        if path.exists() {
+            for line in fs::read_to_string(&path)?.lines().filter(|line| !line.trim().is_empty()) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("远程段清单格式错误: {}", line));
+                if fields.len() != 4 {
+                    return Err(invalid());
+                }
+                let metadata = RemoteSegmentMetadata {
+                    base_offset: fields[0].parse().map_err(|_| invalid())?,
+                    next_offset: fields[1].parse().map_err(|_| invalid())?,
+                    size: fields[2].parse().map_err(|_| invalid())?,
+                    max_timestamp: fields[3].parse().map_err(|_| invalid())?,
+                };
+                segments.insert(metadata.base_offset, metadata);
+            }
+        }
+        Ok(Self { path, segments })
+    }
+
+    /// 原子地写回清单文件
+    fn save(&self) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        for metadata in self.segments.values() {
+            writeln!(
+                file,
+                "{} {} {} {}",
+                metadata.base_offset, metadata.next_offset, metadata.size, metadata.max_timestamp
+            )?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    pub fn contains(&self, base_offset: u64) -> bool {
+        self.segments.contains_key(&base_offset)
+    }
+
+    /// 包含 offset 的远程段
+    pub fn find(&self, offset: u64) -> Option<&RemoteSegmentMetadata> {
+        self.segments
+            .range(..=offset)
+            .next_back()
+            .map(|(_, metadata)| metadata)
+            .filter(|metadata| offset < metadata.next_offset)
+    }
+
+    /// 起始 offset 小于 `offset` 的远程段，按 base_offset 排序
+    pub fn segments_before(&self, offset: u64) -> Vec<RemoteSegmentMetadata> {
+        self.segments.range(..offset).map(|(_, metadata)| *metadata).collect()
+    }
+
+    /// 最早的远程 offset
+    pub fn log_start_offset(&self) -> Option<u64> {
+        self.segments.keys().next().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+/// 分区的远程日志：负责上传、删除远程段，以及把远程段下载到本地缓存读取
+pub struct RemoteLog {
+    storage: Arc<dyn RemoteStorage>,
+    prefix: String,           // 该分区在远程存储中的 key 前缀
+    manifest: RemoteManifest, // 远程段清单
+    cache_dir: String,        // 下载的远程段所在目录
+    config: SegmentConfig,    // 打开缓存段使用的配置
+    cache: Mutex<VecDeque<LogSegment>>, // 最近读取的远程段，多个读取方共享
+}
+
+impl fmt::Debug for RemoteLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteLog")
+            .field("prefix", &self.prefix)
+            .field("segments", &self.manifest.len())
+            .field("cached", &self.cache.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl RemoteLog {
+    /// 打开分区的远程日志，清空上次遗留的缓存
+    pub fn open(log_dir: &str, prefix: &str, storage: Arc<dyn RemoteStorage>, config: SegmentConfig) -> io::Result<Self> {
+        let log_dir = log_dir.trim_end_matches('/');
+        let cache_dir = format!("{}/{}", log_dir, REMOTE_CACHE_DIR);
+        if Path::new(&cache_dir).exists() {
+            fs::remove_dir_all(&cache_dir)?;
+        }
+        Ok(Self {
+            storage,
+            prefix: prefix.to_string(),
+            manifest: RemoteManifest::load(format!("{}/{}", log_dir, REMOTE_MANIFEST_FILE))?,
+            cache_dir,
+            config,
+            cache: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn manifest(&self) -> &RemoteManifest {
+        &self.manifest
+    }
+
+    pub fn storage(&self) -> &Arc<dyn RemoteStorage> {
+        &self.storage
+    }
+
+    /// 该分区在远程存储中的 key 前缀
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn key(&self, base_offset: u64, suffix: &str) -> String {
+        format!("{}/{:020}{}", self.prefix, base_offset, suffix)
+    }
+
+    /// 上传已关闭的日志段及其索引，全部上传后才记录到清单
+    pub fn upload(&mut self, segment: &LogSegment) -> io::Result<()> {
+        let base_offset = segment.get_base_offset();
+        if self.manifest.contains(base_offset) {
+            return Ok(());
+        }
+        for suffix in [INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX] {
+            self.storage.put(&self.key(base_offset, suffix), &segment.file_path(suffix))?;
+        }
+        self.manifest.segments.insert(
+            base_offset,
+            RemoteSegmentMetadata {
+                base_offset,
+                next_offset: segment.get_next_offset(),
+                size: segment.get_size() as u64,
+                max_timestamp: segment.get_max_timestamp(),
+            },
+        );
+        self.manifest.save()
+    }
+
+    /// 删除远程段，先从清单中移除再删除对象
+    pub fn delete_segment(&mut self, base_offset: u64) -> io::Result<()> {
+        if self.manifest.segments.remove(&base_offset).is_none() {
+            return Ok(());
+        }
+        self.manifest.save()?;
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(index) = cache.iter().position(|s| s.get_base_offset() == base_offset) {
+            if let Some(segment) = cache.remove(index) {
+                segment.delete()?;
+            }
+        }
+        for suffix in [LOG_FILE_SUFFIX, INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX] {
+            self.storage.delete(&self.key(base_offset, suffix))?;
+        }
+        Ok(())
+    }
+
+    /// 返回包含 offset 的远程段的读取视图，不在本地缓存中时先下载
+    ///
+    /// 被淘汰的缓存段在仍被读取时保持打开，读取结束后文件才真正释放
+    pub fn segment_for(&self, offset: u64) -> io::Result<Option<SegmentReader>> {
+        let Some(metadata) = self.manifest.find(offset).copied() else {
+            return Ok(None);
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(segment) = cache.iter().find(|s| s.get_base_offset() == metadata.base_offset) {
+            return Ok(Some(segment.reader()));
+        }
+        fs::create_dir_all(&self.cache_dir)?;
+        for suffix in [INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX] {
+            let dest = format!("{}/{:020}{}", self.cache_dir, metadata.base_offset, suffix);
+            self.storage.get(&self.key(metadata.base_offset, suffix), Path::new(&dest))?;
+        }
+        if cache.len() >= REMOTE_CACHE_SEGMENTS {
+            if let Some(evicted) = cache.pop_front() {
+                evicted.delete()?;
+            }
+        }
+        let segment = LogSegment::with_config(&self.cache_dir, metadata.base_offset, self.config.clone())?;
+        let reader = segment.reader();
+        cache.push_back(segment);
+        Ok(Some(reader))
+    }
+}
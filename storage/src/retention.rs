@@ -1,37 +1,86 @@
-use std::fs;
-use std::time::{Duration, SystemTime};
-use std::thread;
-
-const RETENTION_TIME: Duration = Duration::from_secs(7 * 24 * 60 * 60); // 7 天
-const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024 * 1024; // 10GB
-
-pub fn clean_old_segments(log_dir: String) {
-    thread::spawn(move || {
-        loop {
-            if let Ok(entries) = fs::read_dir(&log_dir) {
-                let mut segments = entries
-                    .filter_map(|entry| entry.ok())
-                    .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "log"))
-                    .collect::<Vec<_>>();
-
-                segments.sort_by_key(|entry| entry.metadata().unwrap().created().unwrap());
-
-                let mut total_size = 0;
-                let now = SystemTime::now();
-
-                for segment in &segments {
-                    let metadata = segment.metadata().unwrap();
-                    total_size += metadata.len();
-
-                    if total_size > MAX_LOG_SIZE || now.duration_since(metadata.created().unwrap()).unwrap() > RETENTION_TIME {
-                        fs::remove_file(segment.path()).unwrap();
-                        let index_path = segment.path().with_extension("index");
-                        fs::remove_file(index_path).unwrap();
-                    }
-                }
-            }
+//! 日志保留策略
+//!
+//! 只决定删除哪些段，不直接操作文件：按段的 base_offset 顺序从最旧的段开始判断，
+//! 段内最大时间戳超过保留时间、或删除该段后剩余大小仍不小于保留大小时删除，
+//! 遇到第一个不满足条件的段即停止，保证剩余日志连续。活跃段不参与判断。
+
+/// 日志保留策略，负数表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// 保留时间（毫秒），对应 retention.ms
+    pub retention_ms: i64,
+    /// 每个分区保留的最大字节数，对应 retention.bytes
+    pub retention_bytes: i64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            retention_ms: 7 * 24 * 60 * 60 * 1000,
+            retention_bytes: -1,
+        }
+    }
+}
+
+/// 主题级保留策略覆盖，未设置的项使用 broker 默认值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RetentionOverrides {
+    pub retention_ms: Option<i64>,
+    pub retention_bytes: Option<i64>,
+    /// 启用分层存储时本地副本的保留时间，对应 local.retention.ms
+    pub local_retention_ms: Option<i64>,
+    /// 启用分层存储时本地副本的保留大小，对应 local.retention.bytes
+    pub local_retention_bytes: Option<i64>,
+}
+
+impl RetentionPolicy {
+    pub fn new(retention_ms: i64, retention_bytes: i64) -> Self {
+        Self {
+            retention_ms,
+            retention_bytes,
+        }
+    }
 
-            thread::sleep(Duration::from_secs(60 * 60));
+    /// 由 broker 配置 log_retention_hours / log_retention_bytes 创建
+    pub fn from_hours(retention_hours: u32, retention_bytes: i64) -> Self {
+        Self::new(retention_hours as i64 * 60 * 60 * 1000, retention_bytes)
+    }
+
+    /// 应用主题级覆盖
+    pub fn with_overrides(self, overrides: &RetentionOverrides) -> Self {
+        Self {
+            retention_ms: overrides.retention_ms.unwrap_or(self.retention_ms),
+            retention_bytes: overrides.retention_bytes.unwrap_or(self.retention_bytes),
+        }
+    }
+
+    /// 本地保留策略：未设置本地覆盖的项与总保留策略相同
+    pub fn local_policy(self, overrides: &RetentionOverrides) -> Self {
+        Self {
+            retention_ms: overrides.local_retention_ms.unwrap_or(self.retention_ms),
+            retention_bytes: overrides.local_retention_bytes.unwrap_or(self.retention_bytes),
+        }
+    }
+
+    /// 段内最大时间戳是否已超过保留时间，空段（最大时间戳为 -1）视为过期
+    pub fn is_expired(&self, max_timestamp: i64, now: i64) -> bool {
+        self.retention_ms >= 0 && (max_timestamp < 0 || now - max_timestamp > self.retention_ms)
+    }
+
+    /// 计算需要删除的最旧段数量
+    ///
+    /// `closed` 为按 base_offset 排序的已关闭段 (大小, 最大时间戳)，`total_size` 为包含活跃段在内的分区总大小
+    pub fn segments_to_delete(&self, closed: &[(u64, i64)], total_size: u64, now: i64) -> usize {
+        let mut size = total_size;
+        let mut count = 0;
+        for &(segment_size, max_timestamp) in closed {
+            let over_size = self.retention_bytes >= 0 && size - segment_size >= self.retention_bytes as u64;
+            if !over_size && !self.is_expired(max_timestamp, now) {
+                break;
+            }
+            size -= segment_size;
+            count += 1;
         }
-    });
-}
\ No newline at end of file
+        count
+    }
+}
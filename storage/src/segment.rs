@@ -1,22 +1,157 @@
-use super::{INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX, MSG_HEADER_SIZE, OFFSET_SIZE};
-use crate::concurrency::MutexFile;
-use crate::io_result::IoResult;
+use super::{INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX, MSG_HEADER_SIZE, TIME_INDEX_FILE_SUFFIX};
+use crate::concurrency::LogFile;
+use crate::encryption::{has_segment_magic, BatchCipher, EncryptionKeys, SEGMENT_HEADER_SIZE};
+use crate::error::{Result, StorageError};
+use crate::io_result::{IoResult, RollReason};
 use crate::mmap::MmapIndex;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::slice::FileSlice;
+use crate::record::{batch_crc, now_ms, BatchHeader, Record, RecordBatch, TimestampType, BATCH_HEADER_SIZE};
+use crate::time_index::TimeIndex;
+use std::io;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// 日志段配置
+#[derive(Debug, Clone)]
+pub struct SegmentConfig {
+    /// 单个段的最大大小（字节）
+    pub max_segment_size: usize,
+    /// 每写入多少字节追加一个索引条目（index.interval.bytes）
+    pub index_interval_bytes: usize,
+    /// 索引文件预分配的最大大小（segment.index.bytes）
+    pub max_index_size: usize,
+    /// 段内时间跨度超过该值（毫秒）后滚动（segment.ms），负数表示不按时间滚动
+    pub segment_ms: i64,
+    /// 主题的加密密钥，设置后新建的日志段加密记录内容；打开已加密的日志段也需要设置
+    pub encryption: Option<Arc<EncryptionKeys>>,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_size: 1024 * 1024,
+            index_interval_bytes: 4096,
+            max_index_size: 10 * 1024 * 1024,
+            segment_ms: 7 * 24 * 60 * 60 * 1000,
+            encryption: None,
+        }
+    }
+}
+
+/// 范围读取的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadRange {
+    /// 连续读取到的记录，按 offset 递增
+    pub records: Vec<Record>,
+    /// 下一次读取应使用的 offset
+    pub next_offset: u64,
+    /// 读取的批次在日志中占用的字节数
+    pub bytes: usize,
+}
+
+/// 顺序遍历批次头部时对每个批次的处理方式
+enum Visit {
+    /// 跳过该批次
+    Skip,
+    /// 读取并返回该批次
+    Read,
+    /// 停止遍历
+    Stop,
+}
+
+/// 打开已有日志段时的校验方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenMode {
+    /// 只读取批次头部，不校验
+    Clean,
+    /// 从最后一个索引条目开始校验尾部
+    RecoverTail,
+    /// 从头校验并重建索引
+    Repair,
+}
+
+/// 读取方与写入方共享的日志段状态
+#[derive(Debug)]
+struct SegmentState {
+    log_file: LogFile,                // 存储实际消息数据
+    indexes: RwLock<SegmentIndexes>,  // 偏移量索引与时间索引
+    base_offset: u64,                 // 当前段的起始 offset
+    next_offset: AtomicU64,           // 下一个消息的 offset
+    max_timestamp: AtomicI64,         // 当前段内的最大时间戳，空段为 -1
+    log_end: AtomicU64,               // 已完整写入的批次的结束位置，读取方不会越过该位置
+    data_start: u64,                  // 第一个批次在日志文件中的位置，加密段为段头部大小，否则为 0
+    cipher: Option<BatchCipher>,      // 加密段的数据密钥
+}
+
+#[derive(Debug)]
+struct SegmentIndexes {
+    offset: MmapIndex, // 存储索引,使用预分配的可写mmap
+    time: TimeIndex,   // 时间索引,使用预分配的可写mmap
+}
+
+impl SegmentState {
+    fn indexes(&self) -> RwLockReadGuard<'_, SegmentIndexes> {
+        self.indexes.read().unwrap()
+    }
+
+    fn indexes_mut(&self) -> RwLockWriteGuard<'_, SegmentIndexes> {
+        self.indexes.write().unwrap()
+    }
+
+    fn next_offset(&self) -> u64 {
+        self.next_offset.load(Ordering::Acquire)
+    }
+
+    /// 稀疏索引中不大于 offset 的最后一个条目的位置，没有时从段内第一个批次开始
+    fn position_of(&self, offset: u64) -> u64 {
+        self.indexes().offset.find_position(offset).unwrap_or(self.data_start)
+    }
+}
+
+/// 日志段的只读视图，可以 clone 到多个线程，与写入方同时读取
+///
+/// 写入方先写入日志文件，再依次发布日志结束位置、索引条目与下一个 offset；
+/// 读取按位置进行且不越过已发布的结束位置，因此只会看到完整写入的批次
+#[derive(Debug, Clone)]
+pub struct SegmentReader {
+    state: Arc<SegmentState>,
+}
 
 #[derive(Debug)]
 pub struct LogSegment {
-    log_file: MutexFile,     // 存储实际消息数据
-    index_file: MutexFile,   // 存储索引 //todo 待优化：是否预分配空间？
-    mmap_index: MmapIndex,   // 存储索引,使用mmap
-    #[allow(dead_code)]
-    base_offset: u64,        // 当前段的起始 offset
-    offset: u64,             // 下一个消息的 offset
-    max_segment_size: usize, // 单个段的最大大小
+    log_dir: String,         // 日志段所在目录
+    reader: SegmentReader,   // 与读取方共享的段状态
+    bytes_since_last_index_entry: usize, // 距上一个索引条目写入的字节数
+    rolling_base_timestamp: Option<i64>, // 第一个批次的最大时间戳，按时间滚动时的基准
+    config: SegmentConfig,   // 段配置
 }
 
 impl LogSegment {
     pub fn new(log_dir: &str, base_offset: u64, max_segment_size: usize) -> io::Result<Self> {
+        let config = SegmentConfig {
+            max_segment_size,
+            ..SegmentConfig::default()
+        };
+        Self::with_config(log_dir, base_offset, config)
+    }
+
+    /// 使用指定配置打开或创建日志段，已有数据时从最后一个索引条目开始校验并截断损坏的尾部
+    pub fn with_config(log_dir: &str, base_offset: u64, config: SegmentConfig) -> io::Result<Self> {
+        Self::open(log_dir, base_offset, config, OpenMode::RecoverTail)
+    }
+
+    /// 打开已知完整的日志段（正常关闭或位于恢复点之前），只读取最后一个索引条目之后的批次头部，
+    /// 不校验 CRC；批次头部无效时退回到完整恢复
+    pub fn open_clean(log_dir: &str, base_offset: u64, config: SegmentConfig) -> io::Result<Self> {
+        Self::open(log_dir, base_offset, config, OpenMode::Clean)
+    }
+
+    /// 打开日志段并调用 `repair` 从头校验、重建索引，用于非正常关闭后恢复点之后的日志段
+    pub fn open_repaired(log_dir: &str, base_offset: u64, config: SegmentConfig) -> io::Result<Self> {
+        Self::open(log_dir, base_offset, config, OpenMode::Repair)
+    }
+
+    fn open(log_dir: &str, base_offset: u64, config: SegmentConfig, mode: OpenMode) -> io::Result<Self> {
         let log_dir = log_dir.trim_end_matches('/');
 
         if !std::path::Path::new(log_dir).exists() {
@@ -26,116 +161,556 @@ impl LogSegment {
         let start_offset = format!("{:020}", base_offset);
         let log_file_path = format!("{}/{}{}", log_dir, start_offset, LOG_FILE_SUFFIX);
         let index_file_path = format!("{}/{}{}", log_dir, start_offset, INDEX_FILE_SUFFIX);
+        let time_index_file_path = format!("{}/{}{}", log_dir, start_offset, TIME_INDEX_FILE_SUFFIX);
 
-        let log_file = MutexFile::new(&log_file_path)?;
-        let index_file = MutexFile::new(&index_file_path)?;
-        let mmap_index = MmapIndex::new(&index_file.lock())?;
+        // 索引文件在打开时才会被创建，需要先判断是否缺失
+        let index_missing = !std::path::Path::new(&index_file_path).exists()
+            || !std::path::Path::new(&time_index_file_path).exists();
+        let log_file = LogFile::open(&log_file_path)?;
+        let (data_start, cipher) = Self::open_header(&log_file, &config)?;
+        let mmap_index = MmapIndex::open(&index_file_path, base_offset, config.max_index_size)?;
+        let time_index = TimeIndex::open(&time_index_file_path, base_offset, config.max_index_size)?;
+        let file_len = log_file.size()?;
+
+        let mut segment = Self {
+            log_dir: log_dir.to_string(),
+            reader: SegmentReader {
+                state: Arc::new(SegmentState {
+                    log_file,
+                    indexes: RwLock::new(SegmentIndexes {
+                        offset: mmap_index,
+                        time: time_index,
+                    }),
+                    base_offset,
+                    next_offset: AtomicU64::new(base_offset),
+                    max_timestamp: AtomicI64::new(-1),
+                    log_end: AtomicU64::new(file_len),
+                    data_start,
+                    cipher,
+                }),
+            },
+            bytes_since_last_index_entry: 0,
+            rolling_base_timestamp: None,
+            config,
+        };
 
         // 判断日志文件是否为空，如果为空，则使用 base_offset 否则从文件中恢复
-        let offset = {
-            let mut file = log_file.lock(); // 锁定文件进行操作
-            if file.metadata()?.len() == 0 {
-                base_offset // 文件为空时，偏移量从 base_offset 开始
-            } else {
-                // 恢复消息的偏移量
-                Self::recover_message_offset(&mut file, &mmap_index)?
+        if file_len > data_start {
+            if mode == OpenMode::Repair {
+                segment.repair()?;
+            } else if index_missing || !segment.index_is_consistent(file_len) {
+                eprintln!("日志段 {} 的索引缺失或不一致，从日志重建", base_offset);
+                segment.repair()?;
+            } else if mode == OpenMode::RecoverTail || segment.load_clean_state().is_err() {
+                segment.recover_message_offset()?;
             }
-        };
+            segment.load_rolling_base_timestamp()?;
+        }
+        Ok(segment)
+    }
 
-        Ok(Self {
-            log_file,
-            index_file,
-            mmap_index,
-            base_offset,
-            offset,
-            max_segment_size,
-        })
+    /// 读取加密段的段头部，返回 (第一个批次的位置, 数据密钥)
+    ///
+    /// 空文件（或段头部写入中断）在配置了加密时写入新的段头部；没有段头部的已有日志段按明文读取
+    fn open_header(log_file: &LogFile, config: &SegmentConfig) -> io::Result<(u64, Option<BatchCipher>)> {
+        let file_len = log_file.size()?;
+        let mut header = [0u8; SEGMENT_HEADER_SIZE];
+        let prefix_len = (file_len as usize).min(SEGMENT_HEADER_SIZE);
+        log_file.read_exact_at(&mut header[..prefix_len], 0)?;
+        let has_magic = has_segment_magic(&header[..prefix_len]);
+        if has_magic && prefix_len == SEGMENT_HEADER_SIZE {
+            let keys = config.encryption.as_ref().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "segment is encrypted but no encryption key is configured")
+            })?;
+            return Ok((SEGMENT_HEADER_SIZE as u64, Some(keys.open_segment_header(&header)?)));
+        }
+        if file_len > 0 && !has_magic {
+            return Ok((0, None));
+        }
+
+        log_file.set_len(0)?;
+        match &config.encryption {
+            Some(keys) => {
+                let (header, cipher) = keys.segment_header();
+                log_file.append(&header)?;
+                Ok((header.len() as u64, Some(cipher)))
+            }
+            None => Ok((0, None)),
+        }
+    }
+
+    fn state(&self) -> &SegmentState {
+        &self.reader.state
     }
 
+    /// 返回与写入方共享状态的只读视图，读取不需要持有 `LogSegment`
+    pub fn reader(&self) -> SegmentReader {
+        self.reader.clone()
+    }
+
+    /// 追加单条消息，消息被包装为只含一条记录的批次
     pub fn append_message(&mut self, message: &[u8]) -> io::Result<IoResult> {
-        let mut log_file = self.log_file.lock();
-        let mut index_file = self.index_file.lock();
-        let file_len = log_file.metadata()?.len();
-        if file_len >= self.max_segment_size as u64 {
-            log_file.flush()?;
-            index_file.flush()?;
-            self.mmap_index = MmapIndex::new(&index_file)?;
-            return Ok(IoResult::SegmentFull);
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(message.to_vec()))]);
+        self.append_batch(&mut batch)
+    }
+
+    /// 追加记录批次，为批次分配 offset，成功时返回批次的 base_offset
+    ///
+    /// 写入前按批次编码后的大小、时间戳与索引容量判断是否需要滚动，需要时返回 `IoResult::SegmentFull`
+    /// 且不写入；空段总是接受第一个批次，即使它本身超过段大小
+    pub fn append_batch(&mut self, batch: &mut RecordBatch) -> io::Result<IoResult> {
+        if batch.records.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty record batch"));
         }
+        batch.assign_offsets(self.state().next_offset());
+        if batch.timestamp_type() == TimestampType::LogAppendTime {
+            batch.set_append_time(now_ms());
+        }
+        let buffer = self.encode(batch)?;
+        if let Some(reason) = self.roll_reason(buffer.len(), batch.max_timestamp()) {
+            // 段滚动时把预分配的索引截断到实际大小
+            let mut indexes = self.state().indexes_mut();
+            indexes.offset.trim()?;
+            indexes.time.trim()?;
+            return Ok(IoResult::SegmentFull(reason));
+        }
+        self.write_encoded(batch, &buffer)?;
+        Ok(IoResult::Success(batch.base_offset))
+    }
 
-        let offset_bytes = self.offset.to_be_bytes();
-        let log_pos_bytes = file_len.to_be_bytes();
+    /// 判断写入 `incoming_size` 字节、最大时间戳为 `incoming_timestamp` 的批次前是否需要滚动
+    fn roll_reason(&self, incoming_size: usize, incoming_timestamp: i64) -> Option<RollReason> {
+        let state = self.state();
+        let file_len = state.log_end.load(Ordering::Acquire);
+        if file_len == state.data_start {
+            return None;
+        }
+        if file_len + incoming_size as u64 > self.config.max_segment_size as u64 {
+            return Some(RollReason::SegmentSize);
+        }
+        if self.config.segment_ms >= 0
+            && self
+                .rolling_base_timestamp
+                .is_some_and(|base| incoming_timestamp.saturating_sub(base) > self.config.segment_ms)
+        {
+            return Some(RollReason::SegmentAge);
+        }
+        let indexes = state.indexes();
+        if indexes.offset.is_full() || indexes.time.is_full() {
+            Some(RollReason::IndexFull)
+        } else {
+            None
+        }
+    }
+
+    /// 读取第一个批次的最大时间戳作为按时间滚动的基准
+    fn load_rolling_base_timestamp(&mut self) -> io::Result<()> {
+        let mut timestamp = None;
+        self.reader.visit_batches(
+            self.state().data_start,
+            &mut timestamp,
+            |timestamp, header| {
+                *timestamp = Some(header.max_timestamp);
+                Visit::Stop
+            },
+            |_, _| false,
+        )?;
+        self.rolling_base_timestamp = timestamp;
+        Ok(())
+    }
+
+    /// 按批次中已有的 offset 追加批次（日志压缩时使用），offset 可以不连续但不能回退
+    pub(crate) fn append_retained(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        if batch.records.is_empty() || batch.base_offset < self.state().next_offset() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid retained batch offset"));
+        }
+        self.write_batch(batch)
+    }
+
+    /// 写入已分配好 offset 的批次，并按需写入稀疏索引与时间索引
+    fn write_batch(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        let buffer = self.encode(batch)?;
+        self.write_encoded(batch, &buffer)
+    }
 
-        let mut buffer = Vec::with_capacity(MSG_HEADER_SIZE + message.len());
-        buffer.extend_from_slice(&offset_bytes);
-        buffer.extend_from_slice(&(message.len() as u32).to_be_bytes());
-        buffer.extend_from_slice(message);
+    /// 编码批次，加密段同时加密记录部分
+    fn encode(&self, batch: &RecordBatch) -> Result<Vec<u8>> {
+        let buffer = batch.encode()?;
+        Ok(match &self.state().cipher {
+            Some(cipher) => cipher.encrypt(&buffer),
+            None => buffer,
+        })
+    }
 
-        log_file.write_all(&buffer)?;
+    /// 写入已编码的批次
+    ///
+    /// 依次发布日志结束位置、索引条目与下一个 offset，读取方按索引找到的位置总在已发布的范围内
+    fn write_encoded(&mut self, batch: &RecordBatch, buffer: &[u8]) -> io::Result<()> {
+        let state = Arc::clone(&self.reader.state);
+        let file_len = state.log_end.load(Ordering::Acquire);
+        if let Err(e) = state.log_file.append(buffer) {
+            // 去掉写入了一部分的批次，保证下一次追加的位置与 log_end 一致
+            let _ = state.log_file.set_len(file_len);
+            return Err(e);
+        }
+        let max_timestamp = state.max_timestamp.fetch_max(batch.max_timestamp(), Ordering::AcqRel).max(batch.max_timestamp());
+        if file_len == state.data_start {
+            self.rolling_base_timestamp = Some(batch.max_timestamp());
+        }
+        state.log_end.store(file_len + buffer.len() as u64, Ordering::Release);
 
-        if self.offset % 100 == 0 {
-            let index_entry = [&offset_bytes[..], &log_pos_bytes[..]].concat();
-            log_file.flush()?;
-            index_file.write_all(&index_entry)?;
-            index_file.flush()?;
-            self.mmap_index = MmapIndex::new(&index_file)?; // 重新映射 mmap
+        // 距上一个索引条目超过 index_interval_bytes 时写入稀疏索引，最大时间戳增长时同步写入时间索引
+        if self.bytes_since_last_index_entry >= self.config.index_interval_bytes {
+            let mut indexes = state.indexes_mut();
+            if indexes.offset.append(batch.base_offset, file_len)? {
+                indexes.time.maybe_append(max_timestamp, batch.base_offset)?;
+            }
+            self.bytes_since_last_index_entry = 0;
         }
+        self.bytes_since_last_index_entry += buffer.len();
+
+        state.next_offset.store(batch.last_offset() + 1, Ordering::Release);
+        Ok(())
+    }
 
-        let offset = self.offset; //相对偏移量（8 字节，相对于基准偏移量）
-        self.offset += 1;
-        Ok(IoResult::Success(offset))
+    /// 从最后一个索引条目开始只读取批次头部，恢复下一个 offset、最大时间戳与索引间隔
+    fn load_clean_state(&mut self) -> Result<()> {
+        let state = Arc::clone(&self.reader.state);
+        let file_len = state.log_file.size()?;
+        let (start_offset, start_pos, max_timestamp) = {
+            let indexes = state.indexes();
+            let (start_offset, start_pos) = indexes.offset.last_entry().unwrap_or((state.base_offset, state.data_start));
+            (start_offset, start_pos, indexes.time.last_entry().map(|(ts, _)| ts).unwrap_or(-1))
+        };
+        if start_pos > file_len {
+            return Err(StorageError::InvalidRecordBatch("index entry past end of log"));
+        }
+        // (下一个 offset, 最大时间戳, 已读取到的位置)
+        let mut scan = (start_offset, max_timestamp, start_pos);
+        self.reader.visit_batches(
+            start_pos,
+            &mut scan,
+            |(next_offset, max_timestamp, position), header| {
+                *next_offset = header.last_offset() + 1;
+                *max_timestamp = (*max_timestamp).max(header.max_timestamp);
+                *position += header.size() as u64;
+                Visit::Skip
+            },
+            |_, _| false,
+        )?;
+        let (next_offset, max_timestamp, position) = scan;
+        if position != file_len {
+            return Err(StorageError::InvalidRecordBatch("incomplete batch at end of log"));
+        }
+        state.max_timestamp.store(max_timestamp, Ordering::Release);
+        state.next_offset.store(next_offset, Ordering::Release);
+        self.bytes_since_last_index_entry = (file_len - start_pos) as usize;
+        Ok(())
     }
 
     // * 恢复消息偏移量
-    fn recover_message_offset(log_file: &mut std::sync::MutexGuard<'_, std::fs::File>, mmap_index: &MmapIndex) -> io::Result<u64> {
-
-        let (mut last_offset, last_pos) = mmap_index.last_entry().unwrap_or((0, 0));
-
-        log_file.seek(SeekFrom::Start(last_pos))?;
-        let mut buffer = [0u8; MSG_HEADER_SIZE]; // 8 字节消息号 + 4 字节长度
-        while log_file.read_exact(&mut buffer).is_ok() {
-            last_offset = u64::from_be_bytes(buffer[0..OFFSET_SIZE].try_into().unwrap());
-            let length =
-                u32::from_be_bytes(buffer[OFFSET_SIZE..MSG_HEADER_SIZE].try_into().unwrap());
-            log_file.seek(SeekFrom::Current(length as i64))?;
-        }
-        Ok(last_offset + 1 as u64)
-    }
-
-    /// 读取指定 offset 的消息    
-    pub fn read_message(&mut self, offset: u64) -> io::Result<Option<Vec<u8>>> {
-        let pos = match self.mmap_index.find_position(offset) {
-            Some(pos) => pos,
-            None => {
-                if self.offset > 1000 {
-                    self.mmap_index
-                        .find_position(self.offset - 1000)
-                        .unwrap_or(0) //如果 mmap_index 里没有找到 offset，从 0 位置开始查找可能会导致全文件扫描，影响性能。
-                } else {
-                    0
+    // 从最后一个索引条目开始校验日志尾部，遇到不完整、校验失败或 offset 回退的记录时，
+    // 将 .log、.index 和 .timeindex 截断到最后一条有效记录之后
+    fn recover_message_offset(&mut self) -> io::Result<()> {
+        let state = Arc::clone(&self.reader.state);
+        let file_len = state.log_file.size()?;
+        let mut indexes = state.indexes_mut();
+
+        // 索引条目指向文件末尾之外时（例如日志被截断），先丢弃这些条目
+        if indexes.offset.last_entry().is_some_and(|(_, pos)| pos >= file_len) {
+            let entries = indexes.offset.entries_before_position(file_len);
+            indexes.offset.truncate(entries)?;
+        }
+
+        let (mut next_offset, start_pos) = indexes.offset.last_entry().unwrap_or((state.base_offset, state.data_start));
+        let mut max_timestamp = indexes.time.last_entry().map(|(ts, _)| ts).unwrap_or(-1);
+        let valid_end = Self::scan_valid_records(
+            &state.log_file,
+            start_pos,
+            file_len,
+            &mut next_offset,
+            &mut max_timestamp,
+            |_, _, _| Ok(()),
+        )?;
+
+        if valid_end < file_len {
+            eprintln!(
+                "日志段 {} 在位置 {} 处发现损坏记录，截断 {} 字节",
+                state.base_offset,
+                valid_end,
+                file_len - valid_end
+            );
+            state.log_end.store(valid_end, Ordering::Release);
+            state.log_file.set_len(valid_end)?;
+            let entries = indexes.offset.entries_before_position(valid_end);
+            indexes.offset.truncate(entries)?;
+        }
+
+        // 时间索引中不能存在尚未写入日志的 offset
+        let entries = indexes.time.entries_before_offset(next_offset);
+        indexes.time.truncate(entries)?;
+        drop(indexes);
+
+        state.log_end.store(valid_end, Ordering::Release);
+        state.max_timestamp.store(max_timestamp, Ordering::Release);
+        state.next_offset.store(next_offset, Ordering::Release);
+        self.bytes_since_last_index_entry = (valid_end - start_pos) as usize;
+        Ok(())
+    }
+
+    /// 修复日志段：从头校验全部批次，截断损坏的尾部，并按 `index_interval_bytes` 重建 .index 和 .timeindex，
+    /// 返回截断的字节数
+    ///
+    /// 索引缺失或不一致时打开日志段会自动调用；非正常关闭后恢复点之后的日志段也应调用
+    pub fn repair(&mut self) -> io::Result<u64> {
+        let state = Arc::clone(&self.reader.state);
+        let mut guard = state.indexes_mut();
+        let indexes = &mut *guard;
+        indexes.offset.truncate(0)?;
+        indexes.time.truncate(0)?;
+        let file_len = state.log_file.size()?;
+
+        let interval = self.config.index_interval_bytes;
+        let mut next_offset = state.base_offset;
+        let mut max_timestamp = -1;
+        // 与写入时相同：距上一个索引条目超过间隔后，为下一个批次写入索引条目
+        let mut bytes_since_last_index_entry = 0;
+        let valid_end = Self::scan_valid_records(
+            &state.log_file,
+            state.data_start,
+            file_len,
+            &mut next_offset,
+            &mut max_timestamp,
+            |header, pos, max_timestamp| {
+                if bytes_since_last_index_entry >= interval {
+                    if indexes.offset.append(header.base_offset, pos)? {
+                        indexes.time.maybe_append(max_timestamp, header.base_offset)?;
+                    }
+                    bytes_since_last_index_entry = 0;
                 }
+                bytes_since_last_index_entry += header.size();
+                Ok(())
+            },
+        )?;
+
+        if valid_end < file_len {
+            eprintln!(
+                "日志段 {} 在位置 {} 处发现损坏记录，截断 {} 字节",
+                state.base_offset,
+                valid_end,
+                file_len - valid_end
+            );
+            state.log_end.store(valid_end, Ordering::Release);
+            state.log_file.set_len(valid_end)?;
+        }
+        if valid_end == state.data_start {
+            self.rolling_base_timestamp = None;
+        }
+        indexes.offset.flush()?;
+        indexes.time.flush()?;
+        drop(guard);
+
+        state.log_end.store(valid_end, Ordering::Release);
+        state.max_timestamp.store(max_timestamp, Ordering::Release);
+        state.next_offset.store(next_offset, Ordering::Release);
+        self.bytes_since_last_index_entry = bytes_since_last_index_entry;
+        Ok(file_len - valid_end)
+    }
+
+    /// 索引是否与日志一致：索引文件没有损坏，最后一个条目指向日志中 offset 相同的批次
+    fn index_is_consistent(&self, file_len: u64) -> bool {
+        let last_entry = {
+            let indexes = self.state().indexes();
+            if indexes.offset.is_corrupt() || indexes.time.is_corrupt() {
+                return false;
             }
+            indexes.offset.last_entry()
         };
-        let mut log_file = self.log_file.lock();
-        if pos >= log_file.metadata()?.len() {
-            return Ok(None);
+        let Some((offset, position)) = last_entry else {
+            return true;
+        };
+        if position >= file_len {
+            return false;
+        }
+        let mut base_offset = None;
+        let visited = self.reader.visit_batches(
+            position,
+            &mut base_offset,
+            |base_offset, header| {
+                *base_offset = Some(header.base_offset);
+                Visit::Stop
+            },
+            |_, _| false,
+        );
+        visited.is_ok() && base_offset == Some(offset)
+    }
+
+    /// 从 `start_pos` 顺序校验批次，返回最后一个有效批次的结束位置，并更新下一个 offset 与最大时间戳；
+    /// 每个有效批次以 (批次头部, 批次位置, 截至该批次的最大时间戳) 调用 `on_batch`
+    fn scan_valid_records(
+        log_file: &LogFile,
+        start_pos: u64,
+        file_len: u64,
+        next_offset: &mut u64,
+        max_timestamp: &mut i64,
+        mut on_batch: impl FnMut(&BatchHeader, u64, i64) -> io::Result<()>,
+    ) -> io::Result<u64> {
+        let mut pos = start_pos;
+        let mut buffer = [0u8; BATCH_HEADER_SIZE];
+        while pos + BATCH_HEADER_SIZE as u64 <= file_len {
+            log_file.read_exact_at(&mut buffer, pos)?;
+            let header = BatchHeader::decode(&buffer);
+            let end = pos + header.size() as u64;
+            if header.base_offset < *next_offset
+                || header.length < BATCH_HEADER_SIZE - MSG_HEADER_SIZE
+                || end > file_len
+            {
+                break;
+            }
+            let mut body = buffer[MSG_HEADER_SIZE..].to_vec();
+            body.resize(header.length, 0);
+            log_file.read_exact_at(&mut body[BATCH_HEADER_SIZE - MSG_HEADER_SIZE..], pos + BATCH_HEADER_SIZE as u64)?;
+            if batch_crc(&buffer, &body) != header.crc {
+                break;
+            }
+            *next_offset = header.last_offset() + 1;
+            *max_timestamp = (*max_timestamp).max(header.max_timestamp);
+            on_batch(&header, pos, *max_timestamp)?;
+            pos = end;
+        }
+        Ok(pos)
+    }
+
+    /// 读取指定 offset 的消息内容，offset 不存在或为墓碑记录时返回 None
+    pub fn read_message(&self, offset: u64) -> Result<Option<Vec<u8>>> {
+        self.reader.read_message(offset)
+    }
+
+    /// 读取指定 offset 的记录
+    pub fn read_record(&self, offset: u64) -> Result<Option<Record>> {
+        self.reader.read_record(offset)
+    }
+
+    /// 读取包含指定 offset 的记录批次，校验和不匹配时返回 `StorageError::CorruptRecord`
+    pub fn read_batch(&self, offset: u64) -> Result<Option<RecordBatch>> {
+        self.reader.read_batch(offset)
+    }
+
+    /// 从 `start_offset` 开始顺序读取连续记录，见 [`SegmentReader::read_range`]
+    pub fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> Result<ReadRange> {
+        self.reader.read_range(start_offset, max_bytes, max_records)
+    }
+
+    /// 定位从 `start_offset` 开始的连续批次在日志文件中的区域，见 [`SegmentReader::read_slice`]
+    pub fn read_slice(&self, start_offset: u64, max_bytes: usize) -> Result<Option<FileSlice>> {
+        self.reader.read_slice(start_offset, max_bytes)
+    }
+
+    /// 查找时间戳不小于 `timestamp` 的第一条记录的 offset
+    pub fn find_offset_by_timestamp(&self, timestamp: i64) -> Result<Option<u64>> {
+        self.reader.find_offset_by_timestamp(timestamp)
+    }
+
+    /// 按顺序读取并校验段内全部批次，`f` 返回错误时停止
+    pub(crate) fn for_each_batch(&self, f: impl FnMut(RecordBatch) -> Result<()>) -> Result<()> {
+        self.reader.for_each_batch(f)
+    }
+
+    /// 截断日志段，删除 offset 不小于 `offset` 的全部记录，并同步截断 .index 和 .timeindex
+    ///
+    /// 截断点落在批次中间时，该批次中 offset 更小的记录按原有 offset 重新写入
+    pub fn truncate_to(&mut self, offset: u64) -> Result<()> {
+        let state = Arc::clone(&self.reader.state);
+        if offset >= state.next_offset() {
+            return Ok(());
+        }
+        let pos = state.position_of(offset);
+        // (截断位置, 跨越截断点的批次)
+        let mut scan = (pos, None);
+        self.reader.visit_batches(
+            pos,
+            &mut scan,
+            |(position, _), header| {
+                if header.last_offset() < offset {
+                    *position += header.size() as u64;
+                    Visit::Skip
+                } else if header.base_offset < offset {
+                    Visit::Read
+                } else {
+                    Visit::Stop
+                }
+            },
+            |(_, partial), batch| {
+                *partial = Some(batch);
+                false
+            },
+        )?;
+        let (position, partial) = scan;
+
+        // 先收回读取方可见的范围，再截断文件
+        state.log_end.store(position, Ordering::Release);
+        state.log_file.set_len(position)?;
+        {
+            let mut indexes = state.indexes_mut();
+            let entries = indexes.offset.entries_before_position(position);
+            indexes.offset.truncate(entries)?;
+            let first_removed = partial.as_ref().map_or(offset, |batch: &RecordBatch| batch.base_offset);
+            let entries = indexes.time.entries_before_offset(first_removed);
+            indexes.time.truncate(entries)?;
         }
-        // **遍历日志文件，找到目标 offset**
-        log_file.seek(SeekFrom::Start(pos))?;
-        let mut buffer = [0u8; MSG_HEADER_SIZE];
-        while log_file.read_exact(&mut buffer).is_ok() {
-            let msg_offset = u64::from_be_bytes(buffer[0..OFFSET_SIZE].try_into().unwrap());
-            let length =
-                u32::from_be_bytes(buffer[OFFSET_SIZE..MSG_HEADER_SIZE].try_into().unwrap())
-                    as usize;
-            if msg_offset == offset {
-                let mut message = vec![0u8; length];
-                log_file.read_exact(&mut message)?;
-                return Ok(Some(message));
+        // 按截断后的文件重新计算下一个 offset、最大时间戳与索引间隔
+        state.next_offset.store(state.base_offset, Ordering::Release);
+        state.max_timestamp.store(-1, Ordering::Release);
+        self.bytes_since_last_index_entry = 0;
+        if position > state.data_start {
+            self.recover_message_offset()?;
+        } else {
+            self.rolling_base_timestamp = None;
+        }
+
+        if let Some(mut batch) = partial {
+            batch.records.retain(|record| record.offset < offset);
+            self.append_retained(&batch)?;
+        }
+        Ok(())
+    }
+
+    /// 把日志数据 fsync 到磁盘（`sync_data`），并刷新索引的 mmap
+    pub fn flush(&self) -> io::Result<()> {
+        self.state().log_file.sync_data()?;
+        let indexes = self.state().indexes();
+        indexes.offset.flush()?;
+        indexes.time.flush()
+    }
+
+    /// 封存日志段：刷盘并把预分配的索引截断到实际大小
+    pub fn seal(&mut self) -> io::Result<()> {
+        self.state().log_file.sync_all()?;
+        let mut indexes = self.state().indexes_mut();
+        indexes.offset.trim()?;
+        indexes.time.trim()?;
+        indexes.offset.flush()?;
+        indexes.time.flush()
+    }
+
+    /// 日志段指定后缀（.log / .index / .timeindex）文件的路径
+    pub fn file_path(&self, suffix: &str) -> std::path::PathBuf {
+        std::path::PathBuf::from(format!("{}/{:020}{}", self.log_dir, self.state().base_offset, suffix))
+    }
+
+    /// 删除日志段的 .log、.index 和 .timeindex 文件
+    ///
+    /// 仍持有 [`SegmentReader`] 的读取方在释放前可以继续读取已打开的文件
+    pub fn delete(self) -> io::Result<()> {
+        let name = format!("{}/{:020}", self.log_dir, self.state().base_offset);
+        drop(self);
+        for suffix in [INDEX_FILE_SUFFIX, TIME_INDEX_FILE_SUFFIX, LOG_FILE_SUFFIX] {
+            match std::fs::remove_file(format!("{}{}", name, suffix)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
             }
-            log_file.seek(SeekFrom::Current(length as i64))?;
         }
-        Ok(None) // 没有找到
+        Ok(())
     }
 
     // 清理旧的段
@@ -180,13 +755,28 @@ impl LogSegment {
     //     Ok(())
     // }
 
+    /// 加密段的数据密钥 ID，未加密的段返回 None
+    pub fn encryption_key_id(&self) -> Option<u32> {
+        self.reader.encryption_key_id()
+    }
+
     pub fn get_next_offset(&self) -> u64 {
-        self.offset
+        self.reader.get_next_offset()
+    }
+
+    //返回当前段的起始 offset
+    pub fn get_base_offset(&self) -> u64 {
+        self.reader.get_base_offset()
+    }
+
+    //返回当前段内的最大时间戳，空段为 -1
+    pub fn get_max_timestamp(&self) -> i64 {
+        self.reader.get_max_timestamp()
     }
 
     //返回当前段大小
     pub fn get_size(&self) -> usize {
-        self.log_file.lock().metadata().unwrap().len() as usize
+        self.reader.get_size()
     }
 
     // //返回全局唯一的offset
@@ -194,3 +784,258 @@ impl LogSegment {
     //     super::GLOBAL_OFFSET.fetch_add(1, Ordering::SeqCst)
     // }
 }
+
+impl SegmentReader {
+    /// 读取指定 offset 的消息内容，offset 不存在或为墓碑记录时返回 None
+    pub fn read_message(&self, offset: u64) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .read_record(offset)?
+            .and_then(|record| record.value))
+    }
+
+    /// 读取指定 offset 的记录
+    pub fn read_record(&self, offset: u64) -> Result<Option<Record>> {
+        Ok(self.read_batch(offset)?.and_then(|batch| {
+            batch.records.into_iter().find(|record| record.offset == offset)
+        }))
+    }
+
+    /// 读取包含指定 offset 的记录批次，校验和不匹配时返回 `StorageError::CorruptRecord`
+    pub fn read_batch(&self, offset: u64) -> Result<Option<RecordBatch>> {
+        let pos = self.state.position_of(offset);
+        // **遍历日志文件，找到包含目标 offset 的批次**
+        self.find_batch(pos, |header| {
+            if header.base_offset > offset {
+                Visit::Stop
+            } else if offset <= header.last_offset() {
+                Visit::Read
+            } else {
+                Visit::Skip
+            }
+        })
+    }
+
+    /// 从 `start_offset` 开始一次顺序扫描读取连续记录，批次字节数累计不超过 `max_bytes`，
+    /// 记录数不超过 `max_records`
+    ///
+    /// 第一个批次即使超过 `max_bytes` 也会返回，保证消费者总能向前推进
+    pub fn read_range(&self, start_offset: u64, max_bytes: usize, max_records: usize) -> Result<ReadRange> {
+        let mut range = ReadRange {
+            records: Vec::new(),
+            next_offset: start_offset,
+            bytes: 0,
+        };
+        if max_records == 0 || start_offset >= self.state.next_offset() {
+            return Ok(range);
+        }
+        let pos = self.state.position_of(start_offset);
+        self.visit_batches(
+            pos,
+            &mut range,
+            |range, header| {
+                if header.last_offset() < start_offset {
+                    Visit::Skip
+                } else if !range.records.is_empty() && range.bytes + header.size() > max_bytes {
+                    Visit::Stop
+                } else {
+                    range.bytes += header.size();
+                    Visit::Read
+                }
+            },
+            |range, batch| {
+                for record in batch.records.into_iter().filter(|record| record.offset >= start_offset) {
+                    range.next_offset = record.offset + 1;
+                    range.records.push(record);
+                    if range.records.len() >= max_records {
+                        return false;
+                    }
+                }
+                true
+            },
+        )?;
+        Ok(range)
+    }
+
+    /// 定位从 `start_offset` 开始、总长度不超过 `max_bytes` 的连续批次在日志文件中的区域，
+    /// 只读取批次头部，批次内容留给调用方通过 sendfile 或 mmap 直接发送
+    ///
+    /// 第一个批次即使超过 `max_bytes` 也会包含在内，没有可读批次时返回 None
+    pub fn read_slice(&self, start_offset: u64, max_bytes: usize) -> Result<Option<FileSlice>> {
+        // 加密段的批次需要在 broker 内解密，不能直接发送文件内容；这是调用方的用法错误，不是设备故障
+        if self.state.cipher.is_some() {
+            return Err(StorageError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zero-copy reads are not supported for encrypted segments",
+            )));
+        }
+        if start_offset >= self.state.next_offset() {
+            return Ok(None);
+        }
+        let pos = self.state.position_of(start_offset);
+        // (区域起始位置, 区域长度, 下一个 offset)
+        let mut region = (pos, 0usize, start_offset);
+        self.visit_batches(
+            pos,
+            &mut region,
+            |(position, length, next_offset), header| {
+                if header.last_offset() < start_offset {
+                    *position += header.size() as u64;
+                } else if *length > 0 && *length + header.size() > max_bytes {
+                    return Visit::Stop;
+                } else {
+                    *length += header.size();
+                    *next_offset = header.last_offset() + 1;
+                }
+                Visit::Skip
+            },
+            |_, _| false,
+        )?;
+        let (position, length, next_offset) = region;
+        if length == 0 {
+            return Ok(None);
+        }
+        let file = self.state.log_file.try_clone()?;
+        Ok(Some(FileSlice::new(file, position, length, next_offset)))
+    }
+
+    /// 查找时间戳不小于 `timestamp` 的第一条记录的 offset
+    pub fn find_offset_by_timestamp(&self, timestamp: i64) -> Result<Option<u64>> {
+        if self.get_max_timestamp() < timestamp {
+            return Ok(None);
+        }
+        // 时间索引给出扫描起点，之前的记录时间戳都小于目标
+        let start_offset = self
+            .state
+            .indexes()
+            .time
+            .lookup(timestamp)
+            .map(|(_, offset)| offset)
+            .unwrap_or(self.state.base_offset);
+        let pos = self.state.position_of(start_offset);
+        let batch = self.find_batch(pos, |header| {
+            if header.last_offset() < start_offset || header.max_timestamp < timestamp {
+                Visit::Skip
+            } else {
+                Visit::Read
+            }
+        })?;
+        Ok(batch.and_then(|batch| {
+            batch
+                .records
+                .iter()
+                .find(|record| record.timestamp >= timestamp)
+                .map(|record| record.offset)
+        }))
+    }
+
+    /// 从 `pos` 开始顺序遍历批次头部，直到 `visit` 要求读取某个批次或停止
+    fn find_batch(&self, pos: u64, mut visit: impl FnMut(&BatchHeader) -> Visit) -> Result<Option<RecordBatch>> {
+        let mut found = None;
+        self.visit_batches(
+            pos,
+            &mut found,
+            |_, header| visit(header),
+            |found, batch| {
+                *found = Some(batch);
+                false
+            },
+        )?;
+        Ok(found)
+    }
+
+    /// 按顺序读取并校验段内全部批次，`f` 返回错误时停止
+    pub(crate) fn for_each_batch(&self, mut f: impl FnMut(RecordBatch) -> Result<()>) -> Result<()> {
+        let mut error = None;
+        self.visit_batches(
+            self.state.data_start,
+            &mut error,
+            |_, _| Visit::Read,
+            |error, batch| match f(batch) {
+                Ok(()) => true,
+                Err(e) => {
+                    *error = Some(e);
+                    false
+                }
+            },
+        )?;
+        error.map_or(Ok(()), Err)
+    }
+
+    /// 从 `pos` 开始顺序遍历批次：`visit` 决定跳过、读取或停止，
+    /// 读取并校验后的批次交给 `on_batch`，其返回 false 时停止遍历
+    ///
+    /// 只遍历开始时已发布的结束位置之前的批次，写入方正在追加的批次不可见
+    fn visit_batches<S>(
+        &self,
+        pos: u64,
+        state: &mut S,
+        mut visit: impl FnMut(&mut S, &BatchHeader) -> Visit,
+        mut on_batch: impl FnMut(&mut S, RecordBatch) -> bool,
+    ) -> Result<()> {
+        let log_file = &self.state.log_file;
+        let end = self.state.log_end.load(Ordering::Acquire);
+        let mut position = pos;
+        let mut buffer = [0u8; BATCH_HEADER_SIZE];
+        // 并发截断可能使读取提前遇到文件末尾，此时与读到末尾相同
+        while position + BATCH_HEADER_SIZE as u64 <= end && log_file.read_exact_at(&mut buffer, position).is_ok() {
+            let header = BatchHeader::decode(&buffer);
+            if header.length < BATCH_HEADER_SIZE - MSG_HEADER_SIZE {
+                return Err(StorageError::InvalidRecordBatch("batch length too short"));
+            }
+            // 损坏的长度会越过结束位置，与截断的尾部一样停止遍历，不按该长度分配缓冲区
+            if position + header.size() as u64 > end {
+                break;
+            }
+            match visit(state, &header) {
+                Visit::Stop => break,
+                Visit::Skip => {}
+                Visit::Read => {
+                    let mut body = buffer[MSG_HEADER_SIZE..].to_vec();
+                    body.resize(header.length, 0);
+                    log_file.read_exact_at(&mut body[BATCH_HEADER_SIZE - MSG_HEADER_SIZE..], position + BATCH_HEADER_SIZE as u64)?;
+                    let actual = batch_crc(&buffer, &body);
+                    if actual != header.crc {
+                        return Err(StorageError::CorruptRecord {
+                            offset: header.base_offset,
+                            position,
+                            expected: header.crc,
+                            actual,
+                        });
+                    }
+                    if let Some(cipher) = &self.state.cipher {
+                        body = cipher.decrypt_body(header.base_offset, body)?;
+                    }
+                    if !on_batch(state, RecordBatch::decode_body(header.base_offset, &body)?) {
+                        break;
+                    }
+                }
+            }
+            position += header.size() as u64;
+        }
+        Ok(())
+    }
+
+    /// 加密段的数据密钥 ID，未加密的段返回 None
+    pub fn encryption_key_id(&self) -> Option<u32> {
+        self.state.cipher.as_ref().map(BatchCipher::key_id)
+    }
+
+    /// 段内下一个 offset，只包含已完整写入的批次
+    pub fn get_next_offset(&self) -> u64 {
+        self.state.next_offset()
+    }
+
+    pub fn get_base_offset(&self) -> u64 {
+        self.state.base_offset
+    }
+
+    /// 段内的最大时间戳，空段为 -1
+    pub fn get_max_timestamp(&self) -> i64 {
+        self.state.max_timestamp.load(Ordering::Acquire)
+    }
+
+    /// 已完整写入的批次占用的字节数（加密段包含段头部）
+    pub fn get_size(&self) -> usize {
+        self.state.log_end.load(Ordering::Acquire) as usize
+    }
+}
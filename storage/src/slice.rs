@@ -0,0 +1,62 @@
+use memmap2::{Mmap, MmapOptions};
+use std::fs::File;
+use std::io;
+
+/// 日志文件中由若干完整批次组成的连续区域，用于零拷贝发送（sendfile / mmap）
+///
+/// 区域按批次边界对齐，第一个批次可能包含小于请求 offset 的记录，由消费者自行跳过
+#[derive(Debug)]
+pub struct FileSlice {
+    file: File,       // 日志文件句柄（独立于段内部的读写句柄，只按位置读取）
+    position: u64,    // 区域在文件中的起始位置
+    length: usize,    // 区域长度（字节）
+    next_offset: u64, // 区域之后下一次读取应使用的 offset
+}
+
+impl FileSlice {
+    pub(crate) fn new(file: File, position: u64, length: usize, next_offset: u64) -> Self {
+        Self {
+            file,
+            position,
+            length,
+            next_offset,
+        }
+    }
+
+    /// 日志文件句柄，发送时应按 `position` 读取，不要依赖文件游标
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// 区域在文件中的起始位置
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// 区域长度（字节）
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// 区域之后下一次读取应使用的 offset
+    pub fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    /// 只读映射该区域，供不支持 sendfile 的平台直接写入 socket
+    pub fn map(&self) -> io::Result<Mmap> {
+        unsafe { MmapOptions::new().offset(self.position).len(self.length).map(&self.file) }
+    }
+
+    /// 把区域内容复制到内存中
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self.map()?.to_vec())
+    }
+}
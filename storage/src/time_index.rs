@@ -0,0 +1,90 @@
+use crate::mmap::IndexEntries;
+use std::fmt;
+use std::io;
+
+/// 时间索引，记录 "截至某个 offset 为止的最大时间戳"，条目为 8 字节时间戳 + 8 字节 offset，时间戳单调递增
+pub struct TimeIndex {
+    entries: IndexEntries,
+}
+
+impl fmt::Debug for TimeIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeIndex")
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+impl TimeIndex {
+    /// **加载时间索引文件**
+    pub fn open(path: &str, base_offset: u64, max_size: usize) -> io::Result<Self> {
+        let entries = IndexEntries::open(path, max_size, |(timestamp, offset), prev| match prev {
+            None => offset >= base_offset,
+            Some((prev_timestamp, prev_offset)) => {
+                timestamp as i64 > prev_timestamp as i64 && offset > prev_offset
+            }
+        })?;
+        Ok(Self { entries })
+    }
+
+    /// 条目数量
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 索引是否已写满
+    pub fn is_full(&self) -> bool {
+        self.entries.is_full()
+    }
+
+    /// 打开时是否发现损坏（大小不是 16 的整数倍或存在无效条目）
+    pub fn is_corrupt(&self) -> bool {
+        self.entries.is_corrupt()
+    }
+
+    fn entry(&self, index: usize) -> (i64, u64) {
+        let (timestamp, offset) = self.entries.entry(index);
+        (timestamp as i64, offset)
+    }
+
+    /// 最后一个条目 (timestamp, offset)
+    pub fn last_entry(&self) -> Option<(i64, u64)> {
+        self.entries.last().map(|(timestamp, offset)| (timestamp as i64, offset))
+    }
+
+    /// 追加条目，时间戳未增长或索引已满时忽略
+    pub fn maybe_append(&mut self, timestamp: i64, offset: u64) -> io::Result<()> {
+        if self.last_entry().is_none_or(|(ts, _)| ts < timestamp) {
+            self.entries.append(timestamp as u64, offset)?;
+        }
+        Ok(())
+    }
+
+    /// 查找时间戳小于 `timestamp` 的最后一个条目：该条目之前的记录时间戳都小于目标，
+    /// 因此从该条目的 offset 开始扫描即可
+    pub fn lookup(&self, timestamp: i64) -> Option<(i64, u64)> {
+        match self.entries.partition_point(|(ts, _)| (ts as i64) < timestamp) {
+            0 => None,
+            n => Some(self.entry(n - 1)),
+        }
+    }
+
+    /// offset 小于 `offset` 的条目数量（条目按 offset 递增）
+    pub fn entries_before_offset(&self, offset: u64) -> usize {
+        self.entries.partition_point(|(_, o)| o < offset)
+    }
+
+    /// 只保留前 `entries` 个条目
+    pub fn truncate(&mut self, entries: usize) -> io::Result<()> {
+        self.entries.truncate(entries)
+    }
+
+    /// 段滚动时把预分配的索引文件截断到实际大小
+    pub fn trim(&mut self) -> io::Result<()> {
+        self.entries.trim()
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.entries.flush()
+    }
+}
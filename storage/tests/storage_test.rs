@@ -3,7 +3,9 @@ use storage::io_result::IoResult;
 #[cfg(test)]
 mod tests {
 
-    use storage::retention;
+    use storage::{CompressionType, Record, RecordBatch, RetentionOverrides, RetentionPolicy, RollReason, SegmentConfig, StorageError, TimestampType};
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
 
     use super::*;
 
@@ -24,11 +26,11 @@ mod tests {
                 Ok(IoResult::Success(offset)) => {
                     assert_eq!(offset, except_offset, "message offset is error");
                 },
-                Ok(IoResult::SegmentFull) => {
-                    println!("SegmentFull");
+                Ok(IoResult::SegmentFull(reason)) => {
+                    println!("SegmentFull: {:?}", reason);
                 },
                 Err(e) =>{
-                    panic!("Error: {}, {}",e.kind(),e.to_string());
+                    panic!("Error: {}, {}",e.kind(),e);
                 }
             };
         }
@@ -39,11 +41,11 @@ mod tests {
                 Ok(IoResult::Success(new_offset)) => {
                     assert_eq!(new_offset, new_except_offset, "message offset is error");
                 },
-                Ok(IoResult::SegmentFull) => {
-                    println!("SegmentFull");
+                Ok(IoResult::SegmentFull(reason)) => {
+                    println!("SegmentFull: {:?}", reason);
                 },
                 Err(e) =>{
-                    panic!("Error: {}, {}",e.kind(),e.to_string());
+                    panic!("Error: {}, {}",e.kind(),e);
                 }
             };
         }
@@ -59,11 +61,11 @@ mod tests {
                 Ok(IoResult::Success(write_offset)) => {
                     assert_eq!(offset, write_offset, "message offset is error");
                 },
-                Ok(IoResult::SegmentFull) => {
-                    println!("SegmentFull");
+                Ok(IoResult::SegmentFull(reason)) => {
+                    println!("SegmentFull: {:?}", reason);
                 },
                 Err(e) =>{
-                    panic!("Error: {}, {}",e.kind(),e.to_string());
+                    panic!("Error: {}, {}",e.kind(),e);
                 }
             };
         } 
@@ -74,11 +76,11 @@ mod tests {
                 Ok(IoResult::Success(write_offset)) => {
                     assert_eq!(offset_new, write_offset, "message offset is error");
                 },
-                Ok(IoResult::SegmentFull) => {
-                    println!("SegmentFull");
+                Ok(IoResult::SegmentFull(reason)) => {
+                    println!("SegmentFull: {:?}", reason);
                 },
                 Err(e) =>{
-                    panic!("Error: {}, {}",e.kind(),e.to_string());
+                    panic!("Error: {}, {}",e.kind(),e);
                 }
             };
         }  
@@ -98,7 +100,6 @@ mod tests {
         for offset in 0..601 {
             test_read_times(&mut log, offset);
         }
-        test_storage_read_all_messages();
     }
 
     fn test_read_times(log: &mut LogSegment, offset: u64) {
@@ -137,11 +138,11 @@ mod tests {
                 let message = log.read_message(offset).unwrap();
                 assert_eq!(message, Some(msg1.to_vec()));
             },
-            Ok(IoResult::SegmentFull) => {
-                println!("SegmentFull");
+            Ok(IoResult::SegmentFull(reason)) => {
+                println!("SegmentFull: {:?}", reason);
             },
             Err(e) =>{
-                panic!("Error: {}, {}",e.kind(),e.to_string());
+                panic!("Error: {}, {}",e.kind(),e);
             }
         };
 
@@ -150,11 +151,11 @@ mod tests {
                 let message = log.read_message(offset).unwrap();
                 assert_eq!(message, Some(msg2.to_vec()));
             },
-            Ok(IoResult::SegmentFull) => {
-                println!("SegmentFull");
+            Ok(IoResult::SegmentFull(reason)) => {
+                println!("SegmentFull: {:?}", reason);
             },
             Err(e) =>{
-                panic!("Error: {}, {}",e.kind(),e.to_string());
+                panic!("Error: {}, {}",e.kind(),e);
             }
         };
 
@@ -163,17 +164,904 @@ mod tests {
                 let message = log.read_message(offset).unwrap();
                 assert_eq!(message, Some(msg3.to_vec()));
             },
-            Ok(IoResult::SegmentFull) => {
-                println!("SegmentFull");
+            Ok(IoResult::SegmentFull(reason)) => {
+                println!("SegmentFull: {:?}", reason);
             },
             Err(e) =>{
-                panic!("Error: {}, {}",e.kind(),e.to_string());
+                panic!("Error: {}, {}",e.kind(),e);
             }
         };
         
     }
 
-    fn test_storage_read_all_messages() {
-        retention::clean_old_segments("logs".to_string());
+    /// **为单个测试准备独立目录，避免并行测试互相干扰**
+    fn setup_dir(dir: &str) -> String {
+        let dir = format!("target/{}", dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn log_path(dir: &str) -> String {
+        format!("{}/{:020}.log", dir, 0)
+    }
+
+    #[test]
+    fn test_read_corrupt_record() {
+        let dir = setup_dir("test_read_corrupt_record");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        for _ in 0..3 {
+            log.append_message(b"hello kafka").unwrap();
+        }
+
+        // 翻转 offset 1 的消息内容中的一个字节（消息内容位于记录末尾）
+        let record_size = log.get_size() as u64 / 3;
+        let mut file = OpenOptions::new().write(true).open(log_path(&dir)).unwrap();
+        file.seek(SeekFrom::Start(2 * record_size - 12)).unwrap();
+        file.write_all(b"X").unwrap();
+
+        match log.read_message(1) {
+            Err(StorageError::CorruptRecord { offset, position, .. }) => {
+                assert_eq!(offset, 1);
+                assert_eq!(position, record_size);
+            }
+            other => panic!("expected CorruptRecord, got {:?}", other),
+        }
+        assert_eq!(log.read_message(2).unwrap(), Some(b"hello kafka".to_vec()));
+    }
+
+    #[test]
+    fn test_read_corrupt_batch_length() {
+        let dir = setup_dir("test_read_corrupt_batch_length");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        for _ in 0..3 {
+            log.append_message(b"hello kafka").unwrap();
+        }
+
+        // offset 1 的批次长度被改成接近 4 GiB，遍历在这里停止，不按该长度分配缓冲区
+        let record_size = log.get_size() as u64 / 3;
+        let mut file = OpenOptions::new().write(true).open(log_path(&dir)).unwrap();
+        file.seek(SeekFrom::Start(record_size + 8)).unwrap();
+        file.write_all(&0xffff_fff0u32.to_be_bytes()).unwrap();
+
+        let range = log.read_range(0, usize::MAX, usize::MAX).unwrap();
+        assert_eq!(range.records.len(), 1);
+        assert_eq!(range.records[0].value, Some(b"hello kafka".to_vec()));
+    }
+
+    #[test]
+    fn test_recover_torn_write() {
+        let dir = setup_dir("test_recover_torn_write");
+        {
+            let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+            for _ in 0..150 {
+                log.append_message(b"hello kafka").unwrap();
+            }
+        }
+        let valid_len = std::fs::metadata(log_path(&dir)).unwrap().len();
+
+        // 模拟断电导致的半条记录
+        let mut file = OpenOptions::new().append(true).open(log_path(&dir)).unwrap();
+        file.write_all(&150u64.to_be_bytes()).unwrap();
+        file.write_all(&[0, 0, 0]).unwrap();
+        drop(file);
+
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        assert_eq!(log.get_next_offset(), 150);
+        assert_eq!(std::fs::metadata(log_path(&dir)).unwrap().len(), valid_len);
+        match log.append_message(b"after recovery").unwrap() {
+            IoResult::Success(offset) => assert_eq!(offset, 150),
+            IoResult::SegmentFull(reason) => panic!("unexpected SegmentFull: {:?}", reason),
+        }
+        assert_eq!(log.read_message(150).unwrap(), Some(b"after recovery".to_vec()));
+    }
+
+    #[test]
+    fn test_recover_truncates_index() {
+        let dir = setup_dir("test_recover_truncates_index");
+        // 每个批次都写索引，方便断言截断后的条目数
+        let config = SegmentConfig {
+            index_interval_bytes: 1,
+            ..SegmentConfig::default()
+        };
+        {
+            let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+            for _ in 0..201 {
+                log.append_message(b"hello kafka").unwrap();
+            }
+        }
+
+        // 破坏 offset 200 的记录（最后一个索引条目指向它），恢复后应截断 .log 与 .index
+        let record_size = std::fs::metadata(log_path(&dir)).unwrap().len() / 201;
+        let mut file = OpenOptions::new().write(true).open(log_path(&dir)).unwrap();
+        file.seek(SeekFrom::Start(200 * record_size + 16)).unwrap();
+        file.write_all(b"X").unwrap();
+        drop(file);
+
+        let log = LogSegment::with_config(&dir, 0, config).unwrap();
+        assert_eq!(log.get_next_offset(), 200);
+        assert_eq!(log.get_size() as u64, 200 * record_size);
+        // offset 1..=199 的索引条目保留，预分配的尾部被截掉
+        let index_len = std::fs::metadata(format!("{}/{:020}.index", dir, 0)).unwrap().len();
+        assert_eq!(index_len, 199 * 16);
+        assert_eq!(log.read_message(199).unwrap(), Some(b"hello kafka".to_vec()));
+        assert_eq!(log.read_message(200).unwrap(), None);
+    }
+
+    #[test]
+    fn test_record_batch_roundtrip() {
+        let batch = RecordBatch::new(vec![
+            Record::new(Some(b"user-1".to_vec()), Some(b"created".to_vec()))
+                .with_timestamp(1_000)
+                .with_header("trace-id", Some(b"abc".to_vec())),
+            Record::new(Some(b"user-1".to_vec()), None).with_timestamp(1_005),
+        ])
+        .with_timestamp_type(TimestampType::CreateTime);
+
+        let decoded = RecordBatch::decode(&batch.encode().unwrap()).unwrap();
+        assert_eq!(decoded, batch);
+        assert_eq!(decoded.last_offset_delta(), 1);
+        assert_eq!(decoded.max_timestamp(), 1_005);
+        assert_eq!(decoded.records[0].headers[0].key, "trace-id");
+        assert_eq!(decoded.records[1].value, None);
+
+        let mut corrupted = batch.encode().unwrap();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        assert!(matches!(
+            RecordBatch::decode(&corrupted),
+            Err(StorageError::CorruptRecord { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compressed_record_batch() {
+        let records: Vec<Record> = (0..200)
+            .map(|i| {
+                let event = format!(r#"{{"user_id":{},"event":"page_view","path":"/products/{}"}}"#, i % 10, i % 7);
+                Record::new(Some(format!("user-{}", i % 10).into_bytes()), Some(event.into_bytes())).with_timestamp(1_000 + i)
+            })
+            .collect();
+        let plain = RecordBatch::new(records.clone()).encode().unwrap();
+        for compression in [CompressionType::Gzip, CompressionType::Snappy, CompressionType::Lz4, CompressionType::Zstd] {
+            let batch = RecordBatch::new(records.clone()).with_compression(compression);
+            let encoded = batch.encode().unwrap();
+            assert!(encoded.len() * 3 < plain.len(), "{}: {} vs {}", compression, encoded.len(), plain.len());
+            let decoded = RecordBatch::decode(&encoded).unwrap();
+            assert_eq!(decoded.compression(), compression);
+            assert_eq!(decoded, batch);
+            assert_eq!(compression.name().parse::<CompressionType>().unwrap(), compression);
+        }
+
+        // 写入日志段后按原编码保存，按 offset 读取时解压
+        let dir = setup_dir("test_compressed_record_batch");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        let mut batch = RecordBatch::new(records).with_compression(CompressionType::Zstd);
+        log.append_batch(&mut batch).unwrap();
+        assert!(log.get_size() * 3 < plain.len());
+        let read = log.read_batch(150).unwrap().unwrap();
+        assert_eq!(read.compression(), CompressionType::Zstd);
+        assert_eq!(read.record(150), batch.record(150));
+
+        // 未知的压缩编码
+        let mut unknown = RecordBatch::new(vec![Record::new(None, Some(b"v".to_vec()))]);
+        unknown.attributes |= 0x07;
+        assert!(matches!(
+            RecordBatch::decode(&unknown.encode().unwrap()),
+            Err(StorageError::InvalidRecordBatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_append_batch() {
+        let dir = setup_dir("test_append_batch");
+        {
+            let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+            log.append_message(b"single").unwrap();
+            let mut batch = RecordBatch::new(
+                (0..150)
+                    .map(|i| Record::new(Some(format!("key-{}", i).into_bytes()), Some(vec![i as u8])))
+                    .collect(),
+            );
+            match log.append_batch(&mut batch).unwrap() {
+                IoResult::Success(base_offset) => assert_eq!(base_offset, 1),
+                IoResult::SegmentFull(reason) => panic!("unexpected SegmentFull: {:?}", reason),
+            }
+            assert_eq!(batch.last_offset(), 150);
+            assert_eq!(log.get_next_offset(), 151);
+        }
+
+        // 重新打开后按批次恢复 offset，并能读取批次中间的记录
+        let log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        assert_eq!(log.get_next_offset(), 151);
+        let record = log.read_record(100).unwrap().unwrap();
+        assert_eq!(record.offset, 100);
+        assert_eq!(record.key, Some(b"key-99".to_vec()));
+        assert_eq!(log.read_message(100).unwrap(), Some(vec![99]));
+        assert_eq!(log.read_batch(150).unwrap().unwrap().base_offset, 1);
+        assert_eq!(log.read_message(0).unwrap(), Some(b"single".to_vec()));
+        assert_eq!(log.read_message(151).unwrap(), None);
+    }
+
+    /// 修改批次内容后重新计算长度与 crc
+    fn reseal(encoded: &mut [u8]) {
+        let length = (encoded.len() - 16) as u32;
+        encoded[8..12].copy_from_slice(&length.to_be_bytes());
+        let crc = crc32c::crc32c_append(crc32c::crc32c(&encoded[..12]), &encoded[16..]);
+        encoded[12..16].copy_from_slice(&crc.to_be_bytes());
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        use storage::compression::MAX_DECOMPRESSED_BYTES;
+        use storage::record::BATCH_HEADER_SIZE;
+
+        // 解压后超过上限的批次无效
+        let bomb = RecordBatch::new(vec![Record::new(None, Some(vec![0; MAX_DECOMPRESSED_BYTES]))])
+            .with_compression(CompressionType::Zstd)
+            .encode().unwrap();
+        assert!(bomb.len() < 64 * 1024);
+        assert!(matches!(RecordBatch::decode(&bomb), Err(StorageError::InvalidRecordBatch("decompressed records exceed size limit"))));
+
+        // lz4 记录的解压后大小超过上限时，不分配缓冲区直接拒绝
+        let mut forged = RecordBatch::new(vec![Record::new(None, Some(b"v".to_vec()))])
+            .with_compression(CompressionType::Lz4)
+            .encode().unwrap();
+        forged[BATCH_HEADER_SIZE..BATCH_HEADER_SIZE + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        reseal(&mut forged);
+        assert!(matches!(RecordBatch::decode(&forged), Err(StorageError::InvalidRecordBatch("decompressed records exceed size limit"))));
+
+        // 未超过上限的批次正常解码
+        let batch = RecordBatch::new(vec![Record::new(None, Some(vec![0; 1024 * 1024]))]).with_compression(CompressionType::Lz4);
+        assert_eq!(RecordBatch::decode(&batch.encode().unwrap()).unwrap(), batch);
+    }
+
+    #[test]
+    fn test_timestamp_delta_overflow() {
+        // 时间戳增量超出 i64 范围的批次无法编码
+        let batch = RecordBatch::new(vec![
+            Record::new(None, Some(b"a".to_vec())).with_timestamp(i64::MIN),
+            Record::new(None, Some(b"b".to_vec())).with_timestamp(i64::MAX),
+        ]);
+        assert!(matches!(batch.encode(), Err(StorageError::InvalidRecordBatch("timestamp delta overflow"))));
+
+        // base_timestamp 与增量相加溢出的批次无法解码
+        let mut encoded = RecordBatch::new(vec![
+            Record::new(None, Some(b"a".to_vec())).with_timestamp(0),
+            Record::new(None, Some(b"b".to_vec())).with_timestamp(10),
+        ])
+        .encode()
+        .unwrap();
+        encoded[21..29].copy_from_slice(&i64::MAX.to_be_bytes());
+        reseal(&mut encoded);
+        assert!(matches!(RecordBatch::decode(&encoded), Err(StorageError::InvalidRecordBatch("timestamp delta overflow"))));
+    }
+
+    #[test]
+    fn test_reencode_keeps_producer_compression() {
+        use std::io::Read;
+        use storage::record::BATCH_HEADER_SIZE;
+
+        // 用与默认参数不同的 gzip 输出替换压缩内容，模拟生产者的压缩结果
+        let records: Vec<Record> = (0..50)
+            .map(|i| Record::new(Some(format!("key-{}", i % 5).into_bytes()), Some(vec![i as u8; 64])).with_timestamp(1_000 + i))
+            .collect();
+        let mut encoded = RecordBatch::new(records).with_compression(CompressionType::Gzip).encode().unwrap();
+        let mut plain = Vec::new();
+        flate2::read::GzDecoder::new(&encoded[BATCH_HEADER_SIZE..]).read_to_end(&mut plain).unwrap();
+        let mut encoder = flate2::GzBuilder::new().filename("producer").write(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&plain).unwrap();
+        let producer_compressed = encoder.finish().unwrap();
+        encoded.truncate(BATCH_HEADER_SIZE);
+        encoded.extend_from_slice(&producer_compressed);
+        reseal(&mut encoded);
+
+        // 分配新的 offset 后只重写批次头部，压缩内容保持不变
+        let mut batch = RecordBatch::decode(&encoded).unwrap();
+        batch.assign_offsets(1_000);
+        let reencoded = batch.encode().unwrap();
+        assert_eq!(&reencoded[BATCH_HEADER_SIZE..], &producer_compressed[..]);
+        let decoded = RecordBatch::decode(&reencoded).unwrap();
+        assert_eq!(decoded.base_offset, 1_000);
+        assert_eq!(decoded, batch);
+
+        // 写入日志段同样保留生产者的压缩内容
+        let dir = setup_dir("test_reencode_keeps_producer_compression");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        let mut appended = RecordBatch::decode(&encoded).unwrap();
+        log.append_batch(&mut appended).unwrap();
+        assert_eq!(log.get_size(), reencoded.len());
+        assert_eq!(log.read_record(49).unwrap().unwrap().value, Some(vec![49; 64]));
+
+        // 记录内容或压缩编码改变后重新压缩
+        let mut changed = RecordBatch::decode(&encoded).unwrap();
+        changed.records[0].value = Some(b"changed".to_vec());
+        let reencoded = changed.encode().unwrap();
+        assert_ne!(&reencoded[BATCH_HEADER_SIZE..], &producer_compressed[..]);
+        assert_eq!(RecordBatch::decode(&reencoded).unwrap(), changed);
+        let recompressed = RecordBatch::decode(&encoded).unwrap().with_compression(CompressionType::Zstd);
+        assert_eq!(RecordBatch::decode(&recompressed.encode().unwrap()).unwrap().compression(), CompressionType::Zstd);
+
+        // LogAppendTime 批次重写时间戳时同样不需要重新压缩
+        let append_time = RecordBatch::new((0..10).map(|i| Record::new(None, Some(vec![i; 32])).with_timestamp(i as i64)).collect())
+            .with_timestamp_type(TimestampType::LogAppendTime)
+            .with_compression(CompressionType::Gzip)
+            .encode().unwrap();
+        let mut batch = RecordBatch::decode(&append_time).unwrap();
+        batch.set_append_time(5_000);
+        let reencoded = batch.encode().unwrap();
+        assert_eq!(&reencoded[BATCH_HEADER_SIZE..], &append_time[BATCH_HEADER_SIZE..]);
+        assert!(RecordBatch::decode(&reencoded).unwrap().records.iter().all(|record| record.timestamp == 5_000));
+    }
+
+    #[test]
+    fn test_log_append_time() {
+        let dir = setup_dir("test_log_append_time");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(b"v".to_vec())).with_timestamp(1)])
+            .with_timestamp_type(TimestampType::LogAppendTime);
+        log.append_batch(&mut batch).unwrap();
+        let record = log.read_record(0).unwrap().unwrap();
+        assert!(record.timestamp > 1);
+    }
+
+    #[test]
+    fn test_find_offset_by_timestamp() {
+        let dir = setup_dir("test_find_offset_by_timestamp");
+        {
+            let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+            for i in 0..500i64 {
+                let mut batch = RecordBatch::new(vec![
+                    Record::new(None, Some(b"hello kafka".to_vec())).with_timestamp(10_000 + i * 10),
+                ]);
+                log.append_batch(&mut batch).unwrap();
+            }
+            assert_eq!(log.find_offset_by_timestamp(10_000).unwrap(), Some(0));
+            assert_eq!(log.find_offset_by_timestamp(12_345).unwrap(), Some(235));
+            assert_eq!(log.find_offset_by_timestamp(14_990).unwrap(), Some(499));
+            assert_eq!(log.find_offset_by_timestamp(15_000).unwrap(), None);
+        }
+        assert!(std::path::Path::new(&format!("{}/{:020}.timeindex", dir, 0)).exists());
+
+        // 重新打开后最大时间戳与时间索引依然可用
+        let log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        assert_eq!(log.get_max_timestamp(), 14_990);
+        assert_eq!(log.find_offset_by_timestamp(13_001).unwrap(), Some(301));
+    }
+
+    #[test]
+    fn test_index_interval_bytes_and_trim_on_roll() {
+        let dir = setup_dir("test_index_interval_bytes_and_trim_on_roll");
+        let config = SegmentConfig {
+            max_segment_size: 64 * 1024,
+            index_interval_bytes: 1024,
+            max_index_size: 4096,
+            ..SegmentConfig::default()
+        };
+        let index_path = format!("{}/{:020}.index", dir, 0);
+        let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+        let mut next = 0;
+        while let IoResult::Success(offset) = log.append_message(&[b'x'; 100]).unwrap() {
+            next = offset + 1;
+            if next == 50 {
+                // 首次写入索引后文件被预分配到 max_index_size
+                assert_eq!(std::fs::metadata(&index_path).unwrap().len(), 4096);
+            }
+        }
+
+        // 段滚动后索引被截断到实际条目大小，条目数约为 段大小 / index_interval_bytes
+        let index_len = std::fs::metadata(&index_path).unwrap().len();
+        let entries = index_len / 16;
+        let batch_size = log.get_size() as u64 / next;
+        assert!(entries > 0 && entries < 4096 / 16);
+        assert!(
+            entries >= log.get_size() as u64 / (1024 + batch_size) - 1 && entries <= log.get_size() as u64 / 1024,
+            "entries = {}",
+            entries
+        );
+
+        // 每个 offset 都能通过二分查找 + 顺序扫描读到
+        for offset in 0..next {
+            assert_eq!(log.read_message(offset).unwrap(), Some(vec![b'x'; 100]));
+        }
+        assert_eq!(log.read_message(next).unwrap(), None);
+
+        // 重新打开后索引条目不变
+        drop(log);
+        let log = LogSegment::with_config(&dir, 0, config).unwrap();
+        assert_eq!(log.get_next_offset(), next);
+        assert_eq!(std::fs::metadata(&index_path).unwrap().len(), index_len);
+        assert_eq!(log.read_message(next / 2).unwrap(), Some(vec![b'x'; 100]));
+    }
+
+    #[test]
+    fn test_roll_reasons() {
+        let batch_at = |timestamp: i64| RecordBatch::new(vec![Record::new(None, Some(vec![b'x'; 100])).with_timestamp(timestamp)]);
+        let batch_size = batch_at(0).encode().unwrap().len();
+
+        // 按大小滚动：写入下一个批次会超过段大小时滚动，段大小不会超过上限
+        let dir = setup_dir("test_roll_reasons_size");
+        let config = SegmentConfig {
+            max_segment_size: batch_size * 3 + batch_size / 2,
+            ..SegmentConfig::default()
+        };
+        let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+        for i in 0..3 {
+            assert!(matches!(log.append_batch(&mut batch_at(i)).unwrap(), IoResult::Success(_)));
+        }
+        assert!(matches!(
+            log.append_batch(&mut batch_at(3)).unwrap(),
+            IoResult::SegmentFull(RollReason::SegmentSize)
+        ));
+        assert_eq!(log.get_next_offset(), 3);
+        assert_eq!(log.get_size(), batch_size * 3);
+
+        // 空段总是接受第一个批次，即使它超过段大小
+        let dir = setup_dir("test_roll_reasons_oversized");
+        let mut log = LogSegment::new(&dir, 0, 16).unwrap();
+        assert!(matches!(log.append_batch(&mut batch_at(0)).unwrap(), IoResult::Success(0)));
+        assert!(matches!(
+            log.append_batch(&mut batch_at(0)).unwrap(),
+            IoResult::SegmentFull(RollReason::SegmentSize)
+        ));
+
+        // 按时间滚动：与第一个批次的时间戳相差超过 segment_ms，重新打开后基准不变
+        let dir = setup_dir("test_roll_reasons_age");
+        let config = SegmentConfig {
+            segment_ms: 1_000,
+            ..SegmentConfig::default()
+        };
+        {
+            let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+            assert!(matches!(log.append_batch(&mut batch_at(10_000)).unwrap(), IoResult::Success(0)));
+            assert!(matches!(log.append_batch(&mut batch_at(11_000)).unwrap(), IoResult::Success(1)));
+        }
+        let mut log = LogSegment::with_config(&dir, 0, config).unwrap();
+        assert!(matches!(
+            log.append_batch(&mut batch_at(11_001)).unwrap(),
+            IoResult::SegmentFull(RollReason::SegmentAge)
+        ));
+        // segment_ms 为负数时不按时间滚动
+        let mut log = LogSegment::with_config(&dir, 0, SegmentConfig { segment_ms: -1, ..SegmentConfig::default() }).unwrap();
+        assert!(matches!(log.append_batch(&mut batch_at(i64::MAX)).unwrap(), IoResult::Success(2)));
+
+        // 索引写满后滚动：每个批次一个索引条目，索引只能容纳 4 个条目
+        let dir = setup_dir("test_roll_reasons_index");
+        let config = SegmentConfig {
+            index_interval_bytes: 1,
+            max_index_size: 4 * 16,
+            ..SegmentConfig::default()
+        };
+        let mut log = LogSegment::with_config(&dir, 0, config).unwrap();
+        let mut appended = 0;
+        let reason = loop {
+            match log.append_batch(&mut batch_at(appended)).unwrap() {
+                IoResult::Success(_) => appended += 1,
+                IoResult::SegmentFull(reason) => break reason,
+            }
+        };
+        assert_eq!(reason, RollReason::IndexFull);
+        // 第一个批次位于位置 0 不写索引条目
+        assert_eq!(appended, 5);
+        for offset in 0..appended as u64 {
+            assert_eq!(log.read_message(offset).unwrap(), Some(vec![b'x'; 100]));
+        }
+    }
+
+    #[test]
+    fn test_read_range() {
+        let dir = setup_dir("test_read_range");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        for i in 0..20 {
+            let mut batch = RecordBatch::new(
+                (0..10)
+                    .map(|j| Record::new(None, Some(format!("msg-{}", i * 10 + j).into_bytes())))
+                    .collect(),
+            );
+            log.append_batch(&mut batch).unwrap();
+        }
+
+        // 从批次中间开始读取，按记录数截断
+        let range = log.read_range(15, usize::MAX, 30).unwrap();
+        assert_eq!(range.records.len(), 30);
+        assert_eq!(range.records[0].value, Some(b"msg-15".to_vec()));
+        assert_eq!(range.next_offset, 45);
+
+        // 按字节数截断在批次边界
+        let three_batches = log.read_range(0, usize::MAX, 30).unwrap().bytes;
+        let range = log.read_range(0, three_batches + 1, usize::MAX).unwrap();
+        assert_eq!(range.records.len(), 30);
+        assert_eq!(range.bytes, three_batches);
+        assert_eq!(range.next_offset, 30);
+
+        // 第一个批次超过 max_bytes 时依然返回
+        let range = log.read_range(195, 1, usize::MAX).unwrap();
+        assert_eq!(range.records.len(), 5);
+        assert_eq!(range.next_offset, 200);
+
+        // 读到末尾
+        let range = log.read_range(200, usize::MAX, usize::MAX).unwrap();
+        assert!(range.records.is_empty());
+        assert_eq!(range.next_offset, 200);
+    }
+
+    #[test]
+    fn test_read_slice() {
+        let dir = setup_dir("test_read_slice");
+        let mut log = LogSegment::new(&dir, 0, 1024 * 1024).unwrap();
+        for i in 0..20 {
+            let mut batch = RecordBatch::new(
+                (0..10)
+                    .map(|j| Record::new(None, Some(format!("msg-{}", i * 10 + j).into_bytes())))
+                    .collect(),
+            );
+            log.append_batch(&mut batch).unwrap();
+        }
+
+        // 区域按批次对齐：从 offset 15 开始时包含整个 [10, 20) 批次
+        let range = log.read_range(10, usize::MAX, 30).unwrap();
+        let slice = log.read_slice(15, range.bytes).unwrap().unwrap();
+        assert_eq!(slice.len(), range.bytes);
+        assert_eq!(slice.next_offset(), 40);
+        let bytes = slice.to_vec().unwrap();
+        let first_len = 16 + u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let first = RecordBatch::decode(&bytes[..first_len]).unwrap();
+        assert_eq!(first.base_offset, 10);
+        assert_eq!(first.records[5].value, Some(b"msg-15".to_vec()));
+
+        // 第一个批次超过 max_bytes 时依然返回
+        let slice = log.read_slice(199, 1).unwrap().unwrap();
+        assert_eq!(slice.next_offset(), 200);
+        assert_eq!(slice.position() + slice.len() as u64, log.get_size() as u64);
+
+        assert!(log.read_slice(200, usize::MAX).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_retention_policy() {
+        let now = 1_000_000;
+        // (大小, 最大时间戳)，按 base_offset 排序
+        let closed = [(100, now - 5_000), (100, now - 3_000), (100, now - 1_000)];
+
+        // 按时间：删除最大时间戳早于 now - 2000 的前缀
+        let policy = RetentionPolicy::new(2_000, -1);
+        assert_eq!(policy.segments_to_delete(&closed, 350, now), 2);
+
+        // 按大小：删除后剩余大小仍不小于 150 时继续删除
+        let policy = RetentionPolicy::new(-1, 150);
+        assert_eq!(policy.segments_to_delete(&closed, 350, now), 2);
+
+        // 只删除连续的最旧段
+        let closed = [(100, now), (100, now - 5_000)];
+        assert_eq!(RetentionPolicy::new(2_000, -1).segments_to_delete(&closed, 250, now), 0);
+
+        // 不限制时不删除
+        assert_eq!(RetentionPolicy::new(-1, -1).segments_to_delete(&closed, 250, now), 0);
+
+        // 主题级覆盖
+        let policy = RetentionPolicy::from_hours(168, -1).with_overrides(&RetentionOverrides {
+            retention_ms: Some(1_000),
+            ..Default::default()
+        });
+        assert_eq!(policy, RetentionPolicy::new(1_000, -1));
+        // 本地保留未覆盖的项沿用总保留策略
+        let local = policy.local_policy(&RetentionOverrides {
+            local_retention_bytes: Some(100),
+            ..Default::default()
+        });
+        assert_eq!(local, RetentionPolicy::new(1_000, 100));
+    }
+
+    #[test]
+    fn test_truncate_to() {
+        let dir = setup_dir("test_truncate_to");
+        let config = SegmentConfig {
+            max_segment_size: 1024 * 1024,
+            index_interval_bytes: 1,
+            ..SegmentConfig::default()
+        };
+        {
+            let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+            for i in 0..10 {
+                log.append_message(format!("message-{}", i).as_bytes()).unwrap();
+            }
+            let mut batch = RecordBatch::new((10..20).map(|i| Record::new(None, Some(vec![i as u8]))).collect());
+            log.append_batch(&mut batch).unwrap();
+
+            // 截断点落在批次中间，批次中更早的记录保留
+            log.truncate_to(15).unwrap();
+            assert_eq!(log.get_next_offset(), 15);
+            assert_eq!(log.read_message(14).unwrap(), Some(vec![14]));
+            assert_eq!(log.read_message(15).unwrap(), None);
+
+            // 截断到批次边界
+            log.truncate_to(5).unwrap();
+            assert_eq!(log.get_next_offset(), 5);
+            assert_eq!(log.read_message(4).unwrap(), Some(b"message-4".to_vec()));
+            assert_eq!(log.read_message(5).unwrap(), None);
+            match log.append_message(b"after").unwrap() {
+                IoResult::Success(offset) => assert_eq!(offset, 5),
+                IoResult::SegmentFull(reason) => panic!("unexpected SegmentFull: {:?}", reason),
+            }
+        }
+
+        // 重新打开后索引与日志一致
+        let mut log = LogSegment::with_config(&dir, 0, config).unwrap();
+        assert_eq!(log.get_next_offset(), 6);
+        assert_eq!(log.read_message(5).unwrap(), Some(b"after".to_vec()));
+        log.truncate_to(0).unwrap();
+        assert_eq!(log.get_next_offset(), 0);
+        assert_eq!(log.get_size(), 0);
+    }
+
+    #[test]
+    fn test_flush_policy() {
+        use storage::FlushPolicy;
+
+        assert_eq!(FlushPolicy::from_config("write", 0, 0).unwrap(), FlushPolicy::EveryWrite);
+        assert_eq!(FlushPolicy::from_config("messages", 10, 0).unwrap(), FlushPolicy::EveryMessages(10));
+        assert_eq!(FlushPolicy::from_config("interval", 0, 500).unwrap(), FlushPolicy::Interval(500));
+        assert_eq!(FlushPolicy::from_config("os", 0, 0).unwrap(), FlushPolicy::OsManaged);
+        assert!(FlushPolicy::from_config("messages", 0, 0).is_err());
+        assert!(FlushPolicy::from_config("never", 0, 0).is_err());
+
+        // 没有未刷盘的消息时不需要刷盘
+        assert!(!FlushPolicy::EveryWrite.should_flush(0, 0));
+        assert!(FlushPolicy::EveryWrite.should_flush(1, 0));
+        assert!(!FlushPolicy::EveryMessages(10).should_flush(9, 1_000));
+        assert!(FlushPolicy::EveryMessages(10).should_flush(10, 0));
+        assert!(!FlushPolicy::Interval(500).should_flush(1, 499));
+        assert!(FlushPolicy::Interval(500).should_flush(1, 500));
+        assert!(!FlushPolicy::OsManaged.should_flush(1_000, 1_000_000));
+    }
+
+    #[test]
+    fn test_capacity_limit() {
+        use storage::{CapacityLimit, OverflowPolicy};
+
+        assert_eq!(OverflowPolicy::from_config("reject", 0).unwrap(), OverflowPolicy::Reject);
+        assert_eq!(OverflowPolicy::from_config("block", 500).unwrap(), OverflowPolicy::Block { timeout_ms: 500 });
+        assert_eq!(OverflowPolicy::from_config("drop_oldest", 0).unwrap(), OverflowPolicy::DropOldest);
+        assert!(OverflowPolicy::from_config("block", 0).is_err());
+        assert!(OverflowPolicy::from_config("grow", 0).is_err());
+
+        // 没有上限时永远不会写满
+        assert!(!CapacityLimit::default().would_overflow(u64::MAX, u64::MAX - 1, 1));
+        let limit = CapacityLimit { max_bytes: Some(1_000), max_records: Some(10), ..Default::default() };
+        // 记录数按写入后计算，字节数按写入前计算
+        assert!(!limit.would_overflow(999, 8, 2));
+        assert!(limit.would_overflow(999, 9, 2));
+        assert!(limit.would_overflow(1_000, 0, 1));
+        assert_eq!(limit.records_to_drop(9, 2), 1);
+        assert_eq!(limit.records_to_drop(5, 2), 0);
+        // 一个批次超过上限时丢弃全部已有记录
+        assert_eq!(limit.records_to_drop(5, 20), 5);
+    }
+
+    fn index_path(dir: &str) -> String {
+        format!("{}/{:020}.index", dir, 0)
+    }
+
+    /// 写入 200 条消息并关闭，每个批次都写索引，返回写入后的 .index 内容
+    fn write_indexed_segment(dir: &str, config: &SegmentConfig) -> Vec<u8> {
+        let mut log = LogSegment::with_config(dir, 0, config.clone()).unwrap();
+        for i in 0..200 {
+            log.append_message(format!("message-{:03}", i).as_bytes()).unwrap();
+        }
+        log.seal().unwrap();
+        std::fs::read(index_path(dir)).unwrap()
+    }
+
+    #[test]
+    fn test_rebuild_invalid_index() {
+        let dir = setup_dir("test_rebuild_invalid_index");
+        let config = SegmentConfig {
+            index_interval_bytes: 1,
+            ..SegmentConfig::default()
+        };
+        let expected = write_indexed_segment(&dir, &config);
+        assert_eq!(expected.len(), 199 * 16);
+
+        let corruptions: Vec<Box<dyn Fn()>> = vec![
+            // 索引文件被删除
+            Box::new(|| std::fs::remove_file(index_path(&dir)).unwrap()),
+            // 截断到非 16 的整数倍
+            Box::new(|| OpenOptions::new().write(true).open(index_path(&dir)).unwrap().set_len(100 * 16 + 7).unwrap()),
+            // 日志被截断，索引指向文件末尾之外
+            Box::new(|| {
+                let len = std::fs::metadata(log_path(&dir)).unwrap().len();
+                OpenOptions::new().write(true).open(log_path(&dir)).unwrap().set_len(len / 2).unwrap();
+            }),
+            // 索引中间出现无效条目
+            Box::new(|| {
+                let mut file = OpenOptions::new().write(true).open(index_path(&dir)).unwrap();
+                file.seek(SeekFrom::Start(50 * 16)).unwrap();
+                file.write_all(&[0xff; 16]).unwrap();
+            }),
+        ];
+        for corrupt in corruptions {
+            write_indexed_segment(&setup_dir("test_rebuild_invalid_index"), &config);
+            corrupt();
+            let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+            let next_offset = log.get_next_offset();
+            assert!(next_offset > 0);
+            // 重建后的索引与原索引一致（日志被截断时为原索引的前缀）
+            log.seal().unwrap();
+            let rebuilt = std::fs::read(index_path(&dir)).unwrap();
+            assert_eq!(rebuilt.len(), (next_offset as usize - 1) * 16);
+            assert_eq!(rebuilt[..], expected[..rebuilt.len()]);
+            let last = next_offset - 1;
+            assert_eq!(log.read_message(last).unwrap(), Some(format!("message-{:03}", last).into_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_repair_truncates_corrupt_batch() {
+        let dir = setup_dir("test_repair_truncates_corrupt_batch");
+        let config = SegmentConfig {
+            index_interval_bytes: 1,
+            ..SegmentConfig::default()
+        };
+        write_indexed_segment(&dir, &config);
+
+        // 破坏中间一条记录，尾部恢复只校验最后一个索引条目之后的数据，发现不了
+        let record_size = std::fs::metadata(log_path(&dir)).unwrap().len() / 200;
+        let mut file = OpenOptions::new().write(true).open(log_path(&dir)).unwrap();
+        file.seek(SeekFrom::Start(100 * record_size + 20)).unwrap();
+        file.write_all(b"X").unwrap();
+        drop(file);
+
+        let mut log = LogSegment::with_config(&dir, 0, config).unwrap();
+        assert_eq!(log.get_next_offset(), 200);
+        assert_eq!(log.repair().unwrap(), 100 * record_size);
+        assert_eq!(log.get_next_offset(), 100);
+        log.seal().unwrap();
+        assert_eq!(std::fs::metadata(index_path(&dir)).unwrap().len(), 99 * 16);
+        assert_eq!(log.read_message(99).unwrap(), Some(b"message-099".to_vec()));
+        match log.append_message(b"after repair").unwrap() {
+            IoResult::Success(offset) => assert_eq!(offset, 100),
+            IoResult::SegmentFull(reason) => panic!("unexpected SegmentFull: {:?}", reason),
+        }
+    }
+
+    #[test]
+    fn test_encrypted_segment() {
+        use std::sync::Arc;
+        use storage::{EncryptionKeys, MasterKey};
+
+        let dir = setup_dir("encrypted_segment");
+        let master = Arc::new(MasterKey::generate(format!("{}/master.key", dir)).unwrap());
+        let keys = Arc::new(EncryptionKeys::new(master.clone()));
+        let config = SegmentConfig {
+            index_interval_bytes: 1,
+            encryption: Some(keys.clone()),
+            ..SegmentConfig::default()
+        };
+        let records = |i: u64| vec![
+            Record::new(Some(format!("key-{}", i).into_bytes()), Some(format!("secret-payload-{}", i).into_bytes())),
+            Record::new(None, Some(vec![i as u8; 32])),
+        ];
+        let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+        assert_eq!(log.encryption_key_id(), Some(keys.current_key_id()));
+        for i in 0..10 {
+            log.append_batch(&mut RecordBatch::new(records(i)).with_compression(CompressionType::Lz4)).unwrap();
+        }
+        assert_eq!(log.read_message(6).unwrap(), Some(b"secret-payload-3".to_vec()));
+        assert!(log.read_slice(0, usize::MAX).is_err());
+        log.flush().unwrap();
+
+        // 日志文件中没有明文记录内容，offset 与索引保持明文
+        let contents = std::fs::read(log_path(&dir)).unwrap();
+        assert!(!contents.windows(14).any(|window| window == b"secret-payload"));
+        assert!(!contents.windows(4).any(|window| window == b"key-"));
+        let index = std::fs::read(format!("{}/{:020}.index", dir, 0)).unwrap();
+        assert_eq!(&index[..8], &2u64.to_be_bytes());
+
+        // 轮换密钥后重新打开：已有段按段头部中的密钥读取，之后的写入仍使用该段的密钥
+        let key_id = keys.current_key_id();
+        assert_ne!(keys.rotate(), key_id);
+        drop(log);
+        let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+        assert_eq!(log.encryption_key_id(), Some(key_id));
+        assert_eq!(log.get_next_offset(), 20);
+        log.append_batch(&mut RecordBatch::new(records(10))).unwrap();
+        assert_eq!(log.read_record(20).unwrap().unwrap().key, Some(b"key-10".to_vec()));
+        log.truncate_to(15).unwrap();
+        assert_eq!(log.read_message(14).unwrap(), Some(b"secret-payload-7".to_vec()));
+        assert_eq!(log.get_next_offset(), 15);
+
+        // 从头校验并重建索引时不需要解密
+        drop(log);
+        let log = LogSegment::open_repaired(&dir, 0, config.clone()).unwrap();
+        assert_eq!(log.get_next_offset(), 15);
+        assert_eq!(log.read_record(0).unwrap().unwrap().key, Some(b"key-0".to_vec()));
+
+        // 新段使用轮换后的密钥
+        let rotated = LogSegment::with_config(&dir, 100, config.clone()).unwrap();
+        assert_eq!(rotated.encryption_key_id(), Some(keys.current_key_id()));
+        drop(log);
+
+        // 没有密钥或主密钥错误时无法打开
+        assert!(LogSegment::with_config(&dir, 0, SegmentConfig::default()).is_err());
+        let other = Arc::new(EncryptionKeys::new(Arc::new(MasterKey::from_bytes(&[7; 32]))));
+        assert!(LogSegment::with_config(&dir, 0, SegmentConfig { encryption: Some(other), ..config.clone() }).is_err());
+        let loaded = Arc::new(EncryptionKeys::new(Arc::new(MasterKey::load(format!("{}/master.key", dir)).unwrap())));
+        let log = LogSegment::with_config(&dir, 0, SegmentConfig { encryption: Some(loaded), ..config.clone() }).unwrap();
+        assert_eq!(log.read_message(1).unwrap(), Some(vec![0; 32]));
+        drop(log);
+
+        // 篡改密文后认证失败
+        let mut file = OpenOptions::new().write(true).open(log_path(&dir)).unwrap();
+        file.seek(SeekFrom::End(-1)).unwrap();
+        file.write_all(&[0xff]).unwrap();
+        drop(file);
+        let log = LogSegment::open_clean(&dir, 0, config).unwrap();
+        assert!(log.read_message(0).is_ok());
+        assert!(log.read_message(14).is_err());
+    }
+
+    #[test]
+    fn test_dump_segment() {
+        use storage::{dump_dir, dump_segment, DumpIssue};
+
+        let dir = setup_dir("dump_segment");
+        let config = SegmentConfig { index_interval_bytes: 1, ..SegmentConfig::default() };
+        let mut log = LogSegment::with_config(&dir, 0, config.clone()).unwrap();
+        for i in 0..5 {
+            let records = vec![
+                Record::new(Some(format!("key-{}", i).into_bytes()), Some(format!("value-{}", i).into_bytes())),
+                Record::new(None, None),
+            ];
+            log.append_batch(&mut RecordBatch::new(records)).unwrap();
+        }
+        log.flush().unwrap();
+
+        // 活跃段的索引文件是预分配的，零值尾部不算条目
+        let dump = dump_segment(log_path(&dir), None).unwrap();
+        assert!(dump.is_consistent(), "{:?}", dump.issues);
+        assert_eq!(dump.batches.len(), 5);
+        assert_eq!(dump.next_offset(), 10);
+        assert_eq!(dump.batches[2].base_offset, 4);
+        let records = dump.batches[2].records.as_ref().unwrap();
+        assert_eq!(records[0].value.as_deref(), Some("value-2".as_bytes()));
+        assert_eq!(records[1].value, None);
+        assert_eq!(dump.index.len(), 4);
+        assert!(dump.index.iter().all(|(offset, position)| {
+            dump.batches.iter().any(|batch| batch.base_offset == *offset && batch.position == *position)
+        }));
+        assert!(!dump.time_index.is_empty());
+        // 从索引文件路径打开同一个日志段
+        assert_eq!(dump_segment(format!("{}/{:020}.index", dir, 0), None).unwrap().batches.len(), 5);
+        drop(log);
+
+        // 相邻日志段之间的 offset 间隔只是提示
+        let mut next = LogSegment::with_config(&dir, 20, config).unwrap();
+        next.append_message(b"after-gap").unwrap();
+        next.flush().unwrap();
+        let dumps = dump_dir(&dir, None).unwrap();
+        assert_eq!(dumps.len(), 2);
+        assert_eq!(dumps[1].issues, vec![DumpIssue::SegmentGap { base_offset: 20, expected: 10 }]);
+        assert!(dumps[1].is_consistent());
+
+        // 损坏一个批次的内容、改写一个索引条目并截断文件末尾
+        let position = dump.batches[1].position as usize;
+        let mut contents = std::fs::read(log_path(&dir)).unwrap();
+        contents[position + 50] ^= 0xff;
+        contents.truncate(contents.len() - 3);
+        std::fs::write(log_path(&dir), &contents).unwrap();
+        let index_path = format!("{}/{:020}.index", dir, 0);
+        let mut index = std::fs::read(&index_path).unwrap();
+        index[8..16].copy_from_slice(&(dump.batches[1].position + 1).to_be_bytes());
+        std::fs::write(&index_path, &index).unwrap();
+
+        let dump = dump_segment(log_path(&dir), None).unwrap();
+        assert!(!dump.is_consistent());
+        assert_eq!(dump.batches.len(), 4);
+        assert!(dump.batches[1].records.is_none() && !dump.batches[1].crc_valid);
+        assert!(matches!(dump.issues[0], DumpIssue::CrcMismatch { offset: 2, .. }));
+        assert!(matches!(dump.issues[1], DumpIssue::TruncatedBatch { .. }));
+        assert!(matches!(dump.issues[2], DumpIssue::InvalidIndexEntry { entry: 0, offset: 2, .. }));
+        // 检查不会修改文件
+        assert_eq!(std::fs::read(log_path(&dir)).unwrap(), contents);
     }
 }